@@ -5,6 +5,7 @@ use crate::sys::malloc::*;
 
 pub struct System;
 
+#[cfg(not(test))]
 #[global_allocator]
 static ALLOCATOR: System = System;
 
@@ -1,5 +1,7 @@
 use core::ffi::CStr;
 
+use alloc_::vec::Vec;
+
 use crate::sys::debug::*;
 
 bitflags::bitflags! {
@@ -84,6 +86,47 @@ pub fn backtrace() {
     }
 }
 
+#[doc = "A writer that sends data over the USB debug channel.\n\n Data is buffered in memory, and sent as a single binary packet by\n [`embedded_io::Write::flush`], so that the host receives it as one file\n (eg: UNFLoader saves each binary packet to disk). This is handy to grab\n screenshots (see [`crate::image::Image::write`]) or other dumps from a\n development cartridge."]
+#[derive(Debug)]
+pub struct UsbWriter {
+    buffer: Vec<u8>,
+}
+
+impl UsbWriter {
+    #[doc = "Initialize the USB channel\n\n Returns `None` if no supported flashcart was detected."]
+    pub fn new() -> Option<Self> {
+        (unsafe { crate::sys::usb::usb_initialize() } != 0).then(|| Self { buffer: Vec::new() })
+    }
+}
+
+impl embedded_io::ErrorType for UsbWriter {
+    type Error = crate::io::Error;
+}
+
+impl embedded_io::Write for UsbWriter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> crate::io::Result<()> {
+        use crate::sys::usb::*;
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let size = i32::try_from(self.buffer.len())
+            .ok()
+            .filter(|&size| size as u32 <= DEBUG_ADDRESS_SIZE)
+            .ok_or(crate::io::Error::new(embedded_io::ErrorKind::OutOfMemory))?;
+        let ok = unsafe { usb_write(DATATYPE_RAWBINARY as _, self.buffer.as_ptr() as _, size) };
+        self.buffer.clear();
+        if ok == 0 {
+            return Err(crate::io::Error::new(embedded_io::ErrorKind::NotConnected));
+        }
+        Ok(())
+    }
+}
+
 #[doc(hidden)]
 pub struct Debug;
 
@@ -1,5 +1,9 @@
 pub use crate::sys::display::VI_CRT_MARGIN;
-use crate::{surface::Surface, sys::display::*};
+use crate::{
+    image::{Image, ImageError},
+    surface::Surface,
+    sys::display::*,
+};
 
 #[doc = "Video resolution structure\n\n This structure allows to configure the video resolution, which includes both\n the framebuffer size and some parameters of how the framebuffer is displayed\n on the screen (aspect ratio, TV overscan margins, etc.).\n\n Most users should just use one of the pre-defined constants (such as\n #RESOLUTION_320x240), but it is possible to configure custom resolutions\n by manually filling fields in this structure."]
 #[repr(transparent)]
@@ -297,6 +301,11 @@ impl Display {
     pub fn current_framebuffer(&self) -> Surface<'_> {
         unsafe { Surface(display_get_current_framebuffer(), core::marker::PhantomData) }
     }
+
+    #[doc = "Take a screenshot of the framebuffer currently being shown on screen.\n\n The framebuffer is copied, so the returned image can be encoded at\n leisure (eg: via [`Image::save`]). Its alpha channel, which holds\n coverage information, is discarded."]
+    pub fn screenshot(&self) -> Result<Image<'static>, ImageError> {
+        Image::capture(&self.current_framebuffer(), None).map(|image| image.opaque(true))
+    }
 }
 
 #[repr(transparent)]
@@ -33,6 +33,7 @@ pub fn cosf(x: f32) -> f32 {
 }
 
 #[inline]
+#[cfg(target_arch = "mips")]
 pub fn sincosf(x: f32) -> (f32, f32) {
     let mut sin = core::mem::MaybeUninit::uninit();
     let mut cos = core::mem::MaybeUninit::uninit();
@@ -43,10 +44,37 @@ pub fn sincosf(x: f32) -> (f32, f32) {
 }
 
 #[inline]
+#[cfg(not(target_arch = "mips"))]
+pub fn sincosf(x: f32) -> (f32, f32) {
+    (core::intrinsics::sinf32(x), core::intrinsics::cosf32(x))
+}
+
+#[inline]
+#[cfg(target_arch = "mips")]
 pub fn atan2f(y: f32, x: f32) -> f32 {
     unsafe { fm_atan2f(y, x) }
 }
 
+#[doc = "Polynomial approximation of `atan2` (max error ~2e-4 radians), used off-target"]
+#[cfg(not(target_arch = "mips"))]
+pub fn atan2f(y: f32, x: f32) -> f32 {
+    use core::f32::consts::PI;
+    let (ax, ay) = (x.abs(), y.abs());
+    if ax == 0.0 && ay == 0.0 {
+        return 0.0;
+    }
+    let a = ax.min(ay) / ax.max(ay);
+    let s = a * a;
+    let mut r = ((-0.046_496_473 * s + 0.159_314_22) * s - 0.327_622_77) * s * a + a;
+    if ay > ax {
+        r = PI * 0.5 - r;
+    }
+    if x < 0.0 {
+        r = PI - r;
+    }
+    if y < 0.0 { -r } else { r }
+}
+
 #[inline]
 pub fn exp(x: f32) -> f32 {
     unsafe { fm_exp(x) }
@@ -67,6 +95,26 @@ pub fn wrap_angle(angle: f32) -> f32 {
     unsafe { fm_wrap_angle(angle) }
 }
 
+#[inline]
+pub fn floorf(x: f32) -> f32 {
+    core::intrinsics::floorf32(x)
+}
+
+#[inline]
+pub fn ceilf(x: f32) -> f32 {
+    core::intrinsics::ceilf32(x)
+}
+
+#[inline]
+pub fn sqrtf(x: f32) -> f32 {
+    core::intrinsics::sqrtf32(x)
+}
+
+#[inline]
+pub fn fabsf(x: f32) -> f32 {
+    x.abs()
+}
+
 pub trait FastMathExt: crate::sealed::Sealed + Sized {
     fn sqrt(self) -> Self;
     fn sin(self) -> Self;
@@ -1143,3 +1191,21 @@ impl_vec_ops! { $ty [$($elems),+] }
 impl_const_vec_ops! { Vec3 [0, 1, 2] }
 impl_const_vec_ops! { Vec4 [0, 1, 2, 3] }
 impl_vec_ops! { Quat [0, 1, 2, 3] }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atan2f_approx() {
+        for i in 0..64 {
+            let angle = i as f32 * core::f32::consts::TAU / 64.0 - core::f32::consts::PI;
+            let (s, c) = sincosf(angle);
+            for r in [0.5, 1.0, 100.0] {
+                let a = atan2f(s * r, c * r);
+                assert!((a - (s * r).atan2(c * r)).abs() < 2.5e-4, "{angle}: {a}");
+            }
+        }
+        assert_eq!(atan2f(0.0, 0.0), 0.0);
+    }
+}
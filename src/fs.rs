@@ -116,6 +116,19 @@ impl File {
                 .map(|fd| Self(I32NotAllOnes::new_unchecked(fd)))
         }
     }
+    #[doc = "Create a file for writing, truncating it if it already exists.\n\n This requires a writable filesystem, such as the SD card (see\n [`crate::debug::init_sdfs`])."]
+    #[inline]
+    pub fn create(filename: &CStr) -> crate::io::Result<Self> {
+        use crate::sys::fcntl;
+        unsafe {
+            crate::io::Error::catch_negative(fcntl::open(
+                filename.as_ptr(),
+                (fcntl::O_WRONLY | fcntl::O_CREAT | fcntl::O_TRUNC) as _,
+                0o666 as core::ffi::c_uint,
+            ))
+            .map(|fd| Self(I32NotAllOnes::new_unchecked(fd)))
+        }
+    }
     #[inline]
     pub fn open(filename: &CStr) -> Self {
         use embedded_io::{Error, ErrorKind};
@@ -170,6 +183,20 @@ impl embedded_io::Read for File {
     }
 }
 
+impl embedded_io::Write for File {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        crate::io::Error::catch_negative(unsafe {
+            crate::sys::unistd::write(self.0.as_inner(), buf.as_ptr() as _, buf.len())
+        })
+        .map(|i| i as usize)
+    }
+    #[inline]
+    fn flush(&mut self) -> crate::io::Result<()> {
+        Ok(())
+    }
+}
+
 impl embedded_io::Seek for File {
     #[inline]
     fn seek(&mut self, pos: embedded_io::SeekFrom) -> crate::io::Result<u64> {
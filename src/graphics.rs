@@ -28,6 +28,11 @@ use core::ffi::CStr;
 use crate::{display::Gamma, surface::TexFormat};
 use crate::{sprite::Sprite, surface::Surface, sys::graphics::*};
 
+mod color;
+mod text;
+pub use color::*;
+pub use text::*;
+
 #[doc = "Generic color structure"]
 #[repr(C, align(4))]
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
@@ -51,6 +56,20 @@ impl Color {
     pub const MAGENTA: Self = Self::rgba32(255, 0, 255, 255);
     pub const CYAN: Self = Self::rgba32(0, 255, 255, 255);
     pub const WHITE: Self = Self::rgba32(255, 255, 255, 255);
+    pub const TRANSPARENT: Self = Self::rgba32(0, 0, 0, 0);
+    pub const GRAY: Self = Self::rgba32(128, 128, 128, 255);
+    pub const DARK_GRAY: Self = Self::rgba32(64, 64, 64, 255);
+    pub const LIGHT_GRAY: Self = Self::rgba32(192, 192, 192, 255);
+    pub const SILVER: Self = Self::rgba32(160, 160, 160, 255);
+    pub const ORANGE: Self = Self::rgba32(255, 165, 0, 255);
+    pub const PURPLE: Self = Self::rgba32(128, 0, 128, 255);
+    pub const PINK: Self = Self::rgba32(255, 192, 203, 255);
+    pub const BROWN: Self = Self::rgba32(139, 69, 19, 255);
+    pub const MAROON: Self = Self::rgba32(128, 0, 0, 255);
+    pub const OLIVE: Self = Self::rgba32(128, 128, 0, 255);
+    pub const LIME: Self = Self::rgba32(50, 205, 50, 255);
+    pub const NAVY: Self = Self::rgba32(0, 0, 128, 255);
+    pub const TEAL: Self = Self::rgba32(0, 128, 128, 255);
 
     #[doc = "Create a `Color` from the R,G,B,A components in the RGBA16 range (that is: RGB in 0-31, A in 0-1)"]
     #[inline]
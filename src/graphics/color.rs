@@ -0,0 +1,525 @@
+use super::Color;
+
+#[doc = "sRGB to linear conversion table (8-bit)"]
+const SRGB_TO_LINEAR: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3,
+    4, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 8, 8, 8, 8, 9, 9, 9, 10, 10, 10, 11, 11, 12,
+    12, 12, 13, 13, 13, 14, 14, 15, 15, 16, 16, 17, 17, 17, 18, 18, 19, 19, 20, 20, 21, 22, 22, 23,
+    23, 24, 24, 25, 25, 26, 27, 27, 28, 29, 29, 30, 30, 31, 32, 32, 33, 34, 35, 35, 36, 37, 37, 38,
+    39, 40, 41, 41, 42, 43, 44, 45, 45, 46, 47, 48, 49, 50, 51, 51, 52, 53, 54, 55, 56, 57, 58, 59,
+    60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 76, 77, 78, 79, 80, 81, 82, 84, 85,
+    86, 87, 88, 90, 91, 92, 93, 95, 96, 97, 99, 100, 101, 103, 104, 105, 107, 108, 109, 111, 112,
+    114, 115, 116, 118, 119, 121, 122, 124, 125, 127, 128, 130, 131, 133, 134, 136, 138, 139, 141,
+    142, 144, 146, 147, 149, 151, 152, 154, 156, 157, 159, 161, 163, 164, 166, 168, 170, 171, 173,
+    175, 177, 179, 181, 183, 184, 186, 188, 190, 192, 194, 196, 198, 200, 202, 204, 206, 208, 210,
+    212, 214, 216, 218, 220, 222, 224, 226, 229, 231, 233, 235, 237, 239, 242, 244, 246, 248, 250,
+    253, 255,
+];
+#[doc = "Linear to sRGB conversion table (8-bit)"]
+const LINEAR_TO_SRGB: [u8; 256] = [
+    0, 13, 22, 28, 34, 38, 42, 46, 50, 53, 56, 59, 61, 64, 66, 69, 71, 73, 75, 77, 79, 81, 83, 85,
+    86, 88, 90, 92, 93, 95, 96, 98, 99, 101, 102, 104, 105, 106, 108, 109, 110, 112, 113, 114, 115,
+    117, 118, 119, 120, 121, 122, 124, 125, 126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 136,
+    137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147, 148, 148, 149, 150, 151, 152, 153, 154,
+    155, 155, 156, 157, 158, 159, 159, 160, 161, 162, 163, 163, 164, 165, 166, 167, 167, 168, 169,
+    170, 170, 171, 172, 173, 173, 174, 175, 175, 176, 177, 178, 178, 179, 180, 180, 181, 182, 182,
+    183, 184, 185, 185, 186, 187, 187, 188, 189, 189, 190, 190, 191, 192, 192, 193, 194, 194, 195,
+    196, 196, 197, 197, 198, 199, 199, 200, 200, 201, 202, 202, 203, 203, 204, 205, 205, 206, 206,
+    207, 208, 208, 209, 209, 210, 210, 211, 212, 212, 213, 213, 214, 214, 215, 215, 216, 216, 217,
+    218, 218, 219, 219, 220, 220, 221, 221, 222, 222, 223, 223, 224, 224, 225, 226, 226, 227, 227,
+    228, 228, 229, 229, 230, 230, 231, 231, 232, 232, 233, 233, 234, 234, 235, 235, 236, 236, 237,
+    237, 238, 238, 238, 239, 239, 240, 240, 241, 241, 242, 242, 243, 243, 244, 244, 245, 245, 246,
+    246, 246, 247, 247, 248, 248, 249, 249, 250, 250, 251, 251, 251, 252, 252, 253, 253, 254, 254,
+    255, 255,
+];
+
+#[doc = "A color in the HSV (hue, saturation, value) color space"]
+#[derive(Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+pub struct Hsv {
+    #[doc = "Hue in degrees (0-360)"]
+    pub h: f32,
+    #[doc = "Saturation (0-1)"]
+    pub s: f32,
+    #[doc = "Value (0-1)"]
+    pub v: f32,
+}
+
+#[doc = "A color in the HSL (hue, saturation, lightness) color space"]
+#[derive(Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+pub struct Hsl {
+    #[doc = "Hue in degrees (0-360)"]
+    pub h: f32,
+    #[doc = "Saturation (0-1)"]
+    pub s: f32,
+    #[doc = "Lightness (0-1)"]
+    pub l: f32,
+}
+
+impl Hsv {
+    #[inline]
+    pub const fn new(h: f32, s: f32, v: f32) -> Self {
+        Self { h, s, v }
+    }
+}
+
+impl Hsl {
+    #[inline]
+    pub const fn new(h: f32, s: f32, l: f32) -> Self {
+        Self { h, s, l }
+    }
+}
+
+#[doc = "How [`Color::blend`] combines two colors"]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    #[doc = "Composite the second color over the first one, according to its alpha"]
+    #[default]
+    Over,
+    #[doc = "Multiply the components (see [`Color::multiply`])"]
+    Multiply,
+    #[doc = "Inverse of the product of the inverted components (see [`Color::screen`])"]
+    Screen,
+    #[doc = "Add the components, saturating (see [`Color::add`])"]
+    Add,
+    #[doc = "Subtract the components, saturating (see [`Color::subtract`])"]
+    Subtract,
+}
+
+#[doc = "A color gradient, made of color stops.\n\n Each stop is a position (usually between 0 and 1) and the color at that\n position; stops must be sorted by position. Colors between two stops are\n linearly interpolated, and positions outside of the stops get the color\n of the closest one.\n\n ```ignore\n const FIRE: Gradient = Gradient::new(&[\n     (0.0, Color::rgba32(64, 0, 0, 255)),\n     (0.5, Color::ORANGE),\n     (1.0, Color::YELLOW),\n ]);\n rdpq.set_prim_color(FIRE.sample(heat));\n ```"]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Gradient<'a>(&'a [(f32, Color)]);
+
+impl<'a> Gradient<'a> {
+    #[inline]
+    pub const fn new(stops: &'a [(f32, Color)]) -> Self {
+        Self(stops)
+    }
+    #[inline]
+    pub const fn stops(&self) -> &'a [(f32, Color)] {
+        self.0
+    }
+    #[doc = "Get the color at a position of the gradient\n\n Returns transparent black if the gradient has no stops."]
+    pub const fn sample(&self, t: f32) -> Color {
+        let stops = self.0;
+        let [first, .., last] = stops else {
+            return match stops {
+                [(_, c)] => *c,
+                _ => Color::TRANSPARENT,
+            };
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        let mut i = 1;
+        while i < stops.len() {
+            let (p0, c0) = stops[i - 1];
+            let (p1, c1) = stops[i];
+            if t <= p1 {
+                let span = p1 - p0;
+                return if span > 0.0 {
+                    c0.lerp(c1, (t - p0) / span)
+                } else {
+                    c1
+                };
+            }
+            i += 1;
+        }
+        last.1
+    }
+    #[doc = "Fill a slice with colors sampled at regular intervals from `t0` to `t1`\n (included), eg: to build a palette for a palette animation"]
+    pub fn sample_into(&self, t0: f32, t1: f32, out: &mut [Color]) {
+        let steps = out.len().saturating_sub(1).max(1) as f32;
+        for (i, c) in out.iter_mut().enumerate() {
+            *c = self.sample(t0 + (t1 - t0) * i as f32 / steps);
+        }
+    }
+}
+
+#[inline]
+const fn unit(v: u8) -> f32 {
+    v as f32 / 255.0
+}
+
+#[inline]
+const fn byte(v: f32) -> u8 {
+    (v * 255.0 + 0.5) as u8
+}
+
+#[inline]
+const fn mul8(a: u8, b: u8) -> u8 {
+    ((a as u32 * b as u32 + 127) / 255) as u8
+}
+
+#[inline]
+const fn div8(v: u8, a: u32) -> u8 {
+    let v = (v as u32 * 255 + a / 2) / a;
+    if v > 255 { 255 } else { v as u8 }
+}
+
+#[inline]
+const fn lerp8(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t + 0.5) as u8
+}
+
+#[doc = "Wrap a hue to 0-360 degrees"]
+#[inline]
+const fn wrap_hue(h: f32) -> f32 {
+    let h = h - 360.0 * (h / 360.0) as i32 as f32;
+    if h < 0.0 { h + 360.0 } else { h }
+}
+
+#[doc = "Hue, chroma, minimum and maximum of a color"]
+#[inline]
+const fn hue(c: Color) -> (f32, f32, f32, f32) {
+    let (r, g, b) = (unit(c.r), unit(c.g), unit(c.b));
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let d = max - min;
+    let h = if d == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / d)
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    };
+    (wrap_hue(h), d, min, max)
+}
+
+#[doc = "Color with the given hue and chroma, plus `m` on every component"]
+#[inline]
+const fn from_hue(h: f32, c: f32, m: f32, a: u8) -> Color {
+    let h = wrap_hue(h) / 60.0;
+    let x = c * (1.0 - (h - 2.0 * (h / 2.0) as i32 as f32 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    Color::rgba32(byte(r + m), byte(g + m), byte(b + m), a)
+}
+
+impl Color {
+    #[doc = "Convert the color to HSV (the alpha channel is ignored)"]
+    #[inline]
+    pub const fn to_hsv(self) -> Hsv {
+        let (h, d, _, max) = hue(self);
+        Hsv::new(h, if max == 0.0 { 0.0 } else { d / max }, max)
+    }
+    #[doc = "Create a color from HSV components and alpha"]
+    #[inline]
+    pub const fn from_hsv(hsv: Hsv, a: u8) -> Self {
+        let (s, v) = (hsv.s.clamp(0.0, 1.0), hsv.v.clamp(0.0, 1.0));
+        let c = v * s;
+        from_hue(hsv.h, c, v - c, a)
+    }
+    #[doc = "Convert the color to HSL (the alpha channel is ignored)"]
+    #[inline]
+    pub const fn to_hsl(self) -> Hsl {
+        let (h, d, min, max) = hue(self);
+        let l = (max + min) / 2.0;
+        let s = if d == 0.0 {
+            0.0
+        } else {
+            d / (1.0 - (2.0 * l - 1.0).abs())
+        };
+        Hsl::new(h, s, l)
+    }
+    #[doc = "Create a color from HSL components and alpha"]
+    #[inline]
+    pub const fn from_hsl(hsl: Hsl, a: u8) -> Self {
+        let (s, l) = (hsl.s.clamp(0.0, 1.0), hsl.l.clamp(0.0, 1.0));
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        from_hue(hsl.h, c, l - c / 2.0, a)
+    }
+    #[doc = "Rotate the hue of the color by the given angle in degrees, keeping\n saturation, value and alpha (useful for palette animations)"]
+    #[inline]
+    pub const fn rotate_hue(self, degrees: f32) -> Self {
+        let hsv = self.to_hsv();
+        Self::from_hsv(Hsv::new(hsv.h + degrees, hsv.s, hsv.v), self.a)
+    }
+
+    #[doc = "Convert the color from the sRGB color space to linear (the alpha\n channel is unchanged)\n\n This uses the exact sRGB transfer function. Notice that the gamma\n correction of the video interface ([`crate::display::Gamma::Correct`]) approximates it\n with a power of 2, as done by [`Color::linear32`]."]
+    #[inline]
+    pub const fn to_linear(self) -> Self {
+        let t = &SRGB_TO_LINEAR;
+        Self::rgba32(
+            t[self.r as usize],
+            t[self.g as usize],
+            t[self.b as usize],
+            self.a,
+        )
+    }
+    #[doc = "Convert the color from linear to the sRGB color space (the alpha\n channel is unchanged). This is the inverse of [`Color::to_linear`]"]
+    #[inline]
+    pub const fn to_srgb(self) -> Self {
+        let t = &LINEAR_TO_SRGB;
+        Self::rgba32(
+            t[self.r as usize],
+            t[self.g as usize],
+            t[self.b as usize],
+            self.a,
+        )
+    }
+
+    #[doc = "Multiply the color components by alpha"]
+    #[inline]
+    pub const fn premultiply(self) -> Self {
+        let a = self.a;
+        Self::rgba32(mul8(self.r, a), mul8(self.g, a), mul8(self.b, a), a)
+    }
+    #[doc = "Divide the color components by alpha (the inverse of [`Color::premultiply`])"]
+    #[inline]
+    pub const fn unpremultiply(self) -> Self {
+        let a = self.a as u32;
+        if a == 0 {
+            return Self::TRANSPARENT;
+        }
+        Self::rgba32(div8(self.r, a), div8(self.g, a), div8(self.b, a), self.a)
+    }
+
+    #[doc = "Linearly interpolate between two colors, component-wise\n\n @param other     The color at t = 1\n @param t         Interpolation factor (clamped to 0-1)"]
+    #[inline]
+    pub const fn lerp(self, other: Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        Self::rgba32(
+            lerp8(self.r, other.r, t),
+            lerp8(self.g, other.g, t),
+            lerp8(self.b, other.b, t),
+            lerp8(self.a, other.a, t),
+        )
+    }
+    #[doc = "Multiply two colors, component-wise (including alpha)\n\n This is the same as modulating a color by another in the combiner\n (eg: `TEX0 * PRIM`)."]
+    #[inline]
+    pub const fn multiply(self, other: Self) -> Self {
+        Self::rgba32(
+            mul8(self.r, other.r),
+            mul8(self.g, other.g),
+            mul8(self.b, other.b),
+            mul8(self.a, other.a),
+        )
+    }
+    #[doc = "Screen two colors, component-wise (including alpha): the inverse of\n the product of the inverted components. This brightens the color,\n while [`Color::multiply`] darkens it."]
+    #[inline]
+    pub const fn screen(self, other: Self) -> Self {
+        Self::rgba32(
+            255 - mul8(255 - self.r, 255 - other.r),
+            255 - mul8(255 - self.g, 255 - other.g),
+            255 - mul8(255 - self.b, 255 - other.b),
+            255 - mul8(255 - self.a, 255 - other.a),
+        )
+    }
+    #[doc = "Add two colors, component-wise (including alpha), saturating"]
+    #[inline]
+    pub const fn add(self, other: Self) -> Self {
+        Self::rgba32(
+            self.r.saturating_add(other.r),
+            self.g.saturating_add(other.g),
+            self.b.saturating_add(other.b),
+            self.a.saturating_add(other.a),
+        )
+    }
+    #[doc = "Subtract a color from this one, component-wise (including alpha), saturating"]
+    #[inline]
+    pub const fn subtract(self, other: Self) -> Self {
+        Self::rgba32(
+            self.r.saturating_sub(other.r),
+            self.g.saturating_sub(other.g),
+            self.b.saturating_sub(other.b),
+            self.a.saturating_sub(other.a),
+        )
+    }
+    #[doc = "Composite a color over this one, according to its alpha"]
+    #[inline]
+    pub const fn over(self, src: Self) -> Self {
+        let (a, ia) = (src.a, 255 - src.a);
+        Self::rgba32(
+            mul8(src.r, a) + mul8(self.r, ia),
+            mul8(src.g, a) + mul8(self.g, ia),
+            mul8(src.b, a) + mul8(self.b, ia),
+            a + mul8(self.a, ia),
+        )
+    }
+    #[doc = "Combine a color with this one\n\n @param src       The color to combine\n @param mode      How the colors are combined"]
+    #[inline]
+    pub const fn blend(self, src: Self, mode: BlendMode) -> Self {
+        match mode {
+            BlendMode::Over => self.over(src),
+            BlendMode::Multiply => self.multiply(src),
+            BlendMode::Screen => self.screen(src),
+            BlendMode::Add => self.add(src),
+            BlendMode::Subtract => self.subtract(src),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A sample of colors, including all the primaries and secondaries
+    fn colors() -> impl Iterator<Item = Color> {
+        let levels = [0, 1, 51, 127, 128, 200, 254, 255];
+        levels.into_iter().flat_map(move |r| {
+            levels.into_iter().flat_map(move |g| {
+                levels
+                    .into_iter()
+                    .map(move |b| Color::rgba32(r, g, b, r ^ g))
+            })
+        })
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for c in colors() {
+            assert_eq!(Color::from_hsv(c.to_hsv(), c.a), c, "{:?}", c.to_hsv());
+        }
+        assert_eq!(Color::RED.to_hsv(), Hsv::new(0.0, 1.0, 1.0));
+        assert_eq!(Color::GREEN.to_hsv(), Hsv::new(120.0, 1.0, 1.0));
+        assert_eq!(Color::BLUE.to_hsv(), Hsv::new(240.0, 1.0, 1.0));
+        assert_eq!(Color::MAGENTA.to_hsv(), Hsv::new(300.0, 1.0, 1.0));
+        assert_eq!(Color::BLACK.to_hsv(), Hsv::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        for c in colors() {
+            assert_eq!(Color::from_hsl(c.to_hsl(), c.a), c, "{:?}", c.to_hsl());
+        }
+        assert_eq!(Color::RED.to_hsl(), Hsl::new(0.0, 1.0, 0.5));
+        assert_eq!(Color::CYAN.to_hsl(), Hsl::new(180.0, 1.0, 0.5));
+        assert_eq!(Color::WHITE.to_hsl(), Hsl::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn hsv_hsl_gray() {
+        // Without saturation, the hue is irrelevant
+        let hsv = Color::GRAY.to_hsv();
+        assert_eq!((hsv.h, hsv.s), (0.0, 0.0));
+        assert_eq!(byte(hsv.v), 128);
+        let hsl = Color::GRAY.to_hsl();
+        assert_eq!((hsl.h, hsl.s), (0.0, 0.0));
+        assert_eq!(byte(hsl.l), 128);
+        for h in [0.0, 90.0, 200.0, 359.0] {
+            assert_eq!(Color::from_hsv(Hsv::new(h, 0.0, 0.5), 255), Color::GRAY);
+            assert_eq!(
+                Color::from_hsl(Hsl::new(h, 0.0, 0.25), 255),
+                Color::DARK_GRAY
+            );
+        }
+        // Out of range saturation and value are clamped
+        assert_eq!(Color::from_hsv(Hsv::new(0.0, -1.0, 2.0), 255), Color::WHITE);
+        assert_eq!(Color::from_hsl(Hsl::new(0.0, 2.0, -1.0), 255), Color::BLACK);
+    }
+
+    #[test]
+    fn hue_wrap() {
+        let hsv = |h| Color::from_hsv(Hsv::new(h, 1.0, 1.0), 255);
+        let hsl = |h| Color::from_hsl(Hsl::new(h, 1.0, 0.5), 255);
+        for (h, c) in [
+            (360.0, Color::RED),
+            (480.0, Color::GREEN),
+            (-120.0, Color::BLUE),
+            (-60.0, Color::MAGENTA),
+            (-720.0, Color::RED),
+            (1080.0 + 60.0, Color::YELLOW),
+        ] {
+            assert_eq!(hsv(h), c, "{h}");
+            assert_eq!(hsl(h), c, "{h}");
+        }
+        assert_eq!(Color::RED.rotate_hue(120.0), Color::GREEN);
+        assert_eq!(Color::RED.rotate_hue(-120.0), Color::BLUE);
+        assert_eq!(Color::BLUE.rotate_hue(480.0), Color::RED);
+        let c = Color::rgba32(200, 100, 50, 77);
+        assert_eq!(c.rotate_hue(360.0), c);
+        assert_eq!(Color::GRAY.rotate_hue(90.0), Color::GRAY);
+    }
+
+    #[test]
+    fn premultiply() {
+        let c = Color::rgba32(200, 100, 50, 128);
+        assert_eq!(c.premultiply(), Color::rgba32(100, 50, 25, 128));
+        assert_eq!(
+            c.premultiply().unpremultiply(),
+            Color::rgba32(199, 100, 50, 128)
+        );
+        // Opaque colors are unchanged
+        let c = Color::rgba32(200, 100, 50, 255);
+        assert_eq!(c.premultiply(), c);
+        assert_eq!(c.unpremultiply(), c);
+        // Transparent colors lose their components
+        let c = Color::rgba32(200, 100, 50, 0);
+        assert_eq!(c.premultiply(), Color::TRANSPARENT);
+        assert_eq!(c.unpremultiply(), Color::TRANSPARENT);
+        // Components larger than alpha are not premultiplied, and saturate
+        assert_eq!(
+            Color::rgba32(200, 0, 10, 100).unpremultiply(),
+            Color::rgba32(255, 0, 26, 100)
+        );
+    }
+
+    #[test]
+    fn blend_modes() {
+        let dst = Color::rgba32(200, 100, 50, 255);
+        let src = Color::rgba32(100, 100, 100, 128);
+        for (mode, expected) in [
+            (BlendMode::Over, Color::rgba32(150, 100, 75, 255)),
+            (BlendMode::Multiply, Color::rgba32(78, 39, 20, 128)),
+            (BlendMode::Screen, Color::rgba32(222, 161, 130, 255)),
+            (BlendMode::Add, Color::rgba32(255, 200, 150, 255)),
+            (BlendMode::Subtract, Color::rgba32(100, 0, 0, 127)),
+        ] {
+            assert_eq!(dst.blend(src, mode), expected, "{mode:?}");
+        }
+        // Identities
+        assert_eq!(dst.over(Color::TRANSPARENT), dst);
+        assert_eq!(dst.over(Color::BLUE), Color::BLUE);
+        assert_eq!(dst.multiply(Color::WHITE), dst);
+        assert_eq!(dst.multiply(Color::TRANSPARENT), Color::TRANSPARENT);
+        assert_eq!(dst.screen(Color::TRANSPARENT), dst);
+        assert_eq!(dst.screen(Color::WHITE), Color::WHITE);
+        assert_eq!(dst.add(Color::TRANSPARENT), dst);
+        assert_eq!(dst.subtract(dst), Color::TRANSPARENT);
+    }
+
+    #[test]
+    fn gradient() {
+        let g = Gradient::new(&[(0.0, Color::BLACK), (0.5, Color::RED), (1.0, Color::WHITE)]);
+        // Endpoints and stops
+        assert_eq!(g.sample(0.0), Color::BLACK);
+        assert_eq!(g.sample(0.5), Color::RED);
+        assert_eq!(g.sample(1.0), Color::WHITE);
+        // Between stops
+        assert_eq!(g.sample(0.25), Color::rgba32(128, 0, 0, 255));
+        assert_eq!(g.sample(0.75), Color::rgba32(255, 128, 128, 255));
+        // Outside of [0, 1]
+        assert_eq!(g.sample(-1.0), Color::BLACK);
+        assert_eq!(g.sample(f32::NEG_INFINITY), Color::BLACK);
+        assert_eq!(g.sample(1.5), Color::WHITE);
+        assert_eq!(g.sample(f32::INFINITY), Color::WHITE);
+
+        let mut out = [Color::TRANSPARENT; 3];
+        g.sample_into(0.0, 1.0, &mut out);
+        assert_eq!(out, [Color::BLACK, Color::RED, Color::WHITE]);
+        g.sample_into(1.0, 0.0, &mut out);
+        assert_eq!(out, [Color::WHITE, Color::RED, Color::BLACK]);
+
+        // Coincident stops make a hard edge
+        let g = Gradient::new(&[
+            (0.0, Color::BLACK),
+            (0.5, Color::RED),
+            (0.5, Color::BLUE),
+            (1.0, Color::BLUE),
+        ]);
+        assert_eq!(g.sample(0.5), Color::RED);
+        assert_eq!(g.sample(0.5001), Color::BLUE);
+
+        assert_eq!(Gradient::new(&[]).sample(0.5), Color::TRANSPARENT);
+        assert_eq!(Gradient::new(&[(0.3, Color::RED)]).sample(-5.0), Color::RED);
+        assert_eq!(Gradient::new(&[(0.3, Color::RED)]).sample(5.0), Color::RED);
+    }
+}
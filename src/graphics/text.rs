@@ -0,0 +1,894 @@
+use core::{
+    fmt::{self, Write},
+    mem::ManuallyDrop,
+};
+
+use crate::{
+    sprite::Sprite,
+    surface::{Pixel, Surface, TexFormat, read_raw, write_raw},
+};
+
+use super::Color;
+
+#[doc = "Glyphs of the builtin font, covering the printable ASCII range (8x8 pixels,\n one byte per row, least significant bit on the left)"]
+const BUILTIN: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00],
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00],
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00],
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00],
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00],
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00],
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00],
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00],
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06],
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00],
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00],
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00],
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00],
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00],
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00],
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00],
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00],
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00],
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00],
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00],
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00],
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00],
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06],
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00],
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00],
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00],
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00],
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00],
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00],
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00],
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00],
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00],
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00],
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00],
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00],
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00],
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00],
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00],
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00],
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00],
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00],
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00],
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00],
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00],
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00],
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00],
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00],
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00],
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00],
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00],
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00],
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00],
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00],
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00],
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF],
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00],
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00],
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00],
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00],
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00],
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00],
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F],
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00],
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E],
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00],
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00],
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00],
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00],
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F],
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78],
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00],
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00],
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00],
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00],
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00],
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00],
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00],
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F],
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00],
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00],
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00],
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00],
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+];
+
+#[doc = "Codepoints covered by the builtin font"]
+const BUILTIN_RANGES: &[(char, char)] = &[(' ', '~')];
+
+#[doc = "Maximum number of glyphs of a word that are kept together by [`TextWrap::Word`]"]
+const MAX_WORD: usize = 32;
+
+#[derive(Debug, Copy, Clone)]
+enum Glyphs<'a> {
+    Builtin,
+    Sheet {
+        pixels: &'a [u8],
+        stride: u16,
+        format: TexFormat,
+        palette: Option<&'a [u16]>,
+        columns: u16,
+    },
+}
+
+#[doc = "A bitmap font for drawing text on a [`Surface`] with the CPU (see\n [`Surface::draw_str`]).\n\n This is a pure Rust replacement for the text functions of [`GraphicsExt`],\n that does not depend on the state of the C graphics module.\n\n Glyphs all have the same size, and are either taken from the builtin 8x8\n font (see [`BitmapFont::builtin`]), or from the tiles of a sprite (see\n [`BitmapFont::from_sprite`]).\n\n [`GraphicsExt`]: super::GraphicsExt"]
+#[derive(Debug, Copy, Clone)]
+pub struct BitmapFont<'a> {
+    glyphs: Glyphs<'a>,
+    glyph_width: u16,
+    glyph_height: u16,
+    count: u32,
+    ranges: Option<&'a [(char, char)]>,
+    fallback: Option<char>,
+}
+
+impl BitmapFont<'static> {
+    #[doc = "The builtin 8x8 font\n\n It covers the printable ASCII range; other characters are drawn as `?`."]
+    #[inline]
+    pub const fn builtin() -> Self {
+        Self {
+            glyphs: Glyphs::Builtin,
+            glyph_width: 8,
+            glyph_height: 8,
+            count: BUILTIN.len() as u32,
+            ranges: Some(BUILTIN_RANGES),
+            fallback: Some('?'),
+        }
+    }
+}
+
+impl<'a> BitmapFont<'a> {
+    #[doc = "Create a font from a sprite used as a font sheet\n\n The sprite is split in glyphs according to its horizontal and vertical\n slices (as configured by mksprite), numbered left to right, top to bottom.\n By default, glyph N is used for codepoint N, as done by\n [`super::set_font_sprite`]; use [`BitmapFont::ranges`] to map glyphs to\n other codepoints.\n\n Glyphs are drawn according to their alpha channel (or intensity, for I4\n and I8 sprites), and modulated by the text color: white glyphs take the\n color of the text, while colored glyphs can be drawn as is using white\n text."]
+    pub fn from_sprite(sprite: &'a Sprite) -> Self {
+        let raw = unsafe { &*sprite.as_raw() };
+        let columns = raw.hslices.max(1) as u16;
+        let rows = raw.vslices.max(1) as u16;
+        // The surface points to the sprite contents, and has nothing to free
+        let surface = ManuallyDrop::new(sprite.pixels());
+        let format = surface.format();
+        let pixels = surface
+            .buffer()
+            .map(|buffer| unsafe { core::slice::from_raw_parts(buffer.as_ptr(), buffer.len()) })
+            .unwrap_or_default();
+        Self {
+            glyphs: Glyphs::Sheet {
+                pixels,
+                stride: surface.stride(),
+                format,
+                palette: match format {
+                    TexFormat::CI4 | TexFormat::CI8 => sprite.palette(),
+                    _ => None,
+                },
+                columns,
+            },
+            glyph_width: raw.width / columns,
+            glyph_height: raw.height / rows,
+            count: columns as u32 * rows as u32,
+            ranges: None,
+            fallback: None,
+        }
+    }
+    #[doc = "Set the codepoints covered by the glyphs of the font\n\n Each range is inclusive, and glyphs are assigned to the codepoints of\n the ranges in order. For instance, `&[(' ', '~'), ('à', 'ü')]` maps the\n first 95 glyphs to printable ASCII, and the following ones to some Latin-1\n letters. Codepoints beyond the number of glyphs are not covered."]
+    #[inline]
+    pub const fn ranges(mut self, ranges: &'a [(char, char)]) -> Self {
+        self.ranges = Some(ranges);
+        self
+    }
+    #[doc = "Set the character drawn in place of codepoints not covered by the\n font. If not set, they are left blank"]
+    #[inline]
+    pub const fn fallback(mut self, fallback: char) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+    #[doc = "Size of the glyphs, in pixels"]
+    #[inline]
+    pub const fn glyph_size(&self) -> (u16, u16) {
+        (self.glyph_width, self.glyph_height)
+    }
+    #[doc = "Number of glyphs of the font"]
+    #[inline]
+    pub const fn glyph_count(&self) -> u32 {
+        self.count
+    }
+    #[doc = "Index of the glyph of a codepoint, if covered by the font"]
+    pub const fn glyph_index(&self, ch: char) -> Option<u32> {
+        let Some(ranges) = self.ranges else {
+            return if (ch as u32) < self.count {
+                Some(ch as u32)
+            } else {
+                None
+            };
+        };
+        let mut base = 0;
+        let mut i = 0;
+        while i < ranges.len() {
+            let (start, end) = ranges[i];
+            if ch as u32 >= start as u32 && ch as u32 <= end as u32 {
+                let idx = base + (ch as u32 - start as u32);
+                return if idx < self.count { Some(idx) } else { None };
+            }
+            base += (end as u32).saturating_sub(start as u32) + 1;
+            i += 1;
+        }
+        None
+    }
+    #[doc = "Index of the glyph drawn for a codepoint, including the fallback"]
+    #[inline]
+    fn glyph(&self, ch: char) -> Option<u32> {
+        self.glyph_index(ch)
+            .or_else(|| self.glyph_index(self.fallback?))
+    }
+    #[doc = "Compute the size of a text, as drawn by [`Surface::draw_str`]"]
+    pub fn measure(&self, text: &str, parms: &CpuTextParms) -> TextSize {
+        let mut layout = Layout::new(self, parms, |_, _, _, _| {});
+        layout.push_str(text);
+        layout.finish()
+    }
+}
+
+#[doc = "Text wrapping mode of [`CpuTextParms`]"]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum TextWrap {
+    #[doc = "Do not wrap: glyphs exceeding the width are not drawn"]
+    #[default]
+    None,
+    #[doc = "Wrap at character boundaries"]
+    Char,
+    #[doc = "Wrap at word boundaries (words longer than a line are wrapped at\n character boundaries)"]
+    Word,
+}
+
+#[doc = "Parameters of [`Surface::draw_str`].\n\n The text is drawn in white, without background, with no size limits. The\n zero value of every other field is the most reasonable default."]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CpuTextParms<'p> {
+    color: Color,
+    background: Color,
+    width: u16,
+    height: u16,
+    wrap: TextWrap,
+    tab_size: u8,
+    char_spacing: i16,
+    line_spacing: i16,
+    palette: Option<&'p [u16]>,
+}
+
+impl<'p> Default for CpuTextParms<'p> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'p> CpuTextParms<'p> {
+    pub const EMPTY: Self = Self::new();
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            color: Color::WHITE,
+            background: Color::TRANSPARENT,
+            width: 0,
+            height: 0,
+            wrap: TextWrap::None,
+            tab_size: 0,
+            char_spacing: 0,
+            line_spacing: 0,
+            palette: None,
+        }
+    }
+    #[doc = "Color of the text (default: white). Glyphs are blended over the\n surface according to its alpha"]
+    #[inline]
+    pub const fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+    #[doc = "Color of the background of each character cell (default:\n transparent). If not fully transparent, cells are filled with this\n color (without blending) before drawing the glyphs"]
+    #[inline]
+    pub const fn background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+    #[doc = "Width of the box containing the text, in pixels (0 if unbounded)"]
+    #[inline]
+    pub const fn width(mut self, width: u16) -> Self {
+        self.width = width;
+        self
+    }
+    #[doc = "Height of the box containing the text, in pixels (0 if unbounded).\n Lines that do not fit entirely are not drawn"]
+    #[inline]
+    pub const fn height(mut self, height: u16) -> Self {
+        self.height = height;
+        self
+    }
+    #[doc = "Wrap mode, used when the width is bounded"]
+    #[inline]
+    pub const fn wrap(mut self, wrap: TextWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+    #[doc = "Distance between tab stops, in characters (0 for the default of 4)"]
+    #[inline]
+    pub const fn tab_size(mut self, tab_size: u8) -> Self {
+        self.tab_size = tab_size;
+        self
+    }
+    #[doc = "Extra spacing between characters (in addition to the glyph width)"]
+    #[inline]
+    pub const fn char_spacing(mut self, char_spacing: i16) -> Self {
+        self.char_spacing = char_spacing;
+        self
+    }
+    #[doc = "Extra spacing between lines (in addition to the glyph height)"]
+    #[inline]
+    pub const fn line_spacing(mut self, line_spacing: i16) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+    #[doc = "Palette of the surface, if it is color-indexed"]
+    #[inline]
+    pub const fn palette(mut self, palette: &'p [u16]) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+}
+
+#[doc = "Size of a text drawn by [`Surface::draw_str`] (see also\n [`BitmapFont::measure`])"]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TextSize {
+    #[doc = "Width of the widest line, in pixels"]
+    pub width: u32,
+    #[doc = "Height of the lines, in pixels"]
+    pub height: u32,
+    #[doc = "Number of lines"]
+    pub lines: u32,
+}
+
+#[doc = "Text layout engine: splits the text in lines, and reports each character\n cell (as position, width and glyph) to a callback"]
+struct Layout<'f, F: FnMut(i32, i32, i32, Option<u32>)> {
+    font: &'f BitmapFont<'f>,
+    wrap: TextWrap,
+    width: i32,
+    height: i32,
+    advance: i32,
+    line_height: i32,
+    tab: i32,
+    emit: F,
+    x: i32,
+    y: i32,
+    word: [Option<u32>; MAX_WORD],
+    word_len: usize,
+    size: TextSize,
+    cr: bool,
+    done: bool,
+}
+
+impl<'f, F: FnMut(i32, i32, i32, Option<u32>)> Layout<'f, F> {
+    fn new(font: &'f BitmapFont<'f>, parms: &CpuTextParms, emit: F) -> Self {
+        let (gw, gh) = (font.glyph_width as i32, font.glyph_height as i32);
+        let advance = (gw + parms.char_spacing as i32).max(1);
+        let tab = match parms.tab_size {
+            0 => 4,
+            n => n as i32,
+        };
+        Self {
+            font,
+            wrap: parms.wrap,
+            width: parms.width as i32,
+            height: parms.height as i32,
+            advance,
+            line_height: (gh + parms.line_spacing as i32).max(1),
+            tab: tab * advance,
+            emit,
+            x: 0,
+            y: 0,
+            word: [None; MAX_WORD],
+            word_len: 0,
+            size: TextSize::default(),
+            cr: false,
+            done: parms.height != 0 && gh > parms.height as i32,
+        }
+    }
+    #[doc = "Whether a cell fits in the current line"]
+    #[inline]
+    fn fits(&self, width: i32) -> bool {
+        self.width == 0 || self.x + width <= self.width
+    }
+    fn newline(&mut self) {
+        self.x = 0;
+        self.y += self.line_height;
+        if self.height != 0 && self.y + self.font.glyph_height as i32 > self.height {
+            self.done = true;
+        } else {
+            self.size.lines += 1;
+        }
+    }
+    #[doc = "Place a cell in the current line. Blank cells do not count in the\n width of the text"]
+    fn cell(&mut self, width: i32, glyph: Option<u32>, blank: bool) {
+        if self.done {
+            return;
+        }
+        let drawn = if blank {
+            width
+        } else {
+            self.font.glyph_width as i32
+        };
+        if self.fits(drawn) {
+            (self.emit)(self.x, self.y, drawn, glyph);
+            if !blank {
+                self.size.width = self.size.width.max((self.x + drawn) as u32);
+            }
+        }
+        self.x += width;
+    }
+    #[doc = "Place a glyph, wrapping the line if needed"]
+    fn glyph(&mut self, glyph: Option<u32>) {
+        let gw = self.font.glyph_width as i32;
+        if self.wrap != TextWrap::None && self.x > 0 && !self.fits(gw) {
+            self.newline();
+        }
+        self.cell(self.advance, glyph, false);
+    }
+    #[doc = "Place a blank cell (space or tab); it is dropped if it causes a line\n to wrap"]
+    fn blank(&mut self, width: i32) {
+        if self.wrap != TextWrap::None && !self.fits(width) {
+            self.newline();
+        } else {
+            self.cell(width, None, true);
+        }
+    }
+    fn flush_word(&mut self) {
+        if self.word_len == 0 {
+            return;
+        }
+        let width = self.advance * (self.word_len as i32 - 1) + self.font.glyph_width as i32;
+        if self.x > 0 && !self.fits(width) {
+            self.newline();
+        }
+        for i in 0..self.word_len {
+            self.glyph(self.word[i]);
+        }
+        self.word_len = 0;
+    }
+    fn push(&mut self, ch: char) {
+        if self.done {
+            return;
+        }
+        if self.size.lines == 0 {
+            self.size.lines = 1;
+        }
+        let cr = core::mem::replace(&mut self.cr, ch == '\r');
+        match ch {
+            '\n' if cr => {}
+            '\r' | '\n' => {
+                self.flush_word();
+                self.newline();
+            }
+            '\t' => {
+                self.flush_word();
+                self.blank(self.tab - self.x.rem_euclid(self.tab));
+            }
+            ' ' => {
+                self.flush_word();
+                self.blank(self.advance);
+            }
+            ch if ch.is_control() => {}
+            ch if self.wrap == TextWrap::Word && self.width != 0 => {
+                if self.word_len == MAX_WORD {
+                    self.flush_word();
+                }
+                self.word[self.word_len] = self.font.glyph(ch);
+                self.word_len += 1;
+            }
+            ch => self.glyph(self.font.glyph(ch)),
+        }
+    }
+    fn push_str(&mut self, text: &str) {
+        text.chars().for_each(|ch| self.push(ch));
+    }
+    fn finish(mut self) -> TextSize {
+        self.flush_word();
+        let mut size = self.size;
+        if size.lines > 0 {
+            size.height = (size.lines as i32 * self.line_height - self.line_height
+                + self.font.glyph_height as i32) as u32;
+        }
+        size
+    }
+}
+
+impl<'f, F: FnMut(i32, i32, i32, Option<u32>)> Write for Layout<'f, F> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl BitmapFont<'_> {
+    #[doc = "Color of a pixel of a glyph"]
+    fn texel(&self, glyph: u32, x: u16, y: u16) -> Color {
+        match self.glyphs {
+            Glyphs::Builtin => match (BUILTIN[glyph as usize][y as usize] >> x) & 1 {
+                0 => Color::TRANSPARENT,
+                _ => Color::WHITE,
+            },
+            Glyphs::Sheet {
+                pixels,
+                stride,
+                format,
+                palette,
+                columns,
+            } => {
+                let columns = columns as u32;
+                let tx = (glyph % columns) as usize * self.glyph_width as usize + x as usize;
+                let ty = (glyph / columns) as usize * self.glyph_height as usize + y as usize;
+                let Some(row) = pixels.chunks(stride.max(1) as usize).nth(ty) else {
+                    return Color::TRANSPARENT;
+                };
+                let pixel = Pixel::from_raw(format, read_raw(format, row, tx));
+                match pixel {
+                    Some(Pixel::I4(i)) => Color::rgba32(255, 255, 255, i * 0x11),
+                    Some(Pixel::I8(i)) => Color::rgba32(255, 255, 255, i),
+                    p => p
+                        .and_then(|p| p.to_color(palette))
+                        .unwrap_or(Color::TRANSPARENT),
+                }
+            }
+        }
+    }
+}
+
+impl<'s> Surface<'s> {
+    #[doc = "Draw a text with a bitmap font, using the CPU\n\n The text is UTF-8, and can contain newlines (`\\n`, `\\r` or `\\r\\n`),\n which move to the start of the next line, and tabs, which move to the\n next tab stop. Other control characters are ignored. If the width of the\n text box is bounded, lines are wrapped as configured by the parameters.\n Drawing is clipped to the surface, and glyphs are blended over it.\n\n Returns the size of the text box that was filled.\n\n ```ignore\n let font = BitmapFont::builtin();\n let parms = CpuTextParms::new().width(304).wrap(TextWrap::Word);\n let size = fb.draw_str(8, 8, &font, \"Hello, world!\", &parms);\n fb.draw_fmt(8, 8 + size.height as i32, &font, format_args!(\"Frame {frame}\"), &parms);\n ```\n\n @param x         X coordinate of the top-left corner of the text box\n @param y         Y coordinate of the top-left corner of the text box\n @param font      The font\n @param text      The text\n @param parms     Text parameters (see [`CpuTextParms`])"]
+    pub fn draw_str(
+        &mut self,
+        x: i32,
+        y: i32,
+        font: &BitmapFont,
+        text: &str,
+        parms: &CpuTextParms,
+    ) -> TextSize {
+        self.draw_text_with(x, y, font, parms, |layout| layout.push_str(text))
+    }
+    #[doc = "Draw formatted text with a bitmap font, using the CPU\n\n This is the same as [`Surface::draw_str`], but the text is laid out while\n it is being formatted, without allocating memory.\n\n @param x         X coordinate of the top-left corner of the text box\n @param y         Y coordinate of the top-left corner of the text box\n @param font      The font\n @param args      The formatted text (see [`format_args`])\n @param parms     Text parameters (see [`CpuTextParms`])"]
+    pub fn draw_fmt(
+        &mut self,
+        x: i32,
+        y: i32,
+        font: &BitmapFont,
+        args: fmt::Arguments,
+        parms: &CpuTextParms,
+    ) -> TextSize {
+        self.draw_text_with(x, y, font, parms, |layout| {
+            let _ = layout.write_fmt(args);
+        })
+    }
+    fn draw_text_with<'f>(
+        &mut self,
+        x: i32,
+        y: i32,
+        font: &'f BitmapFont<'f>,
+        parms: &CpuTextParms,
+        text: impl FnOnce(&mut Layout<'f, &mut dyn FnMut(i32, i32, i32, Option<u32>)>),
+    ) -> TextSize {
+        let format = self.format();
+        let (sw, sh) = (self.width() as i32, self.height() as i32);
+        let (gw, gh) = font.glyph_size();
+        let color = parms.color;
+        let background = (parms.background.a != 0).then_some(parms.background);
+        let mut draw = |cx: i32, cy: i32, width: i32, glyph: Option<u32>| {
+            let (x, y) = (x + cx, y + cy);
+            if let Some(bg) = background {
+                self.fill_rect(x, y, width as u32, gh as u32, bg, parms.palette);
+            }
+            let Some(glyph) = glyph else {
+                return;
+            };
+            for gy in 0..gh {
+                let dy = y + gy as i32;
+                if dy < 0 || dy >= sh {
+                    continue;
+                }
+                let Some(row) = self.row_mut(dy as u16) else {
+                    return;
+                };
+                for gx in 0..gw {
+                    let dx = x + gx as i32;
+                    if dx < 0 || dx >= sw {
+                        continue;
+                    }
+                    let src = font.texel(glyph, gx, gy).multiply(color);
+                    if src.a == 0 {
+                        continue;
+                    }
+                    let dx = dx as usize;
+                    let c = if src.a == 255 {
+                        src
+                    } else {
+                        Pixel::from_raw(format, read_raw(format, row, dx))
+                            .and_then(|p| p.to_color(parms.palette))
+                            .unwrap_or(Color::BLACK)
+                            .over(src)
+                    };
+                    if let Some(p) = Pixel::from_color(format, c, parms.palette) {
+                        write_raw(format, row, dx, p.into_raw());
+                    }
+                }
+            }
+        };
+        let mut layout = Layout::new(font, parms, &mut draw as &mut dyn FnMut(_, _, _, _));
+        text(&mut layout);
+        layout.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc_::{string::String, vec::Vec};
+
+    use super::*;
+
+    // Lay out a text with the builtin font, returning the drawn glyphs as one
+    // string per line, and the size of the text
+    fn layout(text: &str, parms: &CpuTextParms) -> (Vec<String>, TextSize) {
+        let font = BitmapFont::builtin();
+        let mut cells = Vec::new();
+        let mut layout = Layout::new(&font, parms, |x, y, _, glyph: Option<u32>| {
+            if let Some(glyph) = glyph {
+                cells.push((x / 8, y / 8, char::from(b' ' + glyph as u8)));
+            }
+        });
+        layout.push_str(text);
+        let size = layout.finish();
+        let mut lines = Vec::<String>::new();
+        for (col, row, ch) in cells {
+            let (col, row) = (col as usize, row as usize);
+            if lines.len() <= row {
+                lines.resize(row + 1, String::new());
+            }
+            let line = &mut lines[row];
+            while line.len() < col {
+                line.push(' ');
+            }
+            line.push(ch);
+        }
+        (lines, size)
+    }
+
+    #[test]
+    fn wrap_none() {
+        let parms = CpuTextParms::new().width(40);
+        let (lines, size) = layout("hello world", &parms);
+        assert_eq!(lines, ["hello"]);
+        assert_eq!(
+            size,
+            TextSize {
+                width: 40,
+                height: 8,
+                lines: 1
+            }
+        );
+        // Unbounded
+        let (lines, size) = layout("hello world", &CpuTextParms::new());
+        assert_eq!(lines, ["hello world"]);
+        assert_eq!(size.width, 88);
+    }
+
+    #[test]
+    fn wrap_char() {
+        let parms = CpuTextParms::new().width(40).wrap(TextWrap::Char);
+        // The space at the end of the line is dropped
+        assert_eq!(layout("hello world", &parms).0, ["hello", "world"]);
+        let (lines, size) = layout("abcdefgh", &parms);
+        assert_eq!(lines, ["abcde", "fgh"]);
+        assert_eq!(
+            size,
+            TextSize {
+                width: 40,
+                height: 16,
+                lines: 2
+            }
+        );
+    }
+
+    #[test]
+    fn wrap_word() {
+        let parms = CpuTextParms::new().width(48).wrap(TextWrap::Word);
+        assert_eq!(layout("hi there world", &parms).0, ["hi", "there", "world"]);
+        // Words longer than a line are wrapped at character boundaries
+        let parms = CpuTextParms::new().width(80).wrap(TextWrap::Word);
+        let long = "a".repeat(12);
+        assert_eq!(
+            layout(&alloc_::format!("xy {long}"), &parms).0,
+            ["xy", "aaaaaaaaaa", "aa"]
+        );
+        // Words longer than MAX_WORD are split, but no glyph is lost
+        let long = "a".repeat(MAX_WORD + 8);
+        let (lines, size) = layout(&alloc_::format!("xy {long} b"), &parms);
+        assert_eq!(lines[0], "xy");
+        assert_eq!(lines[1..5], ["aaaaaaaaaa"; 4]);
+        assert_eq!(lines[5], "b");
+        assert_eq!(size.lines, 6);
+        // Without a width, words are never wrapped
+        let parms = CpuTextParms::new().wrap(TextWrap::Word);
+        let (lines, _) = layout(&long, &parms);
+        assert_eq!(lines, [long]);
+    }
+
+    #[test]
+    fn tabs() {
+        let parms = CpuTextParms::new();
+        assert_eq!(layout("a\tb", &parms).0, ["a   b"]);
+        assert_eq!(layout("abcd\te", &parms).0, ["abcd    e"]);
+        assert_eq!(layout("a\t\tb", &parms).0, ["a       b"]);
+        let parms = CpuTextParms::new().tab_size(2);
+        assert_eq!(layout("a\tb\tc", &parms).0, ["a b c"]);
+        // Trailing blanks do not count in the width
+        assert_eq!(layout("a\t", &parms).1.width, 8);
+        // Tab stops are relative to the start of each line
+        assert_eq!(layout("abc\n\tb", &parms).0, ["abc", "  b"]);
+    }
+
+    #[test]
+    fn newlines() {
+        let parms = CpuTextParms::new();
+        for text in ["a\nb", "a\rb", "a\r\nb"] {
+            let (lines, size) = layout(text, &parms);
+            assert_eq!(lines, ["a", "b"], "{text:?}");
+            assert_eq!(size.lines, 2, "{text:?}");
+        }
+        for text in ["a\n\nb", "a\r\rb", "a\n\rb", "a\r\n\r\nb"] {
+            let (lines, size) = layout(text, &parms);
+            assert_eq!(lines, ["a", "", "b"], "{text:?}");
+            assert_eq!(size.lines, 3, "{text:?}");
+        }
+        // Other control characters are ignored
+        assert_eq!(layout("a\x07b\x1bc", &parms).0, ["abc"]);
+        assert_eq!(layout("", &parms).1, TextSize::default());
+    }
+
+    #[test]
+    fn height_limit() {
+        let parms = CpuTextParms::new().height(20);
+        let (lines, size) = layout("a\nb\nc\nd", &parms);
+        assert_eq!(lines, ["a", "b"]);
+        assert_eq!(
+            size,
+            TextSize {
+                width: 8,
+                height: 16,
+                lines: 2
+            }
+        );
+        // Lines and line spacing
+        let parms = CpuTextParms::new().height(20).line_spacing(4);
+        assert_eq!(layout("a\nb\nc", &parms).1.height, 20);
+        // Not even one line fits
+        let parms = CpuTextParms::new().height(7);
+        assert_eq!(layout("a", &parms), (Vec::new(), TextSize::default()));
+        // Wrapped lines are cut off too
+        let parms = CpuTextParms::new()
+            .width(16)
+            .height(16)
+            .wrap(TextWrap::Char);
+        assert_eq!(layout("abcdef", &parms).0, ["ab", "cd"]);
+    }
+
+    #[test]
+    fn glyph_index() {
+        let font = BitmapFont::builtin();
+        assert_eq!(font.glyph_index(' '), Some(0));
+        assert_eq!(font.glyph_index('A'), Some(33));
+        assert_eq!(font.glyph_index('~'), Some(94));
+        assert_eq!(font.glyph_index('\x7f'), None);
+        assert_eq!(font.glyph_index('é'), None);
+        assert_eq!(font.glyph('é'), font.glyph_index('?'));
+
+        const RANGES: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('à', 'ü'), ('Ā', 'ſ')];
+        let font = BitmapFont::builtin().ranges(RANGES);
+        assert_eq!(font.glyph_index('0'), Some(0));
+        assert_eq!(font.glyph_index('9'), Some(9));
+        assert_eq!(font.glyph_index('A'), Some(10));
+        assert_eq!(font.glyph_index('Z'), Some(35));
+        assert_eq!(font.glyph_index('à'), Some(36));
+        assert_eq!(font.glyph_index('ü'), Some(64));
+        assert_eq!(font.glyph_index('Ā'), Some(65));
+        // Codepoints beyond the number of glyphs (95) are not covered
+        assert_eq!(font.glyph_index('ĝ'), Some(94));
+        assert_eq!(font.glyph_index('Ğ'), None);
+        for ch in ['a', '@', '[', 'ß', 'ý', '€'] {
+            assert_eq!(font.glyph_index(ch), None, "{ch}");
+        }
+
+        // Without ranges, glyphs are indexed by codepoint
+        let font = BitmapFont {
+            ranges: None,
+            fallback: None,
+            ..BitmapFont::builtin()
+        };
+        assert_eq!(font.glyph_index('\0'), Some(0));
+        assert_eq!(font.glyph_index('A'), Some(65));
+        assert_eq!(font.glyph_index('^'), Some(94));
+        assert_eq!(font.glyph_index('_'), None);
+        assert_eq!(font.glyph('_'), None);
+    }
+
+    // Surfaces are not dropped, as that would call into libdragon to free
+    // them: the buffers are leaked instead.
+    fn surface(width: u16, height: u16) -> ManuallyDrop<Surface<'static>> {
+        let buffer = alloc_::vec![0; width as usize * height as usize].into_boxed_slice();
+        ManuallyDrop::new(Surface::from_buffer(
+            buffer,
+            TexFormat::I8,
+            width,
+            height,
+            width,
+        ))
+    }
+
+    #[test]
+    fn draw_str() {
+        let font = BitmapFont::builtin();
+        let parms = CpuTextParms::new();
+        let mut s = surface(20, 10);
+        let size = s.draw_str(1, 2, &font, "Hi", &parms);
+        assert_eq!(size, font.measure("Hi", &parms));
+        assert_eq!(
+            size,
+            TextSize {
+                width: 16,
+                height: 8,
+                lines: 1
+            }
+        );
+        for y in 0..10 {
+            for x in 0..20 {
+                let (gx, gy) = (x as i32 - 1, y as i32 - 2);
+                let expected = match (gx / 8, gx % 8, gy) {
+                    (0..=1, _, 0..=7) if gx >= 0 => {
+                        let glyph = BUILTIN[(b"Hi"[gx as usize / 8] - b' ') as usize];
+                        (glyph[gy as usize] >> (gx % 8)) & 1 != 0
+                    }
+                    _ => false,
+                };
+                let pixel = s.pixel(x, y).unwrap();
+                assert_eq!(pixel, Pixel::I8(if expected { 255 } else { 0 }), "{x},{y}");
+            }
+        }
+
+        // Clipped at the top-left corner, with a background
+        let mut s = surface(4, 4);
+        let parms = CpuTextParms::new()
+            .color(Color::rgba32(100, 100, 100, 255))
+            .background(Color::rgba32(10, 10, 10, 255));
+        s.draw_str(-2, -3, &font, "#", &parms);
+        let rows: Vec<Vec<u32>> = (0..4)
+            .map(|y| (0..4).map(|x| s.pixel(x, y).unwrap().into_raw()).collect())
+            .collect();
+        // Rows 3-6 of '#', from column 2
+        let expected: Vec<Vec<u32>> = [0x36, 0x7F, 0x36, 0x36]
+            .iter()
+            .map(|&bits: &u8| {
+                (2..6)
+                    .map(|x| if (bits >> x) & 1 != 0 { 100 } else { 10 })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows, expected);
+    }
+}
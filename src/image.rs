@@ -0,0 +1,525 @@
+use core::{ffi::CStr, fmt};
+
+use alloc_::{borrow::Cow, vec::Vec};
+
+use crate::surface::{Surface, TexFormat};
+
+mod bmp;
+mod deflate;
+mod png;
+mod qoi;
+
+#[doc = "File format used to encode an [`Image`]"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    #[doc = "Portable Network Graphics (lossless, compressed)"]
+    Png,
+    #[doc = "Windows bitmap (uncompressed)"]
+    Bmp,
+    #[doc = "Quite OK Image format (lossless, fast to encode)"]
+    Qoi,
+}
+
+impl ImageFormat {
+    #[doc = "Guess the format from a file extension (eg: `\"png\"`), ignoring case"]
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        [Self::Png, Self::Bmp, Self::Qoi]
+            .into_iter()
+            .find(|f| f.extension().eq_ignore_ascii_case(ext))
+    }
+    #[doc = "The usual file extension for the format, without the dot"]
+    #[inline]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Bmp => "bmp",
+            Self::Qoi => "qoi",
+        }
+    }
+}
+
+#[doc = "Error returned when an [`Image`] cannot be created"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ImageError {
+    #[doc = "The pixel format cannot be encoded"]
+    UnsupportedFormat(TexFormat),
+    #[doc = "The pixel format is CI8, but no palette was provided"]
+    MissingPalette,
+    #[doc = "The pixel buffer is smaller than the image"]
+    BufferTooSmall {
+        #[doc = "Bytes required by the image"]
+        needed: usize,
+        #[doc = "Bytes available"]
+        available: usize,
+    },
+    #[doc = "The image has a zero size, or its stride is smaller than a row"]
+    EmptySize,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => {
+                write!(f, "cannot encode images with format {format:?}")
+            }
+            Self::MissingPalette => f.write_str("color-indexed image has no palette"),
+            Self::BufferTooSmall { needed, available } => write!(
+                f,
+                "image buffer is too small: needs {needed} bytes, {available} available"
+            ),
+            Self::EmptySize => f.write_str("image has a zero size"),
+        }
+    }
+}
+
+impl core::error::Error for ImageError {}
+
+#[doc = "Channels of the encoded pixels"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Layout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+    Indexed,
+}
+
+impl Layout {
+    #[inline]
+    const fn channels(self) -> usize {
+        match self {
+            Self::Gray | Self::Indexed => 1,
+            Self::GrayAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+#[doc = "A CPU-side image that can be encoded to PNG, BMP or QOI.\n\n An image is a view of a pixel buffer in one of the formats used by\n surfaces (RGBA16, RGBA32, I8, IA8, IA16, or CI8 with its palette). It can\n borrow the buffer of a [`Surface`] via [`Image::from_surface`], or own a\n copy of it via [`Image::capture`], which is the best choice for buffers\n that are still being drawn to, since encoding is slow.\n\n The encoders are written in pure Rust and do not depend on libdragon, so\n they can be used on any buffer. Images can be written to any\n [`embedded_io::Write`] sink: a file on the SD card via [`Image::save`],\n or the USB debug channel via [`crate::debug::UsbWriter`].\n\n ```ignore\n let shot = display.screenshot()?;\n shot.save(c\"sd:/shot.png\", ImageFormat::Png)?;\n ```"]
+#[derive(Debug, Clone)]
+pub struct Image<'a> {
+    format: TexFormat,
+    width: u32,
+    height: u32,
+    stride: usize,
+    pixels: Cow<'a, [u8]>,
+    palette: Option<Cow<'a, [u16]>>,
+    opaque: bool,
+}
+
+impl<'a> Image<'a> {
+    #[doc = "Create an image from a pixel buffer\n\n @param format    Pixel format: RGBA16, RGBA32, I8, IA8, IA16 or CI8\n @param width     Width of the image in pixels\n @param height    Height of the image in pixels\n @param stride    Length of a row in bytes\n @param pixels    Pixel data (16-bit formats are big-endian)\n @param palette   Palette of a CI8 image, as RGBA 5551 colors"]
+    pub fn new(
+        format: TexFormat,
+        width: u32,
+        height: u32,
+        stride: usize,
+        pixels: &'a [u8],
+        palette: Option<&'a [u16]>,
+    ) -> Result<Self, ImageError> {
+        Self::from_cow(
+            format,
+            width,
+            height,
+            stride,
+            Cow::Borrowed(pixels),
+            palette.map(Cow::Borrowed),
+        )
+    }
+    fn from_cow(
+        format: TexFormat,
+        width: u32,
+        height: u32,
+        stride: usize,
+        pixels: Cow<'a, [u8]>,
+        palette: Option<Cow<'a, [u16]>>,
+    ) -> Result<Self, ImageError> {
+        use TexFormat::*;
+        if !matches!(format, RGBA16 | RGBA32 | I8 | IA8 | IA16 | CI8) {
+            return Err(ImageError::UnsupportedFormat(format));
+        }
+        if format == CI8 && palette.as_ref().is_none_or(|p| p.is_empty()) {
+            return Err(ImageError::MissingPalette);
+        }
+        let row = format.pixels_to_bytes(width) as usize;
+        if width == 0 || height == 0 || stride < row {
+            return Err(ImageError::EmptySize);
+        }
+        let needed = stride * (height as usize - 1) + row;
+        if pixels.len() < needed {
+            return Err(ImageError::BufferTooSmall {
+                needed,
+                available: pixels.len(),
+            });
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            stride,
+            pixels,
+            palette,
+            opaque: false,
+        })
+    }
+    #[doc = "Create an image that borrows the pixels of a surface\n\n The RDP must have finished drawing to the surface.\n\n @param surface   The surface\n @param palette   Palette of the surface, if it is CI8"]
+    pub fn from_surface(
+        surface: &'a Surface,
+        palette: Option<&'a [u16]>,
+    ) -> Result<Self, ImageError> {
+        Self::new(
+            surface.format(),
+            surface.width() as u32,
+            surface.height() as u32,
+            surface.stride() as usize,
+            surface_pixels(surface),
+            palette,
+        )
+    }
+    #[doc = "Set whether the alpha channel should be discarded.\n\n This is needed for framebuffers, where the alpha channel holds coverage\n information rather than transparency."]
+    #[inline]
+    pub fn opaque(mut self, opaque: bool) -> Self {
+        self.opaque = opaque;
+        self
+    }
+    #[doc = "Copy the pixels and the palette, so that the image does not borrow anything"]
+    pub fn into_owned(self) -> Image<'static> {
+        Image {
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            pixels: Cow::Owned(self.pixels.into_owned()),
+            palette: self.palette.map(|p| Cow::Owned(p.into_owned())),
+            opaque: self.opaque,
+        }
+    }
+    #[inline]
+    pub fn format(&self) -> TexFormat {
+        self.format
+    }
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[doc = "Encode the image in the given format\n\n @param format    File format\n @param out       Where to write the encoded image"]
+    pub fn write<W: embedded_io::Write>(
+        &self,
+        format: ImageFormat,
+        out: &mut W,
+    ) -> Result<(), W::Error> {
+        match format {
+            ImageFormat::Png => png::encode(self, out)?,
+            ImageFormat::Bmp => bmp::encode(self, out)?,
+            ImageFormat::Qoi => qoi::encode(self, out)?,
+        }
+        out.flush()
+    }
+    #[doc = "Encode the image as PNG.\n\n Grayscale and color-indexed images are stored as such, while other\n formats are stored as RGB (if opaque) or RGBA."]
+    #[inline]
+    pub fn write_png<W: embedded_io::Write>(&self, out: &mut W) -> Result<(), W::Error> {
+        self.write(ImageFormat::Png, out)
+    }
+    #[doc = "Encode the image as BMP.\n\n I8 and CI8 images are stored with 8 bits per pixel and a palette,\n opaque images with 24 bits per pixel, and the others with 32 bits per\n pixel, including alpha."]
+    #[inline]
+    pub fn write_bmp<W: embedded_io::Write>(&self, out: &mut W) -> Result<(), W::Error> {
+        self.write(ImageFormat::Bmp, out)
+    }
+    #[doc = "Encode the image as QOI, with 3 channels if opaque or 4 otherwise"]
+    #[inline]
+    pub fn write_qoi<W: embedded_io::Write>(&self, out: &mut W) -> Result<(), W::Error> {
+        self.write(ImageFormat::Qoi, out)
+    }
+    #[doc = "Encode the image to a file\n\n The filesystem must be writable, such as the SD card (see\n [`crate::debug::init_sdfs`]).\n\n @param path      Path of the file to create\n @param format    File format"]
+    pub fn save(&self, path: &CStr, format: ImageFormat) -> crate::io::Result<()> {
+        let mut file = crate::fs::File::create(path)?;
+        self.write(format, &mut file)
+    }
+
+    #[doc = "Channels used to store the pixels (for formats that support all of them)"]
+    #[inline]
+    fn layout(&self) -> Layout {
+        use TexFormat::*;
+        match (self.format, self.opaque) {
+            (I8, _) | (IA8 | IA16, true) => Layout::Gray,
+            (IA8 | IA16, false) => Layout::GrayAlpha,
+            (CI8, _) => Layout::Indexed,
+            (_, true) => Layout::Rgb,
+            (_, false) => Layout::Rgba,
+        }
+    }
+    #[doc = "Whether the pixels have a meaningful alpha channel"]
+    #[inline]
+    fn has_alpha(&self) -> bool {
+        match self.layout() {
+            Layout::Gray | Layout::Rgb => false,
+            Layout::GrayAlpha | Layout::Rgba => true,
+            Layout::Indexed => !self.opaque && self.palette_rgba().iter().any(|c| c[3] != 255),
+        }
+    }
+    #[inline]
+    fn row(&self, y: u32) -> &[u8] {
+        let start = y as usize * self.stride;
+        &self.pixels[start..start + self.format.pixels_to_bytes(self.width) as usize]
+    }
+    #[doc = "Palette of a CI8 image, converted to RGBA 8888"]
+    fn palette_rgba(&self) -> Vec<[u8; 4]> {
+        self.palette
+            .iter()
+            .flat_map(|p| p.iter().take(256))
+            .map(|&c| rgba5551(c))
+            .collect()
+    }
+    #[doc = "Decode a row of pixels to RGBA 8888"]
+    fn decode_row(&self, y: u32, palette: &[[u8; 4]], out: &mut Vec<[u8; 4]>) {
+        let row = self.row(y);
+        out.clear();
+        match self.format {
+            TexFormat::RGBA16 => out.extend(
+                row.chunks_exact(2)
+                    .map(|p| rgba5551(u16::from_be_bytes([p[0], p[1]]))),
+            ),
+            TexFormat::RGBA32 => out.extend(row.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]])),
+            TexFormat::I8 => out.extend(row.iter().map(|&i| [i, i, i, 255])),
+            TexFormat::IA8 => out.extend(row.iter().map(|&p| {
+                let (i, a) = ((p >> 4) * 17, (p & 0xF) * 17);
+                [i, i, i, a]
+            })),
+            TexFormat::IA16 => out.extend(row.chunks_exact(2).map(|p| [p[0], p[0], p[0], p[1]])),
+            TexFormat::CI8 => out.extend(
+                row.iter()
+                    .map(|&i| palette.get(i as usize).copied().unwrap_or([0, 0, 0, 255])),
+            ),
+            _ => unreachable!(),
+        }
+        if self.opaque {
+            out.iter_mut().for_each(|p| p[3] = 255);
+        }
+    }
+}
+
+impl Image<'static> {
+    #[doc = "Copy the contents of a surface into a new image\n\n This should be preferred over [`Image::from_surface`] when the surface\n is going to be drawn to again soon (eg: a framebuffer), since encoding\n takes much longer than copying. The RDP must have finished drawing to\n the surface.\n\n @param surface   The surface\n @param palette   Palette of the surface, if it is CI8"]
+    pub fn capture(surface: &Surface, palette: Option<&[u16]>) -> Result<Self, ImageError> {
+        Image::from_surface(surface, palette).map(Image::into_owned)
+    }
+}
+
+#[doc = "The pixels of a surface, up to the end of its last row"]
+fn surface_pixels<'a>(surface: &'a Surface) -> &'a [u8] {
+    let raw = surface.as_raw();
+    if raw.buffer.is_null() || raw.height == 0 {
+        return &[];
+    }
+    let len = raw.stride as usize * (raw.height as usize - 1)
+        + surface.format().pixels_to_bytes(raw.width as u32) as usize;
+    unsafe { core::slice::from_raw_parts(raw.buffer as *const u8, len) }
+}
+
+#[doc = "Convert an RGBA 5551 color to RGBA 8888"]
+#[inline]
+fn rgba5551(c: u16) -> [u8; 4] {
+    let expand = |v: u16| {
+        let v = (v & 0x1F) as u8;
+        (v << 3) | (v >> 2)
+    };
+    [
+        expand(c >> 11),
+        expand(c >> 6),
+        expand(c >> 1),
+        if c & 1 != 0 { 255 } else { 0 },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(image: &Image, format: ImageFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        image.write(format, &mut out).unwrap();
+        out
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        s.chunks(2)
+            .map(|c| u8::from_str_radix(core::str::from_utf8(c).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    // Type and length of each chunk of a PNG file
+    fn png_chunks(data: &[u8]) -> Vec<(&[u8], usize)> {
+        let mut chunks = Vec::new();
+        let mut pos = 8;
+        while pos < data.len() {
+            let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            chunks.push((&data[pos + 4..pos + 8], len));
+            pos += len + 12;
+        }
+        assert_eq!(pos, data.len());
+        chunks
+    }
+
+    #[test]
+    fn errors() {
+        let px = [0; 16];
+        let new = |format, width, stride, palette| {
+            Image::new(format, width, 2, stride, &px, palette).map(|_| ())
+        };
+        assert_eq!(
+            new(TexFormat::I4, 2, 2, None),
+            Err(ImageError::UnsupportedFormat(TexFormat::I4))
+        );
+        assert_eq!(
+            new(TexFormat::CI8, 2, 2, Some(&[])),
+            Err(ImageError::MissingPalette)
+        );
+        assert_eq!(new(TexFormat::I8, 0, 2, None), Err(ImageError::EmptySize));
+        assert_eq!(
+            new(TexFormat::RGBA16, 2, 3, None),
+            Err(ImageError::EmptySize)
+        );
+        assert_eq!(new(TexFormat::RGBA32, 2, 8, None), Ok(()));
+        assert_eq!(
+            new(TexFormat::RGBA32, 2, 9, None),
+            Err(ImageError::BufferTooSmall {
+                needed: 17,
+                available: 16
+            })
+        );
+    }
+
+    #[test]
+    fn bmp_rgb() {
+        // Red, green / blue, white
+        let px = [0xF8, 0x01, 0x07, 0xC1, 0x00, 0x3F, 0xFF, 0xFF];
+        let image = Image::new(TexFormat::RGBA16, 2, 2, 4, &px, None).unwrap();
+        let expected = hex("424d 46000000 00000000 36000000
+             28000000 02000000 02000000 0100 1800 00000000 10000000
+             130b0000 130b0000 00000000 00000000
+             ff0000 ffffff 0000
+             0000ff 00ff00 0000");
+        assert_eq!(encode(&image.opaque(true), ImageFormat::Bmp), expected);
+    }
+
+    #[test]
+    fn bmp_rgba() {
+        let px = [0x12, 0x34, 0x56, 0x78];
+        let image = Image::new(TexFormat::RGBA32, 1, 1, 4, &px, None).unwrap();
+        let out = encode(&image, ImageFormat::Bmp);
+        assert_eq!(out.len(), 14 + 108 + 4);
+        assert_eq!(out[2..6], (14u32 + 108 + 4).to_le_bytes());
+        assert_eq!(out[10..14], (14u32 + 108).to_le_bytes());
+        // 32 bits per pixel, with bit fields
+        assert_eq!(out[28..34], [32, 0, 3, 0, 0, 0]);
+        assert_eq!(
+            out[54..74],
+            hex("0000ff00 00ff0000 ff000000 000000ff 42475273")[..]
+        );
+        assert_eq!(out[122..], [0x56, 0x34, 0x12, 0x78]);
+    }
+
+    #[test]
+    fn bmp_indexed() {
+        let palette = [0xF801, 0x07C0];
+        let px = [1, 0, 0];
+        let image = Image::new(TexFormat::CI8, 3, 1, 3, &px, Some(&palette)).unwrap();
+        let out = encode(&image, ImageFormat::Bmp);
+        assert_eq!(out.len(), 14 + 40 + 1024 + 4);
+        assert_eq!(out[28..30], [8, 0]);
+        assert_eq!(out[54..62], [0, 0, 255, 0, 0, 255, 0, 0]);
+        assert!(out[62..1078].iter().all(|&b| b == 0));
+        assert_eq!(out[1078..], [1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn qoi() {
+        // A run, a small difference, a change of alpha and an index hit
+        let px = [
+            0, 0, 0, 255, //
+            1, 1, 1, 255, //
+            10, 20, 30, 128, //
+            1, 1, 1, 255,
+        ];
+        let image = Image::new(TexFormat::RGBA32, 4, 1, 16, &px, None).unwrap();
+        let expected = hex("71 6f 69 66 00000004 00000001 04 00
+             c0 7f ff0a141e80 04
+             00000000 00000001");
+        assert_eq!(encode(&image, ImageFormat::Qoi), expected);
+    }
+
+    #[test]
+    fn qoi_opaque() {
+        // 64 identical pixels (the 63 repeated ones split in two runs) and
+        // luma differences
+        let mut px = [0x12; 65];
+        px[64] = 0xF0;
+        let image = Image::new(TexFormat::IA8, 65, 1, 65, &px, None).unwrap();
+        let expected = hex("71 6f 69 66 00000041 00000001 03 00
+             b1 88 fd c0 8e 88
+             00000000 00000001");
+        assert_eq!(encode(&image.opaque(true), ImageFormat::Qoi), expected);
+    }
+
+    #[test]
+    fn png_gray() {
+        let px = [0, 255, 128, 64];
+        let image = Image::new(TexFormat::I8, 2, 2, 2, &px, None).unwrap();
+        let expected = hex("89504e470d0a1a0a
+             0000000d 49484452 00000002 00000002 0800000000 57dd52f8
+             0000000e 49444154 78016360f8cfd0e00000054201c0 2377d565
+             00000000 49454e44 ae426082");
+        assert_eq!(encode(&image, ImageFormat::Png), expected);
+    }
+
+    #[test]
+    fn png_matches() {
+        // Rows are filtered with the Sub and Up filters, and compressed
+        // with back-references
+        let px = [7; 64];
+        let image = Image::new(TexFormat::I8, 32, 2, 32, &px, None).unwrap();
+        let expected = hex("89504e470d0a1a0a
+             0000000d 49484452 00000020 00000002 0800000000 1c758115
+             00000011 49444154 7801636467c00f9880182f0000028d000b dd2223be
+             00000000 49454e44 ae426082");
+        assert_eq!(encode(&image, ImageFormat::Png), expected);
+    }
+
+    #[test]
+    fn png_layouts() {
+        let color_type = |image: &Image| encode(image, ImageFormat::Png)[25];
+        let px = [0x80; 8];
+        let image = |format| Image::new(format, 1, 1, 4, &px, None).unwrap();
+        assert_eq!(color_type(&image(TexFormat::I8)), 0);
+        assert_eq!(color_type(&image(TexFormat::IA8)), 4);
+        assert_eq!(color_type(&image(TexFormat::IA16).opaque(true)), 0);
+        assert_eq!(color_type(&image(TexFormat::RGBA16)), 6);
+        assert_eq!(color_type(&image(TexFormat::RGBA32).opaque(true)), 2);
+
+        // Transparency of indexed images is stored only if used
+        let palette = [0xFFFF, 0xF800];
+        let image = Image::new(TexFormat::CI8, 2, 1, 2, &px, Some(&palette)).unwrap();
+        let out = encode(&image, ImageFormat::Png);
+        assert_eq!(out[25], 3);
+        let chunks = png_chunks(&out);
+        let kinds: Vec<&[u8]> = chunks.iter().map(|c| c.0).collect();
+        assert_eq!(kinds, [&b"IHDR"[..], b"PLTE", b"tRNS", b"IDAT", b"IEND"]);
+        assert_eq!((chunks[1].1, chunks[2].1), (768, 2));
+        let out = encode(&image.opaque(true), ImageFormat::Png);
+        assert_eq!(png_chunks(&out).len(), 4);
+    }
+
+    #[test]
+    fn rgba5551_expand() {
+        assert_eq!(rgba5551(0xFFFF), [255; 4]);
+        assert_eq!(rgba5551(0x0000), [0; 4]);
+        assert_eq!(rgba5551(0x8420), [132, 132, 132, 0]);
+    }
+}
@@ -0,0 +1,86 @@
+use alloc_::vec::Vec;
+
+use super::{Image, Layout};
+
+const FILE_HEADER_SIZE: u32 = 14;
+#[doc = "Size of BITMAPINFOHEADER"]
+const INFO_HEADER_SIZE: u32 = 40;
+#[doc = "Size of BITMAPV4HEADER, needed to store an alpha mask"]
+const V4_HEADER_SIZE: u32 = 108;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+#[doc = "`LCS_sRGB` color space tag"]
+const LCS_SRGB: u32 = 0x73524742;
+
+pub(super) fn encode<W: embedded_io::Write>(image: &Image, out: &mut W) -> Result<(), W::Error> {
+    let layout = image.layout();
+    let (bpp, header_size, palette_size) = match layout {
+        Layout::Gray | Layout::Indexed => (8, INFO_HEADER_SIZE, 256 * 4),
+        Layout::Rgb => (24, INFO_HEADER_SIZE, 0),
+        Layout::GrayAlpha | Layout::Rgba => (32, V4_HEADER_SIZE, 0),
+    };
+    let row_size = (image.width * bpp / 8).next_multiple_of(4);
+    let offset = FILE_HEADER_SIZE + header_size + palette_size;
+
+    let mut header = Vec::with_capacity(offset as usize);
+    header.extend_from_slice(b"BM");
+    header.extend_from_slice(&(offset + row_size * image.height).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&offset.to_le_bytes());
+
+    let compression = if bpp == 32 { BI_BITFIELDS } else { BI_RGB };
+    for field in [
+        header_size,
+        image.width,
+        image.height, // positive height: rows are stored bottom-up
+        1 | (bpp << 16),
+        compression,
+        row_size * image.height,
+        2835, // 72 DPI
+        2835,
+        0,
+        0,
+    ] {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    if header_size == V4_HEADER_SIZE {
+        for mask in [0x00FF0000u32, 0x0000FF00, 0x000000FF, 0xFF000000, LCS_SRGB] {
+            header.extend_from_slice(&mask.to_le_bytes());
+        }
+        // Endpoints and gamma, unused with sRGB
+        header.resize((FILE_HEADER_SIZE + V4_HEADER_SIZE) as usize, 0);
+    }
+
+    let palette = image.palette_rgba();
+    match layout {
+        Layout::Gray => (0..=255u8).for_each(|i| header.extend_from_slice(&[i, i, i, 0])),
+        Layout::Indexed => (0..256).for_each(|i| {
+            let [r, g, b, _] = palette.get(i).copied().unwrap_or_default();
+            header.extend_from_slice(&[b, g, r, 0]);
+        }),
+        _ => {}
+    }
+    out.write_all(&header)?;
+
+    let mut pixels = Vec::with_capacity(image.width as usize);
+    let mut row = Vec::with_capacity(row_size as usize);
+    for y in (0..image.height).rev() {
+        row.clear();
+        match layout {
+            Layout::Indexed => row.extend_from_slice(image.row(y)),
+            _ => {
+                image.decode_row(y, &palette, &mut pixels);
+                for &[r, g, b, a] in &pixels {
+                    match bpp {
+                        8 => row.push(r),
+                        24 => row.extend_from_slice(&[b, g, r]),
+                        _ => row.extend_from_slice(&[b, g, r, a]),
+                    }
+                }
+            }
+        }
+        row.resize(row_size as usize, 0);
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
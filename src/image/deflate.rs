@@ -0,0 +1,192 @@
+use alloc_::{boxed::Box, vec::Vec};
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 13;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+#[doc = "A streaming zlib compressor.\n\n The data is compressed as a single deflate block with the fixed Huffman\n codes, finding matches with a single-entry hash table. This trades some\n compression ratio for speed and a small memory footprint (about 100 KiB),\n which is a good fit for screenshots. The compressed stream is accumulated\n in [`Zlib::out`], which the caller is expected to drain."]
+pub(super) struct Zlib {
+    window: Vec<u8>,
+    base: usize,
+    pos: usize,
+    head: Box<[u32]>,
+    bits: u32,
+    nbits: u32,
+    adler: (u32, u32),
+    pub(super) out: Vec<u8>,
+}
+
+impl Zlib {
+    pub(super) fn new() -> Self {
+        let mut zlib = Self {
+            window: Vec::with_capacity(3 * WINDOW_SIZE),
+            base: 0,
+            pos: 0,
+            head: alloc_::vec![0; 1 << HASH_BITS].into_boxed_slice(),
+            bits: 0,
+            nbits: 0,
+            adler: (1, 0),
+            out: Vec::new(),
+        };
+        // zlib header: deflate with 32 KiB window, fastest compression
+        zlib.out.extend_from_slice(&[0x78, 0x01]);
+        // Single final block, with fixed Huffman codes
+        zlib.put_bits(0b011, 3);
+        zlib
+    }
+
+    pub(super) fn write(&mut self, data: &[u8]) {
+        let (mut a, mut b) = self.adler;
+        for chunk in data.chunks(5552) {
+            for &byte in chunk {
+                a += byte as u32;
+                b += a;
+            }
+            a %= 65521;
+            b %= 65521;
+        }
+        self.adler = (a, b);
+
+        self.window.extend_from_slice(data);
+        self.compress(MAX_MATCH);
+    }
+
+    pub(super) fn finish(mut self) -> Vec<u8> {
+        self.compress(0);
+        self.put_literal(256);
+        if self.nbits > 0 {
+            self.out.push(self.bits as u8);
+        }
+        let (a, b) = self.adler;
+        self.out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        self.out
+    }
+
+    #[doc = "Compress the pending input, keeping at least `lookahead` bytes for later\n (so that matches are not cut short by the end of the input)"]
+    fn compress(&mut self, lookahead: usize) {
+        while self.pos + lookahead < self.window.len() {
+            let len = self.find_match();
+            if len > 0 {
+                self.pos += len;
+            } else {
+                self.put_literal(self.window[self.pos] as u16);
+                self.pos += 1;
+            }
+        }
+        if self.pos > 2 * WINDOW_SIZE {
+            let slide = self.pos - WINDOW_SIZE;
+            self.window.drain(..slide);
+            self.base += slide;
+            self.pos -= slide;
+        }
+    }
+
+    #[inline]
+    fn hash(&self, idx: usize) -> usize {
+        let w = &self.window[idx..idx + MIN_MATCH];
+        let v = (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32;
+        (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    #[inline]
+    fn insert(&mut self, idx: usize) -> Option<usize> {
+        if idx + MIN_MATCH > self.window.len() {
+            return None;
+        }
+        let h = self.hash(idx);
+        let prev = self.head[h] as usize;
+        self.head[h] = (self.base + idx + 1) as u32;
+        // Positions are stored as absolute offsets + 1, so that 0 means empty
+        prev.checked_sub(1 + self.base)
+    }
+
+    #[doc = "Look for a match at the current position, and emit it if found.\n Returns the length of the match, or 0 if none was found."]
+    fn find_match(&mut self) -> usize {
+        let Some(candidate) = self.insert(self.pos) else {
+            return 0;
+        };
+        let dist = self.pos - candidate;
+        if dist > WINDOW_SIZE {
+            return 0;
+        }
+        let max = (self.window.len() - self.pos).min(MAX_MATCH);
+        let len = self.window[candidate..]
+            .iter()
+            .zip(&self.window[self.pos..self.pos + max])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len < MIN_MATCH {
+            return 0;
+        }
+
+        self.put_match(len, dist);
+        for idx in self.pos + 1..self.pos + len {
+            self.insert(idx);
+        }
+        len
+    }
+
+    #[inline]
+    fn put_bits(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.nbits;
+        self.nbits += count;
+        while self.nbits >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    #[doc = "Emit a Huffman code (codes are stored starting from the most significant bit)"]
+    #[inline]
+    fn put_code(&mut self, code: u32, len: u32) {
+        self.put_bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    #[inline]
+    fn put_literal(&mut self, lit: u16) {
+        let lit = lit as u32;
+        match lit {
+            0..=143 => self.put_code(0x30 + lit, 8),
+            144..=255 => self.put_code(0x190 + lit - 144, 9),
+            256..=279 => self.put_code(lit - 256, 7),
+            _ => self.put_code(0xC0 + lit - 280, 8),
+        }
+    }
+
+    fn put_match(&mut self, len: usize, dist: usize) {
+        let code = LENGTH_BASE
+            .iter()
+            .rposition(|&b| b as usize <= len)
+            .unwrap();
+        self.put_literal(257 + code as u16);
+        self.put_bits(
+            (len - LENGTH_BASE[code] as usize) as u32,
+            LENGTH_EXTRA[code] as u32,
+        );
+
+        let code = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+        self.put_code(code as u32, 5);
+        self.put_bits(
+            (dist - DIST_BASE[code] as usize) as u32,
+            DIST_EXTRA[code] as u32,
+        );
+    }
+}
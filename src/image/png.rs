@@ -0,0 +1,147 @@
+use alloc_::vec::Vec;
+
+use super::{Image, Layout, deflate::Zlib};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+#[doc = "Size of the compressed data accumulated before emitting an IDAT chunk"]
+const IDAT_SIZE: usize = 16 * 1024;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |c, &b| {
+        CRC_TABLE[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8)
+    })
+}
+
+fn write_chunk<W: embedded_io::Write>(
+    out: &mut W,
+    kind: &[u8; 4],
+    data: &[u8],
+) -> Result<(), W::Error> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(crc32(0, kind), data).to_be_bytes())
+}
+
+#[doc = "Apply the PNG filter that is most likely to compress well (the one with\n the lowest sum of absolute values, as suggested by the PNG specification)"]
+fn filter_row(row: &[u8], prev: &[u8], bpp: usize, out: &mut [Vec<u8>; 5]) -> usize {
+    let left = |x: usize, r: &[u8]| if x >= bpp { r[x - bpp] } else { 0 };
+    for buf in out.iter_mut() {
+        buf.clear();
+    }
+    for (x, &cur) in row.iter().enumerate() {
+        let (a, b, c) = (left(x, row), prev[x], left(x, prev));
+        let p = a as i16 + b as i16 - c as i16;
+        let (pa, pb, pc) = (
+            (p - a as i16).abs(),
+            (p - b as i16).abs(),
+            (p - c as i16).abs(),
+        );
+        let paeth = if pa <= pb && pa <= pc {
+            a
+        } else if pb <= pc {
+            b
+        } else {
+            c
+        };
+        out[0].push(cur);
+        out[1].push(cur.wrapping_sub(a));
+        out[2].push(cur.wrapping_sub(b));
+        out[3].push(cur.wrapping_sub(((a as u16 + b as u16) / 2) as u8));
+        out[4].push(cur.wrapping_sub(paeth));
+    }
+    let cost =
+        |buf: &Vec<u8>| -> u32 { buf.iter().map(|&v| (v as i8).unsigned_abs() as u32).sum() };
+    (0..5).min_by_key(|&i| cost(&out[i])).unwrap()
+}
+
+pub(super) fn encode<W: embedded_io::Write>(image: &Image, out: &mut W) -> Result<(), W::Error> {
+    let layout = image.layout();
+    let channels = layout.channels();
+
+    out.write_all(&SIGNATURE)?;
+    let mut ihdr = [0; 13];
+    ihdr[0..4].copy_from_slice(&image.width.to_be_bytes());
+    ihdr[4..8].copy_from_slice(&image.height.to_be_bytes());
+    ihdr[8] = 8;
+    ihdr[9] = match layout {
+        Layout::Gray => 0,
+        Layout::Rgb => 2,
+        Layout::Indexed => 3,
+        Layout::GrayAlpha => 4,
+        Layout::Rgba => 6,
+    };
+    write_chunk(out, b"IHDR", &ihdr)?;
+
+    let palette = image.palette_rgba();
+    if layout == Layout::Indexed {
+        // Pad the palette, so that any index is valid
+        let mut plte: Vec<u8> = palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+        plte.resize(256 * 3, 0);
+        write_chunk(out, b"PLTE", &plte)?;
+        if image.has_alpha() {
+            let trns: Vec<u8> = palette.iter().map(|c| c[3]).collect();
+            write_chunk(out, b"tRNS", &trns)?;
+        }
+    }
+
+    let width = image.width as usize;
+    let mut zlib = Zlib::new();
+    let mut pixels = Vec::with_capacity(width);
+    let mut row = Vec::with_capacity(width * channels);
+    let mut prev = alloc_::vec![0; width * channels];
+    let mut filtered: [Vec<u8>; 5] = Default::default();
+    for y in 0..image.height {
+        row.clear();
+        if layout == Layout::Indexed {
+            row.extend_from_slice(image.row(y));
+        } else {
+            image.decode_row(y, &palette, &mut pixels);
+            for &[r, g, b, a] in &pixels {
+                match layout {
+                    Layout::Gray => row.push(r),
+                    Layout::GrayAlpha => row.extend_from_slice(&[r, a]),
+                    Layout::Rgb => row.extend_from_slice(&[r, g, b]),
+                    _ => row.extend_from_slice(&[r, g, b, a]),
+                }
+            }
+        }
+
+        // Filters are not effective on color-indexed images
+        let filter = if layout == Layout::Indexed {
+            0
+        } else {
+            filter_row(&row, &prev, channels, &mut filtered)
+        };
+        zlib.write(&[filter as u8]);
+        zlib.write(if filter == 0 { &row } else { &filtered[filter] });
+        core::mem::swap(&mut row, &mut prev);
+
+        if zlib.out.len() >= IDAT_SIZE {
+            write_chunk(out, b"IDAT", &zlib.out)?;
+            zlib.out.clear();
+        }
+    }
+    write_chunk(out, b"IDAT", &zlib.finish())?;
+    write_chunk(out, b"IEND", &[])
+}
@@ -0,0 +1,92 @@
+use alloc_::vec::Vec;
+
+use super::Image;
+
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_OP_RUN: u8 = 0xC0;
+const QOI_OP_RGB: u8 = 0xFE;
+const QOI_OP_RGBA: u8 = 0xFF;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+#[inline]
+fn hash([r, g, b, a]: [u8; 4]) -> usize {
+    (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64
+}
+
+pub(super) fn encode<W: embedded_io::Write>(image: &Image, out: &mut W) -> Result<(), W::Error> {
+    let channels = if image.has_alpha() { 4 } else { 3 };
+    let mut header = [0; 14];
+    header[0..4].copy_from_slice(b"qoif");
+    header[4..8].copy_from_slice(&image.width.to_be_bytes());
+    header[8..12].copy_from_slice(&image.height.to_be_bytes());
+    header[12] = channels;
+    header[13] = 0; // sRGB with linear alpha
+    out.write_all(&header)?;
+
+    let palette = image.palette_rgba();
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0, 0, 0, 255];
+    let mut run = 0u8;
+    let mut pixels = Vec::with_capacity(image.width as usize);
+    let mut buf = Vec::with_capacity(image.width as usize * 5 + 1);
+    for y in 0..image.height {
+        image.decode_row(y, &palette, &mut pixels);
+        for &px in &pixels {
+            if px == prev {
+                run += 1;
+                if run == 62 {
+                    buf.push(QOI_OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue;
+            }
+            if run > 0 {
+                buf.push(QOI_OP_RUN | (run - 1));
+                run = 0;
+            }
+
+            let h = hash(px);
+            if index[h] == px {
+                buf.push(QOI_OP_INDEX | h as u8);
+            } else {
+                index[h] = px;
+                if px[3] != prev[3] {
+                    buf.extend_from_slice(&[QOI_OP_RGBA, px[0], px[1], px[2], px[3]]);
+                } else {
+                    let dr = px[0].wrapping_sub(prev[0]) as i8;
+                    let dg = px[1].wrapping_sub(prev[1]) as i8;
+                    let db = px[2].wrapping_sub(prev[2]) as i8;
+                    let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+                    if (-2..2).contains(&dr) && (-2..2).contains(&dg) && (-2..2).contains(&db) {
+                        buf.push(
+                            QOI_OP_DIFF
+                                | ((dr + 2) as u8) << 4
+                                | ((dg + 2) as u8) << 2
+                                | (db + 2) as u8,
+                        );
+                    } else if (-32..32).contains(&dg)
+                        && (-8..8).contains(&dr_dg)
+                        && (-8..8).contains(&db_dg)
+                    {
+                        buf.extend_from_slice(&[
+                            QOI_OP_LUMA | (dg + 32) as u8,
+                            ((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8,
+                        ]);
+                    } else {
+                        buf.extend_from_slice(&[QOI_OP_RGB, px[0], px[1], px[2]]);
+                    }
+                }
+            }
+            prev = px;
+        }
+        out.write_all(&buf)?;
+        buf.clear();
+    }
+    if run > 0 {
+        buf.push(QOI_OP_RUN | (run - 1));
+    }
+    buf.extend_from_slice(&END_MARKER);
+    out.write_all(&buf)
+}
@@ -1,8 +1,8 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(internal_features)]
 #![feature(core_intrinsics)]
 #![feature(temporary_niche_types)]
-#![feature(asm_experimental_arch)]
+#![cfg_attr(target_arch = "mips", feature(asm_experimental_arch))]
 #![feature(allocator_api)]
 
 extern crate alloc as alloc_;
@@ -25,6 +25,7 @@ pub mod display;
 pub mod fmath;
 pub mod fs;
 pub mod graphics;
+pub mod image;
 pub mod io;
 pub mod joybus;
 pub mod joypad;
@@ -36,10 +37,13 @@ pub mod rspq;
 pub mod sprite;
 pub mod surface;
 pub mod ucstr;
+pub mod vi;
+pub mod video;
 pub mod wav64;
 pub mod xm64;
 pub mod ym64;
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     debugln!("{info}");
@@ -84,8 +84,15 @@ pub unsafe fn cached_addr<T>(addr: NonNull<T>) -> NonNull<T> {
 #[doc = "Returns the 32-bit hardware tick counter\n\nThis macro returns the current value of the hardware tick counter,\npresent in the CPU coprocessor 0. The counter increments at half of the\nprocessor clock speed (see `ticks_per_second`), and overflows every\n91.625 seconds.\n\nIt is fine to use this hardware counter for measuring small time intervals,\nas long as `TICKS_DISTANCE` or `TICKS_BEFORE` are used to compare different\ncounter reads, as those macros correctly handle overflows.\n\nMost users might find more convenient to use `get_ticks`, a similar function\nthat returns a 64-bit counter with the same frequency that never overflows.\n\n@see `TICKS_BEFORE`"]
 #[inline]
 pub fn ticks_read() -> u32 {
-    let value;
-    unsafe { core::arch::asm!(".set     noat\n\tmfc0     {}, $9", out(reg) value) };
+    let value: u32;
+    #[cfg(target_arch = "mips")]
+    unsafe {
+        core::arch::asm!(".set     noat\n\tmfc0     {}, $9", out(reg) value)
+    };
+    #[cfg(not(target_arch = "mips"))]
+    {
+        value = 0;
+    }
     value
 }
 
@@ -182,13 +189,13 @@ pub fn ticks_ms() -> u64 {
 #[inline]
 pub fn wait_ticks(wait: u32) {
     unsafe {
-        n64sys::wait_ticks(wait);
+        n64sys::wait_ticks(wait as _);
     }
 }
 #[doc = "Spin wait until the number of milliseconds have elapsed\n\n @param[in] wait_ms\n            Number of milliseconds to wait\n            Maximum accepted value is 91625 ms"]
 #[inline]
 pub fn wait_ms(wait_ms: u32) {
-    unsafe { n64sys::wait_ms(wait_ms) }
+    unsafe { n64sys::wait_ms(wait_ms as _) }
 }
 #[doc = "Force a complete halt of all processors\n\n @note It should occur whenever a reset has been triggered\n and its past its RESET_TIME_LENGTH grace time period.\n This function will shut down the RSP and the CPU, blank the VI.\n Eventually the RDP will flush and complete its work as well.\n The system will recover after a reset or power cycle.\n"]
 #[inline]
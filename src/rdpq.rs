@@ -8,6 +8,38 @@ use crate::{
     sys::{rdpq::*, rdpq_macros::*, rdpq_mode::*, rdpq_tex::*},
 };
 
+mod batch;
+pub mod commands;
+#[cfg(feature = "debug")]
+pub mod debug;
+mod font;
+mod mat;
+mod nineslice;
+mod paragraph;
+pub mod shapes;
+pub mod soft;
+mod state;
+mod target;
+mod tilemap;
+mod tmem;
+
+pub use batch::*;
+pub use font::*;
+pub use mat::*;
+pub use nineslice::*;
+pub use paragraph::*;
+pub use state::*;
+pub use target::*;
+pub use tilemap::*;
+pub use tmem::*;
+
+// Declared in rdpq_rect.h, but only called by its inline functions, so they
+// are not part of the generated bindings.
+unsafe extern "C" {
+    fn __rdpq_fill_rectangle(w0: u32, w1: u32);
+    fn __rdpq_texture_rectangle(w0: u32, w1: u32, w2: u32, w3: u32);
+}
+
 bitflags::bitflags! {
     #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
     pub struct Config: u32 {
@@ -41,6 +73,20 @@ pub enum Tile {
 
 impl Tile {
     pub const INTERNAL: Self = Self::_7;
+    #[doc = "Get the tile descriptor with the given index (only the lowest 3 bits are used)"]
+    #[inline]
+    pub const fn from_index(idx: u32) -> Self {
+        match idx & 7 {
+            0 => Self::_0,
+            1 => Self::_1,
+            2 => Self::_2,
+            3 => Self::_3,
+            4 => Self::_4,
+            5 => Self::_5,
+            6 => Self::_6,
+            _ => Self::_7,
+        }
+    }
     #[inline]
     const fn autosync(self) -> u32 {
         1 << self as u32
@@ -435,6 +481,9 @@ impl<'r> RdpQ<'r> {
     #[doc = "Configure a scissoring rectangle in screen coordinates (RDP command: SET_SCISSOR)\n\nThis function is used to configure a scissor region that the RDP with adhere to\nwhile drawing primitives (triangles or rectangles). Any points that fall outside\nof the specified scissoring rectangle will be ignored.\n\nThe scissoring capability is also the only one that prevents the RDP from drawing\noutside of the current framebuffer (color surface) extents. As such, rdpq actually\ncalls `rdpq_set_scissor` automatically any time a new render target is configured\n(eg: via `rdpq_attach` or `rdpq_set_color_image`), because forgetting to do so might\neasily cause crashes.\n\nBecause `rdpq_set_color_image` will configure a scissoring region automatically,\nit is normally not required to call this function. Use this function if you want\nto restrict drawing to a smaller area of the framebuffer.\n\nThe scissoring rectangle is defined using unsigned coordinates, and thus negative\ncoordinates will always be clipped. Rectangle-drawing primitives do not allow to\nspecify them at all, but triangle-drawing primitives do.\n\n@param[in]   x0      Top-left X coordinate of the rectangle\n@param[in]   y0      Top-left Y coordinate of the rectangle\n@param[in]   x1      Bottom-right *exclusive* X coordinate of the rectangle\n@param[in]   y1      Bottom-right *exclusive* Y coordinate of the rectangle\n\n@see `rdpq_attach`\n@see `rdpq_set_color_image`"]
     #[inline]
     pub fn set_scissor(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        if state::cached(|s| &mut s.scissor, [x0, y0, x1, y1]) {
+            return;
+        }
         let x0fx = (x0 * 4.0) as i32;
         let y0fx = (y0 * 4.0) as i32;
         let x1fx = (x1 * 4.0) as i32;
@@ -451,6 +500,201 @@ impl<'r> RdpQ<'r> {
                 _carg(x1fx as u32, 0xFFF, 12) | _carg(y1fx as u32, 0xFFF, 0),
             );
         }
+        target::track_scissor(Some([x0, y0, x1, y1]));
+    }
+    #[doc = "Draw a filled rectangle (RDP command: FILL_RECTANGLE)\n\n This command is used to render a rectangle filled with a solid color.\n The color must have been configured via `rdpq_set_fill_color`, and the\n render mode should be set to FILL via `rdpq_set_mode_fill`.\n\n The rectangle must be defined using exclusive bottom-right bounds, so for\n instance `rdpq_fill_rectangle(10,10,30,30)` will draw a square of exactly\n 20x20 pixels.\n\n Fractional values can be used, and will create a semi-transparent edge. For\n instance, `rdpq_fill_rectangle(9.75,9.75,30.25,30.25)` will create a 22x22 pixel\n square, with the most external pixel rows and columns having a alpha of 25%.\n This obviously makes more sense in RGBA32 mode where there is enough alpha\n bitdepth to appreciate the result. Make sure to configure the blender via\n `rdpq_mode_blender` (part of the mode API) or via the lower-level `rdpq_set_other_modes_raw`,\n to decide the blending formula.\n\n Portions of the rectangle outside the current scissor are clipped away.\n\n @param[in] x0      Top-left X coordinate of the rectangle\n @param[in] y0      Top-left Y coordinate of the rectangle\n @param[in] x1      Bottom-right *exclusive* X coordinate of the rectangle\n @param[in] y1      Bottom-right *exclusive* Y coordinate of the rectangle\n\n @see `rdpq_fill_rectangle_fx`\n @see `rdpq_set_fill_color`\n @see `rdpq_set_fill_color_stripes`\n @see `rdpq_set_mode_fill`"]
+    #[inline]
+    pub fn fill_rectangle(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        self.fill_rectangle_fx(
+            (x0 * 4.0) as i32,
+            (y0 * 4.0) as i32,
+            (x1 * 4.0) as i32,
+            (y1 * 4.0) as i32,
+        );
+    }
+    #[doc = "Draw a filled rectangle -- fixed point version (RDP command: FILL_RECTANGLE)\n\n This function is similar to `rdpq_fill_rectangle`, but coordinates must be\n specified using fixed point numbers (0.10.2).\n\n @param[in] x0      Top-left X coordinate of the rectangle (fx 0.10.2)\n @param[in] y0      Top-left Y coordinate of the rectangle (fx 0.10.2)\n @param[in] x1      Bottom-right *exclusive* X coordinate of the rectangle (fx 0.10.2)\n @param[in] y1      Bottom-right *exclusive* Y coordinate of the rectangle (fx 0.10.2)\n\n @see `rdpq_fill_rectangle`"]
+    #[inline]
+    pub fn fill_rectangle_fx(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        debug_assert!(x0 <= x1, "x1 must be greater or equal to x0");
+        debug_assert!(y0 <= y1, "y1 must be greater or equal to y0");
+        let x0 = x0.max(0);
+        let y0 = y0.max(0);
+        let x1 = x1.min(0xFFF);
+        let y1 = y1.min(0xFFF);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        unsafe {
+            __rdpq_fill_rectangle(
+                _carg(x1 as u32, 0xFFF, 12) | _carg(y1 as u32, 0xFFF, 0),
+                _carg(x0 as u32, 0xFFF, 12) | _carg(y0 as u32, 0xFFF, 0),
+            );
+        }
+    }
+    #[doc = "Draw a textured rectangle (RDP command: TEXTURE_RECTANGLE)\n\n This function enqueues a RDP TEXTURE_RECTANGLE command, that allows to draw a\n textured rectangle onto the framebuffer (similar to a sprite).\n\n The texture must have been already loaded into TMEM via `rdpq_load_tile` or\n `rdpq_load_block`, and a tile descriptor referring to it must be passed to this\n function.\n\n Input X and Y coordinates are automatically clipped to the screen boundaries (and\n then scissoring also takes effect), so there is no specific range\n limit to them. On the contrary, S and T coordinates have a specific range\n (-1024..1024).\n\n Before calling this function, make sure to also configure an appropriate\n render mode. It is possible to use the fast copy mode (`rdpq_set_mode_copy`) with\n this function, assuming that advanced blending or color combiner capabilities\n are not needed. The copy mode can in fact just blit the pixels from the texture\n unmodified, applying only a per-pixel rejection to mask out transparent pixels\n (via alpha compare). See `rdpq_set_mode_copy` for more information.\n\n Alternatively, it is possible to use this command also in standard render mode\n (`rdpq_set_mode_standard`), with all the per-pixel blending / combining features.\n\n Passing x1 smaller than x0 (or y1 smaller than y0) draws the texture flipped\n horizontally (or vertically).\n\n @param[in] tile      Tile descriptor referring to the texture in TMEM to use for drawing\n @param[in] x0        Top-left X coordinate of the rectangle\n @param[in] y0        Top-left Y coordinate of the rectangle\n @param[in] x1        Bottom-right *exclusive* X coordinate of the rectangle\n @param[in] y1        Bottom-right *exclusive* Y coordinate of the rectangle\n @param[in] s         S coordinate of the texture at the top-left corner (range: -1024..1024)\n @param[in] t         T coordinate of the texture at the top-left corner (range: -1024..1024)\n\n @see `rdpq_texture_rectangle_fx`\n @see `rdpq_texture_rectangle_scaled`"]
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    pub fn texture_rectangle(
+        &mut self,
+        tile: Tile,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        s: f32,
+        t: f32,
+    ) {
+        self.texture_rectangle_fx(
+            tile,
+            (x0 * 4.0) as i32,
+            (y0 * 4.0) as i32,
+            (x1 * 4.0) as i32,
+            (y1 * 4.0) as i32,
+            (s * 32.0) as i32,
+            (t * 32.0) as i32,
+        );
+    }
+    #[doc = "Draw a textured rectangle -- fixed point version (RDP command: TEXTURE_RECTANGLE)\n\n This function is similar to `rdpq_texture_rectangle`, but uses fixed point\n numbers for the arguments. Prefer using `rdpq_texture_rectangle` when possible.\n\n Refer to `rdpq_texture_rectangle` for more details on how this command works.\n\n @param[in] tile      Tile descriptor referring to the texture in TMEM to use for drawing\n @param[in] x0        Top-left X coordinate of the rectangle (fx 10.2)\n @param[in] y0        Top-left Y coordinate of the rectangle (fx 10.2)\n @param[in] x1        Bottom-right *exclusive* X coordinate of the rectangle (fx 10.2)\n @param[in] y1        Bottom-right *exclusive* Y coordinate of the rectangle (fx 10.2)\n @param[in] s         S coordinate of the texture at the top-left corner (fx 10.5)\n @param[in] t         T coordinate of the texture at the top-left corner (fx 10.5)\n\n @see `rdpq_texture_rectangle`"]
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    pub fn texture_rectangle_fx(
+        &mut self,
+        tile: Tile,
+        mut x0: i32,
+        mut y0: i32,
+        mut x1: i32,
+        mut y1: i32,
+        mut s: i32,
+        mut t: i32,
+    ) {
+        debug_assert!(
+            (-1024 * 32..1024 * 32).contains(&s) && (-1024 * 32..1024 * 32).contains(&t),
+            "texture coordinates must be in range [-1024..1024)"
+        );
+        if x1 == x0 || y1 == y0 {
+            return;
+        }
+        let mut dsdx = 1i32 << 10;
+        let mut dtdy = 1i32 << 10;
+        if x0 > x1 {
+            core::mem::swap(&mut x0, &mut x1);
+            x0 += 4;
+            x1 += 4;
+            s += (x1 - x0 - 4) << 3;
+            dsdx = -dsdx;
+        }
+        if y0 > y1 {
+            core::mem::swap(&mut y0, &mut y1);
+            y0 += 4;
+            y1 += 4;
+            t += (y1 - y0 - 4) << 3;
+            dtdy = -dtdy;
+        }
+        if x0 < 0 {
+            s -= (x0 * dsdx) >> 7;
+            x0 = 0;
+        }
+        if y0 < 0 {
+            t -= (y0 * dtdy) >> 7;
+            y0 = 0;
+        }
+        x1 = x1.min(1024 * 4 - 1);
+        y1 = y1.min(1024 * 4 - 1);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        unsafe {
+            __rdpq_texture_rectangle(
+                _carg(x1 as u32, 0xFFF, 12) | _carg(y1 as u32, 0xFFF, 0),
+                _carg(tile, 0x7, 24) | _carg(x0 as u32, 0xFFF, 12) | _carg(y0 as u32, 0xFFF, 0),
+                _carg(s as u32, 0xFFFF, 16) | _carg(t as u32, 0xFFFF, 0),
+                _carg(dsdx as u32, 0xFFFF, 16) | _carg(dtdy as u32, 0xFFFF, 0),
+            );
+        }
+    }
+    #[doc = "Draw a textured rectangle with scaling (RDP command: TEXTURE_RECTANGLE)\n\n This function is similar to `rdpq_texture_rectangle` but allows the rectangle\n to be scaled horizontally and/or vertically, by specifying both the source\n rectangle in the texture, and the rectangle on the screen.\n\n Refer to `rdpq_texture_rectangle` for more details on how this command works.\n\n @param[in] tile      Tile descriptor referring to the texture in TMEM to use for drawing\n @param[in] x0        Top-left X coordinate of the rectangle\n @param[in] y0        Top-left Y coordinate of the rectangle\n @param[in] x1        Bottom-right *exclusive* X coordinate of the rectangle\n @param[in] y1        Bottom-right *exclusive* Y coordinate of the rectangle\n @param[in] s0        S coordinate of the texture at the top-left corner (range: -1024..1024)\n @param[in] t0        T coordinate of the texture at the top-left corner (range: -1024..1024)\n @param[in] s1        S coordinate of the texture at the bottom-right corner (exclusive) (range: -1024..1024)\n @param[in] t1        T coordinate of the texture at the bottom-right corner (exclusive) (range: -1024..1024)\n\n @see `rdpq_texture_rectangle_scaled_fx`\n @see `rdpq_texture_rectangle`"]
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    pub fn texture_rectangle_scaled(
+        &mut self,
+        tile: Tile,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        s0: f32,
+        t0: f32,
+        s1: f32,
+        t1: f32,
+    ) {
+        self.texture_rectangle_scaled_fx(
+            tile,
+            (x0 * 4.0) as i32,
+            (y0 * 4.0) as i32,
+            (x1 * 4.0) as i32,
+            (y1 * 4.0) as i32,
+            (s0 * 32.0) as i32,
+            (t0 * 32.0) as i32,
+            (s1 * 32.0) as i32,
+            (t1 * 32.0) as i32,
+        );
+    }
+    #[doc = "Draw a textured rectangle with scaling -- fixed point version (RDP command: TEXTURE_RECTANGLE)\n\n This function is similar to `rdpq_texture_rectangle_scaled`, but uses fixed point\n numbers for the arguments. Prefer using `rdpq_texture_rectangle_scaled` when possible.\n\n @param[in] tile      Tile descriptor referring to the texture in TMEM to use for drawing\n @param[in] x0        Top-left X coordinate of the rectangle (fx 10.2)\n @param[in] y0        Top-left Y coordinate of the rectangle (fx 10.2)\n @param[in] x1        Bottom-right *exclusive* X coordinate of the rectangle (fx 10.2)\n @param[in] y1        Bottom-right *exclusive* Y coordinate of the rectangle (fx 10.2)\n @param[in] s0        S coordinate of the texture at the top-left corner (fx 10.5)\n @param[in] t0        T coordinate of the texture at the top-left corner (fx 10.5)\n @param[in] s1        S coordinate of the texture at the bottom-right corner (exclusive) (fx 10.5)\n @param[in] t1        T coordinate of the texture at the bottom-right corner (exclusive) (fx 10.5)\n\n @see `rdpq_texture_rectangle_scaled`"]
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    pub fn texture_rectangle_scaled_fx(
+        &mut self,
+        tile: Tile,
+        mut x0: i32,
+        mut y0: i32,
+        mut x1: i32,
+        mut y1: i32,
+        mut s0: i32,
+        mut t0: i32,
+        s1: i32,
+        t1: i32,
+    ) {
+        debug_assert!(
+            (-1024 * 32..=1024 * 32).contains(&s0)
+                && (-1024 * 32..=1024 * 32).contains(&t0)
+                && (-1024 * 32..=1024 * 32).contains(&s1)
+                && (-1024 * 32..=1024 * 32).contains(&t1),
+            "texture coordinates must be in range [-1024..1024]"
+        );
+        if x1 == x0 || y1 == y0 {
+            return;
+        }
+        let dsdx = ((s1 - s0) << 7) / (x1 - x0);
+        let dtdy = ((t1 - t0) << 7) / (y1 - y0);
+        if x0 > x1 {
+            core::mem::swap(&mut x0, &mut x1);
+            s0 = s1;
+        }
+        if y0 > y1 {
+            core::mem::swap(&mut y0, &mut y1);
+            t0 = t1;
+        }
+        if x0 < 0 {
+            s0 -= (x0 * dsdx) >> 7;
+            x0 = 0;
+        }
+        if y0 < 0 {
+            t0 -= (y0 * dtdy) >> 7;
+            y0 = 0;
+        }
+        x1 = x1.min(1024 * 4 - 1);
+        y1 = y1.min(1024 * 4 - 1);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        unsafe {
+            __rdpq_texture_rectangle(
+                _carg(x1 as u32, 0xFFF, 12) | _carg(y1 as u32, 0xFFF, 0),
+                _carg(tile, 0x7, 24) | _carg(x0 as u32, 0xFFF, 12) | _carg(y0 as u32, 0xFFF, 0),
+                _carg(s0 as u32, 0xFFFF, 16) | _carg(t0 as u32, 0xFFFF, 0),
+                _carg(dsdx as u32, 0xFFFF, 16) | _carg(dtdy as u32, 0xFFFF, 0),
+            );
+        }
     }
     #[doc = "Set a fixed Z value to be used instead of a per-pixel value (RDP command; SET_PRIM_DEPTH)\n\n When using z-buffering, normally the Z value used for z-buffering is\n calculated by interpolating the Z of each vertex onto each pixel.\n The RDP allows for usage of a fixed Z value instead, for special\n effects like particles or decals.\n\n This function allows to configure the RDP register that\n holds the fixed Z value. It is then necessary to activate this\n special RDP mode: either manually turning on SOM_ZSOURCE_PRIM via\n `rdpq_change_other_modes_raw`.\n\n For beginners, it is suggested to use the mode API instead, via\n `rdpq_mode_zoverride`.\n\n @param[in] prim_z     Fixed Z value (in range 0..0x7FFF)\n @param[in] prim_dz    Delta Z value (must be a signed power of two).\n                       Pass 0 initially, and increment to next power of two\n                       in case of problems with objects with the same Z.\n\n @note Pending further investigation of the exact usage of this function,\n       and specifically the prim_dz parameter, rdpq does not currently\n       offer a higher-level function (`rdpq_set_prim_depth`)."]
     #[inline]
@@ -489,6 +733,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Load a portion of a texture into TMEM -- fixed point version (RDP command: LOAD_TILE)\n\n This function is similar to `rdpq_load_tile`, but coordinates can be specified\n in fixed point format (0.10.2). Refer to `rdpq_load_tile` for increased performance\n\n @note Beginners are advised to use the rdpq texture API (rdpq_tex.h),\n for instance `rdpq_tex_upload` that takes care of everything required.\n\n\n @param[in]   tile        Tile descriptor to use (TILE0-TILE7).\n @param[in]   s0          Upper-left X coordinate of the portion of the texture to load (fx 0.10.2).\n                          Range: 0-4096\n @param[in]   t0          Upper-left Y coordinate of the portion of the texture to load (fx 0.10.2),\n                          Range: 0-4096\n @param[in]   s1          Bottom-right X coordinate of the portion of the texture to load (fx 0.10.2),\n                          Range: 0-4096\n @param[in]   t1          Bottom-right Y coordinate of the portion of the texture to load (fx 0.10.2),\n                          Range: 0-4096\n\n @see `rdpq_load_tile`\n @see `rdpq_tex_upload`"]
     #[inline]
     pub fn load_tile_fx(&mut self, tile: Tile, s0: u16, t0: u16, s1: u16, t1: u16) {
+        state::update(|s| s.tiles[tile as usize].size = None);
         unsafe {
             __rdpq_write8_syncchangeuse(
                 RDPQ_CMD_LOAD_TILE,
@@ -502,6 +747,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Load a palette of colors into TMEM (RDP command: LOAD_TLUT)\n\n This command is used to load a palette into TMEM. TMEM can hold up\n to 256 16-bit colors in total to be used as palette, and they must be\n stored in the upper half of TMEM. These colors are arranged as a single\n 256-color palette when drawing `FMT_CI8` images, or 16 16-colors palettes\n when drawing `FMT_CI4` images.\n\n Storage of colors in TMEM is a bit wasteful, as each color is replicated\n four times (in fact, 256 colors * 16-bit + 4 = 2048 bytes, which is\n in fact half of TMEM). This command should be preferred for palette\n loading as it automatically handles this replication.\n\n Loading a palette manually is a bit involved. It requires configuring\n the palette in RDRAM via `rdpq_set_texture_image`, and also configure a\n tile descriptor with the TMEM destination address (via `rdpq_set_tile`).\n Instead, prefer using the simpler rdpq texture API (rdpq_tex.h), via\n `rdpq_tex_upload_tlut`.\n\n @param[in] tile         Tile descriptor to use (TILE0-TILE7). This is used\n                         to extract the destination TMEM address (all other fields\n                         of the descriptor are ignored).\n @param[in] color_idx    Index of the first color to load into TMEM (0-255).\n                         This is a 16-bit offset into the RDRAM buffer\n                         set via `rdpq_set_texture_image`.\n @param[in] num_colors   Number of colors to load (1-256).\n\n @see `rdpq_tex_upload_tlut`"]
     #[inline]
     pub fn load_tlut_raw(&mut self, tile: Tile, color_idx: i32, num_colors: i32) {
+        state::update(|s| s.tiles[tile as usize].size = None);
         unsafe {
             __rdpq_write8_syncchangeuse(
                 RDPQ_CMD_LOAD_TLUT,
@@ -531,6 +777,9 @@ impl<'r> RdpQ<'r> {
             s0 <= 1024 * 4 && t0 <= 1024 * 4 && s1 <= 1024 * 4 && t1 <= 1024 * 4,
             "texture coordinates must be smaller than 1024"
         );
+        if state::cached(|s| &mut s.tiles[tile as usize].size, [s0, t0, s1, t1]) {
+            return;
+        }
 
         unsafe {
             __rdpq_write8_syncchange(
@@ -544,6 +793,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Low level function to load a texture image into TMEM in a single memory transfer"]
     #[inline]
     pub fn load_block_fx(&mut self, tile: Tile, s0: u16, t0: u16, num_texels: u16, dxt: u16) {
+        state::update(|s| s.tiles[tile as usize].size = None);
         unsafe {
             __rdpq_write8_syncchangeuse(
                 RDPQ_CMD_LOAD_BLOCK,
@@ -604,9 +854,17 @@ impl<'r> RdpQ<'r> {
             })
             .unwrap_or(&TileParms::EMPTY)
             .0;
+        let desc = TileDesc {
+            format,
+            tmem_addr,
+            tmem_pitch,
+            parms: TileParms(parms),
+        };
         let tmem_addr = tmem_addr as u32;
         let (func, reuse, cmd_id, tmem_addr) =
             if (tmem_addr & (RDPQ_AUTOTMEM | automem_reuse(0))) != 0 {
+                // The actual address depends on the state of the TMEM allocator
+                state::update(|s| s.tiles[tile as usize].desc = None);
                 (
                     __rdpq_fixup_write8_syncchange as unsafe extern "C" fn(u32, u32, u32, u32),
                     (tmem_addr & automem_reuse(0)) != 0,
@@ -619,6 +877,9 @@ impl<'r> RdpQ<'r> {
                     0,
                     "invalid tmem_addr {tmem_addr}: must be multiple of 8"
                 );
+                if state::cached(|s| &mut s.tiles[tile as usize].desc, desc) {
+                    return;
+                }
                 (
                     __rdpq_write8_syncchange as _,
                     false,
@@ -660,11 +921,15 @@ impl<'r> RdpQ<'r> {
     #[doc = "Enqueue a SET_FILL_COLOR RDP command.\n\n This command is used to configure the color used by RDP when running in FILL mode\n (`rdpq_set_mode_fill`) and normally used by `rdpq_fill_rectangle`.\n\n Notice that `rdpq_set_mode_fill` automatically calls this function, because in general\n it makes no sense to configure the FILL mode without also setting a FILL color.\n\n @code{.c}\n      // Fill top half of the screen in red\n      rdpq_set_mode_fill(RGBA32(255, 0, 0, 0));\n      rdpq_fill_rectangle(0, 0, 320, 120);\n\n      // Fill bottom half of the screen in blue.\n      // No need to change mode again (it's already in fill mode),\n      // so just change the fill color.\n      rdpq_set_fill_color(RGBA32(0, 0, 255, 0));\n      rdpq_fill_rectangle(0, 120, 320, 240);\n @endcode\n\n @param[in]    color   The color to use to fill\n\n @see `rdpq_set_mode_fill`"]
     #[inline]
     pub fn set_fill_color(&mut self, c: Color) {
+        if state::cached(|s| &mut s.fill_color, c) {
+            return;
+        }
         unsafe { __rdpq_set_fill_color(c.into_u32()) }
     }
     #[doc = "Enqueue a SET_FILL_COLOR RDP command to draw a striped pattern.\n\n This command is similar to `rdpq_set_fill_color`, but allows to configure\n two colors, and creates a fill pattern that alternates horizontally between\n them every 2 pixels (creating vertical stripes).\n\n This command relies on a low-level hack of how RDP works in filling primitives,\n so there is no configuration knob: it only works with RGBA 16-bit target\n buffers, it only allows two colors, and the vertical stripes are exactly\n 2 pixel width.\n\n @param[in]   color1      Color of the first vertical stripe\n @param[in]   color2      Color of the second vertical stripe\n\n @see `rdpq_set_fill_color`\n"]
     #[inline]
     pub fn set_fill_color_stripes(&mut self, c1: Color, c2: Color) {
+        state::update(|s| s.fill_color = None);
         unsafe {
             __rdpq_write8_syncchange(
                 RDPQ_CMD_SET_FILL_COLOR,
@@ -677,6 +942,9 @@ impl<'r> RdpQ<'r> {
     #[doc = "Set the RDP FOG blender register\n\n This function sets the internal RDP FOG register, part of the blender unit.\n As the name implies, this register is normally used as part of fog calculation,\n but it is actually a generic color register that can be used in custom\n blender formulas.\n\n Another similar blender register is the BLEND register, configured via\n `rdpq_set_blend_color`.\n\n See `RDPQ_BLENDER` and `RDPQ_BLENDER2` on how to configure\n the blender (typically, via `rdpq_mode_blender`).\n\n @param[in] color             Color to set the FOG register to\n\n @see `RDPQ_BLENDER`\n @see `RDPQ_BLENDER2`\n @see `rdpq_set_blend_color`\n @see `rdpq_mode_blender`"]
     #[inline]
     pub fn set_fog_color(&mut self, color: Color) {
+        if state::cached(|s| &mut s.fog_color, color) {
+            return;
+        }
         unsafe {
             __rdpq_write8_syncchange(RDPQ_CMD_SET_FOG_COLOR, 0, color.into_u32(), AUTOSYNC_PIPE)
         }
@@ -684,6 +952,9 @@ impl<'r> RdpQ<'r> {
     #[doc = "Set the RDP BLEND blender register\n\n This function sets the internal RDP BLEND register, part of the blender unit.\n As the name implies, this register is normally used as part of fog calculation,\n but it is actually a generic color register that can be used in custom\n blender formulas.\n\n Another similar blender register is the FOG register, configured via\n `rdpq_set_fog_color`.\n\n See `RDPQ_BLENDER` and `RDPQ_BLENDER2` on how to configure\n the blender (typically, via `rdpq_mode_blender`).\n\n @param[in] color             Color to set the BLEND register to\n\n @see `RDPQ_BLENDER`\n @see `RDPQ_BLENDER2`\n @see `rdpq_set_fog_color`\n @see `rdpq_mode_blender`"]
     #[inline]
     pub fn set_blend_color(&mut self, color: Color) {
+        if state::cached(|s| &mut s.blend_color, color) {
+            return;
+        }
         unsafe {
             __rdpq_write8_syncchange(RDPQ_CMD_SET_BLEND_COLOR, 0, color.into_u32(), AUTOSYNC_PIPE)
         }
@@ -691,6 +962,9 @@ impl<'r> RdpQ<'r> {
     #[doc = "Set the RDP PRIM combiner register (color only) (RDP command: SET_PRIM_COLOR)\n\n This function sets the internal RDP PRIM register, part of the\n color combiner unit. Naming aside, it is a generic color register that\n can be used in custom color combiner formulas.\n\n Another similar blender register is the ENV register, configured via\n `rdpq_set_env_color`.\n\n See `RDPQ_COMBINER1` and `RDPQ_COMBINER2` on how to configure\n the color combiner (typicall, via `rdpq_mode_combiner`).\n\n If you wish to set PRIM LOD or PRIM MIN LOD values of the PRIM register,\n see `rdpq_set_prim_lod_frac`, `rdpq_set_detail_factor` or `rdpq_set_prim_register_raw`.\n\n @param[in] color             Color to set the PRIM register to\n\n @see `RDPQ_COMBINER1`\n @see `RDPQ_COMBINER2`\n @see `rdpq_set_env_color`\n @see `rdpq_mode_combiner`\n @see `rdpq_set_prim_lod_frac`\n @see `rdpq_set_detail_factor`\n @see `rdpq_set_prim_register_raw`\n"]
     #[inline]
     pub fn set_prim_color(&mut self, color: Color) {
+        if state::cached(|s| &mut s.prim_color, color) {
+            return;
+        }
         unsafe {
             __rdpq_fixup_write8_syncchange(
                 RDPQ_CMD_SET_PRIM_COLOR_COMPONENT,
@@ -728,6 +1002,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Set the RDP PRIM combiner register (raw version) (RDP command: SET_PRIM_COLOR)\n\n This function sets the internal RDP PRIM register, part of the\n color combiner unit. Naming aside, it is a generic color register that\n can be used in custom color combiner formulas.\n\n It also sets the PRIM LOD FRAC and PRIM MIN LOD FRAC values for the PRIM register\n For more information, see `rdpq_set_prim_lod_frac`, `rdpq_set_detail_factor`.\n\n Another similar blender register is the ENV register, configured via\n `rdpq_set_env_color`.\n\n See `RDPQ_COMBINER1` and `RDPQ_COMBINER2` on how to configure\n the color combiner (typicall, via `rdpq_mode_combiner`).\n\n If you wish to set PRIM COLOR or PRIM LOD or PRIM MIN LOD values individually,\n see `rdpq_set_prim_lod_frac`, `rdpq_set_detail_factor` or `rdpq_set_prim_color`.\n\n @param[in] color             Color to set the PRIM register to\n @param[in] minlod            Minimum LOD fraction to set the PRIM register to\n @param[in] primlod           Primitive LOD fraction to set the PRIM register to\n\n @see `RDPQ_COMBINER1`\n @see `RDPQ_COMBINER2`\n @see `rdpq_set_env_color`\n @see `rdpq_set_prim_color`\n @see `rdpq_set_prim_lod_frac`\n @see `rdpq_set_detail_factor`\n"]
     #[inline]
     pub fn set_prim_register_raw(&mut self, color: Color, minlod: u8, primlod: u8) {
+        state::update(|s| s.prim_color = Some(color));
         unsafe {
             __rdpq_write8(
                 RDPQ_CMD_SET_PRIM_COLOR,
@@ -739,6 +1014,9 @@ impl<'r> RdpQ<'r> {
     #[doc = "Set the RDP ENV combiner register (RDP command: SET_ENV_COLOR)\n\n This function sets the internal RDP ENV register, part of the\n color combiner unit. Naming aside, it is a generic color register that\n can be used in custom color combiner formulas.\n\n Another similar blender register is the PRIM register, configured via\n `rdpq_set_prim_color`.\n\n See `RDPQ_COMBINER1` and `RDPQ_COMBINER2` on how to configure\n the color combiner (typically, via `rdpq_mode_combiner`).\n\n @param[in] color             Color to set the ENV register to\n\n @see `RDPQ_COMBINER1`\n @see `RDPQ_COMBINER2`\n @see `rdpq_set_prim_color`\n @see `rdpq_mode_combiner`\n"]
     #[inline]
     pub fn set_env_color(&mut self, color: Color) {
+        if state::cached(|s| &mut s.env_color, color) {
+            return;
+        }
         unsafe {
             __rdpq_write8_syncchange(RDPQ_CMD_SET_ENV_COLOR, 0, color.into_u32(), AUTOSYNC_PIPE);
         }
@@ -749,6 +1027,7 @@ impl<'r> RdpQ<'r> {
         unsafe {
             rdpq_set_color_image(surface.as_raw_mut());
         }
+        target::track_scissor(None);
     }
     #[doc = "Configure the Z-buffer to use (RDP command: SET_Z_IMAGE)\n\n This commands is used to specify the Z-buffer that will be used by RDP for the next\n rendering commands.\n\n The surface must have the same width and height of the surface set as render target\n (via `rdpq_set_color_image` or `rdpq_set_color_image_raw`). The color format should be\n FMT_RGBA16, even though Z values will be written to it.\n\n If the passed surface is NULL, rdpq will be detached from the Z buffer. If\n a drawing command using Z is issued without a Z buffer, the behaviour will be\n undefined (but the validator will flag it as an error).\n\n @param surface      Surface to set as Z buffer\n\n @see `rdpq_set_z_image_raw`"]
     #[inline]
@@ -797,6 +1076,7 @@ impl<'r> RdpQ<'r> {
                 _carg(width * 4, 0xFFF, 12) | _carg(height * 4, 0xFFF, 0),
             ); // for set_scissor
         }
+        target::track_scissor(None);
     }
     #[doc = "Low-level version of `rdpq_set_z_image`, with address lookup capability.\n\n This is a low-level version of `rdpq_set_z_image`, that exposes the address lookup\n capability. It allows to either pass a direct buffer, or to use a buffer already stored\n in the address lookup table, adding optionally an offset. See `rdpq_set_lookup_address`\n for more information.\n\n RDP a physical constraint of 64-byte alignment for render targets, so make sure to respect\n that while configuring a buffer. The validator will flag such a mistake.\n\n @param index        Index in the rdpq lookup table of the buffer to set as render target.\n @param offset       Byte offset to add to the buffer stored in the lookup table. Notice that\n                     if index is 0, this can be a physical address to a buffer (use\n                     `PhysicalAddr` to convert a C pointer to a physical address).\n\n @see `rdpq_set_z_image`\n @see `rdpq_set_lookup_address`"]
     #[inline]
@@ -910,12 +1190,14 @@ impl<'r> RdpQ<'r> {
     #[doc = "Low-level function to set the rendering mode register.\n\n This function enqueues a low-level SET_OTHER_MODES RDP command that changes\n the RDP render mode, setting it to a new value\n\n This function is very low level and requires very good knowledge of internal\n RDP state management. Moreover, it completely overwrites any existing\n configuration for all bits, so it must be used with caution within a block.\n\n @note If possible, prefer using the RDPQ mode API (defined in rdpq_mode.h),\n that expose a higher level API for changing the RDP modes\n\n @param      mode     The new render mode. See the RDP_RM\n"]
     #[inline]
     pub fn set_other_modes_raw(&mut self, mode: SOM) {
+        state::track_som_raw(SOMMask::all(), mode);
         let mode = mode.bits();
         unsafe { __rdpq_set_other_modes((mode >> 32) as u32 & 0x00FFFFFF, mode as u32) }
     }
     #[doc = "Low-level function to partly change the rendering mode register.\n\n This function is very low level and requires very good knowledge of internal\n RDP state management.\n\n It allows to partially change the RDP render mode register, enqueuing a\n command that will modify only the requested bits. This function\n is to be preferred to `rdpq_set_other_modes_raw` as it preservers existing\n render mode for all the other bits, so it allows for easier composition.\n\n @note If possible, prefer using the RDPQ mode API (defined in rdpq_mode.h),\n that expose a higher level API for changing the RDP modes\n\n @param[in] mask          Mask of bits of the SOM register that must be changed\n @param[in] val           New value for the bits selected by the mask.\n"]
     #[inline]
     pub fn change_other_modes_raw(&mut self, mask: SOMMask, val: SOM) {
+        state::track_som_raw(mask, val);
         let mask = mask.bits();
         let hi = (mask >> 32) as u32;
         let lo = mask as u32;
@@ -937,6 +1219,10 @@ impl<'r> RdpQ<'r> {
     #[doc = "Low-level function to change the RDP combiner.\n\n This function enqueues a low-level SET_COMBINE RDP command that changes\n the RDP combiner, setting it to a new value.\n You can use `RDPQ_COMBINER1` and `RDPQ_COMBINER2` to create\n the combiner settings for respectively a 1-pass or 2-pass combiner.\n\n @note Prefer using `rdpq_mode_combiner` (part of the RDPQ mode API), as it better\n handles integration with other render mode changes.\n\n @param      comb     The new combiner setting\n\n @see `rdpq_mode_combiner`\n @see `RDPQ_COMBINER1`\n @see `RDPQ_COMBINER2`\n"]
     #[inline]
     pub fn set_combiner_raw(&mut self, comb: u64) {
+        state::update(|s| {
+            s.combiner = None;
+            s.som_known -= state::SOM_DERIVED;
+        });
         unsafe {
             __rdpq_write8_syncchange(
                 RDPQ_CMD_SET_COMBINE_MODE_RAW,
@@ -959,6 +1245,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Send to the RDP a buffer of RDP commands from RDRAM\n\n This command can be used to execute raw RDP commands from RDRAM. It is\n normally not necessary to call this function as normal rdpq functions will\n simply enqueue the commands in the RSP queue, but there can be cases\n where commands have been prepared in RAM somehow (especially, for compatibility\n with existing code that assembled RDP commands in RDRAM, or to playback\n RDP command lists prepared with offline tools).\n\n This function fully interoperates with the rest of RDPQ, so you can freely\n intermix it with standard rdpq calls.\n\n @param buffer        Pointer to the buffer containing RDP commands\n @param size          Size of the buffer, in bytes (must be a multiple of 8)\n\n @note This function cannot be called within a block."]
     #[inline]
     pub unsafe fn exec(&mut self, buffer: &[u64]) {
+        state::forget_all();
         unsafe { rdpq_exec(buffer.as_ptr() as _, buffer.len() as _) }
     }
     #[doc = "Enqueue a callback that will be called after the RSP and the RDP have\n        finished processing all commands enqueued until now.\n\n This function is similar to `rspq_call_deferred`, but it also guarantees\n that the callback is called after the RDP has finished processing all\n commands enqueued until now.\n\n For example:\n\n @code{.c}\n      // Draw a green rectangle\n      rdpq_mode_set_fill(RGBA(0,255,0,0));\n      rdpq_fill_rectangle(10, 10, 100, 100);\n\n      // Enqueue a callback. The callback is guaranteed to be called\n      // after the RSP has finished prepared the RDP command list for the\n      // filled rectangle. It is possible that the RDP would still\n      // be processing the rectangle when the callback is called.\n      rspq_call_deferred(my_callback1, NULL);\n\n      // Enqueue a callback. The callback is guaranteed to be called\n      // after the rectangle has been fully drawn to the target buffer, so\n      // that for instance the callback could readback the green pixels.\n      rdpq_call_deferred(my_callback2, NULL);\n @endcode\n\n @param func          Callback function to call\n @param arg           Argument to pass to the callback function"]
@@ -975,6 +1262,7 @@ impl<'r> RdpQ<'r> {
         cmd_id: u32,
         args: [u32; N],
     ) {
+        state::forget_all();
         unsafe {
             if num_rdp_commands != 0 && !rspq_block.is_null() {
                 __rdpq_block_reserve(num_rdp_commands);
@@ -1001,6 +1289,7 @@ impl<'r> RdpQ<'r> {
                     .unwrap_or_else(core::ptr::null_mut),
             )
         }
+        target::track_scissor(None);
         Attachment(PhantomData)
     }
     #[doc = "Attach the RDP to a surface and clear it\n\n This function is similar to `rdpq_attach`, but it also clears the surface\n to full black (color 0) immediately after attaching. If a z-buffer is\n specified, it is also cleared (to `ZBUF_MAX`).\n\n This function is just a shortcut for calling `rdpq_attach`, `rdpq_clear` and\n `rdpq_clear_z`.\n\n @param[in] surf_color\n            The surface to render to.\n @param[in] surf_z\n            The Z-buffer to render to (can be NULL if no Z-buffer is required).\n\n @see `display_get`\n @see `surface_alloc`\n @see `rdpq_clear`\n @see `rdpq_clear_z`"]
@@ -1019,15 +1308,18 @@ impl<'r> RdpQ<'r> {
                     .unwrap_or_else(core::ptr::null_mut),
             )
         }
+        target::track_scissor(None);
         Attachment(PhantomData)
     }
     #[doc = "Clear the current render target with the specified color.\n\n Note that this function will respect the current scissor rectangle, if\n configured.\n\n @param[in] color\n            Color to use to clear the surface"]
     pub fn clear(&mut self, color: Color) {
         unsafe { crate::sys::rdpq_attach::__rdpq_clear(&color.into_raw()) }
+        state::update(|s| s.fill_color = None);
     }
     #[doc = "Reset the current Z buffer to a given value.\n\n This function clears the Z-buffer with the specified packed 16-bit value. This\n value is composed as follows:\n\n * The top 16-bit contains the Z value in a custom floating point format.\n * The bottom 2-bits (plus the 2 hidden bits) contain the Delta-Z value. The\n   Delta-Z value to use while clearing does not matter in practice for\n   normal Z buffer usages, so it can be left as 0.\n\n The default value to use for clearing the Z-buffer is `ZBUF_MAX`. To set the\n clear value to a custom Z value, use the `ZBUF_VAL` macro.\n\n Note that this function will respect the current scissor rectangle, if\n configured.\n\n @param[in] z\n            Value to reset the Z buffer to"]
     pub fn clear_z(&mut self, z: u16) {
         unsafe { crate::sys::rdpq_attach::__rdpq_clear_z(&z) }
+        state::update(|s| s.fill_color = None);
     }
     #[doc = "Check if the RDP is currently attached to a surface\n\n @return true if it is attached, false otherwise."]
     pub fn is_attached(&self) -> bool {
@@ -1058,11 +1350,13 @@ impl<'s, 'r> Attachment<'s, 'r> {
     #[inline]
     pub fn detach(self) {
         unsafe { crate::sys::rdpq_attach::rdpq_detach_cb(None, core::ptr::null_mut()) }
+        target::track_scissor(None);
     }
     #[doc = "Detach the RDP from the current framebuffer, and show it on screen\n\n This function runs a `rdpq_detach` on the surface, and then schedules in\n background for the surface to be displayed on screen after the RDP has\n finished drawing to it.\n\n The net result is similar to calling `rdpq_detach_wait` and then `display_show`\n manually, but it is more efficient because it does not block the CPU. Thus,\n if this function is called at the end of the frame, the CPU can immediately\n start working on the next one (assuming there is a free framebuffer available).\n\n @see `rdpq_detach_wait`\n @see `display_show`"]
     #[inline]
     pub fn detach_show(self) {
         unsafe { crate::sys::rdpq_attach::rdpq_detach_show() }
+        target::track_scissor(None);
     }
     #[doc = "Detach the RDP from the current surface, waiting for RDP to finish drawing.\n\n This function is similar to `rdpq_detach`, but also waits for the RDP to finish\n drawing to the surface.\n\n @see `rdpq_detach`"]
     #[inline]
@@ -1074,6 +1368,7 @@ impl<'s, 'r> Attachment<'s, 'r> {
     #[inline]
     pub fn detach_cb<T: crate::n64::InterruptArg>(&mut self, func: T::Fn, data: T) {
         unsafe { crate::sys::rdpq_attach::rdpq_detach_cb(Some(T::cast_fn(func)), data.into_ptr()) }
+        target::track_scissor(None);
     }
 }
 
@@ -1224,6 +1519,9 @@ impl BoolExt for bool {
 
 #[inline(always)]
 fn mode_change_som(mask: u64, val: u64) {
+    if state::cached_som(SOMMask::from_bits_retain(mask), SOM::from_bits_retain(val)) {
+        return;
+    }
     unsafe {
         if (mask >> 32) != 0 {
             __rdpq_fixup_mode3(
@@ -1249,6 +1547,7 @@ impl<'r> RdpQ<'r> {
     #[inline]
     pub fn mode_push(&mut self) -> ModeFrame<'_, 'r> {
         unsafe { rdpq_mode_push() }
+        state::mode_push();
         ModeFrame(PhantomData)
     }
     #[doc = "Start a batch of RDP mode changes\n\n This function can be used as an optimization when changing render mode\n and/or multiple render states. It allows to batch the changes, so that\n RDP hardware registers are updated only once.\n\n To use it, put a call to `rdpq_mode_begin` and `rdpq_mode_end` around\n the mode functions that you would like to batch. For instance:\n\n @code{.c}\n      rdpq_mode_begin();\n          rdpq_set_mode_standard();\n          rdpq_mode_mipmap(MIPMAP_INTERPOLATE, 2);\n          rdpq_mode_dithering(DITHER_SQUARE_SQUARE);\n          rdpq_mode_blender(RDPQ_BLENDING_MULTIPLY);\n      rdpq_mode_end();\n @endcode\n\n The only effect of using `rdpq_mode_begin` is more efficient RSP\n and RDP usage, there is no semantic change in the way RDP is\n programmed when `rdpq_mode_end` is called.\n\n @note The functions affected by `rdpq_mode_begin` / `rdpq_mode_end`\n       are just those that are part of the mode API (that is,\n       `rdpq_set_mode_*` and `rdpq_mode_*`). Any other function\n       is not batched and will be issued immediately."]
@@ -1264,22 +1563,26 @@ pub trait RdpQModes: crate::sealed::Sealed {
     #[inline]
     fn set_mode_standard(&mut self) {
         unsafe { rdpq_set_mode_standard() }
+        state::forget_modes();
     }
     #[doc = "Reset render mode to FILL type.\n\n This function sets the render mode type to FILL, which is used to quickly\n fill portions of the screens with a solid color. The specified color is\n configured via `rdpq_set_fill_color`, and can be changed later.\n\n Notice that in FILL mode most of the RDP features are disabled, so all other\n render modes settings (rdpq_mode_* functions) do not work.\n\n @param[in]  color  The fill color to use"]
     #[inline]
     fn set_mode_fill(&mut self, color: Color) {
         unsafe { __rdpq_set_mode_fill() };
+        state::forget_modes();
         RdpQ(PhantomData).set_fill_color(color);
     }
     #[doc = "Reset render mode to COPY type.\n\n This function sets the render mode type to COPY, which is used to quickly\n blit bitmaps. In COPY mode, only texture rectangles (aka \"sprites\") can be\n drawn and no advanced render mode features are working (rdpq_mode_* functions).\n\n The only available feature is transparency: pixels with alpha set to 0 can\n optionally be discarded during blit, so that the target buffer contents is\n not overwritten for those pixels. This is implemented using alpha compare.\n\n The COPY mode is approximately 4 times faster at drawing than the standard\n mode, so make sure to enable it whenever it is possible.\n\n @note The COPY mode only works with 16-bpp framebuffers. It will trigger a\n       hardware crash (!) on 32-bpp framebuffers, so avoid using it. The\n       validator will warn you about this anyway.\n\n @param[in]  transparency   If true, pixels with alpha set to 0 are not drawn\n\n @see `rdpq_set_mode_standard`"]
     #[inline]
     fn set_mode_copy(&mut self, transparency: bool) {
         unsafe { rdpq_set_mode_copy(transparency) }
+        state::forget_modes();
     }
     #[doc = "Reset render mode to YUV mode.\n\n This is a helper function to configure a render mode for YUV conversion.\n In addition of setting the render mode, this function also configures a\n combiner (given that YUV conversion happens also at the combiner level),\n and set standard YUV parameters (for BT.601 TV Range).\n\n After setting the YUV mode, you can load YUV textures to TMEM (using a\n surface with `FMT_YUV16`), and then draw them on the screen as part of\n triangles or rectangles.\n\n @param[in] bilinear      If true, YUV textures will also be filtered with\n                          bilinear interpolation (note: this will require\n                          2-cycle mode so it will be twice as slow)."]
     #[inline]
     fn set_mode_yuv(&mut self, bilinear: bool) {
         unsafe { rdpq_set_mode_yuv(bilinear) }
+        state::forget_modes();
     }
     #[doc = "Activate antialiasing\n\n This function can be used to enable/disable antialias at the RDP level.\n There are two different kinds of antialias on N64:\n\n   * Antialias on internal edges: this is fully performed by RDP.\n   * Antialias on external edges: this is prepared by RDP but is actually\n     performed as a post-processing filter by VI.\n\n This function activates both kinds of antialias, but to display correctly\n the second type, make sure that you did pass `FILTERS_RESAMPLE_ANTIALIAS` or\n `FILTERS_RESAMPLE_ANTIALIAS_DEDITHER` to `display_init`.\n\n On the other hand, if you want to make sure that no antialias is performed,\n disable antialias with `rdpq_mode_antialias(false)` (which is the default\n for `rdpq_set_mode_standard`), and that will make sure that the VI will not\n do anything to the image, even if `display_init` was called with\n `FILTERS_RESAMPLE_ANTIALIAS` or `FILTERS_RESAMPLE_ANTIALIAS_DEDITHER`.\n\n @note Antialiasing internally uses the blender unit. If you already\n       configured a formula via `rdpq_mode_blender`, antialias will just\n       rely on that one to correctly blend pixels with the framebuffer. It is\n       thus important that a custom formula configured via `rdpq_mode_blender`\n       does blend with the background somehow.\n\n @param mode        Antialiasing mode to use (or AA_NONE to disable)"]
     #[inline]
@@ -1293,6 +1596,10 @@ pub trait RdpQModes: crate::sealed::Sealed {
     #[doc = "Configure the color combiner\n\n This function allows to configure the color combiner formula to be used.\n The color combiner is the internal RDP hardware unit that mixes inputs\n from textures, colors and other sources and produces a RGB/Alpha value,\n that is then sent to the blender unit. If the blender is disabled (eg:\n the polygon is solid), the value produced by the combiner is the one\n that will be written into the framebuffer.\n\n For common use cases, rdpq offers ready-to-use macros that you can pass\n to `rdpq_mode_combiner`: `RDPQ_COMBINER_FLAT`, `RDPQ_COMBINER_SHADE`,\n `RDPQ_COMBINER_TEX`, `RDPQ_COMBINER_TEX_FLAT`, `RDPQ_COMBINER_TEX_SHADE`.\n\n For example, to draw a texture rectangle modulated with a flat color:\n\n @code{.c}\n      // Reset to standard rendering mode.\n      rdpq_set_mode_standard();\n\n      // Configure the combiner\n      rdpq_mode_combiner(RDPQ_COMBINER_TEX_FLAT);\n\n      // Configure the flat color that will modulate the texture\n      rdpq_set_prim_color(RGBA32(192, 168, 74, 255));\n\n      // Upload a texture into TMEM (tile descriptor `4`)\n      rdpq_tex_upload(TILE4, &texture, 0);\n\n      // Draw the rectangle\n      rdpq_texture_rectangle(TILE4,\n          0, 0, 32, 16,     // x0, y0, x1, y1\n          0, 0, 1.0, 1.0f   // s, t, ds, dt\n      );\n @endcode\n\n Alternatively, you can use your own combiner formulas, created with either\n `RDPQ_COMBINER1` (one pass) or `RDPQ_COMBINER2` (two passes). See the respective\n documentation for all the details on how to create a custom formula.\n\n When using a custom formula, you must take into account that some render states\n also rely on the combiner to work. Specifically:\n\n  * Mipmap (`rdpq_mode_mipmap`): when activating interpolated mipmapping\n    (`MIPMAP_INTERPOLATE`, also known as \"trilinear filterig\"), a dedicated\n    color combiner pass is needed, so if you set a custom formula, it has to be\n    a one-pass formula. Otherwise, a RSP assertion will trigger.\n  * Fog (`rdpq_mode_fog`): fogging is generally made by substituting the alpha\n    component of the shade color with a depth value, which is then used in\n    the blender formula (eg: `RDPQ_FOG_STANDARD`). The only interaction with the\n    color combiner is that the SHADE alpha component should not be used as\n    a modulation factor in the combiner, otherwise you get wrong results\n    (if you then use the alpha for blending). rdpq automatically adjusts\n    standard combiners using shade (`RDPQ_COMBINER_SHADE` and `RDPQ_COMBINER_TEX_SHADE`)\n    when fog is enabled, but for custom combiners it is up to the user to\n    take care of that.\n\n @param comb      The combiner formula to configure\n\n @see `RDPQ_COMBINER1`\n @see `RDPQ_COMBINER2`\n\n @note For programmers with previous RDP programming experience: this function\n       makes sure that the current cycle type can work correctly with the\n       specified combiner formula. Specifically, it switches automatically\n       between 1-cycle and 2-cycle depending on the formula being set and the\n       blender unit configuration, and also automatically adapts combiner\n       formulas to the required cycle mode. See the documentation in rdpq.c\n       for more information."]
     #[inline]
     fn mode_combiner(&mut self, comb: Combiner) {
+        if state::cached(|s| &mut s.combiner, comb) {
+            return;
+        }
+        state::update(|s| s.som_known -= state::SOM_DERIVED);
         let comb = comb.into_inner();
         if (comb & RDPQ_COMBINER_2PASS as u64) != 0 {
             unsafe {
@@ -1343,6 +1650,10 @@ pub trait RdpQModes: crate::sealed::Sealed {
     #[doc = "Configure the formula to use for blending.\n\n This function can be used to configure the formula used\n in the blender unit.\n\n The standard blending formulas are:\n\n  * `RDPQ_BLENDER_MULTIPLY`: multiplicative alpha blending using texture's alpha\n  * `RDPQ_BLENDER_MULTIPLY_CONST`: multiplicative alpha blending using a constant alpha\n  * `RDPQ_BLENDER_ADDITIVE`: additive alpha blending (mostly broken).\n\n Normally, you would use `RDPQ_BLENDER_MULTIPLY` when your source texture has\n an internal alpha channel that you want to use for blending. Otherwise, if\n you just want to add a fixed-level semi-transparency to an existing texture,\n use `RDPQ_BLENDER_MULTIPLY_CONST`.\n\n `RDPQ_BLENDER_ADDITIVE` is mostly broken on RDP, as it doesn't handle correctly\n overflowing values. Basically, values up to 1.5 are correctly clamped to 1,\n but values above 1.5 are wrapped back to 0, which makes the mode almost useless.\n\n It is possible to also create custom formulas. The blender unit\n allows for up to two passes. Use `RDPQ_BLENDER` to create a one-pass\n blending formula, or `RDPQ_BLENDER2` to create a two-pass formula.\n\n Please notice that two-pass formulas are not compatible with fogging\n (`rdpq_mode_fog`). Also notice that rdpq_mode assumes that any formula\n that you set here (either one-pass or two-passes) does blend with the\n background. If you want to use a formula that does not blend with the\n background, set it via `rdpq_mode_fog`, otherwise you might get incorrect\n results when using anti-alias (see `rdpq_mode_antialias`).\n\n The following example shows how to draw a texture rectangle using\n a fixed blending value of 0.5 (ignoring the alpha channel of the\n texture):\n\n @code{.c}\n      // Set standard mode\n      rdpq_set_mode_standard();\n\n      // Use blending with a constant semi-transparency value\n      rdpq_mode_blender(RDPQ_BLENDER_MULTIPLY_CONST);\n\n      // Configure the blending value to 128 (0.5). Notice that RGB\n      // values are ignored in this formula.\n      rdpq_set_fog_color(RGBA32(0,0,0, 128));\n\n      // Load a texture into TMEM\n      rdpq_tex_upload(TILE0, texture, 0);\n\n      // Draw it\n      rdpq_texture_rectangle(TILE0,\n          0, 0, 64, 64,   // x0,y0 - x1,y1\n          0, 0, 1.0, 1.0  // s0,t0 - ds,dt\n      );\n @endcode\n\n @param blend          Blending formula created with `RDPQ_BLENDER`,\n                       or 0 to disable.\n\n @see `rdpq_mode_fog`\n @see `RDPQ_BLENDER`\n @see `RDPQ_BLENDER_MULTIPLY`\n @see `RDPQ_BLENDER_MULTIPLY_CONST`\n @see `RDPQ_BLENDER_ADDITIVE`"]
     #[inline]
     fn mode_blender(&mut self, blend: Blender) {
+        if state::cached(|s| &mut s.blender, blend) {
+            return;
+        }
+        state::update(|s| s.som_known -= state::SOM_DERIVED);
         let mut blend = blend.into_inner();
         if blend != 0 {
             blend |= SOM_BLENDING;
@@ -1352,6 +1663,10 @@ pub trait RdpQModes: crate::sealed::Sealed {
     #[doc = "Enable or disable fog\n\n This function enables fog on RDP. Fog on RDP is simulated in the\n following way:\n\n  * The T&L pipeline must calculate a depth information for each\n    vertex of the primitive and put it into the alpha channel of\n    the per-vertex color. This is outside of the scope of rdpq,\n    so rdpq assumes that this has already been done when\n    `rdpq_mode_fog` is called.\n  * The RDP blender unit is programmed to modulate a \"fog color\"\n    with the polygon pixel, using SHADE_ALPHA as interpolation\n    factor. Since SHADE_ALPHA contains a depth information, the\n    farther the object, the stronger it will assume the fog color.\n\n To enable fog, pass `RDPQ_FOG_STANDARD` to this function, and\n call `rdpq_set_fog_color` to configure the fog color. This is\n the standard fogging formula.\n\n If you want, you can instead build a custom fogging formula\n using `RDPQ_BLENDER`. Notice that rdpq_mode assumes that the formula\n that you set with rdpq_mode_fog does not blend with the background; for\n that, use `rdpq_mode_blender`.\n\n To disable fog, call `rdpq_mode_fog` passing 0.\n\n @note Fogging uses one pass of the blender unit (the first),\n       so this can coexist with a blending formula (`rdpq_mode_blender`)\n       as long as it's a single pass one (created via `RDPQ_BLENDER`).\n       If a two-pass blending formula (`RDPQ_BLENDER2`) was set with\n       `rdpq_mode_blender`, fogging cannot be used.\n\n @param fog            Fog formula created with `RDPQ_BLENDER`,\n                       or 0 to disable.\n\n @see `RDPQ_FOG_STANDARD`\n @see `rdpq_set_fog_color`\n @see `RDPQ_BLENDER`\n @see `rdpq_mode_blender`"]
     #[inline]
     fn mode_fog(&mut self, fog: Blender) {
+        if state::cached(|s| &mut s.fog, fog) {
+            return;
+        }
+        state::update(|s| s.som_known -= state::SOM_DERIVED);
         let mut fog = fog.into_inner();
         if fog != 0 {
             fog |= SOM_BLENDING;
@@ -1463,6 +1778,7 @@ impl<'s, 'r> Drop for ModeFrame<'s, 'r> {
     #[inline]
     fn drop(&mut self) {
         unsafe { rdpq_mode_pop() }
+        state::mode_pop();
     }
 }
 
@@ -1489,6 +1805,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Upload a sprite to TMEM, making it ready for drawing\n\n This function will upload a sprite to TMEM, making it ready for drawing.\n It is similar to `rdpq_tex_upload` which can be used for any surface, but\n it builds upon it with sprite-specific features:\n\n  * If the sprite contains mipmaps, the whole mipmap chain is uploaded to TMEM\n    as well. Moreover, mipmaps are automatically enabled in the render mode\n    (via `rdpq_mode_mipmap`).\n  * If the sprite contains a palette, it is uploaded to TMEM as well, and the\n    palette is also activated in the render mode (via `rdpq_mode_tlut`).\n  * If the sprite contains pre-configured texture parameters (eg: mirroring,\n    wrapping, translation, etc), they are automatically configured in the\n    RDP for drawing (on the specified RDP tile descriptor).\n\n After calling this function, the specified tile descriptor will be ready\n to be used in drawing primitives like `rdpq_triangle` or `rdpq_texture_rectangle`.\n\n This function is meant for sprites that can be loaded in full into TMEM; it\n will assert if the sprite does not fit TMEM. For larger sprites, either\n use `rdpq_sprite_blit` to directly draw then (handling partial uploads transparently),\n or use `rdpq_tex_upload_sub` to manually upload a smaller portion of the sprite.\n\n To load multiple sprites in TMEM at once (for instance, for multitexturing),\n you can manually specify the @p parms->tmem_addr for the second sprite, or\n call `rdpq_tex_multi_begin` / `rdpq_tex_multi_end` around multiple calls to\n `rdpq_sprite_upload`. For instance:\n\n @code{.c}\n      // Load multiple sprites in TMEM, with auto-TMEM allocation.\n      rdpq_tex_multi_begin();\n      rdpq_sprite_upload(TILE0, sprite0, NULL);\n      rdpq_sprite_upload(TILE1, sprite1, NULL);\n      rdpq_tex_multi_end();\n @endcode\n\n To speed up loading of a sprite, you can record the loading sequence in\n a rspq block and replay it any time later. For instance:\n\n @code{.c}\n      sprite_t *hero = sprite_load(\"rom:/hero.sprite\");\n\n      // Record the loading sequence in a rspq block\n      rspq_block_begin();\n          rdpq_sprite_upload(TILE0, hero, NULL);\n      rspq_block_t *hero_load = rspq_block_end();\n\n      // Later, load the sprite\n      rspq_block_run(hero_load);\n\n      // Remember to free the block when you don't need it anymore\n      rspq_wait();     // wait until RSP is idle\n      rspq_block_free(hero_load);\n      sprite_free(hero);\n @endcode\n\n @param tile      Tile descriptor that will be initialized with this sprite\n @param sprite    Sprite to upload\n @param parms     Texture upload parameters to use\n @return          Number of bytes used in TMEM for this sprite (excluding palette)\n\n @see `rdpq_tex_upload`\n @see `rdpq_tex_upload_sub`\n @see `rdpq_sprite_blit`"]
     #[inline]
     pub fn sprite_upload(&mut self, tile: Tile, sprite: &Sprite, parms: Option<&TexParms>) -> u32 {
+        state::forget_all();
         unsafe {
             crate::sys::rdpq_sprite::rdpq_sprite_upload(
                 tile as _,
@@ -1500,6 +1817,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Blit a sprite to the active framebuffer\n\n This function will perform a blit of a sprite to the active framebuffer,\n with several features like source rectangle selection, scaling, rotation, etc.\n\n The function is similar to `rdpq_tex_blit`, but it works on a sprite rather than\n a generic surface. In addition to the standard features of `rdpq_tex_blit`,\n it will also handle sprite-specific features:\n\n  * If the sprite contains a palette, it is uploaded to TMEM as well, and the\n    palette is also activated in the render mode (via `rdpq_mode_tlut`).\n  * If the sprite is optimized (via mksprite --optimize), the upload function\n    will be faster.\n\n Just like `rdpq_tex_blit`, this function is designed to work with sprites of\n arbitrary sizes; those that won't fit in TMEM will be automatically split\n in multiple chunks to perform the requested operation.\n\n Please refer to `rdpq_tex_blit` for a full overview of the features.\n\n @param sprite    Sprite to blit\n @param x0        X coordinate on the framebuffer where to draw the surface\n @param y0        Y coordinate on the framebuffer where to draw the surface\n @param parms     Parameters for the blit operation (or NULL for default)"]
     #[inline]
     pub fn sprite_blit(&mut self, sprite: &Sprite, x0: f32, y0: f32, parms: Option<&BlitParms>) {
+        state::forget_all();
         unsafe {
             crate::sys::rdpq_sprite::rdpq_sprite_blit(
                 sprite.as_raw(),
@@ -1515,6 +1833,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Load a texture into TMEM\n\n This function helps loading a texture into TMEM, which normally involves:\n\n   * Configuring a tile descriptor (via `rdpq_set_tile`)\n   * Setting the source texture image (via `rdpq_set_texture_image`)\n   * Loading the texture (via `rdpq_load_tile` or `rdpq_load_block`)\n\n After calling this function, the specified tile descriptor will be ready\n to be used in drawing primitives like `rdpq_triangle` or `rdpq_texture_rectangle`.\n\n If the texture uses a palette (`FMT_CI8` or `FMT_CI4`), the tile descriptor\n will be by default pointing to palette 0. In the case of `FMT_CI4`, this\n might not be the correct palette; to specify a different palette number,\n add .palette = X to the tex parms. Before drawing a texture with palette,\n remember to call `rdpq_mode_tlut` to activate palette mode.\n\n If you want to load a portion of a texture rather than the full texture,\n use `rdpq_tex_upload_sub`, or alternatively create a sub-surface using\n `surface_make_sub` and pass it to `rdpq_tex_upload`. See `rdpq_tex_upload_sub`\n for an example of both techniques.\n\n @param tile       Tile descriptor that will be initialized with this texture\n @param tex        Surface containing the texture to load\n @param parms      All optional parameters on where to load the texture and how to sample it. Refer to `rdpq_texparms_t` for more information.\n @return           Number of bytes used in TMEM for this texture\n\n @see `rdpq_tex_upload_sub`\n @see `surface_make_sub`"]
     #[inline]
     pub fn tex_upload(&mut self, tile: Tile, tex: &Surface, parms: Option<&TexParms>) -> u32 {
+        state::forget_tiles();
         unsafe {
             rdpq_tex_upload(
                 tile as _,
@@ -1535,6 +1854,7 @@ impl<'r> RdpQ<'r> {
         s1: i32,
         t1: i32,
     ) -> u32 {
+        state::forget_tiles();
         unsafe {
             rdpq_tex_upload_sub(
                 tile as _,
@@ -1550,6 +1870,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Load one or more palettes into TMEM\n\n This function allows to load one or more palettes into TMEM.\n\n When using palettes, the upper half of TMEM is allocated to them. There is room\n for 256 colors in total, which allows for one palette for a CI8 texture, or up\n to 16 palettes for CI4 textures.\n\n @param tlut          Pointer to the first color entry to load (must be 8-byte aligned)\n @param color_idx     Index of the first color entry in TMEM (0-255)\n @param num_colors    Number of color entries to load (1-256)"]
     #[inline]
     pub fn tex_upload_tlut(&mut self, tlut: &[u16], color_idx: i32) {
+        state::forget_tiles();
         unsafe { rdpq_tex_upload_tlut(tlut.as_ptr() as _, color_idx, tlut.len() as _) }
     }
     #[doc = "Reuse a portion of the previously uploaded texture to TMEM\n\n When a texture has been uploaded, its possible to reuse it for multiple tiles\n without increasing TMEM usage. This function provides a way to achieve this while also\n configuring your own texture parameters for the reused texture.\n\n This sub-variant also allows to specify what part of the uploaded texture must be reused.\n For example, after uploading a 64x64 texture (or a 64x64 sub texture of a larger surface),\n you can reuse an existing portion of it, like (16,16)-(48,48) or (0,0)-(8,32).\n Restrictions of rdpq_texparms_t apply just when reusing just as well as for uploading a texture.\n\n Sub-rectangle must be within the bounds of the texture reused and be 8-byte aligned,\n not all starting positions are valid for different formats.\n\n Starting horizontal position s0 must be 8-byte aligned, meaning for different image formats\n you can use TEX_FORMAT_BYTES2PIX(fmt, bytes) with bytes being in multiples of 8.\n Starting vertical position t0 must be in multiples of 2 pixels due to TMEM arrangement.\n\n Leaving parms to NULL will copy the previous' texture texparms.\n\n NOTE: This function must be executed in a multi-upload block right after the reused texture has been\n uploaded.\n\n @param tile       Tile descriptor that will be initialized with reused texture\n @param parms      All optional parameters on how to sample reused texture. Refer to `rdpq_texparms_t` for more information.\n @param s0         Top-left X coordinate of the rectangle to reuse\n @param t0         Top-left Y coordinate of the rectangle to reuse\n @param s1         Bottom-right *exclusive* X coordinate of the rectangle\n @param t1         Bottom-right *exclusive* Y coordinate of the rectangle\n @return int       Number of bytes used in TMEM for this texture (always 0)"]
@@ -1563,6 +1884,7 @@ impl<'r> RdpQ<'r> {
         s1: i32,
         t1: i32,
     ) -> u32 {
+        state::forget_tiles();
         unsafe {
             rdpq_tex_reuse_sub(
                 tile as _,
@@ -1577,6 +1899,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Reuse the previously uploaded texture to TMEM\n\n When a texture has been uploaded, its possible to reuse it for multiple tiles\n without increasing TMEM usage. This function provides a way to achieve this while also\n configuring your own texture parameters for the reused texture.\n\n This full-variant will use the whole texture that was previously uploaded.\n Leaving parms to NULL will copy the previous' texture texparms.\n\n NOTE: This function must be executed in a multi-upload block right after the reused texture has been\n uploaded.\n\n @param tile       Tile descriptor that will be initialized with reused texture\n @param parms      All optional parameters on how to sample reused texture. Refer to `rdpq_texparms_t` for more information.\n @return int       Number of bytes used in TMEM for this texture (always 0)"]
     #[inline]
     pub fn tex_reuse(&mut self, tile: Tile, parms: Option<&TexParms>) -> u32 {
+        state::forget_tiles();
         unsafe {
             rdpq_tex_reuse(
                 tile as _,
@@ -1594,6 +1917,7 @@ impl<'r> RdpQ<'r> {
     #[doc = "Blit a surface to the active framebuffer\n\n This is the highest level function for drawing an arbitrary-sized surface\n to the screen, possibly scaling and rotating it.\n\n It handles all the required steps to blit the entire contents of a surface\n to the framebuffer, that is:\n\n   * Logically split the surface in chunks that fit the TMEM\n   * Calculate an appropriate scaling factor for each chunk\n   * Load each chunk into TMEM (via `rdpq_tex_upload`)\n   * Draw each chunk to the framebuffer (via `rdpq_texture_rectangle` or `rdpq_triangle`)\n\n Note that this function only performs the actual blits, it does not\n configure the rendering mode or handle palettes. Before calling this\n function, make sure to configure the render mode via\n `rdpq_set_mode_standard` (or `rdpq_set_mode_copy` if no scaling and pixel\n format conversion is required). If the surface uses a palette, you also\n need to load the palette using `rdpq_tex_upload_tlut`.\n\n This function is able to perform many different complex transformations. The\n implementation has been tuned to try to be as fast as possible for simple\n blits, but it scales up nicely for more complex operations.\n\n The parameters that describe the transformations to perform are passed in\n the @p parms structure. The structure contains a lot of fields, but it has\n been designed so that most of them can be simply initalized to zero to\n disable advanced behaviors (and thus simply left unmentioned in an inline\n initialization).\n\n For instance, this blits a large image to the screen, aligning it to the\n top-left corner (eg: a splashscreen).\n\n @code{.c}\n     rdpq_tex_blit(splashscreen, 0, 0, NULL);\n @endcode\n\n This is the same, but the image will be centered on the screen. To do this,\n we specify the center of the screen as position, and then we set the hotspost\n of the image (\"cx\" and \"cy\" fields) to its center:\n\n @code{.c}\n      rdpq_tex_blit(splashscreen, 320/2, 160/2, &(rdpq_blitparms_t){\n          .cx = splashscreen->width / 2,\n          .cy = splashscreen->height / 2,\n      });\n @endcode\n\n This examples scales a 64x64 image to 256x256, putting its center near the\n top-left of the screen (so part of resulting image will be offscreen):\n\n @code{.c}\n      rdpq_tex_blit(splashscreen, 20, 20, &(rdpq_blitparms_t){\n          .cx = splashscreen->width / 2, .cy = splashscreen->height / 2,\n          .scale_x = 4.0f, .scale_y = 4.0f,\n      });\n @endcode\n\n This example assumes that the surface is a spritemap with frames of size\n 32x32. It selects the sprite at row 4, column 2, and draws it centered\n at position 100,100 on the screen applying a rotation of 45 degrees around its center:\n\n @code{.c}\n     rdpq_tex_blit(splashscreen, 100, 100, &(rdpq_blitparms_t){\n          .s0 = 32*2, .t0 = 32*4,\n          .width = 32, .height = 32,\n          .cx = 16, .cy = 16,\n          .theta = M_PI/4,\n     });\n @endcode\n\n @param surf           Surface to draw\n @param x0             X coordinate on the framebuffer where to draw the surface\n @param y0             Y coordinate on the framebuffer where to draw the surface\n @param parms          Parameters for the blit operation (or NULL for default)"]
     #[inline]
     pub fn blit(&mut self, surf: &Surface, x0: f32, y0: f32, parms: Option<&BlitParms>) {
+        state::forget_tiles();
         unsafe {
             rdpq_tex_blit(
                 &surf.0,
@@ -1634,6 +1958,7 @@ impl<'s, 'r> TexMulti<'s, 'r> {
     #[doc = "Finish a multi-texture upload\n\n This function finishes a multi-texture upload. See `rdpq_tex_multi_begin`\n for more information.\n\n @returns The number of bytes used in TMEM for this multi-texture upload\n\n @see `rdpq_tex_multi_begin`."]
     #[inline]
     pub fn end(self) -> u32 {
+        state::forget_tiles();
         unsafe { rdpq_tex_multi_end() as _ }
     }
 }
@@ -1793,6 +2118,104 @@ impl<'r> RdpQ<'r> {
     }
 }
 
+#[doc = "A vertex type whose memory layout matches a triangle format.\n\n The vertex types in [`tri`] implement this trait for each of the predefined\n formats, so that triangles can be drawn without manually packing the\n components into arrays.\n\n # Safety\n\n The type must be `#[repr(C)]` and made only of `f32` values, laid out as\n described by [`TriVertex::Offsets`]."]
+pub unsafe trait TriVertex: Copy {
+    #[doc = "Layout of the components within the vertex"]
+    type Offsets: TriOffsets;
+}
+
+#[doc = "How a sequence of vertices is assembled into triangles"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Topology {
+    #[doc = "Each group of three vertices is a separate triangle"]
+    #[default]
+    List,
+    #[doc = "Each vertex after the first two forms a triangle with the two previous ones"]
+    Strip,
+    #[doc = "Each vertex after the first two forms a triangle with the previous one and the first one"]
+    Fan,
+}
+
+impl Topology {
+    #[doc = "Number of triangles formed by the given number of vertices"]
+    #[inline]
+    pub const fn triangle_count(self, vertices: usize) -> usize {
+        match self {
+            Self::List => vertices / 3,
+            Self::Strip | Self::Fan => vertices.saturating_sub(2),
+        }
+    }
+    #[doc = "Positions (within the vertex sequence) of the vertices of a triangle.\n\n Strip triangles are returned with a consistent winding order."]
+    #[inline]
+    pub const fn triangle(self, idx: usize) -> [usize; 3] {
+        match self {
+            Self::List => [idx * 3, idx * 3 + 1, idx * 3 + 2],
+            Self::Strip if idx.is_multiple_of(2) => [idx, idx + 1, idx + 2],
+            Self::Strip => [idx + 1, idx, idx + 2],
+            Self::Fan => [0, idx + 1, idx + 2],
+        }
+    }
+    #[doc = "Vertex indices of each triangle formed by a sequence of indices.\n\n Extra indices that do not form a whole triangle are ignored."]
+    #[inline]
+    pub fn indexed(self, indices: &[u16]) -> impl Iterator<Item = [u16; 3]> + '_ {
+        (0..self.triangle_count(indices.len()))
+            .map(move |idx| self.triangle(idx).map(|i| indices[i]))
+    }
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Draw a triangle with typed vertices (RDP command: TRI_*)\n\n This is the same as [`RdpQ::triangle`], but the vertices are provided as\n [`TriVertex`] structures (see the types in [`tri`])."]
+    #[inline]
+    pub fn triangle_vertices<V: TriVertex>(
+        &mut self,
+        fmt: &TriFormat<V::Offsets>,
+        v1: &V,
+        v2: &V,
+        v3: &V,
+    ) {
+        const {
+            assert!(
+                size_of::<V>() >= TriFormat::<V::Offsets>::minimum_array_size() * size_of::<f32>()
+            );
+        }
+        unsafe {
+            crate::sys::rdpq_tri::rdpq_triangle(
+                fmt as *const _ as _,
+                v1 as *const V as *const f32,
+                v2 as *const V as *const f32,
+                v3 as *const V as *const f32,
+            )
+        }
+    }
+    #[doc = "Draw a sequence of triangles (RDP command: TRI_*)\n\n The vertices are assembled into triangles according to the topology.\n Extra vertices that do not form a whole triangle are ignored."]
+    #[inline]
+    pub fn triangles<V: TriVertex>(
+        &mut self,
+        fmt: &TriFormat<V::Offsets>,
+        topology: Topology,
+        vertices: &[V],
+    ) {
+        for idx in 0..topology.triangle_count(vertices.len()) {
+            let [a, b, c] = topology.triangle(idx);
+            self.triangle_vertices(fmt, &vertices[a], &vertices[b], &vertices[c]);
+        }
+    }
+    #[doc = "Draw a sequence of indexed triangles (RDP command: TRI_*)\n\n The indices are assembled into triangles according to the topology, and\n each index refers to a vertex in `vertices`. This allows to share vertices\n among triangles (for instance, in a software-transformed mesh).\n\n Panics if an index is out of bounds."]
+    #[inline]
+    pub fn triangles_indexed<V: TriVertex>(
+        &mut self,
+        fmt: &TriFormat<V::Offsets>,
+        topology: Topology,
+        vertices: &[V],
+        indices: &[u16],
+    ) {
+        for [a, b, c] in topology.indexed(indices) {
+            let [a, b, c] = [a, b, c].map(|i| &vertices[i as usize]);
+            self.triangle_vertices(fmt, a, b, c);
+        }
+    }
+}
+
 pub mod tri {
     pub struct Offsets<const POS: u32, const SHADE: i32, const TEX: i32, const Z: i32>(());
 
@@ -1821,6 +2244,42 @@ pub mod tri {
     pub type ZBufTex = Offsets<0, -1, 3, 2>;
     #[doc = "Format descriptor for a z-buffered, shaded, textured triangle.\n\n Vertex array format: `(float){X, Y, Z, R, G, B, A, S, T, INV_W}` (10 floats)"]
     pub type ZBufShadeTex = Offsets<0, 3, 7, 2>;
+
+    macro_rules! vertex {
+        ($(#[doc = $doc:expr] $name:ident: $fmt:ident { $($field:ident: $ty:ty),* })*) => {
+            $(
+                #[doc = $doc]
+                #[repr(C)]
+                #[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+                pub struct $name {
+                    $(pub $field: $ty),*
+                }
+
+                unsafe impl super::TriVertex for $name {
+                    type Offsets = $fmt;
+                }
+            )*
+        };
+    }
+
+    vertex! {
+        #[doc = "Vertex of a solid-filled triangle (see [`Fill`])"]
+        FillVertex: Fill { pos: [f32; 2] }
+        #[doc = "Vertex of a shaded triangle (see [`Shade`]).\n\n Color components are in the 0..1 range."]
+        ShadeVertex: Shade { pos: [f32; 2], color: [f32; 4] }
+        #[doc = "Vertex of a textured triangle (see [`Tex`])"]
+        TexVertex: Tex { pos: [f32; 2], st: [f32; 2], inv_w: f32 }
+        #[doc = "Vertex of a shaded, textured triangle (see [`ShadeTex`]).\n\n Color components are in the 0..1 range."]
+        ShadeTexVertex: ShadeTex { pos: [f32; 2], color: [f32; 4], st: [f32; 2], inv_w: f32 }
+        #[doc = "Vertex of a solid-filled, z-buffered triangle (see [`ZBuf`])"]
+        ZBufVertex: ZBuf { pos: [f32; 2], z: f32 }
+        #[doc = "Vertex of a z-buffered, shaded triangle (see [`ZBufShade`]).\n\n Color components are in the 0..1 range."]
+        ZBufShadeVertex: ZBufShade { pos: [f32; 2], z: f32, color: [f32; 4] }
+        #[doc = "Vertex of a z-buffered, textured triangle (see [`ZBufTex`])"]
+        ZBufTexVertex: ZBufTex { pos: [f32; 2], z: f32, st: [f32; 2], inv_w: f32 }
+        #[doc = "Vertex of a z-buffered, shaded, textured triangle (see [`ZBufShadeTex`]).\n\n Color components are in the 0..1 range."]
+        ZBufShadeTexVertex: ZBufShadeTex { pos: [f32; 2], z: f32, color: [f32; 4], st: [f32; 2], inv_w: f32 }
+    }
 }
 
 bitflags::bitflags! {
@@ -2069,16 +2528,57 @@ bitflags::bitflags! {
 }
 
 macro_rules! impl_macro_enums {
-    (#[repr($repr:ident)] $($item:item)*) => {
+    (#[repr($repr:ident)] $(pub enum $name:ident { $($variant:ident = $value:expr),* $(,)? })*) => {
         $(
             #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
             #[doc(hidden)]
             #[repr($repr)]
-            $item
+            pub enum $name {
+                $($variant = $value),*
+            }
+
+            impl $name {
+                #[doc = "All the variants of this input"]
+                pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+                #[doc = "Convert a raw input selector back into the symbolic input, if valid"]
+                #[inline]
+                pub const fn from_raw(raw: $repr) -> Option<Self> {
+                    let mut i = 0;
+                    while i < Self::ALL.len() {
+                        if Self::ALL[i] as $repr == raw {
+                            return Some(Self::ALL[i]);
+                        }
+                        i += 1;
+                    }
+                    None
+                }
+                #[doc = "Name of the input, using the same syntax accepted by the macros"]
+                #[inline]
+                pub const fn name(self) -> &'static str {
+                    match self {
+                        $(Self::$variant => _macro_input_name(stringify!($variant))),*
+                    }
+                }
+            }
+
+            impl ::core::fmt::Display for $name {
+                #[inline]
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    f.write_str(self.name())
+                }
+            }
         )*
     };
 }
 
+const fn _macro_input_name(name: &'static str) -> &'static str {
+    match name.as_bytes() {
+        b"Zero" => "0",
+        b"One" => "1",
+        _ => name,
+    }
+}
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! _rdpq_mode_input {
@@ -2093,7 +2593,7 @@ macro_rules! _rdpq_mode_input {
     };
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[repr(transparent)]
 pub struct Combiner(pub(crate) u64);
 
@@ -2329,7 +2829,7 @@ macro_rules! rdpq_combiner {
             let _aa1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb1AlphaAddSub>::$aa1) as u64;
             let _ab1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb1AlphaAddSub>::$ab1) as u64;
             let _ac1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb1AlphaMul>::$ac1) as u64;
-            let _ad1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb1AlphaAddSub>::$rd1) as u64;
+            let _ad1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb1AlphaAddSub>::$ad1) as u64;
             let _comb = (_ra1 << 52)
                 | (_rb1 << 28)
                 | (_rc1 << 47)
@@ -2350,7 +2850,7 @@ macro_rules! rdpq_combiner {
         }
     };
     (($ra1:tt, $rb1:tt, $rc1:tt, $rd1:tt $(,)?) , ($aa1:tt, $ab1:tt, $ac1:tt, $ad1:tt $(,)?) ,
-     ($ca2:tt, $cb2:tt, $cc2:tt, $cd2:tt $(,)?) , ($aa2:tt, $ab2:tt, $ac2:tt, $ad2:tt $(,)?) $(,)?) => {
+     ($ra2:tt, $rb2:tt, $rc2:tt, $rd2:tt $(,)?) , ($aa2:tt, $ab2:tt, $ac2:tt, $ad2:tt $(,)?) $(,)?) => {
         const {
             let _ra1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2ARGBSubA>::$ra1) as u64;
             let _rb1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2ARGBSubB>::$rb1) as u64;
@@ -2359,7 +2859,7 @@ macro_rules! rdpq_combiner {
             let _aa1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2AAlphaAddSub>::$aa1) as u64;
             let _ab1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2AAlphaAddSub>::$ab1) as u64;
             let _ac1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2AAlphaMul>::$ac1) as u64;
-            let _ad1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2AAlphaAddSub>::$rd1) as u64;
+            let _ad1 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2AAlphaAddSub>::$ad1) as u64;
             let _ra2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BRGBSubA>::$ra2) as u64;
             let _rb2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BRGBSubB>::$rb2) as u64;
             let _rc2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BRGBMul>::$rc2) as u64;
@@ -2367,7 +2867,7 @@ macro_rules! rdpq_combiner {
             let _aa2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BAlphaAddSub>::$aa2) as u64;
             let _ab2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BAlphaAddSub>::$ab2) as u64;
             let _ac2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BAlphaMul>::$ac2) as u64;
-            let _ad2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BAlphaAddSub>::$rd2) as u64;
+            let _ad2 = $crate::_rdpq_mode_input!(<$crate::rdpq::Comb2BAlphaAddSub>::$ad2) as u64;
             let _comb = $crate::sys::rdpq_macros::RDPQ_COMBINER_2PASS as u64
                 | (_ra1 << 52)
                 | (_rb1 << 28)
@@ -2396,7 +2896,202 @@ pub const COMBINER_TEX: Combiner = rdpq_combiner!((0, 0, 0, Tex0), (0, 0, 0, Tex
 pub const COMBINER_TEX_FLAT: Combiner = rdpq_combiner!((Tex0, 0, Prim, 0), (Tex0, 0, Prim, 0));
 pub const COMBINER_TEX_SHADE: Combiner = rdpq_combiner!((Tex0, 0, Shade, 0), (Tex0, 0, Shade, 0));
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[doc = "Inputs of one pass of the color combiner, as accepted by [`rdpq_combiner!`].\n\n Each pass computes `(A - B) * C + D` for both the RGB and the alpha channels."]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CombinerPass<RA, RB, RC, RD, AA, AC> {
+    #[doc = "RGB inputs (A, B, C, D)"]
+    pub rgb: (RA, RB, RC, RD),
+    #[doc = "Alpha inputs (A, B, C, D)"]
+    pub alpha: (AA, AA, AC, AA),
+}
+
+pub type Comb1Pass = CombinerPass<
+    Comb1RGBSubA,
+    Comb1RGBSubB,
+    Comb1RGBMul,
+    Comb1RGBAdd,
+    Comb1AlphaAddSub,
+    Comb1AlphaMul,
+>;
+pub type Comb2APass = CombinerPass<
+    Comb2ARGBSubA,
+    Comb2ARGBSubB,
+    Comb2ARGBMul,
+    Comb2ARGBAdd,
+    Comb2AAlphaAddSub,
+    Comb2AAlphaMul,
+>;
+pub type Comb2BPass = CombinerPass<
+    Comb2BRGBSubA,
+    Comb2BRGBSubB,
+    Comb2BRGBMul,
+    Comb2BRGBAdd,
+    Comb2BAlphaAddSub,
+    Comb2BAlphaMul,
+>;
+
+impl<RA, RB, RC, RD, AA, AC> core::fmt::Display for CombinerPass<RA, RB, RC, RD, AA, AC>
+where
+    RA: core::fmt::Display,
+    RB: core::fmt::Display,
+    RC: core::fmt::Display,
+    RD: core::fmt::Display,
+    AA: core::fmt::Display,
+    AC: core::fmt::Display,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (ra, rb, rc, rd) = &self.rgb;
+        let (aa, ab, ac, ad) = &self.alpha;
+        write!(f, "({ra}, {rb}, {rc}, {rd}), ({aa}, {ab}, {ac}, {ad})")
+    }
+}
+
+macro_rules! decode_comb_pass {
+    ($cycle:expr => $ra:ident, $rb:ident, $rc:ident, $rd:ident, $aa:ident, $ac:ident) => {{
+        let c = $cycle;
+        CombinerPass {
+            rgb: (
+                $ra::from_raw(c.rgb_suba as u64)?,
+                $rb::from_raw(c.rgb_subb as u64)?,
+                $rc::from_raw(c.rgb_mul as u64)?,
+                $rd::from_raw(c.rgb_add as u64)?,
+            ),
+            alpha: (
+                $aa::from_raw(c.alpha_suba as u64)?,
+                $aa::from_raw(c.alpha_subb as u64)?,
+                $ac::from_raw(c.alpha_mul as u64)?,
+                $aa::from_raw(c.alpha_add as u64)?,
+            ),
+        }
+    }};
+}
+
+#[doc = "Symbolic inputs of a [`Combiner`], as decoded by [`Combiner::decode`]"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CombinerInputs {
+    #[doc = "One-pass combiner"]
+    OnePass(Comb1Pass),
+    #[doc = "Two-pass combiner"]
+    TwoPass(Comb2APass, Comb2BPass),
+}
+
+impl CombinerInputs {
+    #[doc = "Encode the inputs into a [`Combiner`], exactly like [`rdpq_combiner!`] does"]
+    pub const fn encode(self) -> Combiner {
+        const fn pass(rgb: (u64, u64, u64, u64), alpha: (u64, u64, u64, u64), cycle: u32) -> u64 {
+            let (ra, rb, rc, rd) = rgb;
+            let (aa, ab, ac, ad) = alpha;
+            if cycle == 0 {
+                (ra << 52)
+                    | (rb << 28)
+                    | (rc << 47)
+                    | (rd << 15)
+                    | (aa << 44)
+                    | (ab << 12)
+                    | (ac << 41)
+                    | (ad << 9)
+            } else {
+                (ra << 37)
+                    | (rb << 24)
+                    | (rc << 32)
+                    | (rd << 6)
+                    | (aa << 21)
+                    | (ab << 3)
+                    | (ac << 18)
+                    | ad
+            }
+        }
+        match self {
+            Self::OnePass(CombinerPass {
+                rgb: (ra, rb, rc, rd),
+                alpha: (aa, ab, ac, ad),
+            }) => {
+                let rgb = (ra as u64, rb as u64, rc as u64, rd as u64);
+                let alpha = (aa as u64, ab as u64, ac as u64, ad as u64);
+                Combiner(pass(rgb, alpha, 0) | pass(rgb, alpha, 1))
+            }
+            Self::TwoPass(
+                CombinerPass {
+                    rgb: (ra1, rb1, rc1, rd1),
+                    alpha: (aa1, ab1, ac1, ad1),
+                },
+                CombinerPass {
+                    rgb: (ra2, rb2, rc2, rd2),
+                    alpha: (aa2, ab2, ac2, ad2),
+                },
+            ) => Combiner(
+                RDPQ_COMBINER_2PASS as u64
+                    | pass(
+                        (ra1 as u64, rb1 as u64, rc1 as u64, rd1 as u64),
+                        (aa1 as u64, ab1 as u64, ac1 as u64, ad1 as u64),
+                        0,
+                    )
+                    | pass(
+                        (ra2 as u64, rb2 as u64, rc2 as u64, rd2 as u64),
+                        (aa2 as u64, ab2 as u64, ac2 as u64, ad2 as u64),
+                        1,
+                    ),
+            ),
+        }
+    }
+}
+
+impl From<CombinerInputs> for Combiner {
+    #[inline]
+    fn from(value: CombinerInputs) -> Self {
+        value.encode()
+    }
+}
+
+impl core::fmt::Display for CombinerInputs {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::OnePass(pass) => write!(f, "({pass})"),
+            Self::TwoPass(pass0, pass1) => write!(f, "({pass0}, {pass1})"),
+        }
+    }
+}
+
+impl Combiner {
+    #[doc = "Check whether the combiner uses two passes"]
+    #[inline]
+    pub const fn is_two_pass(self) -> bool {
+        self.0 & RDPQ_COMBINER_2PASS as u64 != 0
+    }
+    #[doc = "Decode the combiner back into its symbolic inputs.\n\n Returns `None` if the combiner contains input selectors that cannot be\n expressed with [`rdpq_combiner!`] (for instance, a one-pass combiner whose\n two hardware cycles differ)."]
+    pub fn decode(self) -> Option<CombinerInputs> {
+        let [c0, c1] = self.cycles();
+        if self.is_two_pass() {
+            Some(CombinerInputs::TwoPass(
+                decode_comb_pass!(c0 => Comb2ARGBSubA, Comb2ARGBSubB, Comb2ARGBMul, Comb2ARGBAdd, Comb2AAlphaAddSub, Comb2AAlphaMul),
+                decode_comb_pass!(c1 => Comb2BRGBSubA, Comb2BRGBSubB, Comb2BRGBMul, Comb2BRGBAdd, Comb2BAlphaAddSub, Comb2BAlphaMul),
+            ))
+        } else if c0 == c1 {
+            Some(CombinerInputs::OnePass(
+                decode_comb_pass!(c0 => Comb1RGBSubA, Comb1RGBSubB, Comb1RGBMul, Comb1RGBAdd, Comb1AlphaAddSub, Comb1AlphaMul),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl core::fmt::Display for Combiner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.decode() {
+            Some(inputs) => inputs.fmt(f),
+            None => write!(f, "{:#018x}", self.0),
+        }
+    }
+}
+
+impl core::fmt::Debug for Combiner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Combiner{self}")
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[repr(transparent)]
 pub struct Blender(pub(crate) u32);
 
@@ -2562,3 +3257,486 @@ pub const BLENDER_MULTIPLY: Blender = rdpq_blender!((InRGB, InAlpha, MemoryRGB,
 pub const BLENDER_MULTIPLY_CONST: Blender =
     rdpq_blender!((InRGB, FogAlpha, MemoryRGB, InvMuxAlpha));
 pub const BLENDER_ADDITIVE: Blender = rdpq_blender!((InRGB, InAlpha, MemoryRGB, One));
+
+#[doc = "Inputs of one pass of the blender, as accepted by [`rdpq_blender!`].\n\n Each pass computes `(P * A + M * B) / (A + B)`."]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BlenderPass<P, A, B> {
+    pub p: P,
+    pub a: A,
+    pub m: P,
+    pub b: B,
+}
+
+pub type Blend1Pass = BlenderPass<SOMBlend1PM, SOMBlend1A, SOMBlend1B>;
+pub type Blend2BPass = BlenderPass<SOMBlend2BPM, SOMBlend2BA, SOMBlend2BB>;
+
+impl<P: core::fmt::Display, A: core::fmt::Display, B: core::fmt::Display> core::fmt::Display
+    for BlenderPass<P, A, B>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "({}, {}, {}, {})", self.p, self.a, self.m, self.b)
+    }
+}
+
+#[doc = "Symbolic inputs of a [`Blender`], as decoded by [`Blender::decode`]"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlenderInputs {
+    #[doc = "One-pass blender"]
+    OnePass(Blend1Pass),
+    #[doc = "Two-pass blender"]
+    TwoPass(Blend1Pass, Blend2BPass),
+}
+
+impl BlenderInputs {
+    #[doc = "Encode the inputs into a [`Blender`], exactly like [`rdpq_blender!`] does"]
+    pub const fn encode(self) -> Blender {
+        match self {
+            Self::OnePass(BlenderPass { p, a, m, b }) => {
+                let blend = ((p as u32) << 30)
+                    | ((a as u32) << 26)
+                    | ((m as u32) << 22)
+                    | ((b as u32) << 18);
+                Blender(blend | (blend >> 2) | p.extra() | m.extra() | b.extra())
+            }
+            Self::TwoPass(
+                BlenderPass {
+                    p: p1,
+                    a: a1,
+                    m: m1,
+                    b: b1,
+                },
+                BlenderPass {
+                    p: p2,
+                    a: a2,
+                    m: m2,
+                    b: b2,
+                },
+            ) => Blender(
+                SOMX_BLEND_2PASS
+                    | ((p1 as u32) << 30)
+                    | ((a1 as u32) << 26)
+                    | ((m1 as u32) << 22)
+                    | ((b1 as u32) << 18)
+                    | ((p2 as u32) << 28)
+                    | ((a2 as u32) << 24)
+                    | ((m2 as u32) << 20)
+                    | ((b2 as u32) << 16)
+                    | p1.extra()
+                    | m1.extra()
+                    | b1.extra()
+                    | p2.extra()
+                    | m2.extra()
+                    | b2.extra(),
+            ),
+        }
+    }
+}
+
+impl From<BlenderInputs> for Blender {
+    #[inline]
+    fn from(value: BlenderInputs) -> Self {
+        value.encode()
+    }
+}
+
+impl core::fmt::Display for BlenderInputs {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::OnePass(pass) => write!(f, "({pass})"),
+            Self::TwoPass(pass0, pass1) => write!(f, "({pass0}, {pass1})"),
+        }
+    }
+}
+
+impl Blender {
+    #[doc = "Check whether the blender uses two passes"]
+    #[inline]
+    pub const fn is_two_pass(self) -> bool {
+        self.0 & SOMX_BLEND_2PASS != 0
+    }
+    #[doc = "Decode the blender back into its symbolic inputs.\n\n Returns `None` if the blender contains inputs that cannot be expressed\n with [`rdpq_blender!`] (for instance, a one-pass blender whose two\n hardware cycles differ)."]
+    pub fn decode(self) -> Option<BlenderInputs> {
+        let field = |shift: u32| (self.0 >> shift) & 3;
+        let pass0 = BlenderPass {
+            p: SOMBlend1PM::from_raw(field(30))?,
+            a: SOMBlend1A::from_raw(field(26))?,
+            m: SOMBlend1PM::from_raw(field(22))?,
+            b: SOMBlend1B::from_raw(field(18))?,
+        };
+        if self.is_two_pass() {
+            Some(BlenderInputs::TwoPass(
+                pass0,
+                BlenderPass {
+                    p: SOMBlend2BPM::from_raw(field(28))?,
+                    a: SOMBlend2BA::from_raw(field(24))?,
+                    m: SOMBlend2BPM::from_raw(field(20))?,
+                    b: SOMBlend2BB::from_raw(field(16))?,
+                },
+            ))
+        } else if (self.0 & SOM_BLEND0_MASK & !SOM_READ_ENABLE) >> 2
+            == self.0 & SOM_BLEND1_MASK & !SOM_READ_ENABLE
+        {
+            Some(BlenderInputs::OnePass(pass0))
+        } else {
+            None
+        }
+    }
+}
+
+impl core::fmt::Display for Blender {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.decode() {
+            Some(inputs) => inputs.fmt(f),
+            None => write!(f, "{:#010x}", self.0),
+        }
+    }
+}
+
+impl core::fmt::Debug for Blender {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Blender{self}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc_::{string::ToString, vec::Vec};
+
+    // Set each listed slot of `$pass` to every variant of its input in turn,
+    // leaving the other slots untouched
+    macro_rules! each_input {
+        ($pass:expr => $check:expr; $($($field:tt).+: $ty:ident),* $(,)?) => {$(
+            for &input in $ty::ALL {
+                let mut pass = $pass;
+                pass.$($field).+ = input;
+                ($check)(pass);
+            }
+        )*};
+    }
+
+    #[test]
+    fn macro_enums_from_raw() {
+        macro_rules! check {
+            ($($ty:ident),* $(,)?) => {$(
+                assert!(!$ty::ALL.is_empty());
+                for &input in $ty::ALL {
+                    assert_eq!($ty::from_raw(input as _), Some(input), "{input}");
+                }
+            )*};
+        }
+        check!(
+            Comb1RGBSubA,
+            Comb2ARGBSubA,
+            Comb2BRGBSubA,
+            Comb1RGBSubB,
+            Comb2ARGBSubB,
+            Comb2BRGBSubB,
+            Comb1RGBMul,
+            Comb2ARGBMul,
+            Comb2BRGBMul,
+            Comb1RGBAdd,
+            Comb2ARGBAdd,
+            Comb2BRGBAdd,
+            Comb1AlphaAddSub,
+            Comb2AAlphaAddSub,
+            Comb2BAlphaAddSub,
+            Comb1AlphaMul,
+            Comb2AAlphaMul,
+            Comb2BAlphaMul,
+            SOMBlend1PM,
+            SOMBlend1A,
+            SOMBlend1B,
+            SOMBlend2APM,
+            SOMBlend2AA,
+            SOMBlend2AB,
+            SOMBlend2BPM,
+            SOMBlend2BA,
+            SOMBlend2BB,
+        );
+        assert_eq!(SOMBlend2AB::from_raw(3), None);
+        assert_eq!(Comb1RGBAdd::from_raw(u64::MAX), None);
+    }
+
+    #[test]
+    fn combiner_round_trip_all_inputs() {
+        let check = |inputs: CombinerInputs| {
+            let comb = inputs.encode();
+            assert_eq!(comb.decode(), Some(inputs), "{inputs}");
+            assert_eq!(
+                comb.is_two_pass(),
+                matches!(inputs, CombinerInputs::TwoPass(..))
+            );
+        };
+        let one = Comb1Pass {
+            rgb: (
+                Comb1RGBSubA::Zero,
+                Comb1RGBSubB::Zero,
+                Comb1RGBMul::Zero,
+                Comb1RGBAdd::Zero,
+            ),
+            alpha: (
+                Comb1AlphaAddSub::Zero,
+                Comb1AlphaAddSub::Zero,
+                Comb1AlphaMul::Zero,
+                Comb1AlphaAddSub::Zero,
+            ),
+        };
+        let two_a = Comb2APass {
+            rgb: (
+                Comb2ARGBSubA::Zero,
+                Comb2ARGBSubB::Zero,
+                Comb2ARGBMul::Zero,
+                Comb2ARGBAdd::Zero,
+            ),
+            alpha: (
+                Comb2AAlphaAddSub::Zero,
+                Comb2AAlphaAddSub::Zero,
+                Comb2AAlphaMul::Zero,
+                Comb2AAlphaAddSub::Zero,
+            ),
+        };
+        let two_b = Comb2BPass {
+            rgb: (
+                Comb2BRGBSubA::Zero,
+                Comb2BRGBSubB::Zero,
+                Comb2BRGBMul::Zero,
+                Comb2BRGBAdd::Zero,
+            ),
+            alpha: (
+                Comb2BAlphaAddSub::Zero,
+                Comb2BAlphaAddSub::Zero,
+                Comb2BAlphaMul::Zero,
+                Comb2BAlphaAddSub::Zero,
+            ),
+        };
+        each_input!(one => |pass| check(CombinerInputs::OnePass(pass));
+            rgb.0: Comb1RGBSubA,
+            rgb.1: Comb1RGBSubB,
+            rgb.2: Comb1RGBMul,
+            rgb.3: Comb1RGBAdd,
+            alpha.0: Comb1AlphaAddSub,
+            alpha.1: Comb1AlphaAddSub,
+            alpha.2: Comb1AlphaMul,
+            alpha.3: Comb1AlphaAddSub,
+        );
+        each_input!(two_a => |pass| check(CombinerInputs::TwoPass(pass, two_b));
+            rgb.0: Comb2ARGBSubA,
+            rgb.1: Comb2ARGBSubB,
+            rgb.2: Comb2ARGBMul,
+            rgb.3: Comb2ARGBAdd,
+            alpha.0: Comb2AAlphaAddSub,
+            alpha.1: Comb2AAlphaAddSub,
+            alpha.2: Comb2AAlphaMul,
+            alpha.3: Comb2AAlphaAddSub,
+        );
+        each_input!(two_b => |pass| check(CombinerInputs::TwoPass(two_a, pass));
+            rgb.0: Comb2BRGBSubA,
+            rgb.1: Comb2BRGBSubB,
+            rgb.2: Comb2BRGBMul,
+            rgb.3: Comb2BRGBAdd,
+            alpha.0: Comb2BAlphaAddSub,
+            alpha.1: Comb2BAlphaAddSub,
+            alpha.2: Comb2BAlphaMul,
+            alpha.3: Comb2BAlphaAddSub,
+        );
+    }
+
+    #[test]
+    fn blender_round_trip_all_inputs() {
+        let check = |inputs: BlenderInputs| {
+            let blend = inputs.encode();
+            assert_eq!(blend.decode(), Some(inputs), "{inputs}");
+            assert_eq!(
+                blend.is_two_pass(),
+                matches!(inputs, BlenderInputs::TwoPass(..))
+            );
+        };
+        let one = Blend1Pass {
+            p: SOMBlend1PM::InRGB,
+            a: SOMBlend1A::Zero,
+            m: SOMBlend1PM::InRGB,
+            b: SOMBlend1B::Zero,
+        };
+        let two = Blend2BPass {
+            p: SOMBlend2BPM::Cycle1RGB,
+            a: SOMBlend2BA::Zero,
+            m: SOMBlend2BPM::Cycle1RGB,
+            b: SOMBlend2BB::Zero,
+        };
+        each_input!(one => |pass| check(BlenderInputs::OnePass(pass));
+            p: SOMBlend1PM,
+            a: SOMBlend1A,
+            m: SOMBlend1PM,
+            b: SOMBlend1B,
+        );
+        each_input!(one => |pass| check(BlenderInputs::TwoPass(pass, two));
+            p: SOMBlend1PM,
+            a: SOMBlend1A,
+            m: SOMBlend1PM,
+            b: SOMBlend1B,
+        );
+        each_input!(two => |pass| check(BlenderInputs::TwoPass(one, pass));
+            p: SOMBlend2BPM,
+            a: SOMBlend2BA,
+            m: SOMBlend2BPM,
+            b: SOMBlend2BB,
+        );
+    }
+
+    #[test]
+    fn combiner_round_trip() {
+        let two_pass = rdpq_combiner!(
+            (Tex0, 0, Shade, 0),
+            (0, 0, 0, Tex0),
+            (Combined, 0, Prim, Env),
+            (0, 0, 0, Combined)
+        );
+        for comb in [
+            COMBINER_FLAT,
+            COMBINER_SHADE,
+            COMBINER_TEX,
+            COMBINER_TEX_FLAT,
+            COMBINER_TEX_SHADE,
+            two_pass,
+        ] {
+            let inputs = comb.decode().unwrap();
+            assert_eq!(inputs.encode(), comb);
+            assert_eq!(inputs.encode().decode(), Some(inputs));
+        }
+    }
+
+    #[test]
+    fn topology_expansion() {
+        let expand = |topology: Topology, vertices| {
+            (0..topology.triangle_count(vertices))
+                .map(|idx| topology.triangle(idx))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(expand(Topology::List, 7), [[0, 1, 2], [3, 4, 5]]);
+        // Strips alternate the first two vertices to keep the winding order
+        assert_eq!(
+            expand(Topology::Strip, 5),
+            [[0, 1, 2], [2, 1, 3], [2, 3, 4]]
+        );
+        assert_eq!(expand(Topology::Fan, 5), [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        // Too few vertices for a whole triangle
+        for topology in [Topology::List, Topology::Strip, Topology::Fan] {
+            for vertices in 0..3 {
+                assert_eq!(topology.triangle_count(vertices), 0);
+                assert!(expand(topology, vertices).is_empty());
+            }
+            assert_eq!(topology.triangle_count(3), 1);
+            assert_eq!(expand(topology, 3), [[0, 1, 2]]);
+        }
+    }
+
+    #[test]
+    fn topology_indexed() {
+        let indexed =
+            |topology: Topology, indices: &[u16]| topology.indexed(indices).collect::<Vec<_>>();
+        let quads = [0, 1, 2, 2, 1, 3, 4, 5];
+        assert_eq!(indexed(Topology::List, &quads), [[0, 1, 2], [2, 1, 3]]);
+        assert_eq!(
+            indexed(Topology::Fan, &[7, 3, 4, 5]),
+            [[7, 3, 4], [7, 4, 5]]
+        );
+        // Repeated indices join two strips with degenerate triangles, which are
+        // passed through unchanged
+        assert_eq!(
+            indexed(Topology::Strip, &[0, 1, 2, 3, 3, 4, 4, 5, 6]),
+            [
+                [0, 1, 2],
+                [2, 1, 3],
+                [2, 3, 3],
+                [3, 3, 4],
+                [3, 4, 4],
+                [4, 4, 5],
+                [4, 5, 6]
+            ]
+        );
+        assert_eq!(indexed(Topology::List, &[1, 1, 1]), [[1, 1, 1]]);
+        for topology in [Topology::List, Topology::Strip, Topology::Fan] {
+            assert!(indexed(topology, &[]).is_empty());
+            assert!(indexed(topology, &[9, 8]).is_empty());
+        }
+    }
+
+    #[test]
+    fn combiner_decode() {
+        assert_eq!(
+            COMBINER_TEX_FLAT.decode(),
+            Some(CombinerInputs::OnePass(CombinerPass {
+                rgb: (
+                    Comb1RGBSubA::Tex0,
+                    Comb1RGBSubB::Zero,
+                    Comb1RGBMul::Prim,
+                    Comb1RGBAdd::Zero
+                ),
+                alpha: (
+                    Comb1AlphaAddSub::Tex0,
+                    Comb1AlphaAddSub::Zero,
+                    Comb1AlphaMul::Prim,
+                    Comb1AlphaAddSub::Zero
+                ),
+            }))
+        );
+        assert!(!COMBINER_TEX_FLAT.is_two_pass());
+        assert_eq!(
+            COMBINER_TEX_FLAT.to_string(),
+            "((Tex0, 0, Prim, 0), (Tex0, 0, Prim, 0))"
+        );
+    }
+
+    #[test]
+    fn combiner_decode_mismatched_cycles() {
+        // A one-pass combiner must repeat the same inputs in both cycles
+        let two_pass = rdpq_combiner!(
+            (0, 0, 0, Prim),
+            (0, 0, 0, Prim),
+            (0, 0, 0, Shade),
+            (0, 0, 0, Shade)
+        );
+        let comb = Combiner(two_pass.0 & !(RDPQ_COMBINER_2PASS as u64));
+        assert_eq!(comb.decode(), None);
+        assert_eq!(comb.to_string(), alloc_::format!("{:#018x}", comb.0));
+    }
+
+    #[test]
+    fn blender_round_trip() {
+        let two_pass = rdpq_blender!(
+            (InRGB, ShadeAlpha, FogRGB, InvMuxAlpha),
+            (Cycle1RGB, InAlpha, MemoryRGB, InvMuxAlpha)
+        );
+        for blend in [
+            FOG_STANDARD,
+            BLENDER_MULTIPLY,
+            BLENDER_MULTIPLY_CONST,
+            BLENDER_ADDITIVE,
+            two_pass,
+        ] {
+            let inputs = blend.decode().unwrap();
+            assert_eq!(inputs.encode(), blend);
+            assert_eq!(inputs.encode().decode(), Some(inputs));
+        }
+        assert!(two_pass.is_two_pass());
+    }
+
+    #[test]
+    fn blender_decode() {
+        assert_eq!(
+            BLENDER_MULTIPLY.decode(),
+            Some(BlenderInputs::OnePass(BlenderPass {
+                p: SOMBlend1PM::InRGB,
+                a: SOMBlend1A::InAlpha,
+                m: SOMBlend1PM::MemoryRGB,
+                b: SOMBlend1B::InvMuxAlpha,
+            }))
+        );
+        assert_ne!(BLENDER_MULTIPLY.0 & SOM_READ_ENABLE, 0);
+        assert_eq!(
+            BLENDER_ADDITIVE.to_string(),
+            "((InRGB, InAlpha, MemoryRGB, 1))"
+        );
+    }
+}
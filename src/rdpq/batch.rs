@@ -0,0 +1,230 @@
+use core::{marker::PhantomData, mem::ManuallyDrop};
+
+use alloc_::vec::Vec;
+
+use crate::{graphics::Color, rspq::Block, sprite::Sprite};
+
+use super::{BlitParms, COMBINER_TEX_FLAT, RdpQ, RdpQModes, Tile};
+
+#[doc = "A sprite draw, queued in a [`SpriteBatch`].\n\n The position, source rectangle and transformation follow the same\n conventions of [`RdpQ::sprite_blit`] and [`BlitParms`]."]
+#[derive(Debug, Copy, Clone)]
+pub struct SpriteDraw<'a> {
+    sprite: &'a Sprite,
+    x: f32,
+    y: f32,
+    parms: BlitParms,
+    tint: Option<Color>,
+    layer: i16,
+}
+
+impl<'a> SpriteDraw<'a> {
+    #[doc = "Create a draw of a sprite at the given position\n\n @param sprite    Sprite to draw\n @param x         X coordinate of the transformation center on the screen\n @param y         Y coordinate of the transformation center on the screen"]
+    #[inline]
+    pub const fn new(sprite: &'a Sprite, x: f32, y: f32) -> Self {
+        Self {
+            sprite,
+            x,
+            y,
+            parms: BlitParms::EMPTY,
+            tint: None,
+            layer: 0,
+        }
+    }
+    #[doc = "Blitting parameters (source rectangle, flipping, scaling, rotation, etc.) (default: none)"]
+    #[inline]
+    pub const fn parms(mut self, parms: BlitParms) -> Self {
+        self.parms = parms;
+        self
+    }
+    #[doc = "Color the sprite is multiplied with, via the PRIM register (default: none)"]
+    #[inline]
+    pub const fn tint(mut self, tint: Color) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+    #[doc = "Layer of the draw (default: 0). Draws on lower layers are drawn first, so they appear behind the others"]
+    #[inline]
+    pub const fn layer(mut self, layer: i16) -> Self {
+        self.layer = layer;
+        self
+    }
+    #[inline]
+    pub const fn sprite(&self) -> &'a Sprite {
+        self.sprite
+    }
+
+    #[doc = "Return true if the draw can be done with a single TEXTURE_RECTANGLE,\n reusing the sprite already uploaded in TMEM"]
+    #[inline]
+    fn is_rect(&self) -> bool {
+        let p = &self.parms.0;
+        p.theta == 0.0 && p.nx <= 1 && p.ny <= 1 && !p.filtering && self.sprite.fits_tmem()
+    }
+    #[inline]
+    fn texture_id(&self) -> usize {
+        self.sprite.as_raw() as usize
+    }
+}
+
+#[doc = "A batch of sprite draws.\n\n Drawing many sprites with [`RdpQ::sprite_blit`] uploads each sprite to\n TMEM again for every call. A sprite batch instead collects all the draws\n of a frame, sorts them by layer and then by sprite, and submits them so\n that each sprite is uploaded only once per run of consecutive draws.\n\n Draws whose sprite fits TMEM and that are not rotated, repeated or\n filtered are issued as plain texture rectangles; all others fall back to\n [`RdpQ::sprite_blit`]. Tints are applied by multiplying the texture with\n the PRIM color, which is only changed when it differs from the previous\n draw. The PRIM color is not part of the render mode, so after a batch\n with tinted draws it is left set to the last tint; batches without tints\n do not change it.\n\n The render mode (standard or copy, blending, alpha compare, filtering) is\n left to the caller. Tinting requires the standard mode."]
+#[derive(Debug, Default, Clone)]
+pub struct SpriteBatch<'a> {
+    draws: Vec<SpriteDraw<'a>>,
+}
+
+impl<'a> SpriteBatch<'a> {
+    #[inline]
+    pub const fn new() -> Self {
+        Self { draws: Vec::new() }
+    }
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            draws: Vec::with_capacity(capacity),
+        }
+    }
+    #[doc = "Queue a sprite draw"]
+    #[inline]
+    pub fn push(&mut self, draw: SpriteDraw<'a>) {
+        self.draws.push(draw);
+    }
+    #[doc = "Queue a sprite draw at the given position, with no transformation"]
+    #[inline]
+    pub fn draw(&mut self, sprite: &'a Sprite, x: f32, y: f32) {
+        self.push(SpriteDraw::new(sprite, x, y));
+    }
+    #[doc = "Remove all the queued draws, keeping the allocated memory"]
+    #[inline]
+    pub fn clear(&mut self) {
+        self.draws.clear();
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+    #[inline]
+    pub fn draws(&self) -> &[SpriteDraw<'a>] {
+        &self.draws
+    }
+
+    #[doc = "Sort the queued draws by layer, and then by sprite.\n\n The sort is stable, so draws of the same sprite on the same layer are\n kept in the order they were queued. This is done automatically by\n [`SpriteBatch::submit`]."]
+    #[inline]
+    pub fn sort(&mut self) {
+        self.draws.sort_by_key(|d| (d.layer, d.texture_id()));
+    }
+
+    #[doc = "Sort and draw all the queued draws, then clear the batch.\n\n Returns the number of sprites that were uploaded to TMEM for texture\n rectangles. Draws that fall back to [`RdpQ::sprite_blit`] upload their\n sprite by themselves (possibly in several chunks), so they are not counted."]
+    pub fn submit(&mut self, rdpq: &mut RdpQ) -> usize {
+        self.sort();
+        let uploads = self.emit(rdpq);
+        self.clear();
+        uploads
+    }
+
+    #[doc = "Sort the queued draws and record them into a block.\n\n This is useful for static layers (eg: backgrounds) that are drawn\n identically on every frame: the returned block can be run each frame via\n [`crate::rspq::RspQ::block_run`]. The batch is left untouched, and the\n block keeps the sprites borrowed, as it references their pixels."]
+    pub fn record(&mut self, rdpq: &mut RdpQ) -> BatchBlock<'a> {
+        self.sort();
+        // The builder must not be dropped if emitting panics, as its drop
+        // guard rejects any drop at compile time: the block is left open
+        // instead.
+        let mut builder = ManuallyDrop::new(rdpq.block_begin());
+        self.emit(&mut super::init(&mut builder));
+        BatchBlock {
+            block: ManuallyDrop::into_inner(builder).end(),
+            _sprites: PhantomData,
+        }
+    }
+
+    fn emit(&self, rdpq: &mut RdpQ) -> usize {
+        let mut uploads = 0;
+        let mut frame = rdpq.mode_push();
+        let tinted = self.draws.iter().any(|d| d.tint.is_some());
+        if tinted {
+            frame.mode_combiner(COMBINER_TEX_FLAT);
+        }
+
+        let mut tint = None;
+        let mut uploaded = None;
+        for draw in &self.draws {
+            let color = draw.tint.unwrap_or(Color::WHITE);
+            if tinted && tint != Some(color) {
+                frame.set_prim_color(color);
+                tint = Some(color);
+            }
+
+            if !draw.is_rect() {
+                frame.sprite_blit(draw.sprite, draw.x, draw.y, Some(&draw.parms));
+                uploaded = None;
+                continue;
+            }
+
+            if uploaded != Some(draw.texture_id()) {
+                frame.sprite_upload(Tile::_0, draw.sprite, None);
+                uploaded = Some(draw.texture_id());
+                uploads += 1;
+            }
+
+            let p = &draw.parms.0;
+            let sprite = unsafe { &*draw.sprite.as_raw() };
+            let width = match p.width {
+                0 => sprite.width as i32 - p.s0,
+                w => w,
+            };
+            let height = match p.height {
+                0 => sprite.height as i32 - p.t0,
+                h => h,
+            };
+            let mut sx = if p.scale_x == 0.0 { 1.0 } else { p.scale_x };
+            let mut sy = if p.scale_y == 0.0 { 1.0 } else { p.scale_y };
+            if p.flip_x {
+                sx = -sx;
+            }
+            if p.flip_y {
+                sy = -sy;
+            }
+
+            // The hotspot stays at (x, y); negative scales swap the
+            // rectangle corners, which flips the texture.
+            let x0 = draw.x - p.cx as f32 * sx;
+            let y0 = draw.y - p.cy as f32 * sy;
+            frame.texture_rectangle_scaled(
+                Tile::_0,
+                x0,
+                y0,
+                x0 + width as f32 * sx,
+                y0 + height as f32 * sy,
+                p.s0 as f32,
+                p.t0 as f32,
+                (p.s0 + width) as f32,
+                (p.t0 + height) as f32,
+            );
+        }
+        uploads
+    }
+}
+
+#[doc = "A [`SpriteBatch`] recorded into a block by [`SpriteBatch::record`].\n\n It can be run like any other [`Block`], and it borrows the sprites of the\n batch for as long as it is alive."]
+#[derive(Debug)]
+pub struct BatchBlock<'a> {
+    block: Block,
+    _sprites: PhantomData<&'a Sprite>,
+}
+
+wrapper_ref! { BatchBlock<'a> => Block { self => &self.block } }
+
+impl<'b, 'a> From<&'b BatchBlock<'a>> for crate::rspq::BlockRef<'b> {
+    #[inline]
+    fn from(value: &'b BatchBlock<'a>) -> Self {
+        value.block.as_block_ref()
+    }
+}
+
+impl<'a> Extend<SpriteDraw<'a>> for SpriteBatch<'a> {
+    #[inline]
+    fn extend<T: IntoIterator<Item = SpriteDraw<'a>>>(&mut self, iter: T) {
+        self.draws.extend(iter);
+    }
+}
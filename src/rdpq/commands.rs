@@ -0,0 +1,1112 @@
+use core::fmt;
+
+use crate::{graphics::Color, surface::TexFormat};
+
+use super::{Combiner, SOM, SOMMask, Tile};
+
+#[inline]
+const fn bits(w: u64, shift: u32, width: u32) -> u32 {
+    ((w >> shift) & ((1 << width) - 1)) as u32
+}
+
+#[inline]
+const fn sbits(w: u64, shift: u32, width: u32) -> i32 {
+    ((bits(w, shift, width) << (32 - width)) as i32) >> (32 - width)
+}
+
+#[doc = "Error returned when decoding a RDP command stream"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DecodeError {
+    #[doc = "The buffer is empty"]
+    Empty,
+    #[doc = "The command is longer than the words available in the buffer"]
+    Truncated {
+        #[doc = "Command ID"]
+        id: u8,
+        #[doc = "Number of 64-bit words required by the command"]
+        needed: usize,
+        #[doc = "Number of 64-bit words available in the buffer"]
+        available: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty RDP command buffer"),
+            Self::Truncated {
+                id,
+                needed,
+                available,
+            } => write!(
+                f,
+                "truncated RDP command {id:#04x}: needs {needed} words, {available} available"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+#[doc = "Per-axis configuration of a tile descriptor, as decoded from SET_TILE"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TileDescAxis {
+    #[doc = "Clamp enabled"]
+    pub clamp: bool,
+    #[doc = "Mirror enabled"]
+    pub mirror: bool,
+    #[doc = "Wrapping mask (log2 of the wrapping size, 0 = no wrapping)"]
+    pub mask: u8,
+    #[doc = "Coordinate shift (0-10 shift right, 11-15 shift left)"]
+    pub shift: u8,
+}
+
+#[doc = "Edge of a triangle, as decoded from a triangle command"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TriEdge {
+    #[doc = "X coordinate at the start of the edge (s15.16)"]
+    pub x: i32,
+    #[doc = "Inverse slope of the edge (s15.16)"]
+    pub dxdy: i32,
+}
+
+#[doc = "A triangle command (TRI, TRI_SHADE, TRI_TEX, TRI_ZBUF, ...)"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TriangleCmd {
+    #[doc = "Triangle has per-vertex shade coefficients"]
+    pub shade: bool,
+    #[doc = "Triangle has texture coefficients"]
+    pub tex: bool,
+    #[doc = "Triangle has depth coefficients"]
+    pub zbuf: bool,
+    #[doc = "The major edge is on the left"]
+    pub left_major: bool,
+    #[doc = "Number of mipmap levels minus 1"]
+    pub level: u8,
+    #[doc = "Tile descriptor used for texturing"]
+    pub tile: Tile,
+    #[doc = "Y coordinate of the bottom vertex (s11.2)"]
+    pub yl: i16,
+    #[doc = "Y coordinate of the middle vertex (s11.2)"]
+    pub ym: i16,
+    #[doc = "Y coordinate of the top vertex (s11.2)"]
+    pub yh: i16,
+    #[doc = "Low minor edge"]
+    pub l: TriEdge,
+    #[doc = "Major edge"]
+    pub h: TriEdge,
+    #[doc = "High minor edge"]
+    pub m: TriEdge,
+    #[doc = "Raw shade coefficients (present if `shade` is set)"]
+    pub shade_coeffs: [u64; 8],
+    #[doc = "Raw texture coefficients (present if `tex` is set)"]
+    pub tex_coeffs: [u64; 8],
+    #[doc = "Raw depth coefficients (present if `zbuf` is set)"]
+    pub z_coeffs: [u64; 2],
+}
+
+#[doc = "A decoded RDP command"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RdpCommand {
+    #[doc = "No operation"]
+    Nop,
+    #[doc = "Triangle (any of the 8 variants)"]
+    Triangle(TriangleCmd),
+    #[doc = "Textured rectangle (optionally with S/T flipped)"]
+    TextureRectangle {
+        tile: Tile,
+        #[doc = "Top-left X coordinate (10.2)"]
+        x0: u16,
+        #[doc = "Top-left Y coordinate (10.2)"]
+        y0: u16,
+        #[doc = "Bottom-right X coordinate (10.2)"]
+        x1: u16,
+        #[doc = "Bottom-right Y coordinate (10.2)"]
+        y1: u16,
+        #[doc = "S coordinate at the top-left corner (s10.5)"]
+        s: i16,
+        #[doc = "T coordinate at the top-left corner (s10.5)"]
+        t: i16,
+        #[doc = "S increment per pixel (s5.10)"]
+        dsdx: i16,
+        #[doc = "T increment per line (s5.10)"]
+        dtdy: i16,
+        #[doc = "S and T are swapped"]
+        flip: bool,
+    },
+    SyncLoad,
+    SyncPipe,
+    SyncTile,
+    SyncFull,
+    SetKeyGB {
+        width_g: u16,
+        width_b: u16,
+        center_g: u8,
+        scale_g: u8,
+        center_b: u8,
+        scale_b: u8,
+    },
+    SetKeyR {
+        width_r: u16,
+        center_r: u8,
+        scale_r: u8,
+    },
+    #[doc = "YUV to RGB conversion coefficients (k0-k5)"]
+    SetConvert([i16; 6]),
+    SetScissor {
+        #[doc = "Top-left X coordinate (10.2)"]
+        x0: u16,
+        #[doc = "Top-left Y coordinate (10.2)"]
+        y0: u16,
+        #[doc = "Bottom-right X coordinate (10.2)"]
+        x1: u16,
+        #[doc = "Bottom-right Y coordinate (10.2)"]
+        y1: u16,
+        #[doc = "Interlaced mode enabled"]
+        field: bool,
+        #[doc = "Draw odd lines (in interlaced mode)"]
+        odd: bool,
+    },
+    SetPrimDepth {
+        z: u16,
+        dz: u16,
+    },
+    SetOtherModes(SOM),
+    LoadTlut {
+        tile: Tile,
+        s0: u16,
+        t0: u16,
+        s1: u16,
+        t1: u16,
+    },
+    SetTileSize {
+        tile: Tile,
+        #[doc = "Top-left S coordinate (10.2)"]
+        s0: u16,
+        #[doc = "Top-left T coordinate (10.2)"]
+        t0: u16,
+        #[doc = "Bottom-right S coordinate (10.2)"]
+        s1: u16,
+        #[doc = "Bottom-right T coordinate (10.2)"]
+        t1: u16,
+    },
+    LoadBlock {
+        tile: Tile,
+        s0: u16,
+        t0: u16,
+        #[doc = "Number of texels to load, minus 1"]
+        s1: u16,
+        #[doc = "T increment per 64-bit word (1.11)"]
+        dxt: u16,
+    },
+    LoadTile {
+        tile: Tile,
+        #[doc = "Top-left S coordinate (10.2)"]
+        s0: u16,
+        #[doc = "Top-left T coordinate (10.2)"]
+        t0: u16,
+        #[doc = "Bottom-right S coordinate (10.2)"]
+        s1: u16,
+        #[doc = "Bottom-right T coordinate (10.2)"]
+        t1: u16,
+    },
+    SetTile {
+        tile: Tile,
+        #[doc = "Texture format (`None` if the encoding is not a valid format)"]
+        format: Option<TexFormat>,
+        #[doc = "Pitch of a line, in 64-bit words"]
+        line: u16,
+        #[doc = "TMEM address, in 64-bit words"]
+        tmem_addr: u16,
+        palette: u8,
+        s: TileDescAxis,
+        t: TileDescAxis,
+    },
+    FillRectangle {
+        #[doc = "Top-left X coordinate (10.2)"]
+        x0: u16,
+        #[doc = "Top-left Y coordinate (10.2)"]
+        y0: u16,
+        #[doc = "Bottom-right X coordinate (10.2)"]
+        x1: u16,
+        #[doc = "Bottom-right Y coordinate (10.2)"]
+        y1: u16,
+    },
+    #[doc = "Raw fill value (either two packed RGBA16 colors or one RGBA32 color)"]
+    SetFillColor(u32),
+    SetFogColor(Color),
+    SetBlendColor(Color),
+    SetPrimColor {
+        min_lod: u8,
+        lod_frac: u8,
+        color: Color,
+    },
+    SetEnvColor(Color),
+    SetCombine(Combiner),
+    SetTextureImage {
+        format: Option<TexFormat>,
+        width: u16,
+        address: u32,
+    },
+    SetZImage {
+        address: u32,
+    },
+    SetColorImage {
+        format: Option<TexFormat>,
+        width: u16,
+        address: u32,
+    },
+    #[doc = "Command ID not known to the RDP"]
+    Unknown {
+        id: u8,
+        word: u64,
+    },
+}
+
+impl RdpCommand {
+    #[doc = "Return the number of 64-bit words of the command with the given ID"]
+    #[inline]
+    pub const fn size(id: u8) -> usize {
+        match id & 0x3F {
+            id @ 0x08..=0x0F => {
+                4 + if id & 4 != 0 { 8 } else { 0 }
+                    + if id & 2 != 0 { 8 } else { 0 }
+                    + if id & 1 != 0 { 2 } else { 0 }
+            }
+            0x24 | 0x25 => 2,
+            _ => 1,
+        }
+    }
+    #[doc = "Extract the command ID from the first word of a command"]
+    #[inline]
+    pub const fn id_of(word: u64) -> u8 {
+        ((word >> 56) & 0x3F) as u8
+    }
+    #[doc = "Decode the first command in the buffer.\n\n Returns the decoded command together with the number of 64-bit words it spans."]
+    pub fn decode(words: &[u64]) -> Result<(Self, usize), DecodeError> {
+        let w = *words.first().ok_or(DecodeError::Empty)?;
+        let id = Self::id_of(w);
+        let size = Self::size(id);
+        if words.len() < size {
+            return Err(DecodeError::Truncated {
+                id,
+                needed: size,
+                available: words.len(),
+            });
+        }
+        let tile = Tile::from_index(bits(w, 24, 3));
+        let cmd = match id {
+            0x00 => Self::Nop,
+            0x08..=0x0F => {
+                let shade = id & 4 != 0;
+                let tex = id & 2 != 0;
+                let zbuf = id & 1 != 0;
+                let edge = |w: u64| TriEdge {
+                    x: (w >> 32) as i32,
+                    dxdy: w as i32,
+                };
+                let mut tri = TriangleCmd {
+                    shade,
+                    tex,
+                    zbuf,
+                    left_major: bits(w, 55, 1) != 0,
+                    level: bits(w, 51, 3) as u8,
+                    tile: Tile::from_index(bits(w, 48, 3)),
+                    yl: sbits(w, 32, 14) as i16,
+                    ym: sbits(w, 16, 14) as i16,
+                    yh: sbits(w, 0, 14) as i16,
+                    l: edge(words[1]),
+                    h: edge(words[2]),
+                    m: edge(words[3]),
+                    shade_coeffs: [0; 8],
+                    tex_coeffs: [0; 8],
+                    z_coeffs: [0; 2],
+                };
+                let mut rest = &words[4..size];
+                if shade {
+                    tri.shade_coeffs.copy_from_slice(&rest[..8]);
+                    rest = &rest[8..];
+                }
+                if tex {
+                    tri.tex_coeffs.copy_from_slice(&rest[..8]);
+                    rest = &rest[8..];
+                }
+                if zbuf {
+                    tri.z_coeffs.copy_from_slice(&rest[..2]);
+                }
+                Self::Triangle(tri)
+            }
+            0x24 | 0x25 => {
+                let w1 = words[1];
+                Self::TextureRectangle {
+                    tile,
+                    x1: bits(w, 44, 12) as u16,
+                    y1: bits(w, 32, 12) as u16,
+                    x0: bits(w, 12, 12) as u16,
+                    y0: bits(w, 0, 12) as u16,
+                    s: (w1 >> 48) as i16,
+                    t: (w1 >> 32) as i16,
+                    dsdx: (w1 >> 16) as i16,
+                    dtdy: w1 as i16,
+                    flip: id == 0x25,
+                }
+            }
+            0x26 => Self::SyncLoad,
+            0x27 => Self::SyncPipe,
+            0x28 => Self::SyncTile,
+            0x29 => Self::SyncFull,
+            0x2A => Self::SetKeyGB {
+                width_g: bits(w, 44, 12) as u16,
+                width_b: bits(w, 32, 12) as u16,
+                center_g: bits(w, 24, 8) as u8,
+                scale_g: bits(w, 16, 8) as u8,
+                center_b: bits(w, 8, 8) as u8,
+                scale_b: bits(w, 0, 8) as u8,
+            },
+            0x2B => Self::SetKeyR {
+                width_r: bits(w, 16, 12) as u16,
+                center_r: bits(w, 8, 8) as u8,
+                scale_r: bits(w, 0, 8) as u8,
+            },
+            0x2C => Self::SetConvert([
+                sbits(w, 45, 9) as i16,
+                sbits(w, 36, 9) as i16,
+                sbits(w, 27, 9) as i16,
+                sbits(w, 18, 9) as i16,
+                sbits(w, 9, 9) as i16,
+                sbits(w, 0, 9) as i16,
+            ]),
+            0x2D => Self::SetScissor {
+                x0: bits(w, 44, 12) as u16,
+                y0: bits(w, 32, 12) as u16,
+                field: bits(w, 25, 1) != 0,
+                odd: bits(w, 24, 1) != 0,
+                x1: bits(w, 12, 12) as u16,
+                y1: bits(w, 0, 12) as u16,
+            },
+            0x2E => Self::SetPrimDepth {
+                z: bits(w, 16, 16) as u16,
+                dz: bits(w, 0, 16) as u16,
+            },
+            0x2F => Self::SetOtherModes(SOM::from_bits_retain(w & 0x00FF_FFFF_FFFF_FFFF)),
+            0x30 => Self::LoadTlut {
+                tile,
+                s0: bits(w, 44, 12) as u16,
+                t0: bits(w, 32, 12) as u16,
+                s1: bits(w, 12, 12) as u16,
+                t1: bits(w, 0, 12) as u16,
+            },
+            0x32 => Self::SetTileSize {
+                tile,
+                s0: bits(w, 44, 12) as u16,
+                t0: bits(w, 32, 12) as u16,
+                s1: bits(w, 12, 12) as u16,
+                t1: bits(w, 0, 12) as u16,
+            },
+            0x33 => Self::LoadBlock {
+                tile,
+                s0: bits(w, 44, 12) as u16,
+                t0: bits(w, 32, 12) as u16,
+                s1: bits(w, 12, 12) as u16,
+                dxt: bits(w, 0, 12) as u16,
+            },
+            0x34 => Self::LoadTile {
+                tile,
+                s0: bits(w, 44, 12) as u16,
+                t0: bits(w, 32, 12) as u16,
+                s1: bits(w, 12, 12) as u16,
+                t1: bits(w, 0, 12) as u16,
+            },
+            0x35 => Self::SetTile {
+                tile,
+                format: TexFormat::from_raw(bits(w, 51, 5)),
+                line: bits(w, 41, 9) as u16,
+                tmem_addr: bits(w, 32, 9) as u16,
+                palette: bits(w, 20, 4) as u8,
+                t: TileDescAxis {
+                    clamp: bits(w, 19, 1) != 0,
+                    mirror: bits(w, 18, 1) != 0,
+                    mask: bits(w, 14, 4) as u8,
+                    shift: bits(w, 10, 4) as u8,
+                },
+                s: TileDescAxis {
+                    clamp: bits(w, 9, 1) != 0,
+                    mirror: bits(w, 8, 1) != 0,
+                    mask: bits(w, 4, 4) as u8,
+                    shift: bits(w, 0, 4) as u8,
+                },
+            },
+            0x36 => Self::FillRectangle {
+                x1: bits(w, 44, 12) as u16,
+                y1: bits(w, 32, 12) as u16,
+                x0: bits(w, 12, 12) as u16,
+                y0: bits(w, 0, 12) as u16,
+            },
+            0x37 => Self::SetFillColor(w as u32),
+            0x38 => Self::SetFogColor(Color::from_u32(w as u32)),
+            0x39 => Self::SetBlendColor(Color::from_u32(w as u32)),
+            0x3A => Self::SetPrimColor {
+                min_lod: bits(w, 40, 5) as u8,
+                lod_frac: bits(w, 32, 8) as u8,
+                color: Color::from_u32(w as u32),
+            },
+            0x3B => Self::SetEnvColor(Color::from_u32(w as u32)),
+            0x3C => Self::SetCombine(Combiner(w & 0x00FF_FFFF_FFFF_FFFF)),
+            0x3D => Self::SetTextureImage {
+                format: TexFormat::from_raw(bits(w, 51, 5)),
+                width: bits(w, 32, 10) as u16 + 1,
+                address: bits(w, 0, 26),
+            },
+            0x3E => Self::SetZImage {
+                address: bits(w, 0, 26),
+            },
+            0x3F => Self::SetColorImage {
+                format: TexFormat::from_raw(bits(w, 51, 5)),
+                width: bits(w, 32, 10) as u16 + 1,
+                address: bits(w, 0, 26),
+            },
+            id => Self::Unknown { id, word: w },
+        };
+        Ok((cmd, size))
+    }
+    #[doc = "Return the mnemonic of the command"]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Nop => "NOP",
+            Self::Triangle(tri) => match (tri.tex, tri.shade, tri.zbuf) {
+                (false, false, false) => "TRI",
+                (false, false, true) => "TRI_Z",
+                (true, false, false) => "TRI_TEX",
+                (true, false, true) => "TRI_TEX_Z",
+                (false, true, false) => "TRI_SHADE",
+                (false, true, true) => "TRI_SHADE_Z",
+                (true, true, false) => "TRI_TEX_SHADE",
+                (true, true, true) => "TRI_TEX_SHADE_Z",
+            },
+            Self::TextureRectangle { flip: false, .. } => "TEX_RECT",
+            Self::TextureRectangle { flip: true, .. } => "TEX_RECT_FLIP",
+            Self::SyncLoad => "SYNC_LOAD",
+            Self::SyncPipe => "SYNC_PIPE",
+            Self::SyncTile => "SYNC_TILE",
+            Self::SyncFull => "SYNC_FULL",
+            Self::SetKeyGB { .. } => "SET_KEY_GB",
+            Self::SetKeyR { .. } => "SET_KEY_R",
+            Self::SetConvert(_) => "SET_CONVERT",
+            Self::SetScissor { .. } => "SET_SCISSOR",
+            Self::SetPrimDepth { .. } => "SET_PRIM_DEPTH",
+            Self::SetOtherModes(_) => "SET_OTHER_MODES",
+            Self::LoadTlut { .. } => "LOAD_TLUT",
+            Self::SetTileSize { .. } => "SET_TILE_SIZE",
+            Self::LoadBlock { .. } => "LOAD_BLOCK",
+            Self::LoadTile { .. } => "LOAD_TILE",
+            Self::SetTile { .. } => "SET_TILE",
+            Self::FillRectangle { .. } => "FILL_RECT",
+            Self::SetFillColor(_) => "SET_FILL_COLOR",
+            Self::SetFogColor(_) => "SET_FOG_COLOR",
+            Self::SetBlendColor(_) => "SET_BLEND_COLOR",
+            Self::SetPrimColor { .. } => "SET_PRIM_COLOR",
+            Self::SetEnvColor(_) => "SET_ENV_COLOR",
+            Self::SetCombine(_) => "SET_COMBINE_MODE",
+            Self::SetTextureImage { .. } => "SET_TEX_IMAGE",
+            Self::SetZImage { .. } => "SET_Z_IMAGE",
+            Self::SetColorImage { .. } => "SET_COLOR_IMAGE",
+            Self::Unknown { .. } => "???",
+        }
+    }
+}
+
+#[doc = "Iterator over the commands of a RDP command stream, created by [`decode`]"]
+#[derive(Debug, Clone)]
+pub struct Commands<'a>(&'a [u64]);
+
+#[doc = "Decode a stream of raw RDP command words.\n\n Iteration stops after the first decoding error."]
+#[inline]
+pub fn decode(words: &[u64]) -> Commands<'_> {
+    Commands(words)
+}
+
+impl<'a> Iterator for Commands<'a> {
+    type Item = Result<RdpCommand, DecodeError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        match RdpCommand::decode(self.0) {
+            Ok((cmd, size)) => {
+                self.0 = &self.0[size..];
+                Some(Ok(cmd))
+            }
+            Err(err) => {
+                self.0 = &[];
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl core::iter::FusedIterator for Commands<'_> {}
+
+#[doc = "One cycle of the color combiner, with raw input selectors for the\n `(A - B) * C + D` formula of both the RGB and alpha channels"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CombinerCycle {
+    pub rgb_suba: u8,
+    pub rgb_subb: u8,
+    pub rgb_mul: u8,
+    pub rgb_add: u8,
+    pub alpha_suba: u8,
+    pub alpha_subb: u8,
+    pub alpha_mul: u8,
+    pub alpha_add: u8,
+}
+
+impl Combiner {
+    #[doc = "Extract the raw input selectors of both combiner cycles"]
+    #[inline]
+    pub const fn cycles(self) -> [CombinerCycle; 2] {
+        let w = self.0;
+        [
+            CombinerCycle {
+                rgb_suba: bits(w, 52, 4) as u8,
+                rgb_subb: bits(w, 28, 4) as u8,
+                rgb_mul: bits(w, 47, 5) as u8,
+                rgb_add: bits(w, 15, 3) as u8,
+                alpha_suba: bits(w, 44, 3) as u8,
+                alpha_subb: bits(w, 12, 3) as u8,
+                alpha_mul: bits(w, 41, 3) as u8,
+                alpha_add: bits(w, 9, 3) as u8,
+            },
+            CombinerCycle {
+                rgb_suba: bits(w, 37, 4) as u8,
+                rgb_subb: bits(w, 24, 4) as u8,
+                rgb_mul: bits(w, 32, 5) as u8,
+                rgb_add: bits(w, 6, 3) as u8,
+                alpha_suba: bits(w, 21, 3) as u8,
+                alpha_subb: bits(w, 3, 3) as u8,
+                alpha_mul: bits(w, 18, 3) as u8,
+                alpha_add: bits(w, 0, 3) as u8,
+            },
+        ]
+    }
+}
+
+struct Fx<const FRAC: u32>(i32);
+
+impl<const FRAC: u32> fmt::Display for Fx<FRAC> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.0 as f32 / (1 << FRAC) as f32)
+    }
+}
+
+struct Fmt(Option<TexFormat>);
+
+impl fmt::Display for Fmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.0 {
+            Some(TexFormat::None) | None => "???",
+            Some(TexFormat::RGBA16) => "RGBA16",
+            Some(TexFormat::RGBA32) => "RGBA32",
+            Some(TexFormat::YUV16) => "YUV16",
+            Some(TexFormat::CI4) => "CI4",
+            Some(TexFormat::CI8) => "CI8",
+            Some(TexFormat::IA4) => "IA4",
+            Some(TexFormat::IA8) => "IA8",
+            Some(TexFormat::IA16) => "IA16",
+            Some(TexFormat::I4) => "I4",
+            Some(TexFormat::I8) => "I8",
+        })
+    }
+}
+
+struct Rgba(Color);
+
+impl fmt::Display for Rgba {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Color { r, g, b, a } = self.0;
+        write!(f, "rgba32=({r},{g},{b},{a})")
+    }
+}
+
+fn fmt_som(som: SOM, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let bits = som.bits();
+    let cycle = (bits & SOMMask::CYCLE.bits()) >> SOM::CYCLE_SHIFT;
+    f.write_str(["1cyc", "2cyc", "copy", "fill"][cycle as usize])?;
+    for (flag, name) in [
+        (SOM::ATOMIC_PRIM, "atomic"),
+        (SOM::TEXTURE_PERSP, "persp"),
+        (SOM::TEXTURE_DETAIL, "detail"),
+        (SOM::TEXTURE_SHARPEN, "sharpen"),
+        (SOM::TEXTURE_LOD, "lod"),
+        (SOM::BLENDING, "blend"),
+        (SOM::Z_COMPARE, "z_compare"),
+        (SOM::Z_WRITE, "z_write"),
+        (SOM::ZSOURCE_PRIM, "z_prim"),
+        (SOM::READ_ENABLE, "read"),
+        (SOM::AA_ENABLE, "aa"),
+        (SOM::COLOR_ON_CVG_OVERFLOW, "color_on_cvg"),
+    ] {
+        if som.contains(flag) {
+            write!(f, " {name}")?;
+        }
+    }
+    let tlut = (bits & SOMMask::TLUT.bits()) >> SOM::TLUT_SHIFT;
+    if tlut & 2 != 0 {
+        f.write_str(if tlut & 1 != 0 {
+            " tlut=ia16"
+        } else {
+            " tlut=rgba16"
+        })?;
+    }
+    let sample = (bits & SOMMask::SAMPLE.bits()) >> SOM::SAMPLE_SHIFT;
+    match sample {
+        2 => f.write_str(" sample=bilinear")?,
+        3 => f.write_str(" sample=median")?,
+        _ => {}
+    }
+    let alphacompare = (bits & SOMMask::ALPHACOMPARE.bits()) >> SOM::ALPHACOMPARE_SHIFT;
+    match alphacompare {
+        1 => f.write_str(" alpha_compare")?,
+        3 => f.write_str(" alpha_compare=noise")?,
+        _ => {}
+    }
+    let zmode = (bits & SOMMask::ZMODE.bits()) >> SOM::ZMODE_SHIFT;
+    if som.intersects(SOM::Z_COMPARE | SOM::Z_WRITE) {
+        f.write_str(["", " zmode=inter", " zmode=xlu", " zmode=decal"][zmode as usize])?;
+    }
+    let blend = (bits & SOMMask::BLEND.bits()) >> 16;
+    if blend != 0 {
+        write!(f, " blender={blend:#06x}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for CombinerCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rgb=[{},{},{},{}] alpha=[{},{},{},{}]",
+            self.rgb_suba,
+            self.rgb_subb,
+            self.rgb_mul,
+            self.rgb_add,
+            self.alpha_suba,
+            self.alpha_subb,
+            self.alpha_mul,
+            self.alpha_add,
+        )
+    }
+}
+
+impl fmt::Display for RdpCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<17}", self.name())?;
+        match *self {
+            Self::Nop | Self::SyncLoad | Self::SyncPipe | Self::SyncTile | Self::SyncFull => Ok(()),
+            Self::Triangle(ref tri) => {
+                write!(
+                    f,
+                    "{} tile={} lvl={} y=({}, {}, {})",
+                    if tri.left_major { "left" } else { "right" },
+                    tri.tile as u32,
+                    tri.level + 1,
+                    Fx::<2>(tri.yh as i32),
+                    Fx::<2>(tri.ym as i32),
+                    Fx::<2>(tri.yl as i32),
+                )?;
+                for (name, edge) in [("h", tri.h), ("m", tri.m), ("l", tri.l)] {
+                    write!(
+                        f,
+                        " x{name}={} dx{name}={}",
+                        Fx::<16>(edge.x),
+                        Fx::<16>(edge.dxdy)
+                    )?;
+                }
+                Ok(())
+            }
+            Self::TextureRectangle {
+                tile,
+                x0,
+                y0,
+                x1,
+                y1,
+                s,
+                t,
+                dsdx,
+                dtdy,
+                ..
+            } => write!(
+                f,
+                "tile={} xy=({},{})-({},{}) st=({},{}) dst=({},{})",
+                tile as u32,
+                Fx::<2>(x0 as i32),
+                Fx::<2>(y0 as i32),
+                Fx::<2>(x1 as i32),
+                Fx::<2>(y1 as i32),
+                Fx::<5>(s as i32),
+                Fx::<5>(t as i32),
+                Fx::<10>(dsdx as i32),
+                Fx::<10>(dtdy as i32),
+            ),
+            Self::SetKeyGB {
+                width_g,
+                width_b,
+                center_g,
+                scale_g,
+                center_b,
+                scale_b,
+            } => write!(
+                f,
+                "wg={width_g} wb={width_b} cg={center_g} sg={scale_g} cb={center_b} sb={scale_b}"
+            ),
+            Self::SetKeyR {
+                width_r,
+                center_r,
+                scale_r,
+            } => write!(f, "wr={width_r} cr={center_r} sr={scale_r}"),
+            Self::SetConvert(k) => write!(
+                f,
+                "k0={} k1={} k2={} k3={} k4={} k5={}",
+                k[0], k[1], k[2], k[3], k[4], k[5]
+            ),
+            Self::SetScissor {
+                x0,
+                y0,
+                x1,
+                y1,
+                field,
+                odd,
+            } => {
+                write!(
+                    f,
+                    "xy=({},{})-({},{})",
+                    Fx::<2>(x0 as i32),
+                    Fx::<2>(y0 as i32),
+                    Fx::<2>(x1 as i32),
+                    Fx::<2>(y1 as i32),
+                )?;
+                if field {
+                    f.write_str(if odd { " field=odd" } else { " field=even" })?;
+                }
+                Ok(())
+            }
+            Self::SetPrimDepth { z, dz } => write!(f, "z={z:#06x} dz={dz:#06x}"),
+            Self::SetOtherModes(som) => fmt_som(som, f),
+            Self::LoadTlut {
+                tile, s0, t0, s1, ..
+            } => write!(
+                f,
+                "tile={} palidx=({}-{}) t={}",
+                tile as u32,
+                s0 >> 2,
+                s1 >> 2,
+                t0 >> 2
+            ),
+            Self::SetTileSize {
+                tile,
+                s0,
+                t0,
+                s1,
+                t1,
+            }
+            | Self::LoadTile {
+                tile,
+                s0,
+                t0,
+                s1,
+                t1,
+            } => write!(
+                f,
+                "tile={} st=({},{})-({},{})",
+                tile as u32,
+                Fx::<2>(s0 as i32),
+                Fx::<2>(t0 as i32),
+                Fx::<2>(s1 as i32),
+                Fx::<2>(t1 as i32),
+            ),
+            Self::LoadBlock {
+                tile,
+                s0,
+                t0,
+                s1,
+                dxt,
+            } => write!(
+                f,
+                "tile={} st=({},{}) n={} dxt={}",
+                tile as u32,
+                s0,
+                t0,
+                s1 as u32 + 1,
+                Fx::<11>(dxt as i32),
+            ),
+            Self::SetTile {
+                tile,
+                format,
+                line,
+                tmem_addr,
+                palette,
+                s,
+                t,
+            } => {
+                write!(
+                    f,
+                    "tile={} {} tmem[{:#05x},line={}]",
+                    tile as u32,
+                    Fmt(format),
+                    tmem_addr as u32 * 8,
+                    line as u32 * 8,
+                )?;
+                if matches!(format, Some(TexFormat::CI4)) {
+                    write!(f, " pal={palette}")?;
+                }
+                for (name, axis) in [("s", s), ("t", t)] {
+                    if axis.clamp || axis.mirror || axis.mask != 0 || axis.shift != 0 {
+                        write!(f, " {name}=[")?;
+                        if axis.clamp {
+                            f.write_str("clamp ")?;
+                        }
+                        if axis.mirror {
+                            f.write_str("mirror ")?;
+                        }
+                        write!(f, "mask={} shift={}]", axis.mask, axis.shift)?;
+                    }
+                }
+                Ok(())
+            }
+            Self::FillRectangle { x0, y0, x1, y1 } => write!(
+                f,
+                "xy=({},{})-({},{})",
+                Fx::<2>(x0 as i32),
+                Fx::<2>(y0 as i32),
+                Fx::<2>(x1 as i32),
+                Fx::<2>(y1 as i32),
+            ),
+            Self::SetFillColor(value) => {
+                let c = Color::from_u16(value as u16);
+                write!(
+                    f,
+                    "rgba16=({},{},{},{}) {}",
+                    c.r >> 3,
+                    c.g >> 3,
+                    c.b >> 3,
+                    c.a >> 7,
+                    Rgba(Color::from_u32(value))
+                )
+            }
+            Self::SetFogColor(color) | Self::SetBlendColor(color) | Self::SetEnvColor(color) => {
+                write!(f, "{}", Rgba(color))
+            }
+            Self::SetPrimColor {
+                min_lod,
+                lod_frac,
+                color,
+            } => write!(
+                f,
+                "{} prim_lod_frac={lod_frac} min_lod={min_lod}",
+                Rgba(color)
+            ),
+            Self::SetCombine(comb) => {
+                let [c0, c1] = comb.cycles();
+                write!(f, "cyc0 {c0} cyc1 {c1}")
+            }
+            Self::SetTextureImage {
+                format,
+                width,
+                address,
+            }
+            | Self::SetColorImage {
+                format,
+                width,
+                address,
+            } => write!(f, "dram={address:#08x} {} width={width}", Fmt(format)),
+            Self::SetZImage { address } => write!(f, "dram={address:#08x}"),
+            Self::Unknown { id, word } => write!(f, "id={id:#04x} word={word:#018x}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Command stream captured from the rdpq debug log (see `debug::log_msg`)
+    const LOG: [u64; 8] = [
+        0xef30000000000000,
+        0xed00000000000000,
+        0xf700000000000000,
+        0xf65003c000000000,
+        0xf1020000000332b8,
+        0xe700000000000000,
+        0xf7000000f800f800,
+        0xf65003c000000000,
+    ];
+
+    fn decode_one(words: &[u64]) -> RdpCommand {
+        let (cmd, size) = RdpCommand::decode(words).unwrap();
+        assert_eq!(size, words.len());
+        cmd
+    }
+
+    #[test]
+    fn fill_rectangle() {
+        let cmd = decode_one(&[0xf65003c000000000]);
+        assert_eq!(
+            cmd,
+            RdpCommand::FillRectangle {
+                x0: 0,
+                y0: 0,
+                x1: 320 * 4,
+                y1: 240 * 4,
+            }
+        );
+        assert_eq!(
+            cmd.to_string(),
+            "FILL_RECT        xy=(0.00,0.00)-(320.00,240.00)"
+        );
+    }
+
+    #[test]
+    fn fill_color() {
+        let cmd = decode_one(&[0xf7000000f800f800]);
+        assert_eq!(cmd, RdpCommand::SetFillColor(0xf800f800));
+        assert_eq!(
+            cmd.to_string(),
+            "SET_FILL_COLOR   rgba16=(31,0,0,0) rgba32=(248,0,248,0)"
+        );
+    }
+
+    #[test]
+    fn other_modes() {
+        let cmd = decode_one(&[0xef30000000000000]);
+        assert_eq!(cmd.to_string(), "SET_OTHER_MODES  fill");
+    }
+
+    #[test]
+    fn scissor() {
+        let cmd = decode_one(&[0xed00000000000000]);
+        assert_eq!(
+            cmd.to_string(),
+            "SET_SCISSOR      xy=(0.00,0.00)-(0.00,0.00)"
+        );
+        let cmd = decode_one(&[0xed000000025003c0]);
+        assert_eq!(
+            cmd,
+            RdpCommand::SetScissor {
+                x0: 0,
+                y0: 0,
+                x1: 320 * 4,
+                y1: 240 * 4,
+                field: true,
+                odd: false,
+            }
+        );
+    }
+
+    #[test]
+    fn combiner() {
+        // gsDPSetCombineMode(G_CC_PRIMITIVE, G_CC_PRIMITIVE)
+        let RdpCommand::SetCombine(comb) = decode_one(&[0xfcfffffffffdf6fb]) else {
+            panic!("not a SET_COMBINE_MODE");
+        };
+        let prim = CombinerCycle {
+            rgb_suba: 15,
+            rgb_subb: 15,
+            rgb_mul: 31,
+            rgb_add: 3,
+            alpha_suba: 7,
+            alpha_subb: 7,
+            alpha_mul: 7,
+            alpha_add: 3,
+        };
+        assert_eq!(comb.cycles(), [prim, prim]);
+    }
+
+    #[test]
+    fn set_tile() {
+        let cmd = decode_one(&[0xf510100000000000]);
+        assert_eq!(
+            cmd,
+            RdpCommand::SetTile {
+                tile: Tile::_0,
+                format: Some(TexFormat::RGBA16),
+                line: 8,
+                tmem_addr: 0,
+                palette: 0,
+                s: TileDescAxis::default(),
+                t: TileDescAxis::default(),
+            }
+        );
+        assert_eq!(
+            cmd.to_string(),
+            "SET_TILE         tile=0 RGBA16 tmem[0x000,line=64]"
+        );
+    }
+
+    #[test]
+    fn texture_rectangle() {
+        let words = [0xe408008000000000, 0x0000000004000400];
+        let cmd = decode_one(&words);
+        assert_eq!(
+            cmd.to_string(),
+            "TEX_RECT         tile=0 xy=(0.00,0.00)-(32.00,32.00) st=(0.00,0.00) dst=(1.00,1.00)"
+        );
+        assert_eq!(
+            RdpCommand::decode(&words[..1]),
+            Err(DecodeError::Truncated {
+                id: 0x24,
+                needed: 2,
+                available: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn triangle_sizes() {
+        assert_eq!(RdpCommand::size(0x08), 4);
+        assert_eq!(RdpCommand::size(0x09), 6);
+        assert_eq!(RdpCommand::size(0x0A), 12);
+        assert_eq!(RdpCommand::size(0x0C), 12);
+        assert_eq!(RdpCommand::size(0x0F), 22);
+        assert_eq!(RdpCommand::size(0xCF), 22);
+
+        let mut words = [0u64; 12];
+        words[0] = 0xcc80_0000_0000_0000 | (40 << 32) | (20 << 16) | 10;
+        words[1] = (5 << 48) | 0x0001_0000;
+        let RdpCommand::Triangle(tri) = decode_one(&words) else {
+            panic!("not a triangle");
+        };
+        assert!(tri.shade && !tri.tex && !tri.zbuf && tri.left_major);
+        assert_eq!((tri.yl, tri.ym, tri.yh), (40, 20, 10));
+        assert_eq!(
+            tri.l,
+            TriEdge {
+                x: 5 << 16,
+                dxdy: 1 << 16,
+            }
+        );
+    }
+
+    #[test]
+    fn stream() {
+        let names: Vec<_> = decode(&LOG).map(|cmd| cmd.unwrap().name()).collect();
+        assert_eq!(
+            names,
+            [
+                "SET_OTHER_MODES",
+                "SET_SCISSOR",
+                "SET_FILL_COLOR",
+                "FILL_RECT",
+                "???",
+                "SYNC_PIPE",
+                "SET_FILL_COLOR",
+                "FILL_RECT",
+            ]
+        );
+        assert_eq!(RdpCommand::decode(&[]), Err(DecodeError::Empty));
+        let mut cmds = decode(&[0xe700000000000000, 0xe400000000000000]);
+        assert_eq!(cmds.next(), Some(Ok(RdpCommand::SyncPipe)));
+        assert!(matches!(
+            cmds.next(),
+            Some(Err(DecodeError::Truncated { .. }))
+        ));
+        assert_eq!(cmds.next(), None);
+    }
+}
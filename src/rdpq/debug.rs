@@ -0,0 +1,120 @@
+use core::marker::PhantomData;
+
+use alloc_::{boxed::Box, ffi::CString, vec::Vec};
+
+use crate::{surface::Surface, sys::rdpq_debug::*};
+
+type HookFn = dyn FnMut(&[u64]) + Send;
+
+static mut HOOK_INSTALLED: bool = false;
+
+#[doc = "Messages logged via [`log_msg`], referenced by the command stream"]
+static mut MESSAGES: Vec<CString> = Vec::new();
+#[doc = "Maximum number of distinct messages kept by [`log_msg`]"]
+const MAX_MESSAGES: usize = 256;
+
+#[doc = "Initialize the RDPQ debugging engine\n\n This function initializes the RDP debugging engine. After calling this function,\n all RDP commands sent via the rspq/rdpq libraries and overlays will be analyzed\n and validated, providing insights in case of programming errors that trigger\n hardware undefined behaviors or corrupt graphics. The validation errors\n and warnings are emitted via `debugf`, so make sure to initialize the debugging\n library to see it.\n\n This is especially important with RDP because the chips is very hard to program\n correctly, and it is common to do mistakes. While rdpq tries to shield the\n programmer from most common mistakes via the fixups, it is still possible\n to do mistakes (eg: creating non-working color combiners) that the debugging\n engine can help spotting.\n\n Notice that the validator needs to maintain a representation of the RDP state,\n as it is not possible to query the RDP about it. So it is better to call\n `rdpq_debug_start` immediately after `rdpq_init` when required, so that it can\n track all commands from the start. Otherwise, some spurious validation error\n could be emitted.\n\n @note The validator does cause a measurable overhead. It is advised to enable\n       it only in debugging builds."]
+#[inline]
+pub fn start() {
+    unsafe { rdpq_debug_start() }
+}
+#[doc = "Stop the rdpq debugging engine."]
+#[inline]
+pub fn stop() {
+    unsafe { rdpq_debug_stop() }
+}
+#[doc = "Show a full log of all the RDP commands\n\n This function configures the debugging engine to also log all RDP commands\n to the debugging channel (via `debugf`). This is extremely verbose and should\n be used sparingly to debug specific issues.\n\n This function does enqueue a command in the rspq queue, so it will be\n executed in order with respect to all rspq/rdpq commands. You can thus\n delimit specific portions of your code with `rdpq_debug_log(true)` /\n `rdpq_debug_log(false)`, to see only the RDP log produced by those\n code lines.\n\n @param show_log    true/false to enable/disable the RDP log."]
+#[inline]
+pub fn log(show_log: bool) {
+    unsafe { rdpq_debug_log(show_log) }
+}
+#[doc = "Add a custom message in the RDP logging\n\n If the debug log is active, this function adds a custom message to the log.\n It can be useful to annotate different portions of the disassembly.\n\n For instance, the following code:\n\n ```ignore\n rdpq::debug::log(true);\n\n rdpq::debug::log_msg(\"Black rectangle\");\n rdpq.set_mode_fill(Color::BLACK);\n rdpq.fill_rectangle(0.0, 0.0, 320.0, 120.0);\n\n rdpq::debug::log_msg(\"Red rectangle\");\n rdpq.set_fill_color(Color::RED);\n rdpq.fill_rectangle(0.0, 120.0, 320.0, 240.0);\n\n rdpq::debug::log(false);\n ```\n\n produces this output:\n\n ```text\n [0xa00e7128] f1020000000332a8    RDPQ_MESSAGE     Black rectangle\n [0xa00e7130] ef30000000000000    SET_OTHER_MODES  fill\n [0xa00e7138] ed00000000000000    SET_SCISSOR      xy=(0.00,0.00)-(0.00,0.00)\n [0xa00e7140] f700000000000000    SET_FILL_COLOR   rgba16=(0,0,0,0) rgba32=(0,0,0,0)\n [0xa00e7148] f65003c000000000    FILL_RECT        xy=(0.00,0.00)-(320.00,240.00)\n [0xa00e7150] f1020000000332b8    RDPQ_MESSAGE     Red rectangle\n [0xa00e7158] e700000000000000    SYNC_PIPE\n [0xa00e7160] f7000000f800f800    SET_FILL_COLOR   rgba16=(31,0,0,0) rgba32=(248,0,248,0)\n [0xa00e7168] f65003c000000000    FILL_RECT        xy=(0.00,0.00)-(320.00,240.00)\n ```\n\n The message is referenced by the command stream rather than copied, so a\n NUL-terminated copy of it is kept for the whole program. Copies are shared\n between calls with the same message, so logging the same messages every\n frame does not use more memory. At most 256 distinct messages are kept:\n after that, new messages are replaced by a placeholder in the log.\n\n @param str     message to display"]
+#[inline]
+pub fn log_msg(msg: &str) {
+    let msg = msg.split('\0').next().unwrap_or_default();
+    let messages = &raw mut MESSAGES;
+    let messages = unsafe { &mut *messages };
+    let msg = match messages.iter().position(|m| m.as_bytes() == msg.as_bytes()) {
+        Some(idx) => messages[idx].as_c_str(),
+        None if messages.len() < MAX_MESSAGES => {
+            messages.push(CString::new(msg).unwrap());
+            messages[messages.len() - 1].as_c_str()
+        }
+        None => c"(too many distinct rdpq debug messages)",
+    };
+    unsafe { rdpq_debug_log_msg(msg.as_ptr()) }
+}
+#[doc = "Acquire a dump of the current contents of TMEM\n\n Inspecting TMEM can be useful for debugging purposes, so this function\n dumps it to RDRAM for inspection. It returns a surface that contains the\n contents of TMEM as a 32x64 FMT_RGBA16 (4K) buffer, but obviously the\n contents can vary and have nothing to do with this layout.\n\n The function will do a full sync (via `rspq_wait`) to make sure the\n surface has been fully written by RDP when the function returns.\n\n For the debugging, you can easily dump the contents of the surface calling\n `debug_hexdump`."]
+#[inline]
+pub fn tmem() -> Surface<'static> {
+    Surface(unsafe { rdpq_debug_get_tmem() }, PhantomData)
+}
+
+#[doc = "A hook installed in the RDP debugging engine, created by [`install_hook`].\n\n The hook is uninstalled when this is dropped."]
+pub struct Hook(*mut Box<HookFn>);
+
+impl core::fmt::Debug for Hook {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Hook").field(&self.0).finish()
+    }
+}
+
+unsafe extern "C" fn hook_trampoline(
+    ctx: *mut core::ffi::c_void,
+    cmd: *mut u64,
+    cmd_size: core::ffi::c_int,
+) {
+    unsafe {
+        let hook = &mut *(ctx as *mut Box<HookFn>);
+        hook(core::slice::from_raw_parts(cmd, cmd_size as usize));
+    }
+}
+
+#[doc = "Install a hook that is called for each RDP command\n\n The hook receives each RDP command processed by the debugging engine, as\n a slice of 64-bit words. The debugging engine must have been started via\n [`start`]. Only one hook can be installed at a time."]
+#[inline]
+pub fn install_hook<F: FnMut(&[u64]) + Send + 'static>(hook: F) -> Hook {
+    unsafe {
+        assert!(
+            !(&raw mut HOOK_INSTALLED).read_volatile(),
+            "another RDP debug hook is already installed"
+        );
+        (&raw mut HOOK_INSTALLED).write_volatile(true);
+        let hook: Box<HookFn> = Box::new(hook);
+        let ctx = Box::into_raw(Box::new(hook));
+        rdpq_debug_install_hook(Some(hook_trampoline), ctx as _);
+        Hook(ctx)
+    }
+}
+
+impl Drop for Hook {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            rdpq_debug_install_hook(None, core::ptr::null_mut());
+            (&raw mut HOOK_INSTALLED).write_volatile(false);
+            drop(Box::from_raw(self.0));
+        }
+    }
+}
+
+#[doc = "Return the size of the next RDP command\n\n @param buf     Pointer to RDP command\n @return        Number of 64-bit words the command is composed of"]
+#[inline]
+pub fn disasm_size(buf: &[u64]) -> usize {
+    debug_assert!(!buf.is_empty(), "buffer must not be empty");
+    unsafe { rdpq_debug_disasm_size(buf.as_ptr() as _) as usize }
+}
+
+#[doc = "Disassemble a RDP command\n\n Disassemble the RDP command at the start of the buffer, and print it to\n the debug output (stderr). The buffer must contain the whole command, as\n returned by [`disasm_size`].\n\n @param buf     RDP command\n @return        true if the command was disassembled, false if it is not a valid command"]
+#[inline]
+pub fn disasm(buf: &[u64]) -> bool {
+    assert!(
+        buf.len() >= disasm_size(buf),
+        "buffer does not contain a whole RDP command"
+    );
+    unsafe {
+        rdpq_debug_disasm(
+            buf.as_ptr() as _,
+            (*crate::sys::stdio::_impure_ptr)._stderr as _,
+        )
+    }
+}
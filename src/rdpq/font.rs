@@ -0,0 +1,487 @@
+use core::{ffi::CStr, marker::PhantomData, ptr::NonNull};
+
+use alloc_::boxed::Box;
+
+use crate::{
+    graphics::Color,
+    sys::{rdpq_font::*, rdpq_text::*},
+};
+
+use super::{RdpQ, state};
+
+#[doc = "Bitmap of the font IDs currently registered"]
+static mut REGISTERED_FONTS: [u32; 8] = [0; 8];
+
+#[doc = "Builtin fonts shipped within libdragon"]
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum BuiltinFont {
+    #[doc = "Monospace font suitable for debugging"]
+    DebugMono = rdpq_font_builtin_t_FONT_BUILTIN_DEBUG_MONO,
+    #[doc = "Variable width font suitable for debugging"]
+    DebugVar = rdpq_font_builtin_t_FONT_BUILTIN_DEBUG_VAR,
+}
+
+#[doc = "A font loaded from a `.font64` file.\n\n Fonts are created with the mkfont tool, that converts TTF/OTF or BMFont\n files into the `.font64` format. The font is freed when dropped.\n\n To be used for text rendering, a font must be registered with a font ID\n via [`Font::register`]."]
+#[derive(Debug)]
+pub struct Font {
+    ptr: NonNull<crate::sys::rdpq_font::rdpq_font_t>,
+    _buf: Option<Box<[u8]>>,
+}
+
+impl Font {
+    #[doc = "Load a font from a file (.font64 format).\n\n This function loads a font from a file. The file must be in the\n .font64 format, which is created by the mkfont tool.\n\n @param fn        Path to the font file\n @return          Loaded font"]
+    #[inline]
+    pub fn load(filename: &CStr) -> Self {
+        let ptr = unsafe { rdpq_font_load(filename.as_ptr()) };
+        Self {
+            ptr: NonNull::new(ptr).unwrap(),
+            _buf: None,
+        }
+    }
+    #[doc = "Load a font from a buffer in memory (.font64 format).\n\n This function loads a font from a buffer in memory. The buffer must\n contain a font in the .font64 format, which is created by the mkfont tool.\n\n The font is relocated in place within the buffer, so the buffer is kept\n alive for as long as the font exists. The buffer must be aligned to\n 16 bytes, as returned for instance by [`crate::asset::load`].\n\n @param buf       Buffer containing the font data"]
+    #[inline]
+    pub fn load_buf(mut buf: Box<[u8]>) -> Self {
+        assert!(
+            buf.as_ptr().align_offset(16) == 0,
+            "font buffer must be aligned to 16 bytes"
+        );
+        let ptr = unsafe { rdpq_font_load_buf(buf.as_mut_ptr() as _, buf.len() as _) };
+        Self {
+            ptr: NonNull::new(ptr).unwrap(),
+            _buf: Some(buf),
+        }
+    }
+    #[doc = "Load a builtin font provided by libdragon\n\n libdragon embeds a few fonts that can be used for debugging purposes,\n without requiring any font file to be present in the filesystem."]
+    #[inline]
+    pub fn load_builtin(font: BuiltinFont) -> Self {
+        let ptr = unsafe {
+            match font {
+                BuiltinFont::DebugMono => __rdpq_font_load_builtin_1(),
+                BuiltinFont::DebugVar => __rdpq_font_load_builtin_2(),
+            }
+        };
+        Self {
+            ptr: NonNull::new(ptr).unwrap(),
+            _buf: None,
+        }
+    }
+    #[doc = "Configure a font style\n\n This function allows to configure a font style. A font style is a set of\n parameters that affect how the font is rendered (eg: color, outline).\n\n Styles are identified by an ID (0-255). The default style (ID 0) is\n white with black outline. To change the style while drawing text,\n use `$^xx` escape codes, or the `style_id` field of [`TextParms`].\n\n @param font      Font to configure\n @param style_id  Style ID to configure (0-255)\n @param style     Style to configure"]
+    #[inline]
+    pub fn style(&mut self, style_id: u8, style: &FontStyle) {
+        let style = style.into_raw();
+        unsafe { rdpq_font_style(self.ptr.as_ptr(), style_id, &style) }
+    }
+    #[doc = "Register the font for text rendering with the given font ID.\n\n The font stays borrowed for as long as the returned [`RegisteredFont`]\n is alive, and it is unregistered when that is dropped. Font IDs are\n in range 1-255 (0 is reserved) and each ID can be registered only once\n at a time.\n\n # Panics\n\n Panics if the font ID is 0, or it is already registered."]
+    #[inline]
+    pub fn register(&self, font_id: u8) -> RegisteredFont<'_> {
+        assert!(font_id != 0, "font ID 0 is reserved");
+        let (word, bit) = (font_id as usize / 32, 1 << (font_id % 32));
+        unsafe {
+            let registered = &raw mut REGISTERED_FONTS[word];
+            let fonts = registered.read_volatile();
+            assert!(fonts & bit == 0, "font ID {font_id} is already registered");
+            registered.write_volatile(fonts | bit);
+            rdpq_text_register_font(font_id, self.ptr.as_ptr() as _);
+        }
+        RegisteredFont(font_id, PhantomData)
+    }
+    #[doc = "Get the index of a glyph in the font\n\n Returns `None` if the font does not contain the codepoint."]
+    #[inline]
+    pub fn glyph_index(&self, codepoint: char) -> Option<u32> {
+        let idx = unsafe { rdpq_font_get_glyph_index(self.ptr.as_ptr(), codepoint as u32) };
+        (idx >= 0).then_some(idx as u32)
+    }
+    #[doc = "Get the metrics of a glyph in the font\n\n Returns `None` if the font does not contain the codepoint."]
+    #[inline]
+    pub fn glyph_metrics(&self, codepoint: char) -> Option<GlyphMetrics> {
+        let mut metrics = core::mem::MaybeUninit::uninit();
+        unsafe {
+            rdpq_font_get_glyph_metrics(self.ptr.as_ptr(), codepoint as u32, metrics.as_mut_ptr())
+                .then(|| GlyphMetrics(metrics.assume_init()))
+        }
+    }
+    #[doc = "Get the range of codepoints covered by the font\n\n A font contains one or more ranges of codepoints. This function returns\n the range with the given index, or `None` if the index is out of bounds."]
+    #[inline]
+    pub fn glyph_range(&self, idx: usize) -> Option<GlyphRange> {
+        let mut start = 0;
+        let mut end = 0;
+        let mut sparse = false;
+        unsafe {
+            rdpq_font_get_glyph_ranges(
+                self.ptr.as_ptr(),
+                idx as _,
+                &mut start,
+                &mut end,
+                &mut sparse,
+            )
+        }
+        .then_some(GlyphRange { start, end, sparse })
+    }
+    #[doc = "Iterate over all the ranges of codepoints covered by the font"]
+    #[inline]
+    pub fn glyph_ranges(&self) -> impl Iterator<Item = GlyphRange> + '_ {
+        (0..).map_while(|idx| self.glyph_range(idx))
+    }
+    #[inline]
+    pub const fn as_raw(&self) -> *mut crate::sys::rdpq_font::rdpq_font_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Drop for Font {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { rdpq_font_free(self.ptr.as_ptr()) }
+    }
+}
+
+#[doc = "A font registered for text rendering under a font ID.\n\n The font is unregistered when this is dropped."]
+#[derive(Debug)]
+pub struct RegisteredFont<'f>(u8, PhantomData<&'f Font>);
+
+impl<'f> RegisteredFont<'f> {
+    #[doc = "Font ID this font is registered with"]
+    #[inline]
+    pub const fn id(&self) -> u8 {
+        self.0
+    }
+}
+
+impl<'f> Drop for RegisteredFont<'f> {
+    #[inline]
+    fn drop(&mut self) {
+        let (word, bit) = (self.0 as usize / 32, 1 << (self.0 % 32));
+        unsafe {
+            rdpq_text_unregister_font(self.0);
+            let registered = &raw mut REGISTERED_FONTS[word];
+            registered.write_volatile(registered.read_volatile() & !bit);
+        }
+    }
+}
+
+#[doc = "A font style\n\n This structure contains all the parameters that affect how a font is rendered."]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FontStyle {
+    color: Color,
+    outline_color: Color,
+}
+
+impl Default for FontStyle {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FontStyle {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            color: Color::WHITE,
+            outline_color: Color::BLACK,
+        }
+    }
+    #[doc = "Fill color of the text (default: white)"]
+    #[inline]
+    pub const fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+    #[doc = "Color of the outline (if the font has an outline) (default: black)"]
+    #[inline]
+    pub const fn outline(mut self, outline_color: Color) -> Self {
+        self.outline_color = outline_color;
+        self
+    }
+    #[inline]
+    pub const fn fill_color(&self) -> Color {
+        self.color
+    }
+    #[inline]
+    pub const fn outline_color(&self) -> Color {
+        self.outline_color
+    }
+    #[inline]
+    fn into_raw(self) -> rdpq_fontstyle_t {
+        rdpq_fontstyle_t {
+            color: self.color.into_raw(),
+            outline_color: self.outline_color.into_raw(),
+            custom: None,
+            custom_arg: core::ptr::null_mut(),
+        }
+    }
+}
+
+#[doc = "Metrics of a glyph in the font"]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct GlyphMetrics(rdpq_font_gmetrics_t);
+
+impl GlyphMetrics {
+    #[doc = "Number of pixels to advance horizontally after drawing the glyph"]
+    #[inline]
+    pub const fn xadvance(&self) -> f32 {
+        self.0.xadvance
+    }
+    #[doc = "Top-left X coordinate of the glyph bounding box (relative to the pen position)"]
+    #[inline]
+    pub const fn x0(&self) -> i8 {
+        self.0.x0
+    }
+    #[doc = "Top-left Y coordinate of the glyph bounding box (relative to the pen position)"]
+    #[inline]
+    pub const fn y0(&self) -> i8 {
+        self.0.y0
+    }
+    #[doc = "Bottom-right X coordinate of the glyph bounding box (relative to the pen position)"]
+    #[inline]
+    pub const fn x1(&self) -> i8 {
+        self.0.x1
+    }
+    #[doc = "Bottom-right Y coordinate of the glyph bounding box (relative to the pen position)"]
+    #[inline]
+    pub const fn y1(&self) -> i8 {
+        self.0.y1
+    }
+}
+
+#[doc = "A range of codepoints covered by a font"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct GlyphRange {
+    #[doc = "First codepoint in the range"]
+    pub start: u32,
+    #[doc = "Last codepoint in the range (inclusive)"]
+    pub end: u32,
+    #[doc = "If true, not all codepoints in the range are present in the font"]
+    pub sparse: bool,
+}
+
+#[doc = "Horizontal alignment of the text"]
+#[repr(u32)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Align {
+    #[doc = "Left alignment"]
+    #[default]
+    Left = rdpq_align_t_ALIGN_LEFT,
+    #[doc = "Center alignment"]
+    Center = rdpq_align_t_ALIGN_CENTER,
+    #[doc = "Right alignment"]
+    Right = rdpq_align_t_ALIGN_RIGHT,
+}
+
+#[doc = "Vertical alignment of the text"]
+#[repr(u32)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum VAlign {
+    #[doc = "Top alignment"]
+    #[default]
+    Top = rdpq_valign_t_VALIGN_TOP,
+    #[doc = "Center alignment"]
+    Center = rdpq_valign_t_VALIGN_CENTER,
+    #[doc = "Bottom alignment"]
+    Bottom = rdpq_valign_t_VALIGN_BOTTOM,
+}
+
+#[doc = "Text wrapping mode"]
+#[repr(u32)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Wrap {
+    #[doc = "Truncate the text (if any)"]
+    #[default]
+    None = rdpq_textwrap_t_WRAP_NONE,
+    #[doc = "Truncate the text adding ellipsis (if any)"]
+    Ellipses = rdpq_textwrap_t_WRAP_ELLIPSES,
+    #[doc = "Wrap at character boundaries "]
+    Char = rdpq_textwrap_t_WRAP_CHAR,
+    #[doc = "Wrap at word boundaries "]
+    Word = rdpq_textwrap_t_WRAP_WORD,
+}
+
+#[doc = "Print formatting parameters\n\n This structure contains all possible parameters for text rendering.\n All fields have been made so that the 0 value is always the most\n reasonable default."]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct TextParms(pub(crate) rdpq_textparms_t);
+
+impl Default for TextParms {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextParms {
+    pub const EMPTY: Self = Self::new();
+    #[inline]
+    pub const fn new() -> Self {
+        Self(rdpq_textparms_t {
+            style_id: 0,
+            width: 0,
+            height: 0,
+            align: rdpq_align_t_ALIGN_LEFT,
+            valign: rdpq_valign_t_VALIGN_TOP,
+            indent: 0,
+            max_chars: 0,
+            char_spacing: 0,
+            line_spacing: 0,
+            wrap: rdpq_textwrap_t_WRAP_NONE,
+            tabstops: core::ptr::null_mut(),
+            disable_aa_fix: false,
+            preserve_overlap: false,
+        })
+    }
+    #[doc = "Initial style ID for the text"]
+    #[inline]
+    pub const fn style_id(mut self, style_id: i16) -> Self {
+        self.0.style_id = style_id;
+        self
+    }
+    #[doc = "Maximum horizontal width of the paragraph, in pixels (0 if unbounded)"]
+    #[inline]
+    pub const fn width(mut self, width: i16) -> Self {
+        self.0.width = width;
+        self
+    }
+    #[doc = "Maximum vertical height of the paragraph, in pixels (0 if unbounded)"]
+    #[inline]
+    pub const fn height(mut self, height: i16) -> Self {
+        self.0.height = height;
+        self
+    }
+    #[doc = "Horizontal alignment (0=left, 1=center, 2=right)"]
+    #[inline]
+    pub const fn align(mut self, align: Align) -> Self {
+        self.0.align = align as _;
+        self
+    }
+    #[doc = "Vertical alignment (0=top, 1=center, 2=bottom)"]
+    #[inline]
+    pub const fn valign(mut self, valign: VAlign) -> Self {
+        self.0.valign = valign as _;
+        self
+    }
+    #[doc = "Indentation of the first line, in pixels (only valid for left alignment)"]
+    #[inline]
+    pub const fn indent(mut self, indent: i16) -> Self {
+        self.0.indent = indent;
+        self
+    }
+    #[doc = "Maximum number of characters to print (0 if unbounded)"]
+    #[inline]
+    pub const fn max_chars(mut self, max_chars: i16) -> Self {
+        self.0.max_chars = max_chars;
+        self
+    }
+    #[doc = "Extra spacing between characters (in addition to glyph width and kerning)"]
+    #[inline]
+    pub const fn char_spacing(mut self, char_spacing: i16) -> Self {
+        self.0.char_spacing = char_spacing;
+        self
+    }
+    #[doc = "Extra spacing between lines (in addition to font height)"]
+    #[inline]
+    pub const fn line_spacing(mut self, line_spacing: i16) -> Self {
+        self.0.line_spacing = line_spacing;
+        self
+    }
+    #[doc = "Wrap mode"]
+    #[inline]
+    pub const fn wrap(mut self, wrap: Wrap) -> Self {
+        self.0.wrap = wrap as _;
+        self
+    }
+    #[doc = "Disable reducing texture corruption caused by antialiasing of text"]
+    #[inline]
+    pub const fn disable_aa_fix(mut self, disable_aa_fix: bool) -> Self {
+        self.0.disable_aa_fix = disable_aa_fix;
+        self
+    }
+    #[doc = "Preserve overlapping characters in the text (normally, they are separated)"]
+    #[inline]
+    pub const fn preserve_overlap(mut self, preserve_overlap: bool) -> Self {
+        self.0.preserve_overlap = preserve_overlap;
+        self
+    }
+}
+
+#[doc = "Metrics of text that was laid out"]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct TextMetrics(rdpq_textmetrics_t);
+
+impl TextMetrics {
+    #[doc = "Horizontal advance of the text (in pixels)"]
+    #[inline]
+    pub const fn advance_x(&self) -> f32 {
+        self.0.advance_x
+    }
+    #[doc = "Vertical advance of the text (in pixels)"]
+    #[inline]
+    pub const fn advance_y(&self) -> f32 {
+        self.0.advance_y
+    }
+    #[doc = "Number of bytes of the UTF-8 text that were consumed"]
+    #[inline]
+    pub const fn utf8_text_advance(&self) -> usize {
+        self.0.utf8_text_advance as usize
+    }
+    #[doc = "Number of lines that were printed"]
+    #[inline]
+    pub const fn nlines(&self) -> usize {
+        self.0.nlines as usize
+    }
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Layout and render a text in a single call\n\n This function prints a text using the specified font. The text is laid out\n within the (optional) boundaries specified in `parms`, and then immediately\n rendered.\n\n The text can contain escape codes to change the style (`$^xx`) and the\n font (`$xx`) while drawing.\n\n Before calling this function, make sure to configure a render mode that\n does not use the color combiner's texture input, and register the font\n via [`Font::register`].\n\n @param parms         Layout parameters (`None` to use the defaults)\n @param font          Registered font to use to draw the text\n @param x0            X coordinate where to start drawing the text\n @param y0            Y coordinate where to start drawing the text\n @param utf8_text     Text to draw\n @return              Metrics of the text that was printed"]
+    #[inline]
+    pub fn print(
+        &mut self,
+        parms: Option<&TextParms>,
+        font: &RegisteredFont<'_>,
+        x0: f32,
+        y0: f32,
+        utf8_text: &str,
+    ) -> TextMetrics {
+        state::forget_all();
+        TextMetrics(unsafe {
+            rdpq_text_printn(
+                parms.map(|p| &p.0 as _).unwrap_or_else(core::ptr::null),
+                font.id(),
+                x0,
+                y0,
+                utf8_text.as_ptr() as _,
+                utf8_text.len() as _,
+            )
+        })
+    }
+    #[doc = "Layout and render formatted text in a single call\n\n This is the `core::fmt` counterpart of [`RdpQ::print`]. Prefer using\n the [`rdpq_print!`](crate::rdpq_print) macro instead of calling this directly."]
+    #[inline]
+    pub fn print_fmt(
+        &mut self,
+        parms: Option<&TextParms>,
+        font: &RegisteredFont<'_>,
+        x0: f32,
+        y0: f32,
+        args: core::fmt::Arguments<'_>,
+    ) -> TextMetrics {
+        match args.as_str() {
+            Some(text) => self.print(parms, font, x0, y0, text),
+            None => self.print(parms, font, x0, y0, &alloc_::fmt::format(args)),
+        }
+    }
+}
+
+#[doc = "Layout and render formatted text in a single call\n\n Usage: `rdpq_print!(rdpq, parms, font, x0, y0, \"fmt\", args...)`.\n Refer to [`RdpQ::print`] for more details."]
+#[macro_export]
+macro_rules! rdpq_print {
+    ($rdpq:expr, $parms:expr, $font:expr, $x0:expr, $y0:expr, $($arg:tt)*) => {
+        $rdpq.print_fmt(
+            $parms,
+            $font,
+            $x0,
+            $y0,
+            ::core::format_args!($($arg)*),
+        )
+    };
+}
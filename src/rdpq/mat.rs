@@ -0,0 +1,148 @@
+use core::{ffi::CStr, marker::PhantomData, ptr::NonNull};
+
+use alloc_::boxed::Box;
+
+use crate::sys::rdpq_mat::*;
+
+use super::{RdpQ, state};
+
+#[doc = "A material database, opened from a file.\n\n A material database is a collection of named materials. Each material\n can be loaded via [`MatDb::load`]. The database is closed when dropped."]
+#[derive(Debug)]
+pub struct MatDb(NonNull<rdpq_matdb_t>);
+
+#[doc = "A material, loaded from a [`MatDb`] or from a buffer in memory.\n\n A material describes the full RDP state required to draw with it (render\n mode, combiner, blender, textures, etc.), plus optional user-defined\n extensions. The material is freed when dropped."]
+#[derive(Debug)]
+pub struct Material<'db> {
+    ptr: NonNull<rdpq_mat_t>,
+    _buf: Option<Box<[u8]>>,
+    _db: PhantomData<&'db MatDb>,
+}
+
+impl MatDb {
+    #[doc = "Open a material database from a file.\n\n @param filename    Path to the material database file\n @return            Opened database"]
+    #[inline]
+    pub fn open(filename: &CStr) -> Self {
+        let ptr = unsafe { rdpq_matdb_open(filename.as_ptr()) };
+        Self(NonNull::new(ptr).unwrap())
+    }
+    #[doc = "Load a material from the database.\n\n Returns `None` if the database does not contain a material with the\n given name.\n\n @param mat_name    Name of the material to load"]
+    #[inline]
+    pub fn load(&self, mat_name: &CStr) -> Option<Material<'_>> {
+        let ptr = unsafe { rdpq_matdb_load(self.0.as_ptr(), mat_name.as_ptr()) };
+        NonNull::new(ptr).map(|ptr| Material {
+            ptr,
+            _buf: None,
+            _db: PhantomData,
+        })
+    }
+    #[inline]
+    pub const fn as_raw(&self) -> *mut rdpq_matdb_t {
+        self.0.as_ptr()
+    }
+}
+
+impl Drop for MatDb {
+    #[doc = "Close a material database"]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { rdpq_matdb_close(self.0.as_ptr()) }
+    }
+}
+
+impl Material<'static> {
+    #[doc = "Load a material from a buffer in memory.\n\n The material is relocated in place within the buffer, so the buffer is\n kept alive for as long as the material exists. The buffer must be aligned\n to 16 bytes, as returned for instance by [`crate::asset::load`].\n\n @param buf       Buffer containing the material data\n\n # Panics\n\n Panics if the buffer is not aligned to 16 bytes."]
+    #[inline]
+    pub fn load_buf(mut buf: Box<[u8]>) -> Self {
+        assert!(
+            buf.as_ptr().align_offset(16) == 0,
+            "material buffer must be aligned to 16 bytes"
+        );
+        let ptr = unsafe { rdpq_mat_load_buf(buf.as_mut_ptr() as _, buf.len() as _) };
+        Self {
+            ptr: NonNull::new(ptr).unwrap(),
+            _buf: Some(buf),
+            _db: PhantomData,
+        }
+    }
+}
+
+impl<'db> Material<'db> {
+    #[doc = "Get a boolean extension value of the material.\n\n Returns `None` if the material does not contain the extension, or if\n it has a different type.\n\n @param ext_key     Key of the extension"]
+    #[inline]
+    pub fn get_bool(&self, ext_key: u32) -> Option<bool> {
+        let mut value = false;
+        unsafe { __rdpq_mat_ext_get_bool(self.ptr.as_ptr(), ext_key, &mut value) }.then_some(value)
+    }
+    #[doc = "Get an integer extension value of the material.\n\n Returns `None` if the material does not contain the extension, or if\n it has a different type.\n\n @param ext_key     Key of the extension"]
+    #[inline]
+    pub fn get_int(&self, ext_key: u32) -> Option<u32> {
+        let mut value = 0;
+        unsafe { __rdpq_mat_ext_get_int(self.ptr.as_ptr(), ext_key, &mut value) }.then_some(value)
+    }
+    #[doc = "Get a floating point extension value of the material.\n\n Returns `None` if the material does not contain the extension, or if\n it has a different type.\n\n @param ext_key     Key of the extension"]
+    #[inline]
+    pub fn get_float(&self, ext_key: u32) -> Option<f32> {
+        let mut value = 0.0;
+        unsafe { __rdpq_mat_ext_get_float(self.ptr.as_ptr(), ext_key, &mut value) }.then_some(value)
+    }
+    #[doc = "Get a string extension value of the material.\n\n Returns `None` if the material does not contain the extension, or if\n it has a different type. The string is stored within the material.\n\n @param ext_key     Key of the extension"]
+    #[inline]
+    pub fn get_string(&self, ext_key: u32) -> Option<&CStr> {
+        let mut value = core::ptr::null_mut();
+        unsafe {
+            (__rdpq_mat_ext_get_string(self.ptr.as_ptr(), ext_key, &mut value) && !value.is_null())
+                .then(|| CStr::from_ptr(value))
+        }
+    }
+    #[inline]
+    pub const fn as_raw(&self) -> *mut rdpq_mat_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl<'db> Drop for Material<'db> {
+    #[doc = "Free a material"]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { rdpq_mat_free(self.ptr.as_ptr()) }
+    }
+}
+
+#[doc = "Set the path where textures referenced by materials are loaded from.\n\n By default, textures are loaded using the path stored within the material.\n This function overrides the directory used to look them up. The path is\n referenced by the library, so it must be valid for the whole program.\n\n @param path      Directory containing the textures"]
+#[inline]
+pub fn mat_set_texture_path(path: impl Into<&'static CStr>) {
+    unsafe { rdpq_mat_set_texture_path(path.into().as_ptr()) }
+}
+
+#[doc = "Drawing with a material, created by [`RdpQ::mat_draw_begin`].\n\n The RDP state configured by the material stays active until this is\n dropped (or [`MaterialDraw::end`] is called)."]
+#[derive(Debug)]
+pub struct MaterialDraw<'s, 'r>(
+    NonNull<rdpq_mat_t>,
+    PhantomData<(&'s Material<'s>, &'s mut RdpQ<'r>)>,
+);
+
+static_wrapper! { MaterialDraw<'s, 'r> => RdpQ<'r> { RdpQ(PhantomData) } }
+
+impl<'s, 'r> MaterialDraw<'s, 'r> {
+    #[inline]
+    pub fn end(self) {}
+}
+
+impl<'s, 'r> Drop for MaterialDraw<'s, 'r> {
+    #[doc = "Finish drawing with a material, restoring the previous RDP state"]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { rdpq_mat_draw_end(self.0.as_ptr()) }
+        state::forget_all();
+    }
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Start drawing with a material.\n\n This function configures the RDP for drawing with the material: render\n mode, combiner, blender and textures are all set up as described by the\n material. Draw calls can be issued through the returned guard.\n\n @param mat       Material to draw with"]
+    #[inline]
+    pub fn mat_draw_begin<'s>(&'s mut self, mat: &'s Material<'_>) -> MaterialDraw<'s, 'r> {
+        state::forget_all();
+        unsafe { rdpq_mat_draw_begin(mat.ptr.as_ptr()) }
+        MaterialDraw(mat.ptr, PhantomData)
+    }
+}
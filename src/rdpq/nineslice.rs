@@ -0,0 +1,171 @@
+use crate::{sprite::Sprite, surface::Surface};
+
+use super::{BlitParms, RdpQ, RdpQModes, Tile, TmemPlan, TmemTexture};
+
+#[doc = "How a stretchable part of a nine-slice is filled"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SliceMode {
+    #[doc = "The part is scaled to cover the destination"]
+    #[default]
+    Stretch,
+    #[doc = "The part is repeated unscaled to cover the destination (the last repetition is cut)"]
+    Tile,
+}
+
+#[doc = "Nine-slice (aka 9-patch) drawing parameters, for [`RdpQ::nine_slice`].\n\n The source image is split in a 3x3 grid by four inset margins. The corners\n are drawn unscaled, the edges are stretched or tiled along their length,\n and the center is stretched or tiled in both directions. This allows to\n draw frames of any size (eg: dialog boxes and buttons) from a small image.\n\n If the destination is smaller than the sum of the margins, the corners are\n shrunk proportionally."]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NineSlice {
+    left: u16,
+    top: u16,
+    right: u16,
+    bottom: u16,
+    edges: SliceMode,
+    center: SliceMode,
+}
+
+impl NineSlice {
+    #[doc = "Create nine-slice parameters with the given margins (in source pixels)"]
+    #[inline]
+    pub const fn new(left: u16, top: u16, right: u16, bottom: u16) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+            edges: SliceMode::Stretch,
+            center: SliceMode::Stretch,
+        }
+    }
+    #[doc = "How the four edges are filled (default: stretch)"]
+    #[inline]
+    pub const fn edges(mut self, edges: SliceMode) -> Self {
+        self.edges = edges;
+        self
+    }
+    #[doc = "How the center is filled (default: stretch)"]
+    #[inline]
+    pub const fn center(mut self, center: SliceMode) -> Self {
+        self.center = center;
+        self
+    }
+}
+
+#[inline]
+fn split(lo: f32, hi: f32, m0: u16, m1: u16) -> [f32; 4] {
+    let (m0, m1) = (m0 as f32, m1 as f32);
+    let size = hi - lo;
+    let (m0, m1) = if size < m0 + m1 {
+        (size * m0 / (m0 + m1), size * m1 / (m0 + m1))
+    } else {
+        (m0, m1)
+    };
+    [lo, lo + m0, hi - m1, hi]
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Draw a surface as a nine-slice, covering a destination rectangle\n\n The surface is drawn via texture rectangles, using the current render\n mode (so it also works within a [`super::ModeFrame`]). If the surface fits\n TMEM, it is uploaded once; otherwise, each part is uploaded separately,\n and parts that do not fit TMEM on their own are drawn via [`RdpQ::blit`],\n which splits them in chunks. Color-indexed surfaces require the palette\n to be uploaded beforehand, as with [`RdpQ::tex_upload`].\n\n Copy mode cannot scale textures, so it can only be used if no part is\n scaled: the edges and the center must use [`SliceMode::Tile`], and the\n destination must not be smaller than the sum of the margins.\n\n @param surf      Surface to draw\n @param slice     Margins and fill modes\n @param x0        Top-left X coordinate of the destination\n @param y0        Top-left Y coordinate of the destination\n @param x1        Bottom-right *exclusive* X coordinate of the destination\n @param y1        Bottom-right *exclusive* Y coordinate of the destination"]
+    pub fn nine_slice(
+        &mut self,
+        surf: &Surface,
+        slice: &NineSlice,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+    ) {
+        let whole = TmemPlan::new(&[TmemTexture::surface(surf)]).is_ok();
+        if whole {
+            self.tex_upload(Tile::_0, surf, None);
+        }
+        self.nine_slice_parts(surf, whole, slice, [x0, y0, x1, y1]);
+    }
+    #[doc = "Draw a sprite as a nine-slice, covering a destination rectangle\n\n This is similar to [`RdpQ::nine_slice`], but the palette of color-indexed\n sprites is uploaded and activated automatically, as with\n [`RdpQ::sprite_upload`].\n\n @param sprite    Sprite to draw\n @param slice     Margins and fill modes\n @param x0        Top-left X coordinate of the destination\n @param y0        Top-left Y coordinate of the destination\n @param x1        Bottom-right *exclusive* X coordinate of the destination\n @param y1        Bottom-right *exclusive* Y coordinate of the destination"]
+    pub fn sprite_nine_slice(
+        &mut self,
+        sprite: &Sprite,
+        slice: &NineSlice,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+    ) {
+        let whole = sprite.fits_tmem();
+        if whole {
+            self.sprite_upload(Tile::_0, sprite, None);
+        } else if let Some(palette) = sprite.palette() {
+            self.tex_upload_tlut(palette, 0);
+            self.mode_tlut(sprite.format().tlut());
+        }
+        self.nine_slice_parts(&sprite.pixels(), whole, slice, [x0, y0, x1, y1]);
+    }
+
+    fn nine_slice_parts(&mut self, surf: &Surface, whole: bool, slice: &NineSlice, dst: [f32; 4]) {
+        let (w, h) = (surf.width(), surf.height());
+        let sx = [0, slice.left, w.saturating_sub(slice.right), w].map(|v| v.min(w) as i32);
+        let sy = [0, slice.top, h.saturating_sub(slice.bottom), h].map(|v| v.min(h) as i32);
+        let dx = split(dst[0], dst[2], slice.left, slice.right);
+        let dy = split(dst[1], dst[3], slice.top, slice.bottom);
+
+        for j in 0..3 {
+            for i in 0..3 {
+                let (s0, t0, s1, t1) = (sx[i], sy[j], sx[i + 1], sy[j + 1]);
+                let (x0, y0, x1, y1) = (dx[i], dy[j], dx[i + 1], dy[j + 1]);
+                if s0 >= s1 || t0 >= t1 || x0 >= x1 || y0 >= y1 {
+                    continue;
+                }
+                let mode = match (i, j) {
+                    (1, 1) => slice.center,
+                    (1, _) | (_, 1) => slice.edges,
+                    _ => SliceMode::Stretch,
+                };
+
+                let part = TmemTexture::new(surf.format(), (s1 - s0) as u16, (t1 - t0) as u16);
+                let in_tmem = whole || TmemPlan::new(&[part]).is_ok();
+                if !whole && in_tmem {
+                    self.tex_upload_sub(Tile::_0, surf, None, s0, t0, s1, t1);
+                }
+                let (s0, t0, s1, t1) = (s0 as f32, t0 as f32, s1 as f32, t1 as f32);
+                match mode {
+                    SliceMode::Stretch => {
+                        self.nine_slice_rect(surf, in_tmem, [x0, y0, x1, y1], [s0, t0, s1, t1])
+                    }
+                    SliceMode::Tile => {
+                        let mut y = y0;
+                        while y < y1 {
+                            let mut x = x0;
+                            while x < x1 {
+                                let (xe, ye) = ((x + s1 - s0).min(x1), (y + t1 - t0).min(y1));
+                                let src = [s0, t0, s0 + xe - x, t0 + ye - y];
+                                self.nine_slice_rect(surf, in_tmem, [x, y, xe, ye], src);
+                                x += s1 - s0;
+                            }
+                            y += t1 - t0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[doc = "Draw a part of a nine-slice, either from TMEM or blitting it in chunks"]
+    fn nine_slice_rect(&mut self, surf: &Surface, in_tmem: bool, dst: [f32; 4], src: [f32; 4]) {
+        let [x0, y0, x1, y1] = dst;
+        let [s0, t0, s1, t1] = src;
+        if !in_tmem {
+            // A width or height of 0 would blit the whole surface
+            let (w, h) = (((s1 - s0) as i32).max(1), ((t1 - t0) as i32).max(1));
+            let parms = BlitParms::new()
+                .s0(s0 as i32)
+                .t0(t0 as i32)
+                .width(w)
+                .height(h)
+                .scale_x((x1 - x0) / w as f32)
+                .scale_y((y1 - y0) / h as f32);
+            self.blit(surf, x0, y0, Some(&parms));
+        } else if x1 - x0 == s1 - s0 && y1 - y0 == t1 - t0 {
+            self.texture_rectangle(Tile::_0, x0, y0, x1, y1, s0, t0);
+        } else {
+            self.texture_rectangle_scaled(Tile::_0, x0, y0, x1, y1, s0, t0, s1, t1);
+        }
+    }
+}
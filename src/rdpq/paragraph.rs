@@ -0,0 +1,230 @@
+use core::{marker::PhantomData, ptr::NonNull};
+
+use crate::sys::rdpq_paragraph::*;
+
+use super::{RdpQ, RegisteredFont, TextParms, state};
+
+#[doc = "A paragraph of text, fully laid out.\n\n This structure contains a paragraph of text that has been laid out\n by [`Paragraph::build`] or [`Paragraph::builder`], and is ready to\n be rendered any number of times via [`RdpQ::paragraph_render`].\n\n The paragraph borrows all the fonts used within it, as rendering refers\n to them by font ID."]
+#[derive(Debug)]
+pub struct Paragraph<'f>(NonNull<rdpq_paragraph_t>, PhantomData<&'f ()>);
+
+#[doc = "A single character of a laid out paragraph"]
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct ParagraphChar(rdpq_paragraph_char_t);
+
+#[doc = "Bounding box of a laid out paragraph"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+pub struct BBox {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+}
+
+static mut BUILDER_ACTIVE: bool = false;
+
+impl<'f> Paragraph<'f> {
+    #[doc = "Calculate the layout of a text using the specified parameters.\n\n This function accepts UTF-8 encoded text. It will layout the text according\n to the parameters provided, and return the paragraph together with the\n number of bytes of the text that were processed.\n\n Notice that `max_chars` in the parameters limits the number of characters\n that are laid out; the returned byte count can be used to continue from\n that point.\n\n @param parms             Layout parameters (`None` to use the defaults)\n @param initial_font      Font to use initially\n @param utf8_text         Text to layout\n @return                  Calculated layout and number of bytes processed"]
+    #[inline]
+    pub fn build(
+        parms: Option<&TextParms>,
+        initial_font: &'f RegisteredFont<'_>,
+        utf8_text: &str,
+    ) -> (Self, usize) {
+        let mut nbytes = utf8_text.len() as _;
+        let ptr = unsafe {
+            rdpq_paragraph_build(
+                parms
+                    .map(|p| &p.0 as *const _ as _)
+                    .unwrap_or_else(core::ptr::null),
+                initial_font.id(),
+                utf8_text.as_ptr() as _,
+                &mut nbytes,
+            )
+        };
+        (
+            Self(NonNull::new(ptr).unwrap(), PhantomData),
+            nbytes as usize,
+        )
+    }
+    #[doc = "Start a paragraph builder.\n\n This function is the low-level API to build a paragraph. It allows\n to add spans of text with different fonts and styles, and explicit\n newlines. Only one builder can be active at any given time.\n\n The layout parameters are read again while adding spans, so they stay\n borrowed for as long as the builder is alive.\n\n @param parms             Layout parameters (`None` to use the defaults)\n @param initial_font      Font to use initially"]
+    #[inline]
+    pub fn builder(
+        parms: Option<&'f TextParms>,
+        initial_font: &'f RegisteredFont<'_>,
+    ) -> ParagraphBuilder<'f> {
+        unsafe {
+            assert!(
+                !(&raw mut BUILDER_ACTIVE).read_volatile(),
+                "another paragraph builder is already active"
+            );
+            (&raw mut BUILDER_ACTIVE).write_volatile(true);
+            rdpq_paragraph_builder_begin(
+                parms
+                    .map(|p| &p.0 as *const _ as _)
+                    .unwrap_or_else(core::ptr::null),
+                initial_font.id(),
+                core::ptr::null_mut(),
+            );
+        }
+        ParagraphBuilder(PhantomData)
+    }
+    #[doc = "Bounding box of the text, relative to the drawing position"]
+    #[inline]
+    pub fn bbox(&self) -> BBox {
+        let bbox = unsafe { self.0.as_ref().bbox };
+        BBox {
+            x0: bbox.x0,
+            y0: bbox.y0,
+            x1: bbox.x1,
+            y1: bbox.y1,
+        }
+    }
+    #[doc = "Horizontal advance that the text has caused"]
+    #[inline]
+    pub fn advance_x(&self) -> f32 {
+        unsafe { self.0.as_ref().advance_x }
+    }
+    #[doc = "Vertical advance that the text has caused"]
+    #[inline]
+    pub fn advance_y(&self) -> f32 {
+        unsafe { self.0.as_ref().advance_y }
+    }
+    #[doc = "Number of lines in the text"]
+    #[inline]
+    pub fn nlines(&self) -> usize {
+        unsafe { self.0.as_ref().nlines as usize }
+    }
+    #[doc = "Alignment offset of the text, as calculated by the layout"]
+    #[inline]
+    pub fn offset(&self) -> (f32, f32) {
+        let layout = unsafe { self.0.as_ref() };
+        (layout.x0, layout.y0)
+    }
+    #[doc = "Laid out characters, in drawing order"]
+    #[inline]
+    pub fn chars(&self) -> &[ParagraphChar] {
+        unsafe {
+            let layout = self.0.as_ref();
+            core::slice::from_raw_parts(layout.chars.as_ptr() as _, layout.nchars as usize)
+        }
+    }
+    #[inline]
+    pub const fn as_raw(&self) -> *mut rdpq_paragraph_t {
+        self.0.as_ptr()
+    }
+}
+
+impl<'f> Drop for Paragraph<'f> {
+    #[doc = "Free the memory allocated by a paragraph"]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { rdpq_paragraph_free(self.0.as_ptr()) }
+    }
+}
+
+impl ParagraphChar {
+    #[doc = "ID of the font used to draw the character"]
+    #[inline]
+    pub fn font_id(&self) -> u8 {
+        unsafe { self.0.__bindgen_anon_1.__bindgen_anon_1.font_id() }
+    }
+    #[doc = "ID of the font atlas containing the glyph"]
+    #[inline]
+    pub fn atlas_id(&self) -> u8 {
+        unsafe { self.0.__bindgen_anon_1.__bindgen_anon_1.atlas_id() }
+    }
+    #[doc = "ID of the style used to draw the character"]
+    #[inline]
+    pub fn style_id(&self) -> u8 {
+        unsafe { self.0.__bindgen_anon_1.__bindgen_anon_1.style_id() }
+    }
+    #[doc = "X position of the glyph, relative to the paragraph origin"]
+    #[inline]
+    pub fn x(&self) -> i32 {
+        let x = unsafe { self.0.__bindgen_anon_1.__bindgen_anon_1.x() };
+        (x << 20) >> 20
+    }
+    #[doc = "Y position of the glyph, relative to the paragraph origin"]
+    #[inline]
+    pub fn y(&self) -> i32 {
+        let y = unsafe { self.0.__bindgen_anon_1.__bindgen_anon_1.y() };
+        (y << 20) >> 20
+    }
+    #[doc = "Index of the glyph within the font"]
+    #[inline]
+    pub fn glyph(&self) -> i16 {
+        unsafe { self.0.__bindgen_anon_1.__bindgen_anon_1.glyph() }
+    }
+}
+
+impl core::fmt::Debug for ParagraphChar {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ParagraphChar")
+            .field("font_id", &self.font_id())
+            .field("atlas_id", &self.atlas_id())
+            .field("style_id", &self.style_id())
+            .field("x", &self.x())
+            .field("y", &self.y())
+            .field("glyph", &self.glyph())
+            .finish()
+    }
+}
+
+#[doc = "Builder of a paragraph, created by [`Paragraph::builder`].\n\n The builder must be finished with [`ParagraphBuilder::end`]."]
+#[derive(Debug)]
+pub struct ParagraphBuilder<'f>(PhantomData<&'f ()>);
+
+impl<'f> crate::Undroppable for &mut ParagraphBuilder<'f> {
+    const ERROR: &'static str = "Finish the paragraph with ParagraphBuilder::end";
+}
+
+impl<'f> Drop for ParagraphBuilder<'f> {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = crate::DropBomb::new(self);
+    }
+}
+
+impl<'f> ParagraphBuilder<'f> {
+    #[doc = "Change the current font\n\n @param font       Font to switch to"]
+    #[inline]
+    pub fn font(&mut self, font: &'f RegisteredFont<'_>) {
+        unsafe { rdpq_paragraph_builder_font(font.id()) }
+    }
+    #[doc = "Change the current style\n\n @param style_id   Style to switch to"]
+    #[inline]
+    pub fn style(&mut self, style_id: u8) {
+        unsafe { rdpq_paragraph_builder_style(style_id) }
+    }
+    #[doc = "Add a span of text\n\n Add a span of text to the paragraph. The span will be laid out\n according to the current font and style.\n\n The span is processed immediately, so there is no need to keep\n the text alive after this function returns.\n\n @param utf8_text   Text to add"]
+    #[inline]
+    pub fn span(&mut self, utf8_text: &str) {
+        unsafe { rdpq_paragraph_builder_span(utf8_text.as_ptr() as _, utf8_text.len() as _) }
+    }
+    #[doc = "Start a new line\n\n This function is equivalent to adding a span containing a single\n newline character."]
+    #[inline]
+    pub fn newline(&mut self) {
+        unsafe { rdpq_paragraph_builder_newline() }
+    }
+    #[doc = "Finalize the paragraph and return the layout."]
+    #[inline]
+    pub fn end(self) -> Paragraph<'f> {
+        let _ = core::mem::ManuallyDrop::new(self);
+        unsafe {
+            let ptr = rdpq_paragraph_builder_end();
+            (&raw mut BUILDER_ACTIVE).write_volatile(false);
+            Paragraph(NonNull::new(ptr).unwrap(), PhantomData)
+        }
+    }
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Render a text that was laid out by [`Paragraph::build`]\n\n This function will render the text that was laid out. The paragraph\n can be rendered multiple times at different positions.\n\n @param layout   Layout to render\n @param x0       X coordinate where to start drawing the text\n @param y0       Y coordinate where to start drawing the text"]
+    #[inline]
+    pub fn paragraph_render(&mut self, layout: &Paragraph<'_>, x0: f32, y0: f32) {
+        state::forget_all();
+        unsafe { rdpq_paragraph_render(layout.0.as_ptr(), x0, y0) }
+    }
+}
@@ -0,0 +1,578 @@
+use alloc_::vec::Vec;
+
+use crate::fmath::{atan2f, ceilf, sincosf, sqrtf};
+use crate::graphics::Color;
+
+use super::{RdpQ, Topology, TriFormat, tri};
+
+#[doc = "A 2D point, in screen coordinates"]
+pub type Point = [f32; 2];
+
+const PI: f32 = core::f32::consts::PI;
+
+#[inline]
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+#[inline]
+fn add_scaled(a: Point, d: Point, s: f32) -> Point {
+    [a[0] + d[0] * s, a[1] + d[1] * s]
+}
+
+#[inline]
+fn cross(a: Point, b: Point) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+#[doc = "Unit direction from `a` to `b`, or `None` if the points coincide"]
+#[inline]
+fn direction(a: Point, b: Point) -> Option<Point> {
+    let d = sub(b, a);
+    let len = sqrtf(d[0] * d[0] + d[1] * d[1]);
+    (len > 0.0).then(|| [d[0] / len, d[1] / len])
+}
+
+#[doc = "Normal of a unit direction (rotated by 90 degrees)"]
+#[inline]
+fn normal(d: Point) -> Point {
+    [-d[1], d[0]]
+}
+
+#[doc = "Shape of the ends of open lines"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Cap {
+    #[doc = "The line ends exactly at its end points"]
+    #[default]
+    Butt,
+    #[doc = "The line is extended by half its width beyond its end points"]
+    Square,
+    #[doc = "The line ends with half circles. Joins between segments are rounded as well"]
+    Round,
+}
+
+#[doc = "How shapes are colored"]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Paint {
+    #[doc = "A single color"]
+    Solid(Color),
+    #[doc = "A linear gradient, going from `from` at `start` to `to` at `end`.\n\n Colors are computed at the vertices and interpolated by the RDP, so\n the gradient is exact only when the shape lies between `start` and\n `end` along the gradient axis."]
+    Linear {
+        start: Point,
+        end: Point,
+        from: Color,
+        to: Color,
+    },
+}
+
+impl Paint {
+    #[doc = "Color of the paint at the given point, as RGBA components in the 0..1 range"]
+    pub fn color_at(&self, p: Point) -> [f32; 4] {
+        let rgba = |c: &Color| [c.r, c.g, c.b, c.a].map(|v| v as f32 / 255.0);
+        match self {
+            Self::Solid(c) => rgba(c),
+            Self::Linear {
+                start,
+                end,
+                from,
+                to,
+            } => {
+                let axis = sub(*end, *start);
+                let len2 = axis[0] * axis[0] + axis[1] * axis[1];
+                let t = match len2 > 0.0 {
+                    true => {
+                        let rel = sub(p, *start);
+                        ((rel[0] * axis[0] + rel[1] * axis[1]) / len2).clamp(0.0, 1.0)
+                    }
+                    false => 0.0,
+                };
+                let (from, to) = (rgba(from), rgba(to));
+                core::array::from_fn(|i| from[i] + (to[i] - from[i]) * t)
+            }
+        }
+    }
+}
+
+impl From<Color> for Paint {
+    #[inline]
+    fn from(value: Color) -> Self {
+        Self::Solid(value)
+    }
+}
+
+#[doc = "A tessellator of 2D vector shapes into shaded triangles.\n\n Shapes are added with the current [`Paint`], and accumulated as a list of\n triangles (see [`Shapes::vertices`]) that can be drawn with\n [`Shapes::draw`]. The tessellation is pure Rust and does not access the\n RDP, so it can be done ahead of time and reused for several frames.\n\n Drawing requires a render mode that uses the shade color, for instance\n [`super::COMBINER_SHADE`] in the standard mode (plus blending, for\n translucent colors)."]
+#[derive(Debug, Clone)]
+pub struct Shapes {
+    vertices: Vec<tri::ShadeVertex>,
+    paint: Paint,
+    segments: u16,
+}
+
+impl Default for Shapes {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shapes {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            paint: Paint::Solid(Color::WHITE),
+            segments: 0,
+        }
+    }
+    #[doc = "Set the paint used by the shapes added afterwards (default: solid white)"]
+    #[inline]
+    pub fn set_paint(&mut self, paint: impl Into<Paint>) -> &mut Self {
+        self.paint = paint.into();
+        self
+    }
+    #[doc = "Set the number of segments used to approximate a full circle (default: 0).\n\n Arcs use a proportional number of segments. If 0, the number is chosen\n automatically from the radius."]
+    #[inline]
+    pub fn set_segments(&mut self, segments: u16) -> &mut Self {
+        self.segments = segments;
+        self
+    }
+    #[doc = "Triangles tessellated so far, as a list of vertices (see [`Topology::List`])"]
+    #[inline]
+    pub fn vertices(&self) -> &[tri::ShadeVertex] {
+        &self.vertices
+    }
+    #[doc = "Remove all the tessellated triangles, keeping the allocated memory"]
+    #[inline]
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+    #[doc = "Draw all the tessellated triangles"]
+    #[inline]
+    pub fn draw(&self, rdpq: &mut RdpQ) {
+        rdpq.triangles(
+            &TriFormat::<tri::Shade>::new(),
+            Topology::List,
+            &self.vertices,
+        );
+    }
+
+    #[inline]
+    fn vertex(&self, pos: Point) -> tri::ShadeVertex {
+        tri::ShadeVertex {
+            pos,
+            color: self.paint.color_at(pos),
+        }
+    }
+    #[inline]
+    fn triangle(&mut self, a: Point, b: Point, c: Point) {
+        let v = [self.vertex(a), self.vertex(b), self.vertex(c)];
+        self.vertices.extend_from_slice(&v);
+    }
+    #[inline]
+    fn quad(&mut self, a: Point, b: Point, c: Point, d: Point) {
+        self.triangle(a, b, c);
+        self.triangle(a, c, d);
+    }
+    #[doc = "Fill the region between two rings of points, with the same length"]
+    fn ring_strip(&mut self, outer: &[Point], inner: &[Point], closed: bool) {
+        let n = outer.len();
+        let count = if closed { n } else { n.saturating_sub(1) };
+        for i in 0..count {
+            let j = (i + 1) % n;
+            self.quad(outer[i], outer[j], inner[j], inner[i]);
+        }
+    }
+    #[doc = "Fill a triangle fan around a center"]
+    fn fan(&mut self, center: Point, ring: &[Point], closed: bool) {
+        let n = ring.len();
+        let count = if closed { n } else { n.saturating_sub(1) };
+        for i in 0..count {
+            self.triangle(center, ring[i], ring[(i + 1) % n]);
+        }
+    }
+
+    #[doc = "Number of segments used for an arc of the given radius and angle"]
+    fn arc_segments(&self, radius: f32, sweep: f32) -> usize {
+        let full = match self.segments {
+            0 => (radius as u32 / 2 + 8).min(64),
+            n => n.max(3) as u32,
+        };
+        (ceilf(full as f32 * sweep.abs() / (2.0 * PI)) as usize).max(1)
+    }
+    #[doc = "Points of an arc, from `start` sweeping by `sweep` radians (both ends included)"]
+    fn arc_points(&self, center: Point, radius: f32, start: f32, sweep: f32) -> Vec<Point> {
+        self.arc_points_n(
+            center,
+            radius,
+            start,
+            sweep,
+            self.arc_segments(radius, sweep),
+        )
+    }
+    #[doc = "Points of an arc split into `n` segments (both ends included)"]
+    fn arc_points_n(
+        &self,
+        center: Point,
+        radius: f32,
+        start: f32,
+        sweep: f32,
+        n: usize,
+    ) -> Vec<Point> {
+        let (ds, dc) = sincosf(sweep / n as f32);
+        let (s, c) = sincosf(start);
+        let mut dir = [c, s];
+        let mut points = Vec::with_capacity(n + 1);
+        for _ in 0..=n {
+            points.push(add_scaled(center, dir, radius));
+            dir = [dir[0] * dc - dir[1] * ds, dir[0] * ds + dir[1] * dc];
+        }
+        points
+    }
+
+    #[doc = "Add a thick line between two points\n\n @param a         First end point\n @param b         Second end point\n @param width     Width of the line\n @param cap       Shape of the ends of the line"]
+    pub fn line(&mut self, a: Point, b: Point, width: f32, cap: Cap) {
+        self.polyline(&[a, b], width, cap, false);
+    }
+    #[doc = "Add a thick line through a sequence of points\n\n Consecutive segments are connected with bevel joins, or round joins if\n the cap is [`Cap::Round`]. If `closed` is true, the last point is joined\n back to the first one, and no caps are drawn.\n\n @param points    Points of the line\n @param width     Width of the line\n @param cap       Shape of the ends of the line\n @param closed    Whether the line is closed"]
+    pub fn polyline(&mut self, points: &[Point], width: f32, cap: Cap, closed: bool) {
+        let hw = width * 0.5;
+        let mut points: Vec<Point> =
+            points
+                .iter()
+                .copied()
+                .fold(Vec::with_capacity(points.len()), |mut v, p| {
+                    if v.last() != Some(&p) {
+                        v.push(p);
+                    }
+                    v
+                });
+        if closed && points.len() > 2 && points.first() == points.last() {
+            points.pop();
+        }
+        if hw <= 0.0 || points.is_empty() {
+            return;
+        }
+        if points.len() == 1 {
+            if cap == Cap::Round {
+                self.circle(points[0], hw);
+            }
+            return;
+        }
+
+        let n = points.len();
+        let closed = closed && n > 2;
+        let segs = if closed { n } else { n - 1 };
+        let dirs: Vec<Point> = (0..segs)
+            .filter_map(|i| direction(points[i], points[(i + 1) % n]))
+            .collect();
+
+        for (i, &d) in dirs.iter().enumerate() {
+            let (mut a, mut b) = (points[i], points[(i + 1) % n]);
+            if !closed && cap == Cap::Square {
+                if i == 0 {
+                    a = add_scaled(a, d, -hw);
+                }
+                if i == segs - 1 {
+                    b = add_scaled(b, d, hw);
+                }
+            }
+            let nrm = normal(d);
+            self.quad(
+                add_scaled(a, nrm, hw),
+                add_scaled(b, nrm, hw),
+                add_scaled(b, nrm, -hw),
+                add_scaled(a, nrm, -hw),
+            );
+        }
+
+        let joins = if closed { segs } else { segs - 1 };
+        for i in 0..joins {
+            let (d0, d1) = (dirs[i], dirs[(i + 1) % segs]);
+            let p = points[(i + 1) % n];
+            let turn = cross(d0, d1);
+            if turn == 0.0 {
+                continue;
+            }
+            // The gap to fill is on the outer side of the turn.
+            let side = if turn > 0.0 { -hw } else { hw };
+            let (n0, n1) = (normal(d0), normal(d1));
+            if cap == Cap::Round {
+                let start = atan2f(n0[1] * side, n0[0] * side);
+                let mut sweep = atan2f(n1[1] * side, n1[0] * side) - start;
+                if sweep > PI {
+                    sweep -= 2.0 * PI;
+                } else if sweep < -PI {
+                    sweep += 2.0 * PI;
+                }
+                let arc = self.arc_points(p, hw, start, sweep);
+                self.fan(p, &arc, false);
+            } else {
+                self.triangle(p, add_scaled(p, n0, side), add_scaled(p, n1, side));
+            }
+        }
+
+        if !closed && cap == Cap::Round {
+            let (first, last) = (dirs[0], dirs[segs - 1]);
+            let start = atan2f(first[0], -first[1]);
+            let arc = self.arc_points(points[0], hw, start, PI);
+            self.fan(points[0], &arc, false);
+            let start = atan2f(-last[0], last[1]);
+            let arc = self.arc_points(points[n - 1], hw, start, PI);
+            self.fan(points[n - 1], &arc, false);
+        }
+    }
+
+    #[doc = "Add a filled circle\n\n @param center    Center of the circle\n @param radius    Radius of the circle"]
+    pub fn circle(&mut self, center: Point, radius: f32) {
+        let ring = self.arc_points(center, radius, 0.0, 2.0 * PI);
+        self.fan(center, &ring[..ring.len() - 1], true);
+    }
+    #[doc = "Add the outline of a circle\n\n @param center    Center of the circle\n @param radius    Radius of the circle (at the middle of the outline)\n @param width     Width of the outline"]
+    pub fn circle_outline(&mut self, center: Point, radius: f32, width: f32) {
+        // Both rings need the same number of points to be stitched together,
+        // so the segment count is picked once from the outer radius.
+        let (ro, ri) = (radius + width * 0.5, (radius - width * 0.5).max(0.0));
+        let n = self.arc_segments(ro, 2.0 * PI);
+        let outer = self.arc_points_n(center, ro, 0.0, 2.0 * PI, n);
+        let inner = self.arc_points_n(center, ri, 0.0, 2.0 * PI, n);
+        self.ring_strip(&outer[..n], &inner[..n], true);
+    }
+    #[doc = "Add a thick circular arc\n\n Angles are in radians, with 0 pointing to the right and positive angles\n going clockwise on screen.\n\n @param center    Center of the arc\n @param radius    Radius of the arc (at the middle of the line)\n @param start     Starting angle\n @param end       Ending angle\n @param width     Width of the line\n @param cap       Shape of the ends of the arc"]
+    #[allow(clippy::too_many_arguments)]
+    pub fn arc(&mut self, center: Point, radius: f32, start: f32, end: f32, width: f32, cap: Cap) {
+        let points = self.arc_points(center, radius, start, end - start);
+        self.polyline(&points, width, cap, false);
+    }
+    #[doc = "Add a filled circular sector (a \"pie slice\")\n\n Angles follow the same conventions of [`Shapes::arc`].\n\n @param center    Center of the sector\n @param radius    Radius of the sector\n @param start     Starting angle\n @param end       Ending angle"]
+    pub fn sector(&mut self, center: Point, radius: f32, start: f32, end: f32) {
+        let points = self.arc_points(center, radius, start, end - start);
+        self.fan(center, &points, false);
+    }
+    #[doc = "Add a filled convex polygon\n\n The points can be in either winding order. Concave polygons are not\n tessellated correctly.\n\n @param points    Vertices of the polygon"]
+    pub fn polygon(&mut self, points: &[Point]) {
+        if let [first, rest @ ..] = points {
+            self.fan(*first, rest, false);
+        }
+    }
+    #[doc = "Add the outline of a polygon\n\n @param points    Vertices of the polygon\n @param width     Width of the outline"]
+    pub fn polygon_outline(&mut self, points: &[Point], width: f32) {
+        self.polyline(points, width, Cap::Butt, true);
+    }
+    #[doc = "Add a filled rectangle\n\n @param x0        Top-left X coordinate of the rectangle\n @param y0        Top-left Y coordinate of the rectangle\n @param x1        Bottom-right X coordinate of the rectangle\n @param y1        Bottom-right Y coordinate of the rectangle"]
+    pub fn rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        self.quad([x0, y0], [x1, y0], [x1, y1], [x0, y1]);
+    }
+    #[doc = "Points of the outline of a rounded rectangle, clockwise on screen"]
+    fn rounded_rect_points(&self, x0: f32, y0: f32, x1: f32, y1: f32, radius: f32) -> Vec<Point> {
+        let (x0, x1) = (x0.min(x1), x0.max(x1));
+        let (y0, y1) = (y0.min(y1), y0.max(y1));
+        let r = radius.clamp(0.0, (x1 - x0).min(y1 - y0) * 0.5);
+        if r == 0.0 {
+            return alloc_::vec![[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+        }
+        let corners = [
+            ([x1 - r, y0 + r], -PI * 0.5),
+            ([x1 - r, y1 - r], 0.0),
+            ([x0 + r, y1 - r], PI * 0.5),
+            ([x0 + r, y0 + r], PI),
+        ];
+        corners
+            .iter()
+            .flat_map(|&(c, start)| self.arc_points(c, r, start, PI * 0.5))
+            .collect()
+    }
+    #[doc = "Add a filled rectangle with rounded corners\n\n @param x0        Top-left X coordinate of the rectangle\n @param y0        Top-left Y coordinate of the rectangle\n @param x1        Bottom-right X coordinate of the rectangle\n @param y1        Bottom-right Y coordinate of the rectangle\n @param radius    Radius of the corners"]
+    pub fn rounded_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, radius: f32) {
+        let points = self.rounded_rect_points(x0, y0, x1, y1, radius);
+        let center = [(x0 + x1) * 0.5, (y0 + y1) * 0.5];
+        self.fan(center, &points, true);
+    }
+    #[doc = "Add the outline of a rectangle with rounded corners\n\n @param x0        Top-left X coordinate of the rectangle\n @param y0        Top-left Y coordinate of the rectangle\n @param x1        Bottom-right X coordinate of the rectangle\n @param y1        Bottom-right Y coordinate of the rectangle\n @param radius    Radius of the corners\n @param width     Width of the outline"]
+    #[allow(clippy::too_many_arguments)]
+    pub fn rounded_rect_outline(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        radius: f32,
+        width: f32,
+    ) {
+        let points = self.rounded_rect_points(x0, y0, x1, y1, radius);
+        self.polyline(&points, width, Cap::Butt, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(shapes: &Shapes) -> Vec<Point> {
+        shapes.vertices().iter().map(|v| v.pos).collect()
+    }
+
+    fn dist(a: Point, b: Point) -> f32 {
+        let d = sub(a, b);
+        sqrtf(d[0] * d[0] + d[1] * d[1])
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{a} != {b}");
+    }
+
+    #[test]
+    fn segments() {
+        let mut shapes = Shapes::new();
+        // Automatic: radius / 2 + 8, capped at 64.
+        assert_eq!(shapes.arc_segments(10.0, 2.0 * PI), 13);
+        assert_eq!(shapes.arc_segments(30.0, 2.0 * PI), 23);
+        assert_eq!(shapes.arc_segments(1000.0, 2.0 * PI), 64);
+        assert_eq!(shapes.arc_segments(30.0, PI), 12);
+        assert_eq!(shapes.arc_segments(30.0, 0.0), 1);
+        shapes.set_segments(2);
+        assert_eq!(shapes.arc_segments(30.0, 2.0 * PI), 3);
+        shapes.set_segments(16);
+        assert_eq!(shapes.arc_segments(30.0, -PI * 0.5), 4);
+    }
+
+    #[test]
+    fn circle() {
+        let mut shapes = Shapes::new();
+        shapes.set_segments(8).circle([50.0, 40.0], 10.0);
+        let pos = positions(&shapes);
+        assert_eq!(pos.len(), 8 * 3);
+        for tri in pos.chunks_exact(3) {
+            assert_eq!(tri[0], [50.0, 40.0]);
+            assert_near(dist(tri[1], tri[0]), 10.0);
+            assert_near(dist(tri[2], tri[0]), 10.0);
+        }
+        // The ring closes back on the first point.
+        assert_eq!(pos[pos.len() - 1], pos[1]);
+    }
+
+    #[test]
+    fn circle_outline() {
+        // The outer and inner radii would get 23 and 13 segments if picked
+        // independently.
+        let mut shapes = Shapes::new();
+        shapes.circle_outline([0.0, 0.0], 20.0, 20.0);
+        let pos = positions(&shapes);
+        assert_eq!(pos.len(), 23 * 6);
+        let (mut outer, mut inner) = (0, 0);
+        for &p in &pos {
+            let r = dist(p, [0.0, 0.0]);
+            match (r - 30.0).abs() < 1e-3 {
+                true => outer += 1,
+                false => {
+                    assert_near(r, 10.0);
+                    inner += 1;
+                }
+            }
+        }
+        assert_eq!((outer, inner), (23 * 3, 23 * 3));
+        // Each quad stitches matching angles of the two rings.
+        for quad in pos.chunks_exact(6) {
+            let (o, i) = (quad[0], quad[5]);
+            assert_near(o[0] / 30.0, i[0] / 10.0);
+            assert_near(o[1] / 30.0, i[1] / 10.0);
+        }
+    }
+
+    #[test]
+    fn line_caps() {
+        let bounds = |cap| {
+            let mut shapes = Shapes::new();
+            shapes
+                .set_segments(16)
+                .line([10.0, 10.0], [30.0, 10.0], 4.0, cap);
+            let pos = positions(&shapes);
+            let (mut lo, mut hi) = ([f32::MAX; 2], [f32::MIN; 2]);
+            for p in &pos {
+                for i in 0..2 {
+                    lo[i] = lo[i].min(p[i]);
+                    hi[i] = hi[i].max(p[i]);
+                }
+            }
+            (pos.len(), [lo[0], lo[1], hi[0], hi[1]])
+        };
+        let (n, b) = bounds(Cap::Butt);
+        assert_eq!(n, 6);
+        assert_eq!(b, [10.0, 8.0, 30.0, 12.0]);
+        let (n, b) = bounds(Cap::Square);
+        assert_eq!(n, 6);
+        assert_eq!(b, [8.0, 8.0, 32.0, 12.0]);
+        // Two half circles of 8 segments each.
+        let (n, b) = bounds(Cap::Round);
+        assert_eq!(n, 6 + 2 * 8 * 3);
+        for (v, e) in b.into_iter().zip([8.0, 8.0, 32.0, 12.0]) {
+            assert_near(v, e);
+        }
+    }
+
+    #[test]
+    fn polyline_joins() {
+        // A right angle: two segments and a single bevel join.
+        let mut shapes = Shapes::new();
+        let points = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]];
+        shapes.polyline(&points, 2.0, Cap::Butt, false);
+        assert_eq!(shapes.vertices().len(), (2 * 2 + 1) * 3);
+        // Closed: three segments and three joins, no caps.
+        shapes.clear();
+        shapes.polyline(&points, 2.0, Cap::Butt, true);
+        assert_eq!(shapes.vertices().len(), (3 * 2 + 3) * 3);
+        // Repeated points and collinear segments don't produce joins.
+        shapes.clear();
+        let points = [[0.0, 0.0], [0.0, 0.0], [5.0, 0.0], [10.0, 0.0]];
+        shapes.polyline(&points, 2.0, Cap::Butt, false);
+        assert_eq!(shapes.vertices().len(), 2 * 2 * 3);
+        // Degenerate lines.
+        shapes.clear();
+        shapes.polyline(&[[1.0, 1.0]], 2.0, Cap::Butt, false);
+        shapes.polyline(&points, 0.0, Cap::Butt, false);
+        assert!(shapes.vertices().is_empty());
+    }
+
+    #[test]
+    fn rounded_rect() {
+        let mut shapes = Shapes::new();
+        shapes.set_segments(16);
+        let points = shapes.rounded_rect_points(30.0, 20.0, 10.0, 0.0, 4.0);
+        // Four quarter circles of 4 segments, with both ends included.
+        assert_eq!(points.len(), 4 * 5);
+        for p in &points {
+            assert!((10.0 - 1e-3..=30.0 + 1e-3).contains(&p[0]), "{p:?}");
+            assert!((0.0 - 1e-3..=20.0 + 1e-3).contains(&p[1]), "{p:?}");
+        }
+        // Starts at the end of the top edge, going clockwise on screen.
+        assert_near(points[0][0], 30.0 - 4.0);
+        assert_near(points[0][1], 0.0);
+        assert_near(points[4][0], 30.0);
+        assert_near(points[4][1], 0.0 + 4.0);
+        // The radius is clamped to half of the shorter side.
+        let points = shapes.rounded_rect_points(0.0, 0.0, 10.0, 4.0, 100.0);
+        assert_near(points[0][0], 10.0 - 2.0);
+        // No radius, no arcs.
+        assert_eq!(
+            shapes.rounded_rect_points(0.0, 0.0, 10.0, 4.0, 0.0).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn paint() {
+        let mut shapes = Shapes::new();
+        shapes.set_paint(Paint::Linear {
+            start: [0.0, 0.0],
+            end: [10.0, 0.0],
+            from: Color::BLACK,
+            to: Color::WHITE,
+        });
+        shapes.rect(-5.0, 0.0, 5.0, 1.0);
+        for v in shapes.vertices() {
+            let t = (v.pos[0] / 10.0).max(0.0);
+            assert_eq!(v.color, [t, t, t, 1.0]);
+        }
+        let paint = Paint::from(Color::WHITE);
+        assert_eq!(paint.color_at([123.0, 4.0]), [1.0; 4]);
+    }
+}
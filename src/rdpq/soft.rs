@@ -0,0 +1,1493 @@
+use alloc_::{boxed::Box, vec};
+
+use crate::{
+    fmath::{fabsf, floorf},
+    graphics::Color,
+    surface::TexFormat,
+    sys::rdpq_macros::*,
+};
+
+use super::{
+    Blender, Combiner, SOM, Tile, TriFormat, TriOffsets,
+    commands::{DecodeError, RdpCommand, TileDescAxis, TriEdge, TriangleCmd},
+};
+
+const TMEM_SIZE: usize = 4096;
+const ZBUF_MAX: u16 = 0xFFFC;
+
+#[doc = "Convert a float into the s15.16 fixed point format used by triangle coefficients"]
+#[inline]
+fn to_s15_16(f: f32) -> i32 {
+    if f >= 32768.0 {
+        i32::MAX
+    } else if f < -32768.0 {
+        i32::MIN
+    } else {
+        floorf(f * 65536.0) as i32
+    }
+}
+
+#[doc = "Pack four interpolated attributes in the layout of the RDP shade/texture coefficients"]
+fn pack_coeffs(base: [i32; 4], dx: [i32; 4], de: [i32; 4], dy: [i32; 4]) -> [u64; 8] {
+    let int = |v: [i32; 4]| {
+        v.iter()
+            .fold(0u64, |w, &c| (w << 16) | ((c >> 16) as u16 as u64))
+    };
+    let frac = |v: [i32; 4]| v.iter().fold(0u64, |w, &c| (w << 16) | (c as u16 as u64));
+    [
+        int(base),
+        int(dx),
+        frac(base),
+        frac(dx),
+        int(de),
+        int(dy),
+        frac(de),
+        frac(dy),
+    ]
+}
+
+#[doc = "Unpack the RDP shade/texture coefficients into (base, DxDx, DxDe) values, in s15.16"]
+fn unpack_coeffs(coeffs: &[u64; 8]) -> ([i64; 4], [i64; 4], [i64; 4]) {
+    let get = |int: u64, frac: u64, i: usize| {
+        let shift = 48 - 16 * i as u32;
+        (((int >> shift) as u16 as u32) << 16 | ((frac >> shift) as u16 as u32)) as i32 as i64
+    };
+    let mut base = [0; 4];
+    let mut dx = [0; 4];
+    let mut de = [0; 4];
+    for i in 0..4 {
+        base[i] = get(coeffs[0], coeffs[2], i);
+        dx[i] = get(coeffs[1], coeffs[3], i);
+        de[i] = get(coeffs[4], coeffs[6], i);
+    }
+    (base, dx, de)
+}
+
+#[doc = "Compute the gradients of an attribute over a triangle, as rdpq does"]
+struct TriSetup {
+    hx: f32,
+    hy: f32,
+    mx: f32,
+    my: f32,
+    fy: f32,
+    ish: f32,
+    attr_factor: f32,
+}
+
+impl TriSetup {
+    #[doc = "Return the (base, DxDx, DxDe, DxDy) values of an attribute with the given vertex values"]
+    fn gradient(&self, a1: f32, a2: f32, a3: f32) -> [i32; 4] {
+        let ma = a2 - a1;
+        let ha = a3 - a1;
+        let dadx = (self.hy * ma - self.my * ha) * self.attr_factor;
+        let dady = (self.mx * ha - self.hx * ma) * self.attr_factor;
+        let dade = dady + dadx * self.ish;
+        [
+            to_s15_16(a1 + self.fy * dade),
+            to_s15_16(dadx),
+            to_s15_16(dade),
+            to_s15_16(dady),
+        ]
+    }
+}
+
+#[doc = "Compute the RDP triangle command for a triangle, as `rdpq_triangle` would do.\n\n This runs the same triangle setup as `rdpq_triangle` on the CPU, and returns\n the resulting RDP command, that can then be executed by a [`SoftRdp`].\n\n See [`super::RdpQ::triangle`] for a description of the vertex components."]
+pub fn triangle_setup<O: TriOffsets, const N: usize>(
+    fmt: &TriFormat<O>,
+    v1: &[f32; N],
+    v2: &[f32; N],
+    v3: &[f32; N],
+) -> TriangleCmd {
+    const {
+        assert!(N >= TriFormat::<O>::minimum_array_size());
+    }
+    let pos = O::POS as usize;
+    let (mut v1, mut v2, mut v3) = (v1, v2, v3);
+    let flat = v1;
+    if v1[pos + 1] > v2[pos + 1] {
+        core::mem::swap(&mut v1, &mut v2);
+    }
+    if v2[pos + 1] > v3[pos + 1] {
+        core::mem::swap(&mut v2, &mut v3);
+    }
+    if v1[pos + 1] > v2[pos + 1] {
+        core::mem::swap(&mut v1, &mut v2);
+    }
+
+    let (x1, x2, x3) = (v1[pos], v2[pos], v3[pos]);
+    let y1 = floorf(v1[pos + 1] * 4.0) / 4.0;
+    let y2 = floorf(v2[pos + 1] * 4.0) / 4.0;
+    let y3 = floorf(v3[pos + 1] * 4.0) / 4.0;
+    let to_s11_2 = |y: f32| (((floorf(y * 4.0) as i32) << 18) >> 18) as i16;
+
+    let hx = x3 - x1;
+    let hy = y3 - y1;
+    let mx = x2 - x1;
+    let my = y2 - y1;
+    let lx = x3 - x2;
+    let ly = y3 - y2;
+
+    let nz = hx * my - hy * mx;
+    let inv = |n: f32, d: f32| {
+        if fabsf(d) > f32::MIN_POSITIVE {
+            n / d
+        } else {
+            0.0
+        }
+    };
+    let setup = TriSetup {
+        hx,
+        hy,
+        mx,
+        my,
+        fy: floorf(y1) - y1,
+        ish: inv(hx, hy),
+        attr_factor: if fabsf(nz) > f32::MIN_POSITIVE {
+            -1.0 / nz
+        } else {
+            0.0
+        },
+    };
+    let ism = inv(mx, my);
+    let isl = inv(lx, ly);
+
+    let mut tri = TriangleCmd {
+        shade: O::SHADE.is_some(),
+        tex: O::TEX.is_some(),
+        zbuf: O::Z.is_some(),
+        left_major: nz < 0.0,
+        level: fmt.tex_mipmaps.saturating_sub(1).min(7) as u8,
+        tile: fmt.tex_tile,
+        yl: to_s11_2(y3),
+        ym: to_s11_2(y2),
+        yh: to_s11_2(y1),
+        l: TriEdge {
+            x: to_s15_16(x2),
+            dxdy: to_s15_16(isl),
+        },
+        h: TriEdge {
+            x: to_s15_16(x1 + setup.fy * setup.ish),
+            dxdy: to_s15_16(setup.ish),
+        },
+        m: TriEdge {
+            x: to_s15_16(x1 + setup.fy * ism),
+            dxdy: to_s15_16(ism),
+        },
+        shade_coeffs: [0; 8],
+        tex_coeffs: [0; 8],
+        z_coeffs: [0; 2],
+    };
+
+    if let Some(shade) = O::SHADE {
+        let s = shade as usize;
+        let mut c = [[0; 4]; 4];
+        for i in 0..4 {
+            let g = if fmt.shade_flat {
+                let v = flat[s + i] * 255.0;
+                setup.gradient(v, v, v)
+            } else {
+                setup.gradient(v1[s + i] * 255.0, v2[s + i] * 255.0, v3[s + i] * 255.0)
+            };
+            for j in 0..4 {
+                c[j][i] = g[j];
+            }
+        }
+        tri.shade_coeffs = pack_coeffs(c[0], c[1], c[2], c[3]);
+    }
+
+    if let Some(tex) = O::TEX {
+        let t = tex as usize;
+        let (w1, w2, w3) = (v1[t + 2], v2[t + 2], v3[t + 2]);
+        let minw = 1.0 / w1.max(w2).max(w3);
+        let (w1, w2, w3) = (w1 * minw, w2 * minw, w3 * minw);
+        let gs = setup.gradient(v1[t] * 32.0 * w1, v2[t] * 32.0 * w2, v3[t] * 32.0 * w3);
+        let gt = setup.gradient(
+            v1[t + 1] * 32.0 * w1,
+            v2[t + 1] * 32.0 * w2,
+            v3[t + 1] * 32.0 * w3,
+        );
+        let gw = setup.gradient(w1 * 32767.0, w2 * 32767.0, w3 * 32767.0);
+        let c = |j: usize| [gs[j], gt[j], gw[j], 0];
+        tri.tex_coeffs = pack_coeffs(c(0), c(1), c(2), c(3));
+    }
+
+    if let Some(z) = O::Z {
+        let z = z as usize;
+        let g = setup.gradient(v1[z] * 32767.0, v2[z] * 32767.0, v3[z] * 32767.0);
+        tri.z_coeffs = [
+            ((g[0] as u32 as u64) << 32) | g[1] as u32 as u64,
+            ((g[2] as u32 as u64) << 32) | g[3] as u32 as u64,
+        ];
+    }
+
+    tri
+}
+
+#[doc = "Compress a 18-bit depth value into the 16-bit format stored in the Z-buffer"]
+const fn z_compress(z: u32) -> u16 {
+    let z = if z > 0x3FFFF { 0x3FFFF } else { z };
+    (match (z >> 11) & 0x7F {
+        0x00..=0x3F => (z >> 4) & 0x1FFC,
+        0x40..=0x5F => ((z >> 3) & 0x1FFC) | 0x2000,
+        0x60..=0x6F => ((z >> 2) & 0x1FFC) | 0x4000,
+        0x70..=0x77 => ((z >> 1) & 0x1FFC) | 0x6000,
+        0x78..=0x7B => (z & 0x1FFC) | 0x8000,
+        0x7C..=0x7D => ((z << 1) & 0x1FFC) | 0xA000,
+        0x7E => ((z << 2) & 0x1FFC) | 0xC000,
+        _ => ((z << 2) & 0x1FFC) | 0xE000,
+    }) as u16
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct TileState {
+    format: Option<TexFormat>,
+    line: u16,
+    tmem_addr: u16,
+    palette: u8,
+    s: TileDescAxis,
+    t: TileDescAxis,
+    sl: u16,
+    tl: u16,
+    sh: u16,
+    th: u16,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct ImageState {
+    format: Option<TexFormat>,
+    width: u16,
+    address: u32,
+}
+
+#[doc = "Per-pixel inputs of the color combiner and blender"]
+#[derive(Debug, Copy, Clone)]
+struct PixelInputs {
+    shade: Color,
+    s: i32,
+    t: i32,
+    z: u32,
+}
+
+impl Default for PixelInputs {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            shade: Color::rgba32(0, 0, 0, 0),
+            s: 0,
+            t: 0,
+            z: 0,
+        }
+    }
+}
+
+#[doc = "Software implementation of the RDP, for testing rendering code without a console.\n\n This is a reference rasterizer that executes RDP command streams (such as the\n ones recorded via the debugging hook, or produced by [`triangle_setup`]) into\n an emulated RDRAM. Rendered images use the same memory layout as a [`Surface`]\n of the same format, so that they can be compared byte by byte against golden\n images captured on real hardware.\n\n The implemented subset covers:\n\n  * Fill and copy modes.\n  * 1-cycle and 2-cycle modes, with the full color combiner (see [`Combiner`])\n    and blender (see [`Blender`]), alpha compare and Z-buffering.\n  * Fill and textured rectangles.\n  * Triangles, with flat color, shade, texture (with perspective correction) and\n    depth.\n  * TMEM loading via LOAD_BLOCK, LOAD_TILE and LOAD_TLUT, and texture sampling of\n    all formats (with clamp, mirror and wrap).\n\n Anti-aliasing, dithering, texture filtering (only point sampling is performed),\n mipmapping, YUV textures and chroma keying are not emulated. Pixels are sampled\n at their center, and the noise input of the combiner is always zero, so that\n rendering is fully deterministic.\n\n Addresses in the command stream are RDRAM physical addresses (the upper byte is\n ignored). Accesses outside of the emulated RDRAM are ignored.\n\n [`Surface`]: crate::surface::Surface"]
+pub struct SoftRdp {
+    rdram: Box<[u8]>,
+    tmem: Box<[u8]>,
+    tiles: [TileState; 8],
+    som: SOM,
+    combiner: Combiner,
+    fill_color: u32,
+    fog_color: Color,
+    blend_color: Color,
+    prim_color: Color,
+    prim_lod_frac: u8,
+    env_color: Color,
+    prim_depth: u16,
+    k4: u8,
+    k5: u8,
+    key_center: Color,
+    key_scale: Color,
+    scissor: [u16; 4],
+    color_image: ImageState,
+    z_image: u32,
+    texture_image: ImageState,
+}
+
+impl core::fmt::Debug for SoftRdp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SoftRdp")
+            .field("rdram_size", &self.rdram.len())
+            .field("som", &self.som)
+            .field("combiner", &self.combiner)
+            .field("color_image", &self.color_image)
+            .field("z_image", &self.z_image)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SoftRdp {
+    #[doc = "Create a software RDP with the given amount of emulated RDRAM (in bytes)"]
+    pub fn new(rdram_size: usize) -> Self {
+        Self {
+            rdram: vec![0; rdram_size].into_boxed_slice(),
+            tmem: vec![0; TMEM_SIZE].into_boxed_slice(),
+            tiles: [TileState::default(); 8],
+            som: SOM::empty(),
+            combiner: Combiner(0),
+            fill_color: 0,
+            fog_color: Color::rgba32(0, 0, 0, 0),
+            blend_color: Color::rgba32(0, 0, 0, 0),
+            prim_color: Color::rgba32(0, 0, 0, 0),
+            prim_lod_frac: 0,
+            env_color: Color::rgba32(0, 0, 0, 0),
+            prim_depth: 0,
+            k4: 0,
+            k5: 0,
+            key_center: Color::rgba32(0, 0, 0, 0),
+            key_scale: Color::rgba32(0, 0, 0, 0),
+            scissor: [0; 4],
+            color_image: ImageState::default(),
+            z_image: 0,
+            texture_image: ImageState::default(),
+        }
+    }
+    #[doc = "Contents of the emulated RDRAM"]
+    #[inline]
+    pub fn rdram(&self) -> &[u8] {
+        &self.rdram
+    }
+    #[doc = "Mutable contents of the emulated RDRAM, to upload textures and initial buffers"]
+    #[inline]
+    pub fn rdram_mut(&mut self) -> &mut [u8] {
+        &mut self.rdram
+    }
+    #[doc = "Contents of the emulated TMEM"]
+    #[inline]
+    pub fn tmem(&self) -> &[u8] {
+        &self.tmem
+    }
+    #[doc = "Return the contents of the current color image.\n\n The returned slice has the same layout of a [`Surface`] with the format and\n width configured by the last SET_COLOR_IMAGE command, and the given height.\n\n [`Surface`]: crate::surface::Surface"]
+    pub fn color_image(&self, height: u16) -> &[u8] {
+        let img = self.color_image;
+        let stride = img
+            .format
+            .map_or(0, |f| f.pixels_to_bytes(img.width as u32)) as usize;
+        let start = (img.address & 0x00FF_FFFF) as usize;
+        let end = (start + stride * height as usize).min(self.rdram.len());
+        self.rdram.get(start..end).unwrap_or(&[])
+    }
+    #[doc = "Execute a stream of RDP commands.\n\n Returns the number of commands executed. In case the stream ends with a\n truncated command, all the previous commands are executed and an error\n is returned."]
+    pub fn run(&mut self, mut words: &[u64]) -> Result<usize, DecodeError> {
+        let mut count = 0;
+        while !words.is_empty() {
+            let (cmd, size) = RdpCommand::decode(words)?;
+            self.execute(&cmd);
+            words = &words[size..];
+            count += 1;
+        }
+        Ok(count)
+    }
+    #[doc = "Draw a triangle, as `rdpq_triangle` would do.\n\n This is a shortcut for executing the command returned by [`triangle_setup`]."]
+    pub fn triangle<O: TriOffsets, const N: usize>(
+        &mut self,
+        fmt: &TriFormat<O>,
+        v1: &[f32; N],
+        v2: &[f32; N],
+        v3: &[f32; N],
+    ) {
+        let tri = triangle_setup(fmt, v1, v2, v3);
+        self.draw_triangle(&tri);
+    }
+    #[doc = "Execute a single RDP command"]
+    pub fn execute(&mut self, cmd: &RdpCommand) {
+        match *cmd {
+            RdpCommand::Triangle(ref tri) => self.draw_triangle(tri),
+            RdpCommand::TextureRectangle {
+                tile,
+                x0,
+                y0,
+                x1,
+                y1,
+                s,
+                t,
+                dsdx,
+                dtdy,
+                flip,
+            } => self.texture_rectangle(tile, [x0, y0, x1, y1], s, t, dsdx, dtdy, flip),
+            RdpCommand::SetKeyGB {
+                center_g,
+                scale_g,
+                center_b,
+                scale_b,
+                ..
+            } => {
+                self.key_center.g = center_g;
+                self.key_center.b = center_b;
+                self.key_scale.g = scale_g;
+                self.key_scale.b = scale_b;
+            }
+            RdpCommand::SetKeyR {
+                center_r, scale_r, ..
+            } => {
+                self.key_center.r = center_r;
+                self.key_scale.r = scale_r;
+            }
+            RdpCommand::SetConvert(k) => {
+                self.k4 = k[4] as u8;
+                self.k5 = k[5] as u8;
+            }
+            RdpCommand::SetScissor { x0, y0, x1, y1, .. } => self.scissor = [x0, y0, x1, y1],
+            RdpCommand::SetPrimDepth { z, .. } => self.prim_depth = z,
+            RdpCommand::SetOtherModes(som) => self.som = som,
+            RdpCommand::LoadTlut { tile, s0, s1, .. } => self.load_tlut(tile, s0, s1),
+            RdpCommand::SetTileSize {
+                tile,
+                s0,
+                t0,
+                s1,
+                t1,
+            } => {
+                let ts = &mut self.tiles[tile as usize];
+                (ts.sl, ts.tl, ts.sh, ts.th) = (s0, t0, s1, t1);
+            }
+            RdpCommand::LoadBlock {
+                tile, s0, t0, s1, ..
+            } => self.load_block(tile, s0, t0, s1),
+            RdpCommand::LoadTile {
+                tile,
+                s0,
+                t0,
+                s1,
+                t1,
+            } => self.load_tile(tile, s0, t0, s1, t1),
+            RdpCommand::SetTile {
+                tile,
+                format,
+                line,
+                tmem_addr,
+                palette,
+                s,
+                t,
+            } => {
+                let ts = &mut self.tiles[tile as usize];
+                ts.format = format;
+                ts.line = line;
+                ts.tmem_addr = tmem_addr;
+                ts.palette = palette;
+                ts.s = s;
+                ts.t = t;
+            }
+            RdpCommand::FillRectangle { x0, y0, x1, y1 } => self.fill_rectangle([x0, y0, x1, y1]),
+            RdpCommand::SetFillColor(c) => self.fill_color = c,
+            RdpCommand::SetFogColor(c) => self.fog_color = c,
+            RdpCommand::SetBlendColor(c) => self.blend_color = c,
+            RdpCommand::SetPrimColor {
+                lod_frac, color, ..
+            } => {
+                self.prim_color = color;
+                self.prim_lod_frac = lod_frac;
+            }
+            RdpCommand::SetEnvColor(c) => self.env_color = c,
+            RdpCommand::SetCombine(c) => self.combiner = c,
+            RdpCommand::SetTextureImage {
+                format,
+                width,
+                address,
+            } => {
+                self.texture_image = ImageState {
+                    format,
+                    width,
+                    address,
+                }
+            }
+            RdpCommand::SetZImage { address } => self.z_image = address,
+            RdpCommand::SetColorImage {
+                format,
+                width,
+                address,
+            } => {
+                self.color_image = ImageState {
+                    format,
+                    width,
+                    address,
+                }
+            }
+            RdpCommand::Nop
+            | RdpCommand::SyncLoad
+            | RdpCommand::SyncPipe
+            | RdpCommand::SyncTile
+            | RdpCommand::SyncFull
+            | RdpCommand::Unknown { .. } => {}
+        }
+    }
+
+    #[inline]
+    fn read8(&self, addr: u32) -> u8 {
+        self.rdram
+            .get((addr & 0x00FF_FFFF) as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+    #[inline]
+    fn write8(&mut self, addr: u32, value: u8) {
+        if let Some(b) = self.rdram.get_mut((addr & 0x00FF_FFFF) as usize) {
+            *b = value;
+        }
+    }
+    #[inline]
+    fn read16(&self, addr: u32) -> u16 {
+        u16::from_be_bytes([self.read8(addr), self.read8(addr + 1)])
+    }
+    #[inline]
+    fn write16(&mut self, addr: u32, value: u16) {
+        let [hi, lo] = value.to_be_bytes();
+        self.write8(addr, hi);
+        self.write8(addr + 1, lo);
+    }
+    #[inline]
+    fn read32(&self, addr: u32) -> u32 {
+        ((self.read16(addr) as u32) << 16) | self.read16(addr + 2) as u32
+    }
+    #[inline]
+    fn write32(&mut self, addr: u32, value: u32) {
+        self.write16(addr, (value >> 16) as u16);
+        self.write16(addr + 2, value as u16);
+    }
+
+    #[inline]
+    fn cycle_type(&self) -> u64 {
+        self.som.bits() & SOM_CYCLE_MASK
+    }
+
+    #[doc = "Return the scissored pixel bounds of a rectangle, given in 10.2 coordinates"]
+    fn clip(&self, rect: [u16; 4], inclusive: bool) -> (u32, u32, u32, u32) {
+        let [sx0, sy0, sx1, sy1] = self.scissor;
+        let (x0, y0) = (rect[0].max(sx0) as u32 >> 2, rect[1].max(sy0) as u32 >> 2);
+        let (x1, y1) = if inclusive {
+            ((rect[2] as u32 >> 2) + 1, (rect[3] as u32 >> 2) + 1)
+        } else {
+            ((rect[2] as u32).div_ceil(4), (rect[3] as u32).div_ceil(4))
+        };
+        (
+            x0,
+            y0,
+            x1.min((sx1 as u32).div_ceil(4)),
+            y1.min((sy1 as u32).div_ceil(4)),
+        )
+    }
+
+    fn pixel_addr(img: &ImageState, x: u32, y: u32) -> (TexFormat, u32) {
+        let format = img.format.unwrap_or(TexFormat::RGBA16);
+        let stride = format.pixels_to_bytes(img.width as u32);
+        (format, img.address + y * stride + format.pixels_to_bytes(x))
+    }
+
+    fn read_pixel(&self, x: u32, y: u32) -> Color {
+        match Self::pixel_addr(&self.color_image, x, y) {
+            (TexFormat::RGBA32, addr) => Color::from_u32(self.read32(addr)),
+            (TexFormat::RGBA16, addr) => Color::from_u16(self.read16(addr)),
+            (_, addr) => {
+                let i = self.read8(addr);
+                Color::rgba32(i, i, i, i)
+            }
+        }
+    }
+
+    fn write_pixel(&mut self, x: u32, y: u32, c: Color) {
+        match Self::pixel_addr(&self.color_image, x, y) {
+            (TexFormat::RGBA32, addr) => self.write32(addr, Color { a: 0xE0, ..c }.into_u32()),
+            (TexFormat::RGBA16, addr) => self.write16(addr, Color { a: 0xFF, ..c }.into_u16()),
+            (_, addr) => self.write8(addr, c.r),
+        }
+    }
+
+    fn fill_rectangle(&mut self, rect: [u16; 4]) {
+        let cycle = self.cycle_type();
+        if cycle != SOM_CYCLE_FILL {
+            self.rectangle(rect, cycle == SOM_CYCLE_COPY, |_, _| {
+                (PixelInputs::default(), None)
+            });
+            return;
+        }
+        let (x0, y0, x1, y1) = self.clip(rect, true);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.fill_pixel(x, y);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn texture_rectangle(
+        &mut self,
+        tile: Tile,
+        rect: [u16; 4],
+        s: i16,
+        t: i16,
+        dsdx: i16,
+        dtdy: i16,
+        flip: bool,
+    ) {
+        let copy = self.cycle_type() == SOM_CYCLE_COPY;
+        let (px0, py0) = (rect[0] as i32 >> 2, rect[1] as i32 >> 2);
+        // In copy mode, the texture coordinates advance by 4 pixels per clock
+        let dsdx = if copy { dsdx as i32 / 4 } else { dsdx as i32 };
+        let dtdy = dtdy as i32;
+        self.rectangle(rect, copy, |x, y| {
+            let (dx, dy) = (x as i32 - px0, y as i32 - py0);
+            let (dx, dy) = if flip { (dy, dx) } else { (dx, dy) };
+            let px = PixelInputs {
+                s: s as i32 + ((dsdx * dx) >> 5),
+                t: t as i32 + ((dtdy * dy) >> 5),
+                ..Default::default()
+            };
+            (px, Some(tile))
+        });
+    }
+
+    fn rectangle(
+        &mut self,
+        rect: [u16; 4],
+        copy: bool,
+        inputs: impl Fn(u32, u32) -> (PixelInputs, Option<Tile>),
+    ) {
+        let (x0, y0, x1, y1) = self.clip(rect, copy || self.cycle_type() == SOM_CYCLE_FILL);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (mut px, tile) = inputs(x, y);
+                if self.som.bits() & SOM_ZSOURCE_PRIM as u64 != 0 {
+                    px.z = (self.prim_depth as u32 & 0x7FFF) << 3;
+                }
+                if copy {
+                    let tile = tile.unwrap_or(Tile::_0);
+                    let texel = self.sample(tile, px.s, px.t);
+                    let alpha_compare = self.som.bits() & SOM_ALPHACOMPARE_MASK as u64 != 0;
+                    if !alpha_compare || texel.a != 0 {
+                        self.write_pixel(x, y, texel);
+                    }
+                } else {
+                    self.shade_pixel(x, y, px, tile.unwrap_or(Tile::_0));
+                }
+            }
+        }
+    }
+
+    fn draw_triangle(&mut self, tri: &TriangleCmd) {
+        let cycle = self.cycle_type();
+        if cycle == SOM_CYCLE_COPY {
+            return;
+        }
+        let (shade, dshade_dx, dshade_de) = unpack_coeffs(&tri.shade_coeffs);
+        let (tex, dtex_dx, dtex_de) = unpack_coeffs(&tri.tex_coeffs);
+        let z = (tri.z_coeffs[0] >> 32) as i32 as i64;
+        let dzdx = tri.z_coeffs[0] as i32 as i64;
+        let dzde = (tri.z_coeffs[1] >> 32) as i32 as i64;
+
+        // All coordinates below are in s15.16; pixels are sampled at their center
+        let (yh, ym, yl) = (
+            (tri.yh as i64) << 14,
+            (tri.ym as i64) << 14,
+            (tri.yl as i64) << 14,
+        );
+        let ystart = ((tri.yh as i64) >> 2) << 16;
+        let [sx0, sy0, sx1, sy1] = self.scissor.map(|v| (v as i64) << 14);
+        let edge = |e: &TriEdge, y0: i64, y: i64| e.x as i64 + ((e.dxdy as i64 * (y - y0)) >> 16);
+
+        let row0 = (yh.max(sy0) >> 16).max(0);
+        let row1 = (yl.min(sy1) + 0xFFFF) >> 16;
+        for row in row0..row1 {
+            let y = (row << 16) + 0x8000;
+            if y < yh || y >= yl || y < sy0 || y >= sy1 {
+                continue;
+            }
+            let xh = edge(&tri.h, ystart, y);
+            let xm = if y < ym {
+                edge(&tri.m, ystart, y)
+            } else {
+                edge(&tri.l, ym, y)
+            };
+            let (xleft, xright) = (xh.min(xm).max(sx0), xh.max(xm).min(sx1));
+            let dy = y - ystart;
+            let col0 = (xleft >> 16).max(0);
+            let col1 = (xright + 0xFFFF) >> 16;
+            for col in col0..col1 {
+                let x = (col << 16) + 0x8000;
+                if x < xleft || x >= xright {
+                    continue;
+                }
+                let dx = x - xh;
+                let attr =
+                    |base: i64, de: i64, ddx: i64| base + ((de * dy) >> 16) + ((ddx * dx) >> 16);
+                let (col, row) = (col as u32, row as u32);
+                if cycle == SOM_CYCLE_FILL {
+                    self.fill_pixel(col, row);
+                    continue;
+                }
+                let mut px = PixelInputs::default();
+                if tri.shade {
+                    let c: [u8; 4] = core::array::from_fn(|i| {
+                        (attr(shade[i], dshade_de[i], dshade_dx[i]) >> 16).clamp(0, 255) as u8
+                    });
+                    px.shade = Color::rgba32(c[0], c[1], c[2], c[3]);
+                }
+                if tri.tex {
+                    let s = attr(tex[0], dtex_de[0], dtex_dx[0]);
+                    let t = attr(tex[1], dtex_de[1], dtex_dx[1]);
+                    let w = attr(tex[2], dtex_de[2], dtex_dx[2]);
+                    let persp = self.som.bits() & SOM_TEXTURE_PERSP != 0;
+                    let (s, t) = if persp && w > 0 {
+                        ((s * 0x7FFF) / w, (t * 0x7FFF) / w)
+                    } else {
+                        (s >> 16, t >> 16)
+                    };
+                    px.s = s.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+                    px.t = t.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+                }
+                px.z = if self.som.bits() & SOM_ZSOURCE_PRIM as u64 != 0 {
+                    (self.prim_depth as u32 & 0x7FFF) << 3
+                } else if tri.zbuf {
+                    (attr(z, dzde, dzdx) >> 13).clamp(0, 0x3FFFF) as u32
+                } else {
+                    0
+                };
+                self.shade_pixel(col, row, px, tri.tile);
+            }
+        }
+    }
+
+    fn fill_pixel(&mut self, x: u32, y: u32) {
+        match Self::pixel_addr(&self.color_image, x, y) {
+            (TexFormat::RGBA32, addr) => self.write32(addr, self.fill_color),
+            (TexFormat::RGBA16, addr) => {
+                let shift = if x & 1 == 0 { 16 } else { 0 };
+                self.write16(addr, (self.fill_color >> shift) as u16)
+            }
+            (_, addr) => self.write8(addr, (self.fill_color >> (24 - 8 * (x & 3))) as u8),
+        }
+    }
+
+    #[doc = "Run a pixel through the combiner, alpha compare, Z-buffer and blender"]
+    fn shade_pixel(&mut self, x: u32, y: u32, px: PixelInputs, tile: Tile) {
+        let two_cycle = self.cycle_type() == SOM_CYCLE_2;
+        let tex0 = self.sample(tile, px.s, px.t);
+        let tex1 = self.sample(Tile::from_index(tile as u32 + 1), px.s, px.t);
+
+        // In 1-cycle mode, the combiner uses the settings of the second cycle
+        let cycles = self.combiner.cycles();
+        let cycles = if two_cycle { &cycles[..] } else { &cycles[1..] };
+        let mut combined = Color::rgba32(0, 0, 0, 0);
+        for cycle in cycles {
+            combined = self.combine(cycle, combined, tex0, tex1, px.shade);
+        }
+
+        let som = self.som.bits();
+        if som & SOM_ALPHACOMPARE_MASK as u64 == SOM_ALPHACOMPARE_THRESHOLD as u64
+            && combined.a < self.blend_color.a
+        {
+            return;
+        }
+
+        let zaddr = self.z_image + (y * self.color_image.width as u32 + x) * 2;
+        let znew = z_compress(px.z);
+        if som & SOM_Z_COMPARE as u64 != 0 {
+            let zold = self.read16(zaddr) & !3;
+            let pass = if som & SOM_ZMODE_MASK as u64 == SOM_ZMODE_DECAL as u64 {
+                znew == zold
+            } else {
+                znew < zold
+            };
+            if !pass {
+                return;
+            }
+        }
+        if som & SOM_Z_WRITE as u64 != 0 {
+            self.write16(zaddr, znew.min(ZBUF_MAX));
+        }
+
+        // In 1-cycle mode, the blender uses the settings of the first cycle
+        let memory = self.read_pixel(x, y);
+        let blender = Blender(som as u32);
+        let force_blend = som & SOM_BLENDING as u64 != 0;
+        let (a, shade_a) = (combined.a, px.shade.a);
+        let color = if two_cycle {
+            let color = self.blend(blender, 30, combined, a, shade_a, memory, true);
+            self.blend(blender, 28, color, a, shade_a, memory, force_blend)
+        } else {
+            self.blend(blender, 30, combined, a, shade_a, memory, force_blend)
+        };
+        self.write_pixel(x, y, color);
+    }
+
+    fn combine(
+        &self,
+        cycle: &super::commands::CombinerCycle,
+        combined: Color,
+        tex0: Color,
+        tex1: Color,
+        shade: Color,
+    ) -> Color {
+        let (prim, env) = (self.prim_color, self.env_color);
+        let rgb = |c: Color| [c.r as i32, c.g as i32, c.b as i32];
+        let splat = |v: u8| [v as i32; 3];
+        let input = |sel: u8| match sel {
+            0 => rgb(combined),
+            1 => rgb(tex0),
+            2 => rgb(tex1),
+            3 => rgb(prim),
+            4 => rgb(shade),
+            5 => rgb(env),
+            _ => splat(0),
+        };
+        let suba = match cycle.rgb_suba {
+            6 => splat(255),
+            s => input(s),
+        };
+        let subb = match cycle.rgb_subb {
+            6 => rgb(self.key_center),
+            7 => splat(self.k4),
+            s => input(s),
+        };
+        let mul = match cycle.rgb_mul {
+            6 => rgb(self.key_scale),
+            7 => splat(combined.a),
+            8 => splat(tex0.a),
+            9 => splat(tex1.a),
+            10 => splat(prim.a),
+            11 => splat(shade.a),
+            12 => splat(env.a),
+            14 => splat(self.prim_lod_frac),
+            15 => splat(self.k5),
+            s => input(s),
+        };
+        let add = match cycle.rgb_add {
+            6 => splat(255),
+            s => input(s),
+        };
+        let alpha = |sel: u8| match sel {
+            0 => combined.a,
+            1 => tex0.a,
+            2 => tex1.a,
+            3 => prim.a,
+            4 => shade.a,
+            5 => env.a,
+            6 => 255,
+            _ => 0,
+        } as i32;
+        let amul = match cycle.alpha_mul {
+            0 => 0,
+            6 => self.prim_lod_frac as i32,
+            s => alpha(s),
+        };
+        let eq = |a: i32, b: i32, c: i32, d: i32| {
+            // Multiplier is 0..255, with 255 meaning 1.0
+            let c = c + (c >> 7);
+            ((((a - b) * c + 0x80) >> 8) + d).clamp(0, 255) as u8
+        };
+        let c: [u8; 3] = core::array::from_fn(|i| eq(suba[i], subb[i], mul[i], add[i]));
+        Color::rgba32(
+            c[0],
+            c[1],
+            c[2],
+            eq(
+                alpha(cycle.alpha_suba),
+                alpha(cycle.alpha_subb),
+                amul,
+                alpha(cycle.alpha_add),
+            ),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[doc = "Run one cycle of the blender; `shift` selects the fields of the cycle (30 or 28)"]
+    fn blend(
+        &self,
+        blender: Blender,
+        shift: u32,
+        input: Color,
+        in_alpha: u8,
+        shade_alpha: u8,
+        memory: Color,
+        enabled: bool,
+    ) -> Color {
+        let field = |s: u32| (blender.0 >> s) & 3;
+        let pm = |sel: u32| match sel {
+            0 => input,
+            1 => memory,
+            2 => self.blend_color,
+            _ => self.fog_color,
+        };
+        let p = pm(field(shift));
+        if !enabled {
+            return p;
+        }
+        let a = match field(shift - 4) {
+            0 => in_alpha,
+            1 => self.fog_color.a,
+            2 => shade_alpha,
+            _ => 0,
+        } as u32;
+        let m = pm(field(shift - 8));
+        let b = match field(shift - 12) {
+            0 => 255 - a,
+            1 if memory.a != 0 => 255,
+            2 => 255,
+            _ => 0,
+        };
+        let eq = |p: u8, m: u8| ((p as u32 * a + m as u32 * b + 127) / 255).min(255) as u8;
+        Color::rgba32(eq(p.r, m.r), eq(p.g, m.g), eq(p.b, m.b), input.a)
+    }
+
+    #[doc = "Apply shift, clamp, mirror and mask to a texture coordinate (in 10.5)"]
+    fn wrap(coord: i32, axis: &TileDescAxis, lo: u16, hi: u16) -> u32 {
+        let coord = match axis.shift {
+            0 => coord,
+            s @ 1..=10 => coord >> s,
+            s => coord << (16 - s as u32),
+        };
+        let mut c = coord.saturating_sub((lo as i32) << 3) >> 5;
+        if axis.clamp || axis.mask == 0 {
+            c = c.clamp(0, ((hi as i32 - lo as i32) >> 2).max(0));
+        }
+        if axis.mask != 0 {
+            if axis.mirror && (c >> axis.mask) & 1 != 0 {
+                c = !c;
+            }
+            c &= (1 << axis.mask) - 1;
+        }
+        c as u32
+    }
+
+    fn tmem16(&self, addr: u32) -> u16 {
+        let addr = addr as usize & (TMEM_SIZE - 1) & !1;
+        u16::from_be_bytes([self.tmem[addr], self.tmem[addr + 1]])
+    }
+
+    fn palette(&self, index: u32) -> Color {
+        let entry = self.tmem16(0x800 + index * 8);
+        if self.som.bits() & SOM_TLUT_MASK == SOM_TLUT_IA16 {
+            let i = (entry >> 8) as u8;
+            Color::rgba32(i, i, i, entry as u8)
+        } else {
+            Color::from_u16(entry)
+        }
+    }
+
+    #[doc = "Point-sample a texel from TMEM, given the texture coordinates in 10.5"]
+    fn sample(&self, tile: Tile, s: i32, t: i32) -> Color {
+        let ts = &self.tiles[tile as usize];
+        let Some(format) = ts.format else {
+            return Color::rgba32(0, 0, 0, 0);
+        };
+        let s = Self::wrap(s, &ts.s, ts.sl, ts.sh);
+        let t = Self::wrap(t, &ts.t, ts.tl, ts.th);
+        let row = ts.tmem_addr as u32 * 8 + t * ts.line as u32 * 8;
+        let tlut = self.som.bits() & SOM_TLUT_MASK != 0;
+        let nibble = || {
+            let b = self.tmem[(row + s / 2) as usize & (TMEM_SIZE - 1)];
+            if s & 1 == 0 { b >> 4 } else { b & 0xF }
+        };
+        let byte = || self.tmem[(row + s) as usize & (TMEM_SIZE - 1)];
+        let intensity = |i: u8, a: u8| Color::rgba32(i, i, i, a);
+        match format {
+            TexFormat::RGBA32 => {
+                let rg = self.tmem16(row + s * 2);
+                let ba = self.tmem16(row + s * 2 + 0x800);
+                Color::rgba32((rg >> 8) as u8, rg as u8, (ba >> 8) as u8, ba as u8)
+            }
+            TexFormat::RGBA16 => Color::from_u16(self.tmem16(row + s * 2)),
+            TexFormat::IA16 => {
+                let ia = self.tmem16(row + s * 2);
+                intensity((ia >> 8) as u8, ia as u8)
+            }
+            TexFormat::CI8 if tlut => self.palette(byte() as u32),
+            TexFormat::CI4 if tlut => self.palette(((ts.palette as u32) << 4) | nibble() as u32),
+            TexFormat::IA8 => {
+                let b = byte();
+                intensity((b & 0xF0) | (b >> 4), (b << 4) | (b & 0xF))
+            }
+            TexFormat::IA4 => {
+                let n = nibble();
+                let i = ((n >> 1) * 0x24) | (n >> 2);
+                intensity(i, if n & 1 != 0 { 255 } else { 0 })
+            }
+            TexFormat::I8 | TexFormat::CI8 => {
+                let i = byte();
+                intensity(i, i)
+            }
+            TexFormat::I4 | TexFormat::CI4 => {
+                let i = nibble() * 0x11;
+                intensity(i, i)
+            }
+            TexFormat::YUV16 | TexFormat::None => Color::rgba32(0, 0, 0, 0),
+        }
+    }
+
+    #[doc = "Copy bytes from RDRAM into TMEM, splitting 32-bit texels in the two TMEM halves"]
+    fn load_texels(&mut self, src: u32, dst: u32, count: u32, format: TexFormat) {
+        if format == TexFormat::RGBA32 {
+            for i in 0..count {
+                let texel = self.read32(src + i * 4);
+                let lo = (dst + i * 2) as usize & 0x7FF;
+                self.tmem[lo..lo + 2].copy_from_slice(&((texel >> 16) as u16).to_be_bytes());
+                self.tmem[lo + 0x800..lo + 0x802].copy_from_slice(&(texel as u16).to_be_bytes());
+            }
+        } else {
+            for i in 0..format.pixels_to_bytes(count) {
+                self.tmem[(dst + i) as usize & (TMEM_SIZE - 1)] = self.read8(src + i);
+            }
+        }
+    }
+
+    fn load_block(&mut self, tile: Tile, s0: u16, t0: u16, s1: u16) {
+        let img = self.texture_image;
+        let Some(format) = img.format else { return };
+        let ts = &mut self.tiles[tile as usize];
+        (ts.sl, ts.tl, ts.sh) = (s0 << 2, t0 << 2, s1 << 2);
+        let dst = ts.tmem_addr as u32 * 8;
+        let first = s0 as u32 + t0 as u32 * img.width as u32;
+        let count = (s1 as u32 + 1).saturating_sub(s0 as u32);
+        self.load_texels(
+            img.address + format.pixels_to_bytes(first),
+            dst,
+            count,
+            format,
+        );
+    }
+
+    fn load_tile(&mut self, tile: Tile, s0: u16, t0: u16, s1: u16, t1: u16) {
+        let img = self.texture_image;
+        let Some(format) = img.format else { return };
+        let ts = &mut self.tiles[tile as usize];
+        (ts.sl, ts.tl, ts.sh, ts.th) = (s0, t0, s1, t1);
+        let (tmem_addr, line) = (ts.tmem_addr as u32 * 8, ts.line as u32 * 8);
+        let (s0, t0, s1, t1) = (
+            s0 as u32 >> 2,
+            t0 as u32 >> 2,
+            s1 as u32 >> 2,
+            t1 as u32 >> 2,
+        );
+        let stride = format.pixels_to_bytes(img.width as u32);
+        for t in t0..=t1 {
+            let src = img.address + (t * stride) + format.pixels_to_bytes(s0);
+            let dst = tmem_addr + (t - t0) * line;
+            self.load_texels(src, dst, (s1 + 1).saturating_sub(s0), format);
+        }
+    }
+
+    fn load_tlut(&mut self, tile: Tile, s0: u16, s1: u16) {
+        let img = self.texture_image;
+        let dst = self.tiles[tile as usize].tmem_addr as u32 * 8;
+        let (s0, s1) = (s0 as u32 >> 2, s1 as u32 >> 2);
+        for (i, s) in (s0..=s1).enumerate() {
+            let entry = self.read16(img.address + s * 2).to_be_bytes();
+            let addr = (dst as usize + i * 8) & (TMEM_SIZE - 1) & !1;
+            self.tmem[addr..addr + 2].copy_from_slice(&entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc_::{string::String, vec::Vec};
+
+    use super::*;
+    use crate::rdpq::{COMBINER_FLAT, COMBINER_SHADE, COMBINER_TEX, COMBINER_TEX_FLAT, tri};
+
+    const W: u16 = 8;
+    const H: u16 = 8;
+    const Z_ADDR: u32 = 0x400;
+    const TEX_ADDR: u32 = 0x800;
+
+    const RED: Color = Color::rgba32(0xFF, 0, 0, 0xFF);
+    const GREEN: Color = Color::rgba32(0, 0xFF, 0, 0xFF);
+    const BLUE: Color = Color::rgba32(0, 0, 0xFF, 0xFF);
+    const WHITE: Color = Color::rgba32(0xFF, 0xFF, 0xFF, 0xFF);
+
+    // Colors of the golden images; anything else is rendered as `?`
+    const LEGEND: &[(char, Color)] = &[
+        ('.', Color::rgba32(0, 0, 0, 0)),
+        ('R', RED),
+        ('G', GREEN),
+        ('B', BLUE),
+        ('W', WHITE),
+    ];
+
+    // Create a software RDP rendering into a cleared W x H RGBA16 color image
+    fn target(som: SOM) -> SoftRdp {
+        let mut rdp = SoftRdp::new(0x1000);
+        for cmd in [
+            RdpCommand::SetColorImage {
+                format: Some(TexFormat::RGBA16),
+                width: W,
+                address: 0,
+            },
+            RdpCommand::SetScissor {
+                x0: 0,
+                y0: 0,
+                x1: W << 2,
+                y1: H << 2,
+                field: false,
+                odd: false,
+            },
+            RdpCommand::SetOtherModes(som),
+        ] {
+            rdp.execute(&cmd);
+        }
+        rdp
+    }
+
+    // Compare the color image against a golden image, one string per row
+    #[track_caller]
+    fn assert_golden(rdp: &SoftRdp, golden: &[&str]) {
+        let rows: Vec<String> = rdp
+            .color_image(H)
+            .chunks_exact(W as usize * 2)
+            .map(|row| {
+                row.chunks_exact(2)
+                    .map(|px| {
+                        // Ignore the coverage bit
+                        let px = u16::from_be_bytes([px[0], px[1]]) & !1;
+                        LEGEND
+                            .iter()
+                            .find(|(_, c)| c.into_u16() & !1 == px)
+                            .map_or('?', |&(ch, _)| ch)
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows, golden);
+    }
+
+    // Read back a pixel of the color image
+    fn pixel(rdp: &SoftRdp, x: usize, y: usize) -> Color {
+        let i = (y * W as usize + x) * 2;
+        let img = rdp.color_image(H);
+        Color::from_u16(u16::from_be_bytes([img[i], img[i + 1]]))
+    }
+
+    // Read back a value of the Z-buffer
+    fn depth(rdp: &SoftRdp, x: usize, y: usize) -> u16 {
+        let i = Z_ADDR as usize + (y * W as usize + x) * 2;
+        u16::from_be_bytes([rdp.rdram()[i], rdp.rdram()[i + 1]])
+    }
+
+    // Upload a 4x4 RGBA16 texture into RDRAM and load it into TMEM for tile 0
+    fn load_texture(rdp: &mut SoftRdp, texels: [[Color; 4]; 4], s: TileDescAxis, t: TileDescAxis) {
+        let bytes = texels
+            .iter()
+            .flatten()
+            .flat_map(|c| c.into_u16().to_be_bytes());
+        for (dst, b) in rdp.rdram_mut()[TEX_ADDR as usize..].iter_mut().zip(bytes) {
+            *dst = b;
+        }
+        for cmd in [
+            RdpCommand::SetTextureImage {
+                format: Some(TexFormat::RGBA16),
+                width: 4,
+                address: TEX_ADDR,
+            },
+            RdpCommand::SetTile {
+                tile: Tile::_0,
+                format: Some(TexFormat::RGBA16),
+                line: 1,
+                tmem_addr: 0,
+                palette: 0,
+                s,
+                t,
+            },
+            RdpCommand::LoadTile {
+                tile: Tile::_0,
+                s0: 0,
+                t0: 0,
+                s1: 3 << 2,
+                t1: 3 << 2,
+            },
+        ] {
+            rdp.execute(&cmd);
+        }
+    }
+
+    #[test]
+    fn fill_rectangle() {
+        let mut rdp = target(SOM::CYCLE_FILL);
+        let red = RED.into_u16() as u32;
+        rdp.execute(&RdpCommand::SetFillColor((red << 16) | red));
+        rdp.execute(&RdpCommand::FillRectangle {
+            x0: 2 << 2,
+            y0: 1 << 2,
+            x1: 5 << 2,
+            y1: 3 << 2,
+        });
+        assert_golden(
+            &rdp,
+            &[
+                "........", "..RRRR..", "..RRRR..", "..RRRR..", "........", "........", "........",
+                "........",
+            ],
+        );
+    }
+
+    #[test]
+    fn flat_triangle() {
+        let mut rdp = target(SOM::CYCLE_1);
+        rdp.execute(&RdpCommand::SetCombine(COMBINER_FLAT));
+        rdp.execute(&RdpCommand::SetPrimColor {
+            min_lod: 0,
+            lod_frac: 0,
+            color: GREEN,
+        });
+        let fmt = TriFormat::<tri::Fill>::new();
+        rdp.triangle(&fmt, &[0.0, 0.0], &[8.0, 0.0], &[0.0, 8.0]);
+        assert_golden(
+            &rdp,
+            &[
+                "GGGGGGG.", "GGGGGG..", "GGGGG...", "GGGG....", "GGG.....", "GG......", "G.......",
+                "........",
+            ],
+        );
+    }
+
+    #[test]
+    fn shaded_triangle() {
+        let fmt = TriFormat::<tri::Shade>::new();
+        let v = |x, y, c: [f32; 4]| [x, y, c[0], c[1], c[2], c[3]];
+
+        // Uniform vertex colors cover the same pixels as a flat triangle
+        let mut rdp = target(SOM::CYCLE_1);
+        rdp.execute(&RdpCommand::SetCombine(COMBINER_SHADE));
+        let white = [1.0; 4];
+        rdp.triangle(
+            &fmt,
+            &v(0.0, 0.0, white),
+            &v(8.0, 0.0, white),
+            &v(0.0, 8.0, white),
+        );
+        assert_golden(
+            &rdp,
+            &[
+                "WWWWWWW.", "WWWWWW..", "WWWWW...", "WWWW....", "WWW.....", "WW......", "W.......",
+                "........",
+            ],
+        );
+
+        // Each vertex color fades into the others
+        let mut rdp = target(SOM::CYCLE_1);
+        rdp.execute(&RdpCommand::SetCombine(COMBINER_SHADE));
+        rdp.triangle(
+            &fmt,
+            &v(0.0, 0.0, [1.0, 0.0, 0.0, 1.0]),
+            &v(8.0, 0.0, [0.0, 1.0, 0.0, 1.0]),
+            &v(0.0, 8.0, [0.0, 0.0, 1.0, 1.0]),
+        );
+        let (c0, c1, c2) = (pixel(&rdp, 0, 0), pixel(&rdp, 6, 0), pixel(&rdp, 0, 6));
+        assert!(c0.r > 0xC0 && c0.g < 0x20 && c0.b < 0x20, "{c0:?}");
+        assert!(c1.g > 0xC0 && c1.r < 0x30 && c1.b < 0x20, "{c1:?}");
+        assert!(c2.b > 0xC0 && c2.r < 0x30 && c2.g < 0x20, "{c2:?}");
+        let row: Vec<Color> = (0..7).map(|x| pixel(&rdp, x, 0)).collect();
+        assert!(
+            row.windows(2)
+                .all(|c| c[0].r >= c[1].r && c[0].g <= c[1].g && c[0].b == c[1].b),
+            "{row:?}"
+        );
+    }
+
+    #[test]
+    fn textured_triangle() {
+        let wrap = TileDescAxis {
+            mask: 2,
+            ..Default::default()
+        };
+        let fmt = TriFormat::<tri::Tex>::new();
+        // Perspective correction with a constant W must not change the result
+        for som in [SOM::CYCLE_1, SOM::CYCLE_1 | SOM::TEXTURE_PERSP] {
+            let mut rdp = target(som);
+            load_texture(&mut rdp, [[RED, GREEN, BLUE, WHITE]; 4], wrap, wrap);
+            rdp.execute(&RdpCommand::SetCombine(COMBINER_TEX));
+            // Texture coordinates follow the screen, so the texture repeats every 4 pixels
+            rdp.triangle(
+                &fmt,
+                &[0.0, 0.0, 0.0, 0.0, 1.0],
+                &[8.0, 0.0, 8.0, 0.0, 1.0],
+                &[0.0, 8.0, 0.0, 8.0, 1.0],
+            );
+            assert_golden(
+                &rdp,
+                &[
+                    "RGBWRGB.", "RGBWRG..", "RGBWR...", "RGBW....", "RGB.....", "RG......",
+                    "R.......", "........",
+                ],
+            );
+        }
+    }
+
+    #[test]
+    fn zbuffer_compare_and_update() {
+        let mut rdp = target(SOM::CYCLE_1 | SOM::Z_COMPARE | SOM::Z_WRITE);
+        rdp.execute(&RdpCommand::SetZImage { address: Z_ADDR });
+        rdp.execute(&RdpCommand::SetCombine(COMBINER_FLAT));
+        for z in rdp.rdram_mut()[Z_ADDR as usize..][..(W * H * 2) as usize].chunks_exact_mut(2) {
+            z.copy_from_slice(&ZBUF_MAX.to_be_bytes());
+        }
+        let fmt = TriFormat::<tri::ZBuf>::new();
+        let mut draw = |color, size: f32, z: f32| {
+            rdp.execute(&RdpCommand::SetPrimColor {
+                min_lod: 0,
+                lod_frac: 0,
+                color,
+            });
+            rdp.triangle(&fmt, &[0.0, 0.0, z], &[size, 0.0, z], &[0.0, size, z]);
+        };
+        draw(RED, 8.0, 0.5);
+        // Farther away: only drawn where the buffer is still clear
+        draw(GREEN, 16.0, 0.8);
+        // Closer: drawn over everything
+        draw(BLUE, 4.0, 0.2);
+        assert_golden(
+            &rdp,
+            &[
+                "BBBRRRRG", "BBRRRRGG", "BRRRRGGG", "RRRRGGGG", "RRRGGGGG", "RRGGGGGG", "RGGGGGGG",
+                "GGGGGGGG",
+            ],
+        );
+        assert!(depth(&rdp, 0, 0) < depth(&rdp, 3, 0));
+        assert!(depth(&rdp, 3, 0) < depth(&rdp, 7, 0));
+        assert!(depth(&rdp, 7, 0) < ZBUF_MAX);
+    }
+
+    #[test]
+    fn combiner_one_and_two_cycles() {
+        let wrap = TileDescAxis {
+            mask: 2,
+            ..Default::default()
+        };
+        let rect = RdpCommand::TextureRectangle {
+            tile: Tile::_0,
+            x0: 0,
+            y0: 0,
+            x1: W << 2,
+            y1: 1 << 2,
+            s: 0,
+            t: 0,
+            dsdx: 1 << 10,
+            dtdy: 1 << 10,
+            flip: false,
+        };
+        let setup = |som| {
+            let mut rdp = target(som);
+            load_texture(&mut rdp, [[RED, GREEN, BLUE, WHITE]; 4], wrap, wrap);
+            rdp.execute(&RdpCommand::SetPrimColor {
+                min_lod: 0,
+                lod_frac: 0,
+                color: GREEN,
+            });
+            rdp.execute(&RdpCommand::SetEnvColor(GREEN));
+            rdp
+        };
+
+        // Tex0 * Prim
+        let mut rdp = setup(SOM::CYCLE_1);
+        rdp.execute(&RdpCommand::SetCombine(COMBINER_TEX_FLAT));
+        rdp.execute(&rect);
+        assert_golden(
+            &rdp,
+            &[
+                ".G.G.G.G", "........", "........", "........", "........", "........", "........",
+                "........",
+            ],
+        );
+
+        // (1 - Tex0 * Prim) * Env, with the second cycle reading the first one
+        let mut rdp = setup(SOM::CYCLE_2);
+        rdp.execute(&RdpCommand::SetCombine(crate::rdpq_combiner!(
+            (Tex0, 0, Prim, 0),
+            (Tex0, 0, Prim, 0),
+            (1, Combined, Env, 0),
+            (0, 0, 0, Combined)
+        )));
+        rdp.execute(&rect);
+        assert_golden(
+            &rdp,
+            &[
+                "G.G.G.G.", "........", "........", "........", "........", "........", "........",
+                "........",
+            ],
+        );
+    }
+
+    #[test]
+    fn copy_mode_wrap_and_mirror() {
+        let mut rdp = target(SOM::CYCLE_COPY);
+        let row = [RED, GREEN, BLUE, WHITE];
+        let wrap = TileDescAxis {
+            mask: 2,
+            ..Default::default()
+        };
+        let mirror = TileDescAxis {
+            mirror: true,
+            ..wrap
+        };
+        load_texture(&mut rdp, [row; 4], mirror, wrap);
+        rdp.execute(&RdpCommand::TextureRectangle {
+            tile: Tile::_0,
+            x0: 0,
+            y0: 0,
+            x1: 7 << 2,
+            y1: 3 << 2,
+            s: 0,
+            t: 0,
+            dsdx: 4 << 10,
+            dtdy: 1 << 10,
+            flip: false,
+        });
+        assert_golden(
+            &rdp,
+            &[
+                "RGBWWBGR", "RGBWWBGR", "RGBWWBGR", "RGBWWBGR", "........", "........", "........",
+                "........",
+            ],
+        );
+    }
+
+    #[test]
+    fn copy_mode_clamp() {
+        let mut rdp = target(SOM::CYCLE_COPY);
+        let clamp = TileDescAxis {
+            clamp: true,
+            mask: 2,
+            ..Default::default()
+        };
+        let mut texels = [[RED; 4]; 4];
+        texels[3] = [GREEN, GREEN, GREEN, BLUE];
+        load_texture(&mut rdp, texels, clamp, clamp);
+        rdp.execute(&RdpCommand::TextureRectangle {
+            tile: Tile::_0,
+            x0: 0,
+            y0: 0,
+            x1: 7 << 2,
+            y1: 7 << 2,
+            s: 0,
+            t: 0,
+            dsdx: 4 << 10,
+            dtdy: 1 << 10,
+            flip: false,
+        });
+        assert_golden(
+            &rdp,
+            &[
+                "RRRRRRRR", "RRRRRRRR", "RRRRRRRR", "GGGBBBBB", "GGGBBBBB", "GGGBBBBB", "GGGBBBBB",
+                "GGGBBBBB",
+            ],
+        );
+    }
+
+    #[test]
+    fn wrap_extreme_coordinates() {
+        let clamp = TileDescAxis {
+            clamp: true,
+            ..Default::default()
+        };
+        for shift in [0, 5, 10, 11, 15] {
+            let axis = TileDescAxis { shift, ..clamp };
+            assert_eq!(SoftRdp::wrap(i32::MIN, &axis, 0x3FF << 2, 0xFFF), 0);
+            assert_eq!(SoftRdp::wrap(i32::MAX, &axis, 0x3FF << 2, 0xFFF), 0);
+            assert_eq!(SoftRdp::wrap(0, &axis, 0xFFF, 0), 0);
+        }
+        let axis = TileDescAxis {
+            mask: 4,
+            mirror: true,
+            ..Default::default()
+        };
+        assert!(SoftRdp::wrap(i32::MIN, &axis, 0xFFF, 0) < 16);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{graphics::Color, surface::TexFormat, sys::rdpq::rspq_block};
+
+use super::{Blender, Combiner, RdpQ, SOM, SOMMask, TileParms};
+
+#[doc = "Bits of the render mode that rdpq recalculates after each change made\n through the mode API (cycle type, blender configuration, coverage)"]
+pub(super) const SOM_DERIVED: SOMMask = SOMMask::CYCLE
+    .union(SOMMask::BLEND)
+    .union(SOMMask::X_BLEND_2PASS)
+    .union(SOMMask::BLENDING)
+    .union(SOMMask::BLALPHA)
+    .union(SOMMask::READ_ENABLE)
+    .union(SOMMask::COVERAGE_DEST)
+    .union(SOMMask::COLOR_ON_CVG_OVERFLOW);
+
+#[doc = "Depth of the render mode stack (see [`RdpQ::mode_push`])"]
+const MODE_STACK_SIZE: usize = 4;
+
+#[doc = "Configuration of a tile descriptor, as set via [`RdpQ::set_tile`]"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TileDesc {
+    pub format: TexFormat,
+    pub tmem_addr: i32,
+    pub tmem_pitch: u16,
+    pub parms: TileParms,
+}
+
+#[doc = "Known state of a tile descriptor"]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TileState {
+    #[doc = "Tile configuration, or `None` if unknown"]
+    pub desc: Option<TileDesc>,
+    #[doc = "Tile extents in 10.2 fixed point (s0, t0, s1, t1), as set via\n [`RdpQ::set_tile_size_fx`], or `None` if unknown"]
+    pub size: Option<[u16; 4]>,
+}
+
+#[doc = "Snapshot of the RDP render state, as tracked by the state cache.\n\n Every field is `None` (or, for the render mode, absent from `som_known`)\n when its value is unknown: the state cache only knows the values that\n were set through [`RdpQ`] since the cache was enabled or last\n invalidated, and forgets those that might have been changed by\n higher-level functions (eg: sprite and texture uploads, text rendering,\n materials) or by running blocks.\n\n See [`RdpQ::set_state_cache`]."]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RdpState {
+    #[doc = "Render mode bits that are known. Bits not in `som_known` are zero"]
+    pub som: SOM,
+    #[doc = "Mask of the render mode bits whose value is known"]
+    pub som_known: SOMMask,
+    #[doc = "Combiner set via [`super::RdpQModes::mode_combiner`]"]
+    pub combiner: Option<Combiner>,
+    #[doc = "Blender set via [`super::RdpQModes::mode_blender`]"]
+    pub blender: Option<Blender>,
+    #[doc = "Fog blender set via [`super::RdpQModes::mode_fog`]"]
+    pub fog: Option<Blender>,
+    pub fill_color: Option<Color>,
+    pub fog_color: Option<Color>,
+    pub blend_color: Option<Color>,
+    pub prim_color: Option<Color>,
+    pub env_color: Option<Color>,
+    #[doc = "Scissor rectangle (x0, y0, x1, y1)"]
+    pub scissor: Option<[f32; 4]>,
+    pub tiles: [TileState; 8],
+}
+
+impl RdpState {
+    #[doc = "A state where nothing is known"]
+    pub const UNKNOWN: Self = Self {
+        som: SOM::empty(),
+        som_known: SOMMask::empty(),
+        combiner: None,
+        blender: None,
+        fog: None,
+        fill_color: None,
+        fog_color: None,
+        blend_color: None,
+        prim_color: None,
+        env_color: None,
+        scissor: None,
+        tiles: [TileState {
+            desc: None,
+            size: None,
+        }; 8],
+    };
+    #[doc = "Return the render mode, if all of its bits are known"]
+    #[inline]
+    pub fn som_full(&self) -> Option<SOM> {
+        self.som_known.is_all().then_some(self.som)
+    }
+    #[inline]
+    fn modes(&self) -> Modes {
+        Modes {
+            som: self.som,
+            som_known: self.som_known,
+            combiner: self.combiner,
+            blender: self.blender,
+            fog: self.fog,
+        }
+    }
+    #[inline]
+    fn set_modes(&mut self, modes: Modes) {
+        self.som = modes.som;
+        self.som_known = modes.som_known;
+        self.combiner = modes.combiner;
+        self.blender = modes.blender;
+        self.fog = modes.fog;
+    }
+}
+
+impl Default for RdpState {
+    #[inline]
+    fn default() -> Self {
+        Self::UNKNOWN
+    }
+}
+
+#[doc = "The part of the state saved by [`RdpQ::mode_push`]"]
+#[derive(Copy, Clone)]
+struct Modes {
+    som: SOM,
+    som_known: SOMMask,
+    combiner: Option<Combiner>,
+    blender: Option<Blender>,
+    fog: Option<Blender>,
+}
+
+impl Modes {
+    const UNKNOWN: Self = Self {
+        som: SOM::empty(),
+        som_known: SOMMask::empty(),
+        combiner: None,
+        blender: None,
+        fog: None,
+    };
+}
+
+struct Cache {
+    enabled: bool,
+    state: RdpState,
+    stack: [Modes; MODE_STACK_SIZE],
+    depth: usize,
+}
+
+static mut CACHE: Cache = Cache {
+    enabled: false,
+    state: RdpState::UNKNOWN,
+    stack: [Modes::UNKNOWN; MODE_STACK_SIZE],
+    depth: 0,
+};
+
+#[inline]
+fn cache_raw() -> &'static mut Cache {
+    let cache = &raw mut CACHE;
+    unsafe { &mut *cache }
+}
+
+#[doc = "Access the cache, if it is enabled.\n\n While a block is being recorded, the cache is bypassed: the recorded\n commands are not run now, and the block can later be run in any state."]
+#[inline]
+fn cache() -> Option<&'static mut Cache> {
+    let cache = cache_raw();
+    (cache.enabled && unsafe { rspq_block.is_null() }).then_some(cache)
+}
+
+#[doc = "Record that a register is being set to `value`.\n\n Returns true if the register is known to already hold that value, so\n that the command can be skipped."]
+#[inline]
+pub(super) fn cached<T: PartialEq>(
+    field: impl FnOnce(&mut RdpState) -> &mut Option<T>,
+    value: T,
+) -> bool {
+    let Some(cache) = cache() else {
+        return false;
+    };
+    let field = field(&mut cache.state);
+    if field.as_ref() == Some(&value) {
+        return true;
+    }
+    *field = Some(value);
+    false
+}
+
+#[doc = "Record a change of the render mode bits in `mask` made through the mode\n API. Returns true if the bits are known to already hold those values."]
+#[inline]
+pub(super) fn cached_som(mask: SOMMask, val: SOM) -> bool {
+    let Some(cache) = cache() else {
+        return false;
+    };
+    let state = &mut cache.state;
+    let val = val & SOM::from_bits_retain(mask.bits());
+    if state.som_known.contains(mask) && (state.som & SOM::from_bits_retain(mask.bits())) == val {
+        return true;
+    }
+    state.som = (state.som & SOM::from_bits_retain(!mask.bits())) | val;
+    state.som_known = (state.som_known | mask) - SOM_DERIVED;
+    false
+}
+
+#[doc = "Record a change of the render mode bypassing the mode API"]
+#[inline]
+pub(super) fn track_som_raw(mask: SOMMask, val: SOM) {
+    update(|state| {
+        state.som = (state.som & SOM::from_bits_retain(!mask.bits()))
+            | (val & SOM::from_bits_retain(mask.bits()));
+        state.som_known |= mask;
+        state.blender = None;
+        state.fog = None;
+    });
+}
+
+#[doc = "Update the tracked state, if the cache is enabled"]
+#[inline]
+pub(super) fn update(f: impl FnOnce(&mut RdpState)) {
+    if let Some(cache) = cache() {
+        f(&mut cache.state);
+    }
+}
+
+#[doc = "Forget the render mode (eg: after it was reset)"]
+#[inline]
+pub(super) fn forget_modes() {
+    update(|state| state.set_modes(Modes::UNKNOWN));
+}
+
+#[doc = "Forget all the tile descriptors (eg: after a texture upload)"]
+#[inline]
+pub(super) fn forget_tiles() {
+    update(|state| state.tiles = RdpState::UNKNOWN.tiles);
+}
+
+#[doc = "Forget the whole state (eg: after running commands that are not tracked)"]
+#[inline]
+pub(crate) fn forget_all() {
+    update(|state| *state = RdpState::UNKNOWN);
+}
+
+#[inline]
+pub(super) fn mode_push() {
+    if let Some(cache) = cache() {
+        if cache.depth < MODE_STACK_SIZE {
+            cache.stack[cache.depth] = cache.state.modes();
+        }
+        cache.depth += 1;
+    }
+}
+
+#[inline]
+pub(super) fn mode_pop() {
+    if let Some(cache) = cache() {
+        let modes = match cache.depth.checked_sub(1) {
+            Some(depth) => {
+                cache.depth = depth;
+                cache.stack.get(depth).copied().unwrap_or(Modes::UNKNOWN)
+            }
+            None => Modes::UNKNOWN,
+        };
+        cache.state.set_modes(modes);
+    }
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Enable or disable the RDP state cache\n\n When enabled, the render mode, combiner, blender, fog, the color\n registers, the scissor and the tile descriptors set through [`RdpQ`] and\n [`super::RdpQModes`] are tracked on the CPU, and commands that would set\n a register to the value it already holds are skipped. This saves RSP\n and RDP time when the same state is configured over and over (eg: by\n independent drawing functions), and allows to inspect the current state\n via [`RdpQ::state`] without synchronizing with the RSP.\n\n The cache is disabled by default, and it starts with an unknown state\n every time it is enabled. State changes made by C code, or by raw RDP\n commands written via [`RdpQ::exec`], are not tracked: call\n [`RdpQ::state_invalidate`] after them. Functions of this crate that\n change the state in ways that cannot be tracked (eg: sprite and texture\n uploads, text rendering, materials, running blocks) forget the affected\n part of the state automatically.\n\n While a block is being recorded, the cache is bypassed, so that the\n block contains all the commands.\n\n @param enable    True to enable the cache, false to disable it"]
+    pub fn set_state_cache(&mut self, enable: bool) {
+        let cache = cache_raw();
+        cache.enabled = enable;
+        cache.state = RdpState::UNKNOWN;
+        cache.stack = [Modes::UNKNOWN; MODE_STACK_SIZE];
+        cache.depth = 0;
+    }
+    #[doc = "Return true if the RDP state cache is enabled (see [`RdpQ::set_state_cache`])"]
+    #[inline]
+    pub fn state_cache_enabled(&self) -> bool {
+        cache_raw().enabled
+    }
+    #[doc = "Get the RDP state, as known to the state cache\n\n Returns `None` if the state cache is disabled. This is useful for\n debugging and for debug overlays; it does not synchronize with the RSP,\n unlike [`RdpQ::other_modes_raw`] and [`RdpQ::get_combiner_raw`]."]
+    #[inline]
+    pub fn state(&self) -> Option<RdpState> {
+        let cache = cache_raw();
+        cache.enabled.then_some(cache.state)
+    }
+    #[doc = "Forget the state tracked by the state cache\n\n This must be called after the RDP state was changed behind the back of\n the cache (eg: by C code). The next state changes will be emitted\n regardless of their value. Render modes saved via [`RdpQ::mode_push`]\n are kept, and are restored as usual when popped."]
+    #[inline]
+    pub fn state_invalidate(&mut self) {
+        forget_all();
+    }
+}
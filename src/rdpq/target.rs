@@ -0,0 +1,160 @@
+use core::marker::PhantomData;
+
+use alloc_::vec::Vec;
+
+use crate::{
+    surface::{Surface, TexFormat},
+    sys::rdpq_attach::*,
+};
+
+use super::RdpQ;
+
+#[doc = "Scissor configured via [`RdpQ::set_scissor`], or `None` if it covers the whole render target"]
+static mut SCISSOR: Option<[f32; 4]> = None;
+
+#[inline]
+pub(super) fn track_scissor(scissor: Option<[f32; 4]>) {
+    unsafe { (&raw mut SCISSOR).write_volatile(scissor) }
+    if scissor.is_none() {
+        // The render target changed: the fill color is converted
+        // according to the format of the new target.
+        super::state::update(|s| {
+            s.scissor = None;
+            s.fill_color = None;
+        });
+    }
+}
+
+#[inline]
+fn tracked_scissor() -> Option<[f32; 4]> {
+    unsafe { (&raw mut SCISSOR).read_volatile() }
+}
+
+#[doc = "A render target pushed on the attachment stack, created by [`RdpQ::attach_push`].\n\n When this is dropped (or [`AttachFrame::pop`] is called), the RDP is\n detached from the surface, and the previous render target and scissor\n are restored. Frames can be nested by pushing new render targets\n through the frame itself; the stack is shared with [`RdpQ::attach`],\n and holds up to 4 render targets."]
+#[derive(Debug)]
+pub struct AttachFrame<'s, 'r> {
+    scissor: Option<[f32; 4]>,
+    _rdpq: PhantomData<&'s mut RdpQ<'r>>,
+}
+
+static_wrapper! { AttachFrame<'s, 'r> => RdpQ<'r> { RdpQ(PhantomData) } }
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Push a render target on the attachment stack\n\n This is similar to [`RdpQ::attach`], but the returned frame restores the\n previous render target (color and Z surfaces) and the previous scissor\n rectangle when dropped. This allows to temporarily render to an offscreen\n surface (eg: for minimaps, mirrors or cached UI), and then resume drawing\n where it was left.\n\n @param surf_color    The surface to render to\n @param surf_z        The Z-buffer to render to (if any)"]
+    #[must_use]
+    #[inline]
+    pub fn attach_push<'t>(
+        &'t mut self,
+        surf_color: &'t mut Surface,
+        surf_z: Option<&'t mut Surface>,
+    ) -> AttachFrame<'t, 'r> {
+        let frame = AttachFrame::push();
+        unsafe {
+            rdpq_attach(
+                surf_color.as_raw_mut(),
+                surf_z
+                    .map(|s| s.as_raw_mut() as *mut _)
+                    .unwrap_or_else(core::ptr::null_mut),
+            )
+        }
+        frame
+    }
+    #[doc = "Push a render target on the attachment stack and clear it\n\n This is similar to [`RdpQ::attach_push`], but the surface (and the\n Z-buffer, if any) is cleared as in [`RdpQ::attach_clear`].\n\n @param surf_color    The surface to render to\n @param surf_z        The Z-buffer to render to (if any)"]
+    #[must_use]
+    #[inline]
+    pub fn attach_push_clear<'t>(
+        &'t mut self,
+        surf_color: &'t mut Surface,
+        surf_z: Option<&'t mut Surface>,
+    ) -> AttachFrame<'t, 'r> {
+        let frame = AttachFrame::push();
+        unsafe {
+            rdpq_attach_clear(
+                surf_color.as_raw_mut(),
+                surf_z
+                    .map(|s| s.as_raw_mut() as *mut _)
+                    .unwrap_or_else(core::ptr::null_mut),
+            )
+        }
+        frame
+    }
+}
+
+impl<'s, 'r> AttachFrame<'s, 'r> {
+    #[inline]
+    fn push() -> Self {
+        let scissor = tracked_scissor();
+        track_scissor(None);
+        Self {
+            scissor,
+            _rdpq: PhantomData,
+        }
+    }
+    #[inline]
+    pub fn pop(self) {}
+}
+
+impl<'s, 'r> Drop for AttachFrame<'s, 'r> {
+    #[doc = "Detach the RDP from the surface, and restore the previous render target and scissor"]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { rdpq_detach_cb(None, core::ptr::null_mut()) }
+        track_scissor(None);
+        if let Some([x0, y0, x1, y1]) = self.scissor {
+            RdpQ(PhantomData).set_scissor(x0, y0, x1, y1);
+        }
+    }
+}
+
+#[doc = "A pool of reusable offscreen surfaces.\n\n Allocating surfaces every frame (eg: for render-to-texture effects) is\n slow and fragments memory. A pool hands out surfaces of a given format and\n size via [`SurfacePool::acquire`], reusing the ones that were given back\n via [`SurfacePool::release`].\n\n Since the RDP might still be drawing to (or reading from) a released\n surface, released surfaces are recycled only when [`SurfacePool::end_frame`]\n is called. This must be done once the RDP has finished processing the\n frame (for instance, after [`super::Attachment::detach_wait`], or once\n the display has returned the framebuffer used by that frame)."]
+#[derive(Debug, Default)]
+pub struct SurfacePool {
+    free: Vec<Surface<'static>>,
+    pending: Vec<Surface<'static>>,
+}
+
+impl SurfacePool {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            free: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+    #[doc = "Get a surface with the given format and size\n\n A free surface is reused if available, otherwise a new one is allocated.\n The contents of the surface are undefined.\n\n @param format    Pixel format of the surface\n @param width     Width of the surface in pixels\n @param height    Height of the surface in pixels"]
+    pub fn acquire(&mut self, format: TexFormat, width: u16, height: u16) -> Surface<'static> {
+        match self
+            .free
+            .iter()
+            .position(|s| s.format() == format && s.width() == width && s.height() == height)
+        {
+            Some(idx) => self.free.swap_remove(idx),
+            None => Surface::new(format, width, height),
+        }
+    }
+    #[doc = "Give a surface back to the pool\n\n The surface will be available again after the next call to\n [`SurfacePool::end_frame`]."]
+    #[inline]
+    pub fn release(&mut self, surface: Surface<'static>) {
+        self.pending.push(surface);
+    }
+    #[doc = "Recycle all the surfaces released since the previous call"]
+    #[inline]
+    pub fn end_frame(&mut self) {
+        self.free.append(&mut self.pending);
+    }
+    #[doc = "Free all the surfaces that are available for reuse"]
+    #[inline]
+    pub fn trim(&mut self) {
+        self.free.clear();
+    }
+    #[doc = "Number of surfaces available for reuse"]
+    #[inline]
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+    #[doc = "Number of released surfaces waiting for the end of the frame"]
+    #[inline]
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
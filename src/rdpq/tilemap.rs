@@ -0,0 +1,582 @@
+use core::{ffi::CStr, fmt};
+
+use alloc_::vec::Vec;
+
+use crate::sprite::Sprite;
+
+use super::{RdpQ, RdpQModes, Tile};
+
+const MAGIC: [u8; 4] = *b"TMAP";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 16;
+
+#[doc = "Error returned when parsing a binary tilemap"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TilemapError {
+    #[doc = "The data does not start with the `TMAP` magic"]
+    BadMagic,
+    #[doc = "The format version is not supported"]
+    UnsupportedVersion(u8),
+    #[doc = "The data is shorter than declared in the header"]
+    Truncated {
+        #[doc = "Bytes required by the header"]
+        needed: usize,
+        #[doc = "Bytes available"]
+        available: usize,
+    },
+    #[doc = "The map or its tiles have a zero size"]
+    EmptySize,
+    #[doc = "The size of the map data does not fit in memory"]
+    TooLarge,
+    #[doc = "The map has more layers than the binary format can store (255)"]
+    TooManyLayers(usize),
+}
+
+impl fmt::Display for TilemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("invalid tilemap magic"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported tilemap version {v}"),
+            Self::Truncated { needed, available } => write!(
+                f,
+                "tilemap is truncated: needs {needed} bytes, {available} available"
+            ),
+            Self::EmptySize => f.write_str("tilemap has a zero size"),
+            Self::TooLarge => f.write_str("tilemap is too large"),
+            Self::TooManyLayers(n) => write!(f, "tilemap has too many layers ({n}, max 255)"),
+        }
+    }
+}
+
+impl core::error::Error for TilemapError {}
+
+#[doc = "A cell of a tilemap layer.\n\n The lowest 14 bits store the tile index plus one (so that 0 means an empty\n cell), while the two topmost bits store the horizontal and vertical flip\n flags. This is also the encoding used in the binary map format."]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MapTile(pub u16);
+
+impl MapTile {
+    pub const EMPTY: Self = Self(0);
+    const INDEX_MASK: u16 = 0x3FFF;
+    const FLIP_X: u16 = 1 << 14;
+    const FLIP_Y: u16 = 1 << 15;
+    #[doc = "Maximum tile index that can be stored in a cell"]
+    pub const MAX_INDEX: u16 = Self::INDEX_MASK - 1;
+
+    #[doc = "Create a cell referring to a tile of the tileset"]
+    #[inline]
+    pub const fn new(index: u16) -> Self {
+        assert!(index <= Self::MAX_INDEX);
+        Self(index + 1)
+    }
+    #[doc = "Flip the tile horizontally (default: false)"]
+    #[inline]
+    pub const fn flip_x(mut self, flip_x: bool) -> Self {
+        self.0 = (self.0 & !Self::FLIP_X) | if flip_x { Self::FLIP_X } else { 0 };
+        self
+    }
+    #[doc = "Flip the tile vertically (default: false)"]
+    #[inline]
+    pub const fn flip_y(mut self, flip_y: bool) -> Self {
+        self.0 = (self.0 & !Self::FLIP_Y) | if flip_y { Self::FLIP_Y } else { 0 };
+        self
+    }
+    #[doc = "Index of the tile within the tileset, or `None` if the cell is empty"]
+    #[inline]
+    pub const fn index(self) -> Option<u16> {
+        match self.0 & Self::INDEX_MASK {
+            0 => None,
+            i => Some(i - 1),
+        }
+    }
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.index().is_none()
+    }
+    #[inline]
+    pub const fn is_flip_x(self) -> bool {
+        self.0 & Self::FLIP_X != 0
+    }
+    #[inline]
+    pub const fn is_flip_y(self) -> bool {
+        self.0 & Self::FLIP_Y != 0
+    }
+}
+
+#[doc = "An animated tile.\n\n When a cell refers to the `first` tile of the animation, the tiles from\n `first` to `first + frames - 1` are displayed in sequence, each one for\n `duration` time units."]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TileAnim {
+    pub first: u16,
+    pub frames: u16,
+    pub duration: u16,
+}
+
+impl TileAnim {
+    #[inline]
+    pub const fn new(first: u16, frames: u16, duration: u16) -> Self {
+        Self {
+            first,
+            frames,
+            duration,
+        }
+    }
+    #[doc = "Tile displayed at the given time"]
+    #[inline]
+    pub const fn frame(&self, time: u32) -> u16 {
+        if self.frames == 0 || self.duration == 0 {
+            return self.first;
+        }
+        self.first + ((time / self.duration as u32) % self.frames as u32) as u16
+    }
+}
+
+#[doc = "A set of tiles, backed by a [`Sprite`] used as a spritemap.\n\n The sprite is split in tiles according to its horizontal and vertical\n slices (as configured by mksprite), and tiles are numbered left to right,\n top to bottom."]
+#[derive(Debug, Clone)]
+pub struct Tileset<'a> {
+    sprite: &'a Sprite,
+    columns: u16,
+    rows: u16,
+    tile_width: u16,
+    tile_height: u16,
+    anims: Vec<TileAnim>,
+}
+
+impl<'a> Tileset<'a> {
+    #[doc = "Create a tileset from a spritemap"]
+    #[inline]
+    pub fn new(sprite: &'a Sprite) -> Self {
+        let raw = unsafe { &*sprite.as_raw() };
+        let columns = raw.hslices.max(1) as u16;
+        let rows = raw.vslices.max(1) as u16;
+        Self {
+            sprite,
+            columns,
+            rows,
+            tile_width: raw.width / columns,
+            tile_height: raw.height / rows,
+            anims: Vec::new(),
+        }
+    }
+    #[doc = "Add an animated tile (see [`TileAnim`])"]
+    #[inline]
+    pub fn animate(&mut self, anim: TileAnim) -> &mut Self {
+        self.anims.retain(|a| a.first != anim.first);
+        self.anims.push(anim);
+        self
+    }
+    #[inline]
+    pub const fn sprite(&self) -> &'a Sprite {
+        self.sprite
+    }
+    #[inline]
+    pub const fn tile_size(&self) -> (u16, u16) {
+        (self.tile_width, self.tile_height)
+    }
+    #[doc = "Number of tiles in the tileset"]
+    #[inline]
+    pub const fn len(&self) -> u16 {
+        self.columns * self.rows
+    }
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    #[doc = "Tile displayed at the given time in place of `index`, resolving animations"]
+    #[inline]
+    pub fn resolve(&self, index: u16, time: u32) -> u16 {
+        self.anims
+            .iter()
+            .find(|a| a.first == index)
+            .map_or(index, |a| a.frame(time))
+    }
+    #[inline]
+    const fn slice(&self, index: u16) -> (u16, u16) {
+        (index % self.columns, index / self.columns)
+    }
+}
+
+#[doc = "A camera looking at a tilemap.\n\n The camera position is the map coordinate (in pixels, with sub-pixel\n precision) shown at the top-left corner of the viewport. The viewport is\n the screen rectangle where the map is drawn."]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct MapCamera {
+    pub x: f32,
+    pub y: f32,
+    pub viewport_x: f32,
+    pub viewport_y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl MapCamera {
+    #[doc = "Create a camera with a viewport of the given size, placed at the top-left corner of the screen"]
+    #[inline]
+    pub const fn new(width: f32, height: f32) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            viewport_x: 0.0,
+            viewport_y: 0.0,
+            width,
+            height,
+        }
+    }
+    #[doc = "Map coordinate shown at the top-left corner of the viewport (default: 0, 0)"]
+    #[inline]
+    pub const fn position(mut self, x: f32, y: f32) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+    #[doc = "Screen coordinate of the top-left corner of the viewport (default: 0, 0)"]
+    #[inline]
+    pub const fn viewport(mut self, x: f32, y: f32) -> Self {
+        self.viewport_x = x;
+        self.viewport_y = y;
+        self
+    }
+    #[doc = "Move the camera by the given offset"]
+    #[inline]
+    pub fn scroll(&mut self, dx: f32, dy: f32) {
+        self.x += dx;
+        self.y += dy;
+    }
+}
+
+#[doc = "A multi-layer map of tiles.\n\n All layers have the same size. Layers are drawn independently via\n [`Tilemap::draw_layer`], so that other graphics (eg: sprites) can be drawn\n between them.\n\n Tilemaps can be created in code, or loaded from a compact binary format\n (all values are big-endian):\n\n | Offset | Type     | Description                                   |\n |--------|----------|-----------------------------------------------|\n | 0      | `[u8;4]` | Magic: `TMAP`                                 |\n | 4      | `u8`     | Version (1)                                   |\n | 5      | `u8`     | Number of layers                              |\n | 6      | `u16`    | Reserved (0)                                  |\n | 8      | `u16`    | Width of the map, in tiles                    |\n | 10     | `u16`    | Height of the map, in tiles                   |\n | 12     | `u16`    | Width of a tile, in pixels                    |\n | 14     | `u16`    | Height of a tile, in pixels                   |\n | 16     | `[u16]`  | Cells of each layer, row by row ([`MapTile`]) |"]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tilemap {
+    width: u16,
+    height: u16,
+    tile_width: u16,
+    tile_height: u16,
+    layers: Vec<Vec<MapTile>>,
+}
+
+impl Tilemap {
+    #[doc = "Create an empty tilemap\n\n @param width         Width of the map, in tiles\n @param height        Height of the map, in tiles\n @param tile_width    Width of a tile, in pixels\n @param tile_height   Height of a tile, in pixels\n @param layers        Number of layers"]
+    #[inline]
+    pub fn new(width: u16, height: u16, tile_width: u16, tile_height: u16, layers: usize) -> Self {
+        assert!(
+            width > 0 && height > 0 && tile_width > 0 && tile_height > 0,
+            "tilemap size must not be zero"
+        );
+        let cells = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            layers: (0..layers)
+                .map(|_| alloc_::vec![MapTile::EMPTY; cells])
+                .collect(),
+        }
+    }
+    #[doc = "Parse a tilemap from its binary format"]
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TilemapError> {
+        if data.len() < HEADER_SIZE {
+            return Err(TilemapError::Truncated {
+                needed: HEADER_SIZE,
+                available: data.len(),
+            });
+        }
+        if data[0..4] != MAGIC {
+            return Err(TilemapError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(TilemapError::UnsupportedVersion(data[4]));
+        }
+        let be16 = |off: usize| u16::from_be_bytes([data[off], data[off + 1]]);
+        let num_layers = data[5] as usize;
+        let (width, height) = (be16(8), be16(10));
+        let (tile_width, tile_height) = (be16(12), be16(14));
+        if width == 0 || height == 0 || tile_width == 0 || tile_height == 0 {
+            return Err(TilemapError::EmptySize);
+        }
+
+        let layer_size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|cells| cells.checked_mul(2))
+            .ok_or(TilemapError::TooLarge)?;
+        let needed = num_layers
+            .checked_mul(layer_size)
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .ok_or(TilemapError::TooLarge)?;
+        if data.len() < needed {
+            return Err(TilemapError::Truncated {
+                needed,
+                available: data.len(),
+            });
+        }
+        let layers = data[HEADER_SIZE..needed]
+            .chunks_exact(layer_size)
+            .map(|layer| {
+                layer
+                    .chunks_exact(2)
+                    .map(|c| MapTile(u16::from_be_bytes([c[0], c[1]])))
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            layers,
+        })
+    }
+    #[doc = "Load a tilemap in binary format from a file (possibly compressed, see [`crate::asset::load`])"]
+    #[inline]
+    pub fn load(filename: &CStr) -> Result<Self, TilemapError> {
+        Self::from_bytes(&crate::asset::load(filename))
+    }
+    #[doc = "Serialize the tilemap into its binary format.\n\n Returns an error if the map has more than 255 layers."]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TilemapError> {
+        let num_layers = u8::try_from(self.layers.len())
+            .map_err(|_| TilemapError::TooManyLayers(self.layers.len()))?;
+        let mut out = Vec::with_capacity(
+            HEADER_SIZE + self.layers.len() * self.width as usize * self.height as usize * 2,
+        );
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        out.push(num_layers);
+        for v in [
+            0,
+            self.width,
+            self.height,
+            self.tile_width,
+            self.tile_height,
+        ] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        for cell in self.layers.iter().flatten() {
+            out.extend_from_slice(&cell.0.to_be_bytes());
+        }
+        Ok(out)
+    }
+
+    #[doc = "Size of the map, in tiles"]
+    #[inline]
+    pub const fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+    #[doc = "Size of a tile, in pixels"]
+    #[inline]
+    pub const fn tile_size(&self) -> (u16, u16) {
+        (self.tile_width, self.tile_height)
+    }
+    #[inline]
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+    #[doc = "Cells of a layer, row by row"]
+    #[inline]
+    pub fn layer(&self, layer: usize) -> &[MapTile] {
+        &self.layers[layer]
+    }
+    #[inline]
+    pub fn layer_mut(&mut self, layer: usize) -> &mut [MapTile] {
+        &mut self.layers[layer]
+    }
+    #[doc = "Get a cell of a layer, or `None` if the coordinates are outside of the map"]
+    #[inline]
+    pub fn get(&self, layer: usize, x: u16, y: u16) -> Option<MapTile> {
+        (x < self.width && y < self.height)
+            .then(|| self.layers[layer][y as usize * self.width as usize + x as usize])
+    }
+    #[doc = "Set a cell of a layer.\n\n Panics if the coordinates are outside of the map."]
+    #[inline]
+    pub fn set(&mut self, layer: usize, x: u16, y: u16, tile: MapTile) {
+        assert!(x < self.width && y < self.height, "tile outside of the map");
+        self.layers[layer][y as usize * self.width as usize + x as usize] = tile;
+    }
+
+    #[doc = "Draw a layer of the map, as seen by the camera\n\n Only the tiles that are visible within the camera viewport are drawn.\n Tiles partially outside of the viewport are drawn in full, so the\n scissor should be configured to the viewport if it does not cover the\n whole framebuffer.\n\n If the whole tileset fits in TMEM, it is uploaded once and all tiles are\n drawn from it. Otherwise, the visible tiles are drawn grouped by tile\n (tiles in a layer never overlap, so their order does not matter), which\n loads each distinct tile only once.\n\n The render mode is left to the caller (for instance, copy mode with\n transparency, or standard mode with alpha compare).\n\n Panics if the tiles of the tileset do not have the same size of the\n tiles of the map.\n\n @param rdpq      RDP queue\n @param tileset   Tileset the cells refer to\n @param layer     Index of the layer to draw\n @param camera    MapCamera looking at the map\n @param time      Current time, used to resolve animated tiles (in the\n                  same units as [`TileAnim::duration`], eg: frames)"]
+    pub fn draw_layer(
+        &self,
+        rdpq: &mut RdpQ,
+        tileset: &Tileset,
+        layer: usize,
+        camera: &MapCamera,
+        time: u32,
+    ) {
+        assert_eq!(
+            tileset.tile_size(),
+            self.tile_size(),
+            "tileset and tilemap have different tile sizes"
+        );
+        let (tw, th) = (self.tile_width as f32, self.tile_height as f32);
+        let (sw, sh) = tileset.tile_size();
+        let col0 = ((camera.x / tw) as i32).clamp(0, self.width as i32);
+        let row0 = ((camera.y / th) as i32).clamp(0, self.height as i32);
+        let col1 = (((camera.x + camera.width) / tw) as i32 + 1).clamp(col0, self.width as i32);
+        let row1 = (((camera.y + camera.height) / th) as i32 + 1).clamp(row0, self.height as i32);
+
+        let cells = &self.layers[layer];
+        let mut visible = Vec::with_capacity(((col1 - col0) * (row1 - row0)) as usize);
+        for row in row0..row1 {
+            for col in col0..col1 {
+                let cell = cells[row as usize * self.width as usize + col as usize];
+                if let Some(index) = cell.index() {
+                    let index = tileset.resolve(index, time);
+                    if index < tileset.len() {
+                        visible.push((index, cell, col as u16, row as u16));
+                    }
+                }
+            }
+        }
+        if visible.is_empty() {
+            return;
+        }
+
+        let sprite = tileset.sprite();
+        let whole = sprite.fits_tmem();
+        if whole {
+            rdpq.sprite_upload(Tile::_0, sprite, None);
+        } else {
+            visible.sort_unstable_by_key(|&(index, ..)| index);
+            if let Some(palette) = sprite.palette() {
+                rdpq.tex_upload_tlut(palette, 0);
+            }
+            rdpq.mode_tlut(sprite.format().tlut());
+        }
+
+        let mut uploaded = None;
+        for (index, cell, col, row) in visible {
+            let (h, v) = tileset.slice(index);
+            let (s, t) = if whole {
+                ((h * sw) as f32, (v * sh) as f32)
+            } else {
+                if uploaded != Some(index) {
+                    rdpq.tex_upload(Tile::_0, &sprite.tile(h as u32, v as u32), None);
+                    uploaded = Some(index);
+                }
+                (0.0, 0.0)
+            };
+
+            let x = camera.viewport_x + col as f32 * tw - camera.x;
+            let y = camera.viewport_y + row as f32 * th - camera.y;
+            // Swapping the rectangle corners flips the texture.
+            let (x0, x1) = match cell.is_flip_x() {
+                false => (x, x + sw as f32),
+                true => (x + sw as f32, x),
+            };
+            let (y0, y1) = match cell.is_flip_y() {
+                false => (y, y + sh as f32),
+                true => (y + sh as f32, y),
+            };
+            rdpq.texture_rectangle(Tile::_0, x0, y0, x1, y1, s, t);
+        }
+    }
+    #[doc = "Draw all the layers of the map, from the first to the last (see [`Tilemap::draw_layer`])"]
+    #[inline]
+    pub fn draw(&self, rdpq: &mut RdpQ, tileset: &Tileset, camera: &MapCamera, time: u32) {
+        for layer in 0..self.layers.len() {
+            self.draw_layer(rdpq, tileset, layer, camera, time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(layers: u8, width: u16, height: u16) -> Vec<u8> {
+        let mut data = Vec::from(MAGIC);
+        data.extend_from_slice(&[VERSION, layers, 0, 0]);
+        for v in [width, height, 8, 8] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut map = Tilemap::new(3, 2, 16, 8, 2);
+        map.set(0, 0, 0, MapTile::new(5));
+        map.set(0, 2, 1, MapTile::new(MapTile::MAX_INDEX).flip_x(true));
+        map.set(1, 1, 0, MapTile::new(0).flip_y(true));
+
+        let data = map.to_bytes().unwrap();
+        assert_eq!(data.len(), HEADER_SIZE + 2 * 3 * 2 * 2);
+        assert_eq!(
+            data[..HEADER_SIZE],
+            [b'T', b'M', b'A', b'P', 1, 2, 0, 0, 0, 3, 0, 2, 0, 16, 0, 8]
+        );
+        assert_eq!(data[HEADER_SIZE..HEADER_SIZE + 2], [0, 6]);
+
+        let parsed = Tilemap::from_bytes(&data).unwrap();
+        assert_eq!(parsed, map);
+        assert_eq!(
+            parsed.get(0, 2, 1).unwrap().index(),
+            Some(MapTile::MAX_INDEX)
+        );
+        assert!(parsed.get(0, 2, 1).unwrap().is_flip_x());
+        assert!(parsed.get(1, 1, 0).unwrap().is_flip_y());
+        assert_eq!(parsed.get(1, 3, 0), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Tilemap::from_bytes(b"TMAP"),
+            Err(TilemapError::Truncated {
+                needed: HEADER_SIZE,
+                available: 4,
+            })
+        );
+        let mut data = header(1, 2, 2);
+        data[0] = b'X';
+        assert_eq!(Tilemap::from_bytes(&data), Err(TilemapError::BadMagic));
+        let mut data = header(1, 2, 2);
+        data[4] = 2;
+        assert_eq!(
+            Tilemap::from_bytes(&data),
+            Err(TilemapError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Tilemap::from_bytes(&header(1, 0, 2)),
+            Err(TilemapError::EmptySize)
+        );
+
+        let mut data = header(2, 2, 2);
+        data.extend_from_slice(&[0; 15]);
+        assert_eq!(
+            Tilemap::from_bytes(&data),
+            Err(TilemapError::Truncated {
+                needed: HEADER_SIZE + 16,
+                available: HEADER_SIZE + 15,
+            })
+        );
+        // Trailing data is ignored
+        data.extend_from_slice(&[0; 2]);
+        assert_eq!(Tilemap::from_bytes(&data).unwrap().num_layers(), 2);
+    }
+
+    #[test]
+    fn huge_header() {
+        let data = header(255, u16::MAX, u16::MAX);
+        match Tilemap::from_bytes(&data) {
+            Err(TilemapError::TooLarge) => {}
+            Err(TilemapError::Truncated { needed, available }) => {
+                assert_eq!(needed, HEADER_SIZE + 255 * 65535 * 65535 * 2);
+                assert_eq!(available, HEADER_SIZE);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn too_many_layers() {
+        let map = Tilemap::new(1, 1, 8, 8, 256);
+        assert_eq!(map.to_bytes(), Err(TilemapError::TooManyLayers(256)));
+        let map = Tilemap::new(1, 1, 8, 8, 255);
+        assert_eq!(Tilemap::from_bytes(&map.to_bytes().unwrap()), Ok(map));
+    }
+
+    #[test]
+    fn anim() {
+        let anim = TileAnim::new(10, 3, 4);
+        let frames: Vec<_> = (0..14).step_by(2).map(|t| anim.frame(t)).collect();
+        assert_eq!(frames, [10, 10, 11, 11, 12, 12, 10]);
+        assert_eq!(TileAnim::new(7, 0, 4).frame(100), 7);
+    }
+}
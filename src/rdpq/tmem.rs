@@ -0,0 +1,560 @@
+use core::fmt;
+
+use alloc_::vec::Vec;
+
+use crate::{
+    sprite::Sprite,
+    surface::{Surface, TexFormat},
+};
+
+use super::{Mipmap, RdpQ, RdpQModes, TexParms, Tile};
+
+const TMEM_SIZE: u32 = 4096;
+const TMEM_HALF: u32 = TMEM_SIZE / 2;
+const MAX_LEVELS: usize = 8;
+
+#[doc = "Error returned when a set of textures cannot be laid out in TMEM"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TmemError {
+    #[doc = "The texture does not fit in the TMEM left by the previous ones"]
+    Overflow {
+        #[doc = "Index of the texture that overflows"]
+        index: usize,
+        #[doc = "Bytes of TMEM required by the texture"]
+        needed: u32,
+        #[doc = "Bytes of TMEM still available"]
+        available: u32,
+    },
+    #[doc = "There are no more tile descriptors available for the texture"]
+    OutOfTiles {
+        #[doc = "Index of the texture"]
+        index: usize,
+        #[doc = "Number of tile descriptors required by the texture (one per mipmap level)"]
+        needed: u32,
+    },
+    #[doc = "There are no more palette slots available for the texture"]
+    OutOfPalettes {
+        #[doc = "Index of the texture"]
+        index: usize,
+    },
+    #[doc = "The texture is RGBA32, which uses the upper half of TMEM, reserved for palettes"]
+    PaletteConflict {
+        #[doc = "Index of the texture"]
+        index: usize,
+    },
+    #[doc = "The texture format cannot be uploaded to TMEM"]
+    UnsupportedFormat {
+        #[doc = "Index of the texture"]
+        index: usize,
+        format: TexFormat,
+    },
+    #[doc = "The texture is a sprite with a detail texture, which cannot be planned"]
+    DetailTexture {
+        #[doc = "Index of the texture"]
+        index: usize,
+    },
+}
+
+impl fmt::Display for TmemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow {
+                index,
+                needed,
+                available,
+            } => write!(
+                f,
+                "texture {index} does not fit TMEM: needs {needed} bytes, {available} available"
+            ),
+            Self::OutOfTiles { index, needed } => write!(
+                f,
+                "texture {index} needs {needed} tile descriptors, but none are left"
+            ),
+            Self::OutOfPalettes { index } => {
+                write!(f, "texture {index} needs a palette, but no slots are left")
+            }
+            Self::PaletteConflict { index } => write!(
+                f,
+                "texture {index} is RGBA32, which cannot be used together with palettes"
+            ),
+            Self::UnsupportedFormat { index, format } => {
+                write!(f, "texture {index} has unsupported format {format:?}")
+            }
+            Self::DetailTexture { index } => {
+                write!(f, "texture {index} is a sprite with a detail texture")
+            }
+        }
+    }
+}
+
+impl core::error::Error for TmemError {}
+
+#[derive(Debug, Copy, Clone)]
+enum TexSource<'a> {
+    Surface(&'a Surface<'a>, Option<&'a [u16]>),
+    Sprite(&'a Sprite),
+}
+
+#[doc = "Description of a texture to be laid out in TMEM by [`TmemPlan`].\n\n A texture can be described by its format and size only (for instance, to\n plan a layout ahead of time), or created from a [`Surface`] or a [`Sprite`],\n in which case the texture can also be uploaded via [`TmemPlan::upload`]."]
+#[derive(Debug, Clone)]
+pub struct TmemTexture<'a> {
+    format: TexFormat,
+    levels: [(u16, u16); MAX_LEVELS],
+    num_levels: u8,
+    parms: TexParms,
+    source: Option<TexSource<'a>>,
+    detail: bool,
+}
+
+impl<'a> TmemTexture<'a> {
+    #[doc = "Describe a texture by its format and size"]
+    #[inline]
+    pub const fn new(format: TexFormat, width: u16, height: u16) -> Self {
+        let mut levels = [(0, 0); MAX_LEVELS];
+        levels[0] = (width, height);
+        Self {
+            format,
+            levels,
+            num_levels: 1,
+            parms: TexParms::new(),
+            source: None,
+            detail: false,
+        }
+    }
+    #[doc = "Describe a surface to be uploaded to TMEM.\n\n If the surface is color-indexed, the palette to upload along with it can\n be specified via [`TmemTexture::tlut`]."]
+    #[inline]
+    pub fn surface(surface: &'a Surface<'a>) -> Self {
+        Self {
+            source: Some(TexSource::Surface(surface, None)),
+            ..Self::new(surface.format(), surface.width(), surface.height())
+        }
+    }
+    #[doc = "Describe a sprite to be uploaded to TMEM.\n\n All the mipmap levels of the sprite are taken into account, and the\n texture parameters stored within the sprite (if any) are used as default.\n Sprites with a detail texture are rejected by [`TmemPlan::new`]."]
+    pub fn sprite(sprite: &'a Sprite) -> Self {
+        let main = sprite.pixels();
+        let mut tex = Self::new(sprite.format(), main.width(), main.height());
+        let count = (sprite.lod_count() as usize).clamp(1, MAX_LEVELS);
+        for level in 1..count {
+            let lod = sprite.lod_pixels(level as u8);
+            tex.levels[level] = (lod.width(), lod.height());
+        }
+        tex.num_levels = count as u8;
+        if let Some(parms) = sprite.texparms() {
+            tex.parms = parms.into_builder();
+        }
+        tex.source = Some(TexSource::Sprite(sprite));
+        tex.detail = sprite.detail_pixels(None).0.buffer().is_some();
+        tex
+    }
+    #[doc = "Set the number of mipmap levels of a texture described via [`TmemTexture::new`].\n\n Each level is half the size of the previous one, as generated by mksprite."]
+    #[inline]
+    pub const fn mipmaps(mut self, levels: u8) -> Self {
+        let levels = if levels == 0 {
+            1
+        } else if levels as usize > MAX_LEVELS {
+            MAX_LEVELS as u8
+        } else {
+            levels
+        };
+        let mut i = 1;
+        while i < levels as usize {
+            let (w, h) = self.levels[i - 1];
+            self.levels[i] = (if w > 1 { w / 2 } else { 1 }, if h > 1 { h / 2 } else { 1 });
+            i += 1;
+        }
+        self.num_levels = levels;
+        self
+    }
+    #[doc = "Set the texture sampling parameters.\n\n The TMEM address and the palette are overwritten by the plan."]
+    #[inline]
+    pub const fn parms(mut self, parms: TexParms) -> Self {
+        self.parms = parms;
+        self
+    }
+    #[doc = "Set the palette to upload along with a color-indexed surface"]
+    #[inline]
+    pub fn tlut(mut self, tlut: &'a [u16]) -> Self {
+        if let Some(TexSource::Surface(surface, _)) = self.source {
+            self.source = Some(TexSource::Surface(surface, Some(tlut)));
+        }
+        self
+    }
+    #[doc = "Texture format"]
+    #[inline]
+    pub const fn format(&self) -> TexFormat {
+        self.format
+    }
+    #[doc = "Number of mipmap levels"]
+    #[inline]
+    pub const fn levels(&self) -> u8 {
+        self.num_levels
+    }
+    #[doc = "Width and height of a mipmap level"]
+    #[inline]
+    pub const fn level_size(&self, level: u8) -> Option<(u16, u16)> {
+        if level < self.num_levels {
+            Some(self.levels[level as usize])
+        } else {
+            None
+        }
+    }
+    #[doc = "Size in bytes of a line of a mipmap level in TMEM.\n\n Lines are padded to 8 bytes. RGBA32 textures are split in two halves of\n TMEM, so only half of each texel counts here."]
+    #[inline]
+    pub const fn tmem_pitch(&self, level: u8) -> u32 {
+        let width = self.levels[level as usize].0 as u32;
+        let bytes = match self.format {
+            TexFormat::RGBA32 => width * 2,
+            format => format.pixels_to_bytes(width),
+        };
+        (bytes + 7) & !7
+    }
+    #[doc = "Total bytes of TMEM used by the texture, including all its mipmap levels"]
+    #[inline]
+    pub const fn tmem_bytes(&self) -> u32 {
+        let mut total = 0;
+        let mut level = 0;
+        while level < self.num_levels {
+            total += self.tmem_pitch(level) * self.levels[level as usize].1 as u32;
+            level += 1;
+        }
+        total
+    }
+    #[inline]
+    const fn is_ci(&self) -> bool {
+        matches!(self.format, TexFormat::CI4 | TexFormat::CI8)
+    }
+}
+
+#[doc = "Placement of a texture in TMEM, as computed by [`TmemPlan`]"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TmemSlot {
+    #[doc = "Tile descriptor of the first mipmap level (the following levels use the following tiles)"]
+    pub tile: Tile,
+    #[doc = "Number of tile descriptors used (one per mipmap level)"]
+    pub tiles: u8,
+    #[doc = "TMEM address of the texture, in bytes"]
+    pub tmem_addr: u16,
+    #[doc = "Bytes of TMEM used by the texture"]
+    pub tmem_bytes: u16,
+    #[doc = "Palette slot (0-15) for color-indexed textures. CI8 textures always use slot 0 and the whole TLUT."]
+    pub palette: Option<u8>,
+}
+
+#[doc = "Layout of a set of textures in TMEM.\n\n The plan assigns to each texture a TMEM address, a tile descriptor for each\n of its mipmap levels and, for color-indexed textures, a palette slot. It is\n computed ahead of time, so that it is possible to know whether a set of\n textures fits in TMEM before uploading them, and which one does not fit.\n\n Textures are laid out in order, like [`RdpQ::tex_multi_begin`] does. Tile\n descriptors are assigned starting from [`Tile::_0`]; [`Tile::INTERNAL`] is\n never used. When any texture is color-indexed, textures must fit in the\n lower half of TMEM, as the upper half contains the palettes."]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TmemPlan {
+    slots: Vec<TmemSlot>,
+    used: u32,
+    limit: u32,
+}
+
+impl TmemPlan {
+    #[doc = "Compute the TMEM layout of a set of textures.\n\n Returns an error describing the first texture that could not be placed."]
+    pub fn new(textures: &[TmemTexture<'_>]) -> Result<Self, TmemError> {
+        let has_ci = textures.iter().any(TmemTexture::is_ci);
+        let limit = if has_ci || textures.iter().any(|tex| tex.format == TexFormat::RGBA32) {
+            TMEM_HALF
+        } else {
+            TMEM_SIZE
+        };
+
+        let mut slots = Vec::with_capacity(textures.len());
+        let mut addr = 0;
+        let mut tile = 0;
+        let mut next_palette = 0;
+        for (index, tex) in textures.iter().enumerate() {
+            match tex.format {
+                TexFormat::None | TexFormat::YUV16 => {
+                    return Err(TmemError::UnsupportedFormat {
+                        index,
+                        format: tex.format,
+                    });
+                }
+                TexFormat::RGBA32 if has_ci => return Err(TmemError::PaletteConflict { index }),
+                _ => {}
+            }
+            if tex.detail {
+                return Err(TmemError::DetailTexture { index });
+            }
+
+            let needed = tex.tmem_bytes();
+            if addr + needed > limit {
+                return Err(TmemError::Overflow {
+                    index,
+                    needed,
+                    available: limit - addr,
+                });
+            }
+
+            let tiles = tex.num_levels as u32;
+            if tile + tiles > Tile::INTERNAL as u32 {
+                return Err(TmemError::OutOfTiles {
+                    index,
+                    needed: tiles,
+                });
+            }
+
+            let palette = match tex.format {
+                TexFormat::CI4 if next_palette < 16 => {
+                    next_palette += 1;
+                    Some(next_palette - 1)
+                }
+                TexFormat::CI8 if next_palette == 0 => {
+                    next_palette = 16;
+                    Some(0)
+                }
+                TexFormat::CI4 | TexFormat::CI8 => {
+                    return Err(TmemError::OutOfPalettes { index });
+                }
+                _ => None,
+            };
+
+            slots.push(TmemSlot {
+                tile: Tile::from_index(tile),
+                tiles: tiles as u8,
+                tmem_addr: addr as u16,
+                tmem_bytes: needed as u16,
+                palette,
+            });
+            addr += needed;
+            tile += tiles;
+        }
+
+        Ok(Self {
+            slots,
+            used: addr,
+            limit,
+        })
+    }
+    #[doc = "Placement of each texture, in the same order as they were given"]
+    #[inline]
+    pub fn slots(&self) -> &[TmemSlot] {
+        &self.slots
+    }
+    #[doc = "Total bytes of TMEM used by the textures"]
+    #[inline]
+    pub const fn used_bytes(&self) -> u32 {
+        self.used
+    }
+    #[doc = "Bytes of TMEM still available for textures"]
+    #[inline]
+    pub const fn available_bytes(&self) -> u32 {
+        self.limit - self.used
+    }
+    #[doc = "Texture parameters to upload a texture of the plan, with the planned TMEM address and palette"]
+    #[inline]
+    pub fn texparms(&self, textures: &[TmemTexture<'_>], index: usize) -> TexParms {
+        let slot = &self.slots[index];
+        textures[index]
+            .parms
+            .tmem_addr(slot.tmem_addr as i32)
+            .palette(slot.palette.unwrap_or(0) as i32)
+    }
+    #[doc = "Upload the textures to TMEM, according to the plan.\n\n The textures must be the same ones (in the same order) that were used to\n compute the plan. Textures described via [`TmemTexture::new`] have no\n contents and are skipped. Palettes of sprites and of surfaces with a\n palette set via [`TmemTexture::tlut`] are uploaded too, in the planned\n palette slot.\n\n Sprites are uploaded level by level at the planned TMEM addresses and\n tiles; like [`RdpQ::sprite_upload`], this also configures the palette and\n mipmapping in the render mode."]
+    pub fn upload(&self, rdpq: &mut RdpQ<'_>, textures: &[TmemTexture<'_>]) {
+        assert_eq!(
+            textures.len(),
+            self.slots.len(),
+            "textures do not match the plan"
+        );
+        for (index, (tex, slot)) in textures.iter().zip(&self.slots).enumerate() {
+            let parms = self.texparms(textures, index);
+            match tex.source {
+                Some(TexSource::Surface(surface, tlut)) => {
+                    if let (Some(tlut), Some(palette)) = (tlut, slot.palette) {
+                        rdpq.tex_upload_tlut(tlut, palette as i32 * 16);
+                    }
+                    rdpq.tex_upload(slot.tile, surface, Some(&parms));
+                }
+                Some(TexSource::Sprite(sprite)) => {
+                    if let (Some(tlut), Some(palette)) = (sprite.palette(), slot.palette) {
+                        rdpq.tex_upload_tlut(tlut, palette as i32 * 16);
+                    }
+                    let mut parms = parms;
+                    for level in 0..slot.tiles {
+                        let tile = Tile::from_index(slot.tile as u32 + level as u32);
+                        rdpq.tex_upload(tile, &sprite.lod_pixels(level), Some(&parms));
+                        let (_, height) = tex.levels[level as usize];
+                        parms.0.tmem_addr += (tex.tmem_pitch(level) * height as u32) as i32;
+                        // Each level is sampled at half the scale of the previous one, like
+                        // rdpq_sprite_upload does
+                        for axis in [&mut parms.0.s, &mut parms.0.t] {
+                            axis.scale_log += 1;
+                            axis.translate *= 0.5;
+                        }
+                    }
+                    rdpq.mode_tlut(tex.format.tlut());
+                    match slot.tiles {
+                        1 => rdpq.mode_mipmap(Mipmap::None, 0),
+                        n => rdpq.mode_mipmap(Mipmap::Interpolate, n as u32),
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmem_size() {
+        let tex = TmemTexture::new(TexFormat::RGBA16, 32, 16);
+        assert_eq!(tex.tmem_pitch(0), 64);
+        assert_eq!(tex.tmem_bytes(), 1024);
+
+        // Lines are padded to 8 bytes
+        let tex = TmemTexture::new(TexFormat::CI4, 6, 4);
+        assert_eq!(tex.tmem_pitch(0), 8);
+        assert_eq!(tex.tmem_bytes(), 32);
+
+        // RGBA32 texels are split in the two halves of TMEM
+        let tex = TmemTexture::new(TexFormat::RGBA32, 16, 16);
+        assert_eq!(tex.tmem_pitch(0), 32);
+        assert_eq!(tex.tmem_bytes(), 512);
+    }
+
+    #[test]
+    fn mipmaps() {
+        let tex = TmemTexture::new(TexFormat::RGBA16, 32, 8).mipmaps(5);
+        assert_eq!(tex.levels(), 5);
+        assert_eq!(tex.level_size(1), Some((16, 4)));
+        assert_eq!(tex.level_size(4), Some((2, 1)));
+        assert_eq!(tex.level_size(5), None);
+        assert_eq!(tex.tmem_bytes(), 512 + 128 + 32 + 8 + 8);
+
+        assert_eq!(tex.clone().mipmaps(0).levels(), 1);
+        assert_eq!(tex.mipmaps(20).levels(), MAX_LEVELS as u8);
+    }
+
+    #[test]
+    fn layout() {
+        let textures = [
+            TmemTexture::new(TexFormat::RGBA16, 16, 16).mipmaps(2),
+            TmemTexture::new(TexFormat::I8, 32, 32),
+            TmemTexture::new(TexFormat::IA16, 8, 8),
+        ];
+        let plan = TmemPlan::new(&textures).unwrap();
+        assert_eq!(
+            plan.slots(),
+            &[
+                TmemSlot {
+                    tile: Tile::_0,
+                    tiles: 2,
+                    tmem_addr: 0,
+                    tmem_bytes: 640,
+                    palette: None,
+                },
+                TmemSlot {
+                    tile: Tile::_2,
+                    tiles: 1,
+                    tmem_addr: 640,
+                    tmem_bytes: 1024,
+                    palette: None,
+                },
+                TmemSlot {
+                    tile: Tile::_3,
+                    tiles: 1,
+                    tmem_addr: 1664,
+                    tmem_bytes: 128,
+                    palette: None,
+                },
+            ]
+        );
+        assert_eq!(plan.used_bytes(), 1792);
+        assert_eq!(plan.available_bytes(), 4096 - 1792);
+
+        let parms = plan.texparms(&textures, 1);
+        assert_eq!(parms.0.tmem_addr, 640);
+        assert_eq!(parms.0.palette, 0);
+    }
+
+    #[test]
+    fn palettes() {
+        let textures = [
+            TmemTexture::new(TexFormat::CI4, 16, 16),
+            TmemTexture::new(TexFormat::RGBA16, 8, 8),
+            TmemTexture::new(TexFormat::CI4, 16, 16),
+        ];
+        let plan = TmemPlan::new(&textures).unwrap();
+        let palettes: Vec<_> = plan.slots().iter().map(|s| s.palette).collect();
+        assert_eq!(palettes, [Some(0), None, Some(1)]);
+        assert_eq!(plan.texparms(&textures, 2).0.palette, 1);
+        // The upper half of TMEM is reserved for the palettes
+        assert_eq!(plan.available_bytes(), 2048 - 128 - 128 - 128);
+
+        let ci8 = TmemTexture::new(TexFormat::CI8, 8, 8);
+        let plan = TmemPlan::new(core::slice::from_ref(&ci8)).unwrap();
+        assert_eq!(plan.slots()[0].palette, Some(0));
+        assert_eq!(
+            TmemPlan::new(&[ci8.clone(), ci8.clone()]),
+            Err(TmemError::OutOfPalettes { index: 1 })
+        );
+        assert_eq!(
+            TmemPlan::new(&[TmemTexture::new(TexFormat::CI4, 8, 8), ci8]),
+            Err(TmemError::OutOfPalettes { index: 1 })
+        );
+
+        let ci4 = [const { TmemTexture::new(TexFormat::CI4, 8, 2) }; 7];
+        let plan = TmemPlan::new(&ci4).unwrap();
+        assert_eq!(plan.slots()[6].palette, Some(6));
+        assert_eq!(plan.slots()[6].tile, Tile::_6);
+    }
+
+    #[test]
+    fn errors() {
+        let rgba16 = |w, h| TmemTexture::new(TexFormat::RGBA16, w, h);
+        assert_eq!(
+            TmemPlan::new(&[rgba16(32, 32), rgba16(32, 32), rgba16(4, 4)]),
+            Err(TmemError::Overflow {
+                index: 2,
+                needed: 32,
+                available: 0,
+            })
+        );
+        // Any RGBA32 texture restricts the layout to the lower half of TMEM
+        assert_eq!(
+            TmemPlan::new(&[rgba16(32, 32), TmemTexture::new(TexFormat::RGBA32, 8, 8)]),
+            Err(TmemError::Overflow {
+                index: 1,
+                needed: 128,
+                available: 0,
+            })
+        );
+        assert_eq!(
+            TmemPlan::new(&[
+                TmemTexture::new(TexFormat::CI4, 8, 8),
+                TmemTexture::new(TexFormat::RGBA32, 8, 8),
+            ]),
+            Err(TmemError::PaletteConflict { index: 1 })
+        );
+        assert_eq!(
+            TmemPlan::new(&[rgba16(8, 8), TmemTexture::new(TexFormat::YUV16, 8, 8)]),
+            Err(TmemError::UnsupportedFormat {
+                index: 1,
+                format: TexFormat::YUV16,
+            })
+        );
+
+        // Tile::INTERNAL is never assigned
+        let textures = [rgba16(4, 4).mipmaps(3), rgba16(4, 4).mipmaps(5)];
+        assert!(TmemPlan::new(&textures[..1]).is_ok());
+        assert_eq!(
+            TmemPlan::new(&textures),
+            Err(TmemError::OutOfTiles {
+                index: 1,
+                needed: 5,
+            })
+        );
+        assert_eq!(
+            TmemError::OutOfTiles {
+                index: 1,
+                needed: 4,
+            }
+            .to_string(),
+            "texture 1 needs 4 tile descriptors, but none are left"
+        );
+    }
+}
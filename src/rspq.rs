@@ -137,6 +137,7 @@ impl RspQ {
     #[inline]
     pub fn block_run<'b>(&self, block: impl Into<BlockRef<'b>>) {
         unsafe { rspq_block_run(block.into().0.as_ptr()) }
+        crate::rdpq::forget_all();
     }
     #[doc = "Start building a high-priority queue.\n\n This function enters a special mode in which a high-priority queue is\n activated and can be filled with commands. After this function has been\n called, all commands will be put in the high-priority queue, until\n `rspq_highpri_end` is called.\n\n The RSP will start processing the high-priority queue almost instantly\n (as soon as the current command is done), pausing the normal queue. This will\n also happen while the high-priority queue is being built, to achieve the\n lowest possible latency. When the RSP finishes processing the high priority\n queue (after `rspq_highpri_end` closes it), it resumes processing the normal\n queue from the exact point that was left.\n\n The goal of the high-priority queue is to either schedule latency-sensitive\n commands like audio processing, or to schedule immediate RSP calculations\n that should be performed right away, just like they were preempting what\n the RSP is currently doing.\n\n It is possible to create multiple high-priority queues by calling\n `rspq_highpri_begin` / `rspq_highpri_end` multiple times with short\n delays in-between. The RSP will process them in order. Notice that\n there is a overhead in doing so, so it might be advisable to keep\n the high-priority mode active for a longer period if possible. On the\n other hand, a shorter high-priority queue allows for the RSP to\n switch back to processing the normal queue before the next one\n is created.\n\n @note It is not possible to create a block while the high-priority queue is\n       active. Arrange for constructing blocks beforehand.\n\n @note It is currently not possible to call a block from the\n       high-priority queue. (FIXME: to be implemented)\n"]
     #[inline]
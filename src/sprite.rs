@@ -5,6 +5,10 @@ use crate::{
     sys::sprite::*,
 };
 
+mod file;
+
+pub use file::*;
+
 #[doc = "Sprite structure.\n\n A \"sprite\" (as saved in a `.sprite` file) is a 2D image with\n metadata attached to them to facilitate drawing it onto N64.\n\n Despite the name, a libdragon sprite is basically the basic format\n to handle assets for images. It is commonly used for handling\n textures, full screen images like splash screens, tile maps,\n font pictures, and even \"real\" 2D sprites.\n\n If the sprite uses a color-indexed format like `FMT_CI4` or `FMT_CI8`,\n the sprite contains also the corresponding palette.\n\n To convert an image file to libdragon's sprite format, use\n the mksprite tool. To load a sprite into memory, use `sprite_load`."]
 #[repr(transparent)]
 #[derive(Debug)]
@@ -93,6 +97,15 @@ impl Sprite {
     pub unsafe fn load_buf(buf: &'static [u8]) -> Self {
         unsafe { Self(NonNull::new(sprite_load_buf(buf.as_ptr() as _, buf.len() as _)).unwrap()) }
     }
+    #[doc = "Load a sprite from a buffer, checking its contents first\n\n This is a safe version of [`Sprite::load_buf`]: the sprite data is\n validated with [`SpriteFile::parse`], so that invalid data is reported\n as an error instead of crashing. As with [`Sprite::load_buf`], the sprite\n is loaded in place, so the buffer is borrowed for the lifetime of the\n program; it must be aligned to 8 bytes.\n\n @param buf           The sprite file data"]
+    pub fn from_bytes(buf: &'static mut [u8]) -> Result<Self, SpriteError> {
+        SpriteFile::parse(buf)?;
+        if !(buf.as_ptr() as usize).is_multiple_of(8) {
+            return Err(SpriteError::Misaligned);
+        }
+        let sprite = unsafe { sprite_load_buf(buf.as_mut_ptr() as _, buf.len() as _) };
+        Ok(Self(NonNull::new(sprite).unwrap()))
+    }
 }
 
 impl Drop for Sprite {
@@ -0,0 +1,689 @@
+use core::fmt;
+
+use alloc_::{borrow::Cow, vec::Vec};
+
+use crate::{
+    rdpq::TexParms,
+    surface::{Surface, TexFormat},
+    sys::rdpq_tex::{rdpq_texparms_s__bindgen_ty_1, rdpq_texparms_t},
+    sys::sprite::{SPRITE_FLAGS_EXT, SPRITE_FLAGS_TEXFORMAT},
+};
+
+use super::SpriteTexParms;
+
+#[doc = "Size of the sprite header"]
+const HEADER_SIZE: usize = 8;
+#[doc = "Size of the extended header (`sprite_ext_t`)"]
+const EXT_SIZE: usize = 124;
+#[doc = "Version of the extended header"]
+const EXT_VERSION: u16 = 4;
+#[doc = "Number of LOD slots in the extended header (the last one is used by the\n detail texture, if any)"]
+const LOD_SLOTS: usize = 7;
+#[doc = "Offset of the texture parameters in the extended header"]
+const EXT_TEXPARMS: usize = 68;
+#[doc = "Offset of the detail texture information in the extended header"]
+const EXT_DETAIL: usize = 92;
+
+const FLAG_NUMLODS: u16 = 0x0007;
+const FLAG_HAS_TEXPARMS: u16 = 0x0008;
+const FLAG_HAS_DETAIL: u16 = 0x0010;
+const FLAG_FITS_TMEM: u16 = 0x0020;
+const FLAG_SHQ: u16 = 0x0040;
+
+#[doc = "Error returned when a sprite file cannot be parsed or written"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SpriteError {
+    #[doc = "The data ends before the end of the sprite"]
+    Truncated,
+    #[doc = "The sprite uses an unknown texture format"]
+    InvalidFormat(u8),
+    #[doc = "The extended header has an unsupported version"]
+    UnsupportedVersion(u16),
+    #[doc = "The pixel buffer does not match the size of the image"]
+    SizeMismatch {
+        #[doc = "Bytes required by the image"]
+        needed: usize,
+        #[doc = "Bytes available"]
+        available: usize,
+    },
+    #[doc = "The sprite has too many LOD levels (at most 7, including the main image)"]
+    TooManyLods,
+    #[doc = "The sprite is color-indexed, but has no palette"]
+    MissingPalette,
+    #[doc = "The sprite is larger than the 16 MiB addressable by the file format"]
+    TooLarge,
+    #[doc = "The surface has no buffer"]
+    NoBuffer,
+    #[doc = "The sprite data is not aligned to 8 bytes"]
+    Misaligned,
+}
+
+impl fmt::Display for SpriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("sprite data is truncated"),
+            Self::InvalidFormat(format) => write!(f, "invalid sprite texture format {format}"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported sprite extended header version {version}")
+            }
+            Self::SizeMismatch { needed, available } => write!(
+                f,
+                "sprite pixel buffer has the wrong size: needs {needed} bytes, {available} available"
+            ),
+            Self::TooManyLods => f.write_str("sprite has too many LOD levels"),
+            Self::MissingPalette => f.write_str("color-indexed sprite has no palette"),
+            Self::TooLarge => f.write_str("sprite is too large"),
+            Self::NoBuffer => f.write_str("surface has no buffer"),
+            Self::Misaligned => f.write_str("sprite data is not aligned to 8 bytes"),
+        }
+    }
+}
+
+impl core::error::Error for SpriteError {}
+
+#[doc = "An image stored in a sprite file (the main image, a LOD level or a detail\n texture).\n\n Pixels are stored linearly, with no padding between rows, in the same\n layout used by [`Surface`] (16-bit and 32-bit formats are big-endian)."]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteImage<'a> {
+    format: TexFormat,
+    width: u16,
+    height: u16,
+    pixels: Cow<'a, [u8]>,
+}
+
+impl<'a> SpriteImage<'a> {
+    #[doc = "Create an image from its pixels\n\n @param format    Pixel format\n @param width     Width of the image in pixels\n @param height    Height of the image in pixels\n @param pixels    Pixel data, with no padding between rows"]
+    pub fn new(
+        format: TexFormat,
+        width: u16,
+        height: u16,
+        pixels: impl Into<Cow<'a, [u8]>>,
+    ) -> Result<Self, SpriteError> {
+        if format == TexFormat::None {
+            return Err(SpriteError::InvalidFormat(format as u8));
+        }
+        let pixels = pixels.into();
+        let needed = image_size(format, width, height)?;
+        if pixels.len() != needed {
+            return Err(SpriteError::SizeMismatch {
+                needed,
+                available: pixels.len(),
+            });
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            pixels,
+        })
+    }
+    #[doc = "Create an image with a copy of the pixels of a surface"]
+    pub fn from_surface(surface: &Surface) -> Result<SpriteImage<'static>, SpriteError> {
+        let format = surface.format();
+        if surface.buffer().is_none() {
+            return Err(SpriteError::NoBuffer);
+        }
+        let pixels: Vec<u8> = surface.rows().flatten().copied().collect();
+        SpriteImage::new(format, surface.width(), surface.height(), pixels)
+    }
+    #[inline]
+    pub const fn format(&self) -> TexFormat {
+        self.format
+    }
+    #[inline]
+    pub const fn width(&self) -> u16 {
+        self.width
+    }
+    #[inline]
+    pub const fn height(&self) -> u16 {
+        self.height
+    }
+    #[doc = "Length of a row in bytes"]
+    #[inline]
+    pub const fn stride(&self) -> u16 {
+        self.format.pixels_to_bytes(self.width as u32) as u16
+    }
+    #[inline]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+    #[doc = "Create a surface pointing to the pixels of the image\n\n No memory allocations or copies are performed. The surface must not be\n written to."]
+    #[inline]
+    pub fn surface(&self) -> Surface<'_> {
+        Surface(
+            crate::sys::surface::surface_t {
+                flags: self.format as _,
+                width: self.width,
+                height: self.height,
+                stride: self.stride(),
+                buffer: self.pixels.as_ptr() as *mut _,
+            },
+            core::marker::PhantomData,
+        )
+    }
+    #[doc = "Make the image own its pixels"]
+    #[inline]
+    pub fn into_owned(self) -> SpriteImage<'static> {
+        SpriteImage {
+            pixels: Cow::Owned(self.pixels.into_owned()),
+            ..self
+        }
+    }
+}
+
+#[doc = "Detail texture of a sprite (see [`super::Detail`])"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteDetail<'a> {
+    #[doc = "The detail texture, or `None` if the main image is used as detail\n texture (for fractal detailing)"]
+    pub image: Option<SpriteImage<'a>>,
+    #[doc = "Blend factor of the detail texture in range of 0 to 1"]
+    pub blend_factor: f32,
+    #[doc = "Texture parameters of the detail texture"]
+    pub texparms: SpriteTexParms,
+}
+
+#[doc = "Contents of a `.sprite` file, as created by mksprite.\n\n This is a pure Rust parser and writer of the libdragon sprite format,\n which does not depend on the C library: it can be used to inspect sprites\n (eg: to validate them before calling [`super::Sprite::from_bytes`]), or\n to generate them at runtime or from host tools.\n\n Sprite files compressed via the asset library (eg: with `mksprite\n --compress`) are not supported: they must be decompressed first.\n\n ```ignore\n let quantized = surface.quantize(TexFormat::CI8, &QuantizeParms::new())?;\n let mut sprite = SpriteFile::new(SpriteImage::from_surface(&quantized.surface)?);\n sprite.palette = Some(quantized.tlut);\n let bytes = sprite.to_bytes()?;\n ```"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteFile<'a> {
+    #[doc = "The main image"]
+    pub image: SpriteImage<'a>,
+    #[doc = "Number of horizontal slices, when used as a spritemap"]
+    pub hslices: u8,
+    #[doc = "Number of vertical slices, when used as a spritemap"]
+    pub vslices: u8,
+    #[doc = "LOD levels (mipmaps) following the main image"]
+    pub lods: Vec<SpriteImage<'a>>,
+    #[doc = "Palette of a color-indexed sprite, as RGBA 5551 colors"]
+    pub palette: Option<Vec<u16>>,
+    #[doc = "Texture parameters used when uploading the sprite"]
+    pub texparms: Option<SpriteTexParms>,
+    #[doc = "Detail texture"]
+    pub detail: Option<SpriteDetail<'a>>,
+    #[doc = "Whether the sprite is in SHQ format (see [`super::Sprite::is_shq`])"]
+    pub shq: bool,
+}
+
+#[doc = "Size of the pixels of an image, which might not fit in memory for
+ corrupted headers"]
+#[inline]
+fn image_size(format: TexFormat, width: u16, height: u16) -> Result<usize, SpriteError> {
+    (format.pixels_to_bytes(width as u32) as usize)
+        .checked_mul(height as usize)
+        .ok_or(SpriteError::TooLarge)
+}
+
+#[inline]
+const fn align8(v: usize) -> usize {
+    (v + 7) & !7
+}
+
+#[inline]
+fn get<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], SpriteError> {
+    data.get(offset..offset + N)
+        .map(|b| b.try_into().unwrap())
+        .ok_or(SpriteError::Truncated)
+}
+
+#[inline]
+fn get_u16(data: &[u8], offset: usize) -> Result<u16, SpriteError> {
+    get(data, offset).map(u16::from_be_bytes)
+}
+
+#[inline]
+fn get_u32(data: &[u8], offset: usize) -> Result<u32, SpriteError> {
+    get(data, offset).map(u32::from_be_bytes)
+}
+
+#[doc = "Parse the texture parameters of an axis (`translate`, `scale_log`,\n `repeats` and `mirror`, padded to 12 bytes)"]
+fn get_axis(data: &[u8], offset: usize) -> Result<rdpq_texparms_s__bindgen_ty_1, SpriteError> {
+    Ok(rdpq_texparms_s__bindgen_ty_1 {
+        translate: f32::from_bits(get_u32(data, offset)?),
+        scale_log: get_u16(data, offset + 4)? as i16 as _,
+        repeats: get_u16(data, offset + 6)? as i16 as f32,
+        mirror: get::<1>(data, offset + 8)?[0] != 0,
+    })
+}
+
+fn get_texparms(data: &[u8], offset: usize) -> Result<SpriteTexParms, SpriteError> {
+    Ok(SpriteTexParms(rdpq_texparms_t {
+        s: get_axis(data, offset)?,
+        t: get_axis(data, offset + 12)?,
+        ..Default::default()
+    }))
+}
+
+fn put_axis(out: &mut Vec<u8>, axis: &super::SpriteTexAxis) {
+    out.extend_from_slice(&axis.translate().to_bits().to_be_bytes());
+    out.extend_from_slice(&(axis.scale_log() as i16).to_be_bytes());
+    out.extend_from_slice(&(axis.repeats().min(i16::MAX as u32) as i16).to_be_bytes());
+    out.extend_from_slice(&[axis.mirror() as u8, 0, 0, 0]);
+}
+
+fn put_texparms(out: &mut Vec<u8>, parms: &SpriteTexParms) {
+    put_axis(out, parms.s());
+    put_axis(out, parms.t());
+}
+
+#[doc = "Parse an image stored at a given offset"]
+fn get_image(
+    data: &[u8],
+    format: TexFormat,
+    width: u16,
+    height: u16,
+    offset: usize,
+) -> Result<SpriteImage<'_>, SpriteError> {
+    let size = image_size(format, width, height)?;
+    let pixels = offset
+        .checked_add(size)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(SpriteError::Truncated)?;
+    SpriteImage::new(format, width, height, pixels)
+}
+
+#[doc = "Parse the texture format of a sprite or LOD"]
+fn get_format(raw: u8) -> Result<TexFormat, SpriteError> {
+    match TexFormat::from_raw(raw as u32) {
+        Some(TexFormat::None) | None => Err(SpriteError::InvalidFormat(raw)),
+        Some(format) => Ok(format),
+    }
+}
+
+impl<'a> SpriteFile<'a> {
+    #[doc = "Create a sprite made of a single image, with no palette nor\n additional data"]
+    #[inline]
+    pub fn new(image: SpriteImage<'a>) -> Self {
+        Self {
+            image,
+            hslices: 1,
+            vslices: 1,
+            lods: Vec::new(),
+            palette: None,
+            texparms: None,
+            detail: None,
+            shq: false,
+        }
+    }
+    #[doc = "Parse a sprite file\n\n The images of the sprite borrow the pixels from `data`. Both the legacy\n format (without the extended header) and the current one are supported."]
+    pub fn parse(data: &'a [u8]) -> Result<Self, SpriteError> {
+        let width = get_u16(data, 0)?;
+        let height = get_u16(data, 2)?;
+        let [bitdepth, flags, hslices, vslices] = get(data, 4)?;
+        let format = match (flags & SPRITE_FLAGS_TEXFORMAT as u8, bitdepth) {
+            // Old sprites only stored the bit depth
+            (0, 2) => TexFormat::RGBA16,
+            (0, 4) => TexFormat::RGBA32,
+            (raw, _) => get_format(raw)?,
+        };
+        let image = get_image(data, format, width, height, HEADER_SIZE)?;
+        let mut sprite = Self {
+            hslices,
+            vslices,
+            ..Self::new(image)
+        };
+        if flags & SPRITE_FLAGS_EXT as u8 == 0 {
+            return Ok(sprite);
+        }
+
+        let ext = align8(HEADER_SIZE + sprite.image.pixels.len());
+        let version = get_u16(data, ext + 2)?;
+        if version != EXT_VERSION {
+            return Err(SpriteError::UnsupportedVersion(version));
+        }
+        if data.len() < ext + EXT_SIZE {
+            return Err(SpriteError::Truncated);
+        }
+        let ext_flags = get_u16(data, ext + 64)?;
+        let lod = |idx: usize| -> Result<SpriteImage<'a>, SpriteError> {
+            let offset = ext + 8 + idx * 8;
+            let pos = get_u32(data, offset + 4)?;
+            get_image(
+                data,
+                get_format((pos >> 24) as u8)?,
+                get_u16(data, offset)?,
+                get_u16(data, offset + 2)?,
+                (pos & 0xFF_FFFF) as usize,
+            )
+        };
+
+        let count = ((ext_flags & FLAG_NUMLODS) as usize).max(1);
+        sprite.lods = (1..count)
+            .map(|idx| lod(idx - 1))
+            .collect::<Result<_, _>>()?;
+        if ext_flags & FLAG_HAS_TEXPARMS != 0 {
+            sprite.texparms = Some(get_texparms(data, ext + EXT_TEXPARMS)?);
+        }
+        if ext_flags & FLAG_HAS_DETAIL != 0 {
+            let use_main_tex = data[ext + EXT_DETAIL] != 0;
+            sprite.detail = Some(SpriteDetail {
+                image: match use_main_tex {
+                    true => None,
+                    false => Some(lod(LOD_SLOTS - 1)?),
+                },
+                blend_factor: f32::from_bits(get_u32(data, ext + EXT_DETAIL + 4)?),
+                texparms: get_texparms(data, ext + EXT_DETAIL + 8)?,
+            });
+        }
+        sprite.shq = ext_flags & FLAG_SHQ != 0;
+
+        let pal_pos = get_u32(data, ext + 4)? as usize;
+        let colors: usize = match format {
+            TexFormat::CI4 => 16,
+            TexFormat::CI8 => 256,
+            _ => 0,
+        };
+        if colors != 0 && pal_pos != 0 {
+            // The C library always reads the full palette
+            let palette = pal_pos
+                .checked_add(colors * 2)
+                .and_then(|end| data.get(pal_pos..end))
+                .ok_or(SpriteError::Truncated)?;
+            sprite.palette = Some(
+                palette
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect(),
+            );
+        }
+        Ok(sprite)
+    }
+    #[doc = "Number of LOD levels, including the main image"]
+    #[inline]
+    pub fn lod_count(&self) -> usize {
+        self.lods.len() + 1
+    }
+    #[doc = "Return true if the sprite fits in TMEM without splitting (see\n [`super::Sprite::fits_tmem`])\n\n All the images (with rows padded to 8 bytes) must fit in 4 KiB, or 2 KiB\n for color-indexed sprites, whose palette uses the upper half of TMEM."]
+    pub fn fits_tmem(&self) -> bool {
+        let tmem = |img: &SpriteImage| align8(img.stride() as usize) * img.height as usize;
+        let detail = self.detail.iter().filter_map(|d| d.image.as_ref());
+        let used: usize = core::iter::once(&self.image)
+            .chain(&self.lods)
+            .chain(detail)
+            .map(tmem)
+            .sum();
+        let limit = match self.image.format {
+            TexFormat::CI4 | TexFormat::CI8 => 2048,
+            _ => 4096,
+        };
+        used <= limit
+    }
+    #[doc = "Encode the sprite in the format read by [`super::Sprite::load`]\n\n The extended header is always written, so that the sprite can contain\n LODs, palette and texture parameters. Palettes are padded to 16 colors\n for CI4 sprites and 256 for CI8 sprites."]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SpriteError> {
+        let format = self.image.format;
+        let colors: usize = match format {
+            TexFormat::CI4 => 16,
+            TexFormat::CI8 => 256,
+            _ => 0,
+        };
+        if colors != 0 && self.palette.is_none() {
+            return Err(SpriteError::MissingPalette);
+        }
+        // The LOD count is stored in 3 bits, including the main image, and the
+        // last LOD slot is left to the detail texture
+        if self.lods.len() >= LOD_SLOTS {
+            return Err(SpriteError::TooManyLods);
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.image.width.to_be_bytes());
+        out.extend_from_slice(&self.image.height.to_be_bytes());
+        out.push((format.bitdepth() / 8) as u8);
+        out.push(format as u8 | SPRITE_FLAGS_EXT as u8);
+        out.push(self.hslices);
+        out.push(self.vslices);
+        out.extend_from_slice(&self.image.pixels);
+        out.resize(align8(out.len()), 0);
+
+        // The extended header is filled after the data, once the offsets are known
+        let ext = out.len();
+        out.resize(ext + EXT_SIZE, 0);
+        let mut slots = [(0u16, 0u16, 0u32); LOD_SLOTS];
+        let detail = self.detail.as_ref().and_then(|d| d.image.as_ref());
+        let images = self
+            .lods
+            .iter()
+            .enumerate()
+            .chain(detail.map(|img| (LOD_SLOTS - 1, img)));
+        for (idx, img) in images {
+            out.resize(align8(out.len()), 0);
+            if out.len() > 0xFF_FFFF {
+                return Err(SpriteError::TooLarge);
+            }
+            slots[idx] = (
+                img.width,
+                img.height,
+                ((img.format as u32) << 24) | out.len() as u32,
+            );
+            out.extend_from_slice(&img.pixels);
+        }
+        let mut pal_pos = 0;
+        if let Some(palette) = self.palette.as_ref().filter(|_| colors != 0) {
+            out.resize(align8(out.len()), 0);
+            pal_pos = out.len() as u32;
+            let padding = colors.saturating_sub(palette.len());
+            palette
+                .iter()
+                .take(colors)
+                .chain(core::iter::repeat_n(&0, padding))
+                .for_each(|c| out.extend_from_slice(&c.to_be_bytes()));
+        }
+        if out.len() > 0xFF_FFFF {
+            return Err(SpriteError::TooLarge);
+        }
+
+        let mut flags = self.lod_count() as u16;
+        flags |= if self.texparms.is_some() {
+            FLAG_HAS_TEXPARMS
+        } else {
+            0
+        };
+        flags |= if self.detail.is_some() {
+            FLAG_HAS_DETAIL
+        } else {
+            0
+        };
+        flags |= if self.fits_tmem() { FLAG_FITS_TMEM } else { 0 };
+        flags |= if self.shq { FLAG_SHQ } else { 0 };
+        let mut header = Vec::with_capacity(EXT_SIZE);
+        header.extend_from_slice(&(EXT_SIZE as u16).to_be_bytes());
+        header.extend_from_slice(&EXT_VERSION.to_be_bytes());
+        header.extend_from_slice(&pal_pos.to_be_bytes());
+        for (width, height, pos) in slots {
+            header.extend_from_slice(&width.to_be_bytes());
+            header.extend_from_slice(&height.to_be_bytes());
+            header.extend_from_slice(&pos.to_be_bytes());
+        }
+        header.extend_from_slice(&flags.to_be_bytes());
+        header.extend_from_slice(&[0, 0]);
+        put_texparms(&mut header, &self.texparms.unwrap_or_default());
+        match &self.detail {
+            Some(detail) => {
+                header.extend_from_slice(&[detail.image.is_none() as u8, 0, 0, 0]);
+                header.extend_from_slice(&detail.blend_factor.to_bits().to_be_bytes());
+                put_texparms(&mut header, &detail.texparms);
+            }
+            None => header.resize(EXT_SIZE, 0),
+        }
+        out[ext..ext + EXT_SIZE].copy_from_slice(&header);
+        Ok(out)
+    }
+}
+
+impl From<TexParms> for SpriteTexParms {
+    #[inline]
+    fn from(parms: TexParms) -> Self {
+        Self(parms.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdpq::TexAxis;
+
+    fn image(format: TexFormat, width: u16, height: u16, seed: u8) -> SpriteImage<'static> {
+        let size = image_size(format, width, height).unwrap();
+        let pixels: Vec<u8> = (0..size).map(|i| seed.wrapping_add(i as u8)).collect();
+        SpriteImage::new(format, width, height, pixels).unwrap()
+    }
+
+    fn texparms(translate: f32, scale_log: i32, repeats: u32, mirror: bool) -> SpriteTexParms {
+        let axis = TexAxis::new()
+            .translate(translate)
+            .scale_log(scale_log)
+            .repeats(repeats)
+            .mirror(mirror);
+        crate::rdpq::TexParms::new()
+            .s(axis)
+            .t(axis.mirror(!mirror))
+            .into()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut sprite = SpriteFile::new(image(TexFormat::RGBA16, 3, 2, 1));
+        sprite.hslices = 3;
+        let bytes = sprite.to_bytes().unwrap();
+        assert_eq!(
+            bytes[..8],
+            [
+                0,
+                3,
+                0,
+                2,
+                2,
+                TexFormat::RGBA16 as u8 | SPRITE_FLAGS_EXT as u8,
+                3,
+                1
+            ]
+        );
+        // The extended header follows the pixels, aligned to 8 bytes
+        assert_eq!(bytes[24..28], [0, EXT_SIZE as u8, 0, EXT_VERSION as u8]);
+        assert_eq!(bytes.len(), 24 + EXT_SIZE);
+        assert_eq!(SpriteFile::parse(&bytes).unwrap(), sprite);
+    }
+
+    #[test]
+    fn round_trip_full() {
+        let mut sprite = SpriteFile::new(image(TexFormat::CI4, 4, 4, 10));
+        sprite.lods = alloc_::vec![
+            image(TexFormat::CI4, 2, 2, 20),
+            image(TexFormat::CI4, 1, 1, 30)
+        ];
+        sprite.palette = Some((0..16).map(|i| i * 0x1111).collect());
+        sprite.texparms = Some(texparms(1.5, -1, 2, true));
+        sprite.detail = Some(SpriteDetail {
+            image: Some(image(TexFormat::I8, 2, 2, 40)),
+            blend_factor: 0.5,
+            texparms: texparms(-3.0, 2, 1, false),
+        });
+        sprite.shq = true;
+        let bytes = sprite.to_bytes().unwrap();
+        let parsed = SpriteFile::parse(&bytes).unwrap();
+        assert_eq!(parsed, sprite);
+        assert_eq!(parsed.lod_count(), 3);
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+
+        // Fractal detailing, and a short palette padded to 16 colors
+        sprite.detail.as_mut().unwrap().image = None;
+        sprite.palette = Some(alloc_::vec![0xFFFF, 0x0001]);
+        let bytes = sprite.to_bytes().unwrap();
+        let parsed = SpriteFile::parse(&bytes).unwrap();
+        assert_eq!(parsed.detail, sprite.detail);
+        let mut palette = alloc_::vec![0; 16];
+        palette[..2].copy_from_slice(&[0xFFFF, 0x0001]);
+        assert_eq!(parsed.palette, Some(palette));
+    }
+
+    #[test]
+    fn legacy() {
+        let mut bytes = alloc_::vec![0, 1, 0, 2, 4, 0, 1, 1];
+        bytes.extend_from_slice(&[0xAA; 8]);
+        let sprite = SpriteFile::parse(&bytes).unwrap();
+        assert_eq!(sprite.image.format(), TexFormat::RGBA32);
+        assert_eq!((sprite.image.width(), sprite.image.height()), (1, 2));
+        assert_eq!(sprite.image.pixels(), [0xAA; 8]);
+        assert!(sprite.lods.is_empty() && sprite.palette.is_none());
+
+        bytes[4] = 3;
+        assert_eq!(
+            SpriteFile::parse(&bytes),
+            Err(SpriteError::InvalidFormat(0))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut sprite = SpriteFile::new(image(TexFormat::CI8, 4, 2, 0));
+        sprite.palette = Some(alloc_::vec![0x1234; 256]);
+        sprite.lods = alloc_::vec![image(TexFormat::CI8, 2, 1, 0)];
+        let bytes = sprite.to_bytes().unwrap();
+        // Images and palette are aligned to 8 bytes
+        let (ext, lod, pal_pos) = (16, align8(16 + EXT_SIZE), align8(16 + EXT_SIZE) + 8);
+        assert_eq!(bytes.len(), pal_pos + 512);
+
+        for len in [
+            0,
+            7,
+            15,
+            ext + 3,
+            ext + EXT_SIZE - 1,
+            lod + 1,
+            pal_pos,
+            bytes.len() - 1,
+        ] {
+            assert_eq!(
+                SpriteFile::parse(&bytes[..len]),
+                Err(SpriteError::Truncated),
+                "{len}"
+            );
+        }
+
+        let mut bad = bytes.clone();
+        bad[ext + 3] = 3;
+        assert_eq!(
+            SpriteFile::parse(&bad),
+            Err(SpriteError::UnsupportedVersion(3))
+        );
+
+        // LODs and palettes out of the data
+        let mut bad = bytes.clone();
+        bad[ext + 8..ext + 12].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(SpriteFile::parse(&bad), Err(SpriteError::Truncated));
+        let mut bad = bytes.clone();
+        bad[ext + 13..ext + 16].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+        assert_eq!(SpriteFile::parse(&bad), Err(SpriteError::Truncated));
+        let mut bad = bytes.clone();
+        bad[ext + 4..ext + 8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(SpriteFile::parse(&bad), Err(SpriteError::Truncated));
+
+        // The largest image a header can describe
+        let mut bad = bytes.clone();
+        bad[..6].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 4, TexFormat::RGBA32 as u8]);
+        assert!(matches!(
+            SpriteFile::parse(&bad),
+            Err(SpriteError::Truncated | SpriteError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn write_errors() {
+        assert_eq!(
+            SpriteImage::new(TexFormat::IA4, 3, 2, &[0u8; 3][..]),
+            Err(SpriteError::SizeMismatch {
+                needed: 4,
+                available: 3
+            })
+        );
+        assert_eq!(
+            SpriteImage::new(TexFormat::None, 1, 1, &[][..]),
+            Err(SpriteError::InvalidFormat(0))
+        );
+
+        let mut sprite = SpriteFile::new(image(TexFormat::CI4, 2, 2, 0));
+        assert_eq!(sprite.to_bytes(), Err(SpriteError::MissingPalette));
+        sprite.palette = Some(Vec::new());
+        sprite.lods = alloc_::vec![image(TexFormat::CI4, 1, 1, 0); LOD_SLOTS];
+        assert_eq!(sprite.to_bytes(), Err(SpriteError::TooManyLods));
+        sprite.lods.pop();
+        assert!(sprite.to_bytes().is_ok());
+    }
+}
@@ -4,6 +4,14 @@ use alloc_::boxed::Box;
 
 use crate::{sys::surface::*, ucstr::UCStr};
 
+mod blit;
+mod pixel;
+mod quantize;
+
+pub use blit::*;
+pub use pixel::*;
+pub use quantize::*;
+
 #[doc = "A surface buffer for graphics\n\n This structure holds the basic information about a buffer used to hold graphics.\n It is commonly used by graphics routines in libdragon as either a source (eg: texture)\n or a target (eg: framebuffer). It can be used for both CPU-based drawing\n (such as graphics.h) or RDP-basic drawing (such as rdp.h and rdpq.h).\n\n Use #surface_alloc / #surface_free to allocate / free a surface. If you already have\n a memory pointer to a graphics buffer and you just need to wrap it in a #surface_t,\n use #surface_make."]
 #[repr(transparent)]
 #[derive(Debug)]
@@ -52,6 +60,31 @@ impl From<TexFormat> for u32 {
 }
 
 impl TexFormat {
+    #[doc = "Convert a raw `tex_format_t` value (RDP format in bits 2-4, pixel size in bits 0-1) into a `TexFormat`.\n\n Returns `None` if the value does not correspond to a known format."]
+    #[inline]
+    pub const fn from_raw(raw: u32) -> Option<Self> {
+        const ALL: [TexFormat; 11] = [
+            TexFormat::None,
+            TexFormat::RGBA16,
+            TexFormat::RGBA32,
+            TexFormat::YUV16,
+            TexFormat::CI4,
+            TexFormat::CI8,
+            TexFormat::IA4,
+            TexFormat::IA8,
+            TexFormat::IA16,
+            TexFormat::I4,
+            TexFormat::I8,
+        ];
+        let mut i = 0;
+        while i < ALL.len() {
+            if ALL[i] as u32 == raw {
+                return Some(ALL[i]);
+            }
+            i += 1;
+        }
+        None
+    }
     #[doc = "Return the name of the texture format as a string (for debugging purposes)"]
     #[inline]
     pub fn name(self) -> &'static UCStr {
@@ -77,7 +110,7 @@ impl Surface<'static> {
     pub fn new(format: TexFormat, width: u16, height: u16) -> Self {
         unsafe { Self(surface_alloc(format as _, width, height), PhantomData) }
     }
-    #[doc = "Initialize a surface_t structure with the provided linear buffer.\n\n This function is similar to #surface_make, but it works for images that\n are linearly mapped with no per-line padding or extraneous data.\n\n Compared to #surface_make, it does not accept a stride parameter, and\n calculate the stride from the width and the pixel format.\n\n @param[in] buffer    Pointer to the memory buffer\n @param[in] format    Pixel format\n @param[in] width     Width in pixels\n @param[in] height    Height in pixels\n @return              The initialized surface\n\n @see #surface_make"]
+    #[doc = "Initialize a surface_t structure with the provided buffer.\n\n This function is similar to #surface_make: the buffer contains `height`\n rows of pixels, each `stride` bytes apart. The buffer must be exactly\n `stride * height` bytes long, and the stride must be large enough to\n contain a row of pixels.\n\n @param[in] buffer    Memory buffer\n @param[in] format    Pixel format\n @param[in] width     Width in pixels\n @param[in] height    Height in pixels\n @param[in] stride    Stride in bytes (length of a row plus its padding)\n @return              The initialized surface\n\n @see #surface_make"]
     #[inline]
     pub fn from_buffer(
         buffer: Box<[u8]>,
@@ -86,10 +119,8 @@ impl Surface<'static> {
         height: u16,
         stride: u16,
     ) -> Self {
-        assert_eq!(
-            buffer.len(),
-            stride as usize * width as usize * format.bitdepth() as usize
-        );
+        assert!(stride as usize >= format.pixels_to_bytes(width as _) as usize);
+        assert_eq!(buffer.len(), stride as usize * height as usize);
         let buffer = Box::into_raw(buffer) as *mut _;
         Self(
             surface_t {
@@ -215,7 +246,18 @@ impl<'s> Surface<'s> {
     pub fn stride(&self) -> u16 {
         self.0.stride
     }
-    #[doc = "Gets the pixel buffer"]
+    #[doc = "Length of the pixel buffer in bytes, from the first pixel to the end of the last row.\n\n The padding after the last row is not included, since it might not be part\n of the buffer (eg: for a sub-surface)."]
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        match self.0.height {
+            0 => 0,
+            h => {
+                (h as usize - 1) * self.0.stride as usize
+                    + self.format().pixels_to_bytes(self.0.width as _) as usize
+            }
+        }
+    }
+    #[doc = "Gets the pixel buffer\n\n The buffer spans from the first pixel to the end of the last row, so it\n does not include the padding after the last row."]
     #[inline]
     pub fn buffer(&self) -> Option<&[u8]> {
         if self.0.buffer.is_null() {
@@ -224,11 +266,11 @@ impl<'s> Surface<'s> {
         unsafe {
             Some(core::slice::from_raw_parts(
                 self.0.buffer as *const _,
-                self.0.width as usize * self.0.stride as usize,
+                self.buffer_len(),
             ))
         }
     }
-    #[doc = "Gets the mutable pixel buffer"]
+    #[doc = "Gets the mutable pixel buffer\n\n The buffer spans from the first pixel to the end of the last row, so it\n does not include the padding after the last row."]
     #[inline]
     pub fn mut_buffer(&mut self) -> Option<&mut [u8]> {
         if self.0.buffer.is_null() {
@@ -237,7 +279,7 @@ impl<'s> Surface<'s> {
         unsafe {
             Some(core::slice::from_raw_parts_mut(
                 self.0.buffer as *mut _,
-                self.0.width as usize * self.0.stride as usize,
+                self.buffer_len(),
             ))
         }
     }
@@ -0,0 +1,616 @@
+use alloc_::vec::Vec;
+
+use crate::graphics::Color;
+
+use super::{ConvertError, Pixel, Surface, check_convert, read_raw, write_raw};
+
+#[doc = "How [`Surface::blit`] combines the source pixels with the destination"]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum BlitMode {
+    #[doc = "Replace the destination pixels, including their alpha"]
+    #[default]
+    Copy,
+    #[doc = "Blend the source pixels over the destination, according to their alpha"]
+    Blend,
+    #[doc = "Replace the destination pixels, except where the source matches the\n given color (alpha is not compared). Filtering is ignored in this mode."]
+    ColorKey(Color),
+}
+
+#[doc = "Parameters of [`Surface::blit`].\n\n As in [`crate::rdpq::BlitParms`], the zero value of every field is the most\n reasonable default: the whole source surface is copied at its original\n size."]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CpuBlitParms<'p> {
+    s0: u16,
+    t0: u16,
+    width: u16,
+    height: u16,
+    flip_x: bool,
+    flip_y: bool,
+    scale_x: f32,
+    scale_y: f32,
+    filtering: bool,
+    mode: BlitMode,
+    src_palette: Option<&'p [u16]>,
+    dst_palette: Option<&'p [u16]>,
+}
+
+impl<'p> CpuBlitParms<'p> {
+    pub const EMPTY: Self = Self::new();
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            s0: 0,
+            t0: 0,
+            width: 0,
+            height: 0,
+            flip_x: false,
+            flip_y: false,
+            scale_x: 0.0,
+            scale_y: 0.0,
+            filtering: false,
+            mode: BlitMode::Copy,
+            src_palette: None,
+            dst_palette: None,
+        }
+    }
+    #[doc = "Source sub-rect top-left X coordinate"]
+    #[inline]
+    pub const fn s0(mut self, s0: u16) -> Self {
+        self.s0 = s0;
+        self
+    }
+    #[doc = "Source sub-rect top-left Y coordinate"]
+    #[inline]
+    pub const fn t0(mut self, t0: u16) -> Self {
+        self.t0 = t0;
+        self
+    }
+    #[doc = "Source sub-rect width. If 0, the width of the surface is used"]
+    #[inline]
+    pub const fn width(mut self, width: u16) -> Self {
+        self.width = width;
+        self
+    }
+    #[doc = "Source sub-rect height. If 0, the height of the surface is used"]
+    #[inline]
+    pub const fn height(mut self, height: u16) -> Self {
+        self.height = height;
+        self
+    }
+    #[doc = "Flip horizontally"]
+    #[inline]
+    pub const fn flip_x(mut self, flip_x: bool) -> Self {
+        self.flip_x = flip_x;
+        self
+    }
+    #[doc = "Flip vertically"]
+    #[inline]
+    pub const fn flip_y(mut self, flip_y: bool) -> Self {
+        self.flip_y = flip_y;
+        self
+    }
+    #[doc = "Horizontal scale factor to apply to the surface. If 0, no scaling is performed (the same as 1.0f)"]
+    #[inline]
+    pub const fn scale_x(mut self, scale_x: f32) -> Self {
+        self.scale_x = scale_x;
+        self
+    }
+    #[doc = "Vertical scale factor to apply to the surface. If 0, no scaling is performed (the same as 1.0f)"]
+    #[inline]
+    pub const fn scale_y(mut self, scale_y: f32) -> Self {
+        self.scale_y = scale_y;
+        self
+    }
+    #[doc = "True if bilinear filtering should be used when scaling (otherwise, nearest sampling is used)"]
+    #[inline]
+    pub const fn filtering(mut self, filtering: bool) -> Self {
+        self.filtering = filtering;
+        self
+    }
+    #[doc = "How the source is combined with the destination (default: [`BlitMode::Copy`])"]
+    #[inline]
+    pub const fn mode(mut self, mode: BlitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    #[doc = "Palette of the source surface, if it is color-indexed"]
+    #[inline]
+    pub const fn src_palette(mut self, palette: &'p [u16]) -> Self {
+        self.src_palette = Some(palette);
+        self
+    }
+    #[doc = "Palette of the destination surface, if it is color-indexed"]
+    #[inline]
+    pub const fn dst_palette(mut self, palette: &'p [u16]) -> Self {
+        self.dst_palette = Some(palette);
+        self
+    }
+}
+
+#[doc = "Clip a rectangle to a surface, returning its bounds (x0, y0, x1, y1)"]
+fn clip(x: i32, y: i32, width: u32, height: u32, sw: u16, sh: u16) -> Option<[usize; 4]> {
+    let x0 = x.clamp(0, sw as i32) as usize;
+    let y0 = y.clamp(0, sh as i32) as usize;
+    let x1 = (x as i64 + width as i64).clamp(0, sw as i64) as usize;
+    let y1 = (y as i64 + height as i64).clamp(0, sh as i64) as usize;
+    (x0 < x1 && y0 < y1).then_some([x0, y0, x1, y1])
+}
+
+#[doc = "Source coordinates sampled by a destination row or column"]
+enum Samples {
+    #[doc = "Source coordinate of each destination pixel"]
+    Nearest(Vec<u16>),
+    #[doc = "Source coordinates of each destination pixel, and weight (0-256) of the second one"]
+    Bilinear(Vec<(u16, u16, u16)>),
+}
+
+impl Samples {
+    #[doc = "Map `count` destination pixels, starting `first` pixels after the\n destination origin, to a source range of `size` pixels starting at `base`"]
+    fn new(
+        base: u16,
+        size: u16,
+        scaled: u32,
+        first: u32,
+        count: u32,
+        flip: bool,
+        bilinear: bool,
+    ) -> Self {
+        let (size, scaled) = (size as u32, scaled.max(1));
+        let mirror = |x: u32| if flip { size - 1 - x } else { x };
+        let range = first..first + count;
+        if !bilinear {
+            return Self::Nearest(
+                range
+                    .map(|d| {
+                        // Computed in 64 bits, as `(2 * d + 1) * size` overflows for
+                        // large sizes.
+                        let s = (2 * d as u64 + 1) * size as u64 / (2 * scaled as u64);
+                        base + mirror(s.min(size as u64 - 1) as u32) as u16
+                    })
+                    .collect(),
+            );
+        }
+        let ratio = size as f32 / scaled as f32;
+        Self::Bilinear(
+            range
+                .map(|d| {
+                    let mut s = ((d as f32 + 0.5) * ratio - 0.5).clamp(0.0, (size - 1) as f32);
+                    if flip {
+                        s = (size - 1) as f32 - s;
+                    }
+                    let i = s as u32;
+                    let w = ((s - i as f32) * 256.0) as u16;
+                    (base + i as u16, base + (i + 1).min(size - 1) as u16, w)
+                })
+                .collect(),
+        )
+    }
+    #[inline]
+    fn nearest(&self, idx: usize) -> u16 {
+        match self {
+            Self::Nearest(v) => v[idx],
+            Self::Bilinear(v) => {
+                let (a, b, w) = v[idx];
+                if w < 128 { a } else { b }
+            }
+        }
+    }
+}
+
+#[inline]
+fn lerp(a: Color, b: Color, w: u16) -> Color {
+    let l = |a: u8, b: u8| ((a as u32 * (256 - w as u32) + b as u32 * w as u32 + 128) >> 8) as u8;
+    Color::rgba32(l(a.r, b.r), l(a.g, b.g), l(a.b, b.b), l(a.a, b.a))
+}
+
+#[doc = "Blend `src` over `dst`"]
+#[inline]
+fn blend(src: Color, dst: Color) -> Color {
+    let (a, ia) = (src.a as u32, 255 - src.a as u32);
+    let l = |s: u8, d: u8| ((s as u32 * a + d as u32 * ia + 127) / 255) as u8;
+    Color::rgba32(
+        l(src.r, dst.r),
+        l(src.g, dst.g),
+        l(src.b, dst.b),
+        (a + (dst.a as u32 * ia + 127) / 255) as u8,
+    )
+}
+
+impl<'s> Surface<'s> {
+    #[doc = "Fill the whole surface with a color (see [`Surface::fill_rect`])"]
+    #[inline]
+    pub fn fill(&mut self, color: Color, palette: Option<&[u16]>) {
+        self.fill_rect(0, 0, u32::MAX, u32::MAX, color, palette);
+    }
+    #[doc = "Fill a rectangle with a color, using the CPU\n\n The rectangle is clipped to the surface. The color is converted to the\n format of the surface as in [`Pixel::from_color`]; nothing is drawn if\n it cannot be converted (eg: a color-indexed surface without palette).\n\n @param x         X coordinate of the top-left corner\n @param y         Y coordinate of the top-left corner\n @param width     Width of the rectangle\n @param height    Height of the rectangle\n @param color     The color\n @param palette   Palette of the surface, if it is color-indexed"]
+    pub fn fill_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+        palette: Option<&[u16]>,
+    ) {
+        let format = self.format();
+        let Some(pixel) = Pixel::from_color(format, color, palette) else {
+            return;
+        };
+        let Some([x0, y0, x1, y1]) = clip(x, y, width, height, self.width(), self.height()) else {
+            return;
+        };
+        let raw = pixel.into_raw();
+        for row in self.rows_mut().skip(y0).take(y1 - y0) {
+            (x0..x1).for_each(|x| write_raw(format, row, x, raw));
+        }
+    }
+    #[doc = "Draw a surface (or a portion of it) onto this surface, using the CPU\n\n This is a software counterpart of [`crate::rdpq::RdpQ::tex_blit`],\n useful to compose textures at load time without involving the RDP. The\n source can have any format (except YUV16) and is converted to the format\n of this surface as in [`Surface::convert_into`]. The drawn rectangle is\n clipped to this surface.\n\n Copies between surfaces with the same format and palette, without\n scaling, are exact and fast, since they do not go through colors.\n\n @param x         X coordinate where to draw the top-left corner of the source\n @param y         Y coordinate where to draw the top-left corner of the source\n @param src       The surface to draw\n @param parms     Blitting parameters (see [`CpuBlitParms`])"]
+    pub fn blit(
+        &mut self,
+        x: i32,
+        y: i32,
+        src: &Surface,
+        parms: &CpuBlitParms,
+    ) -> Result<(), ConvertError> {
+        let (sf, df) = (src.format(), self.format());
+        check_convert(sf, parms.src_palette)?;
+        check_convert(df, parms.dst_palette)?;
+        if src.buffer().is_none() || self.buffer().is_none() {
+            return Err(ConvertError::NoBuffer);
+        }
+
+        let s0 = parms.s0.min(src.width());
+        let t0 = parms.t0.min(src.height());
+        let sw = match parms.width {
+            0 => src.width() - s0,
+            w => w.min(src.width() - s0),
+        };
+        let sh = match parms.height {
+            0 => src.height() - t0,
+            h => h.min(src.height() - t0),
+        };
+        if sw == 0 || sh == 0 {
+            return Ok(());
+        }
+        let scaled = |size: u16, scale: f32| {
+            if scale == 0.0 {
+                size as u32
+            } else {
+                (size as f32 * scale.abs() + 0.5) as u32
+            }
+        };
+        let (dw, dh) = (scaled(sw, parms.scale_x), scaled(sh, parms.scale_y));
+        let Some([x0, y0, x1, y1]) = clip(x, y, dw, dh, self.width(), self.height()) else {
+            return Ok(());
+        };
+
+        let bilinear = parms.filtering
+            && (dw, dh) != (sw as u32, sh as u32)
+            && !matches!(parms.mode, BlitMode::ColorKey(_));
+        let first = |d0: usize, o: i32| (d0 as i64 - o as i64) as u32;
+        let cols = Samples::new(
+            s0,
+            sw,
+            dw,
+            first(x0, x),
+            (x1 - x0) as u32,
+            parms.flip_x,
+            bilinear,
+        );
+        let rows = Samples::new(
+            t0,
+            sh,
+            dh,
+            first(y0, y),
+            (y1 - y0) as u32,
+            parms.flip_y,
+            bilinear,
+        );
+
+        let exact = parms.mode == BlitMode::Copy
+            && sf == df
+            && parms.src_palette == parms.dst_palette
+            && (dw, dh) == (sw as u32, sh as u32);
+        let color = |row: &[u8], x: u16| {
+            Pixel::from_raw(sf, read_raw(sf, row, x as usize))
+                .and_then(|p| p.to_color(parms.src_palette))
+                .unwrap_or(Color::BLACK)
+        };
+        let mut last: Option<(Color, u32)> = None;
+        for (dy, dst) in self.rows_mut().skip(y0).take(y1 - y0).enumerate() {
+            if exact {
+                let src = src.row(rows.nearest(dy)).unwrap();
+                if !parms.flip_x && sf.bitdepth() >= 8 {
+                    let bpp = sf.bitdepth() as usize / 8;
+                    let s = cols.nearest(0) as usize * bpp;
+                    dst[x0 * bpp..x1 * bpp].copy_from_slice(&src[s..s + (x1 - x0) * bpp]);
+                } else {
+                    for (i, dx) in (x0..x1).enumerate() {
+                        write_raw(df, dst, dx, read_raw(sf, src, cols.nearest(i) as usize));
+                    }
+                }
+                continue;
+            }
+
+            for (i, dx) in (x0..x1).enumerate() {
+                let c = match (&rows, &cols) {
+                    (Samples::Bilinear(r), Samples::Bilinear(c)) => {
+                        let ((t0, t1, wy), (u0, u1, wx)) = (r[dy], c[i]);
+                        let (r0, r1) = (src.row(t0).unwrap(), src.row(t1).unwrap());
+                        lerp(
+                            lerp(color(r0, u0), color(r0, u1), wx),
+                            lerp(color(r1, u0), color(r1, u1), wx),
+                            wy,
+                        )
+                    }
+                    _ => color(src.row(rows.nearest(dy)).unwrap(), cols.nearest(i)),
+                };
+                let c = match parms.mode {
+                    BlitMode::Copy => c,
+                    BlitMode::ColorKey(k) if (c.r, c.g, c.b) == (k.r, k.g, k.b) => continue,
+                    BlitMode::ColorKey(_) => c,
+                    BlitMode::Blend if c.a == 0 => continue,
+                    BlitMode::Blend if c.a == 255 => c,
+                    BlitMode::Blend => {
+                        let d = Pixel::from_raw(df, read_raw(df, dst, dx))
+                            .and_then(|p| p.to_color(parms.dst_palette))
+                            .unwrap_or(Color::BLACK);
+                        blend(c, d)
+                    }
+                };
+                let raw = match last {
+                    Some((l, raw)) if l == c => raw,
+                    _ => {
+                        let raw =
+                            Pixel::from_color(df, c, parms.dst_palette).map_or(0, Pixel::into_raw);
+                        last = Some((c, raw));
+                        raw
+                    }
+                };
+                write_raw(df, dst, dx, raw);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::ManuallyDrop;
+
+    use super::*;
+    use crate::surface::TexFormat;
+
+    // Surfaces are not dropped, as that would call into libdragon to free
+    // them: the buffers are leaked instead.
+    fn surface(
+        format: TexFormat,
+        width: u16,
+        height: u16,
+        pixels: &[u32],
+    ) -> ManuallyDrop<Surface<'static>> {
+        let stride = format.pixels_to_bytes(width as u32) as u16;
+        let buffer = alloc_::vec![0; stride as usize * height as usize].into_boxed_slice();
+        let mut surface =
+            ManuallyDrop::new(Surface::from_buffer(buffer, format, width, height, stride));
+        for (i, &raw) in pixels.iter().enumerate() {
+            let (x, y) = (i as u16 % width, i as u16 / width);
+            surface.set_pixel(x, y, Pixel::from_raw(format, raw).unwrap());
+        }
+        surface
+    }
+
+    // An I8 surface, with one value per pixel
+    fn gray(width: u16, height: u16, pixels: &[u32]) -> ManuallyDrop<Surface<'static>> {
+        surface(TexFormat::I8, width, height, pixels)
+    }
+
+    fn pixels(surface: &Surface) -> Vec<u32> {
+        (0..surface.height())
+            .flat_map(|y| (0..surface.width()).map(move |x| (x, y)))
+            .map(|(x, y)| surface.pixel(x, y).unwrap().into_raw())
+            .collect()
+    }
+
+    // Blit onto a cleared I8 surface
+    fn blit(
+        width: u16,
+        height: u16,
+        x: i32,
+        y: i32,
+        src: &Surface,
+        parms: CpuBlitParms,
+    ) -> Vec<u32> {
+        let mut dst = gray(width, height, &[]);
+        dst.blit(x, y, src, &parms).unwrap();
+        pixels(&dst)
+    }
+
+    // Never matches a gray pixel, but forces the blit through colors
+    const NO_KEY: BlitMode = BlitMode::ColorKey(Color::rgba32(1, 2, 3, 255));
+
+    #[test]
+    fn fill_rect_clipping() {
+        let mut s = gray(4, 3, &[]);
+        s.fill_rect(-2, -1, 4, 3, Color::rgba32(9, 9, 9, 255), None);
+        s.fill_rect(3, 2, u32::MAX, u32::MAX, Color::rgba32(7, 7, 7, 255), None);
+        // Entirely outside of the surface
+        s.fill_rect(-5, 0, 5, 3, Color::WHITE, None);
+        s.fill_rect(4, 0, 1, 1, Color::WHITE, None);
+        s.fill_rect(0, 3, 4, 1, Color::WHITE, None);
+        s.fill_rect(i32::MAX, i32::MAX, u32::MAX, u32::MAX, Color::WHITE, None);
+        assert_eq!(pixels(&s), [9, 9, 0, 0, 9, 9, 0, 0, 0, 0, 0, 7]);
+        // Nothing can be drawn on a color-indexed surface without palette
+        let mut s = surface(TexFormat::CI8, 2, 1, &[]);
+        s.fill(Color::WHITE, None);
+        assert_eq!(pixels(&s), [0, 0]);
+    }
+
+    #[test]
+    fn blit_clipping() {
+        let src = gray(3, 3, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        for mode in [BlitMode::Copy, NO_KEY] {
+            let parms = CpuBlitParms::new().mode(mode);
+            assert_eq!(
+                blit(4, 4, -1, -2, &src, parms),
+                [8, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+            );
+            assert_eq!(
+                blit(4, 4, 2, 3, &src, parms),
+                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2]
+            );
+            for (x, y) in [(4, 0), (0, 4), (-3, 0), (0, -3), (i32::MIN, i32::MAX)] {
+                assert_eq!(blit(4, 4, x, y, &src, parms), [0; 16]);
+            }
+            // Flipped sources are clipped after flipping
+            assert_eq!(
+                blit(2, 2, -1, -1, &src, parms.flip_x(true).flip_y(true)),
+                [5, 4, 2, 1]
+            );
+        }
+    }
+
+    #[test]
+    fn blit_exact_and_converted() {
+        let src = gray(3, 2, &[1, 2, 3, 4, 5, 6]);
+        for (flip_x, expected) in [
+            (false, [0, 0, 0, 0, 0, 1, 2, 3, 0, 4, 5, 6]),
+            (true, [0, 0, 0, 0, 0, 3, 2, 1, 0, 6, 5, 4]),
+        ] {
+            let parms = CpuBlitParms::new().flip_x(flip_x);
+            // Same format without scaling copies the raw pixels
+            assert_eq!(blit(4, 3, 1, 1, &src, parms), expected);
+            // A color key goes through colors, with the same result
+            assert_eq!(blit(4, 3, 1, 1, &src, parms.mode(NO_KEY)), expected);
+        }
+        // Sub-byte formats cannot be copied a row at a time
+        let src = surface(TexFormat::I4, 3, 1, &[1, 2, 3]);
+        let mut dst = surface(TexFormat::I4, 5, 1, &[]);
+        dst.blit(1, 0, &src, &CpuBlitParms::new()).unwrap();
+        assert_eq!(pixels(&dst), [0, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn blit_scaled() {
+        let src = gray(2, 2, &[1, 2, 3, 4]);
+        let parms = CpuBlitParms::new().scale_x(2.0).scale_y(1.5);
+        assert_eq!(
+            blit(4, 3, 0, 0, &src, parms),
+            [1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]
+        );
+        // Scaled and clipped: the samples start from the clipped position
+        assert_eq!(blit(2, 2, -1, 0, &src, parms), [1, 2, 3, 4]);
+        // A sub-rect of the source, shrunk
+        let src = gray(4, 1, &[1, 2, 3, 4]);
+        let parms = CpuBlitParms::new().s0(1).width(2).scale_x(0.5);
+        assert_eq!(blit(2, 1, 0, 0, &src, parms), [3, 0]);
+    }
+
+    #[test]
+    fn blit_flip() {
+        let src = gray(3, 2, &[1, 2, 3, 4, 5, 6]);
+        for mode in [BlitMode::Copy, NO_KEY] {
+            let parms = CpuBlitParms::new().mode(mode);
+            let flip = |x, y| blit(3, 2, 0, 0, &src, parms.flip_x(x).flip_y(y));
+            assert_eq!(flip(false, false), [1, 2, 3, 4, 5, 6]);
+            assert_eq!(flip(true, false), [3, 2, 1, 6, 5, 4]);
+            assert_eq!(flip(false, true), [4, 5, 6, 1, 2, 3]);
+            assert_eq!(flip(true, true), [6, 5, 4, 3, 2, 1]);
+        }
+        // Flips apply to the whole scaled image
+        let parms = CpuBlitParms::new().scale_x(2.0).flip_x(true);
+        assert_eq!(
+            blit(6, 2, 0, 0, &src, parms),
+            [3, 3, 2, 2, 1, 1, 6, 6, 5, 5, 4, 4]
+        );
+    }
+
+    #[test]
+    fn blit_color_key() {
+        let src = gray(4, 1, &[0, 50, 0, 100]);
+        let mut dst = gray(4, 1, &[7, 7, 7, 7]);
+        let key = BlitMode::ColorKey(Color::rgba32(0, 0, 0, 0));
+        dst.blit(0, 0, &src, &CpuBlitParms::new().mode(key))
+            .unwrap();
+        assert_eq!(pixels(&dst), [7, 50, 7, 100]);
+        // Filtering is ignored, so that keyed pixels are not blended in
+        let parms = CpuBlitParms::new().mode(key).scale_x(2.0).filtering(true);
+        let mut dst = gray(8, 1, &[7; 8]);
+        dst.blit(0, 0, &src, &parms).unwrap();
+        assert_eq!(pixels(&dst), [7, 7, 50, 50, 7, 7, 100, 100]);
+    }
+
+    #[test]
+    fn blit_blend() {
+        let red = |a| Color::rgba32(255, 0, 0, a).into_u32();
+        let blue = Color::rgba32(0, 0, 255, 255).into_u32();
+        let src = surface(TexFormat::RGBA32, 3, 1, &[red(0), red(128), red(255)]);
+        let mut dst = surface(TexFormat::RGBA32, 3, 1, &[blue; 3]);
+        let parms = CpuBlitParms::new().mode(BlitMode::Blend);
+        dst.blit(0, 0, &src, &parms).unwrap();
+        assert_eq!(
+            pixels(&dst),
+            [blue, Color::rgba32(128, 0, 127, 255).into_u32(), red(255)]
+        );
+        // Over a transparent destination, the alpha of the source is kept
+        let mut dst = surface(TexFormat::RGBA32, 3, 1, &[]);
+        dst.blit(0, 0, &src, &parms).unwrap();
+        assert_eq!(
+            pixels(&dst),
+            [0, Color::rgba32(128, 0, 0, 128).into_u32(), red(255)]
+        );
+    }
+
+    #[test]
+    fn blit_bilinear() {
+        let src = gray(2, 2, &[0, 255, 255, 255]);
+        let parms = CpuBlitParms::new().scale_x(2.0).scale_y(2.0);
+        assert_eq!(
+            blit(4, 4, 0, 0, &src, parms.filtering(true)),
+            [
+                0, 64, 191, 255, //
+                64, 112, 207, 255, //
+                191, 207, 239, 255, //
+                255, 255, 255, 255,
+            ]
+        );
+        assert_eq!(
+            blit(4, 4, 0, 0, &src, parms),
+            [
+                0, 0, 255, 255, //
+                0, 0, 255, 255, //
+                255, 255, 255, 255, //
+                255, 255, 255, 255,
+            ]
+        );
+        // Without scaling, filtering has no effect
+        assert_eq!(
+            blit(2, 2, 0, 0, &src, CpuBlitParms::new().filtering(true)),
+            [0, 255, 255, 255]
+        );
+    }
+
+    fn nearest(samples: &Samples, count: usize) -> Vec<u16> {
+        (0..count).map(|i| samples.nearest(i)).collect()
+    }
+
+    #[test]
+    fn samples() {
+        let s = Samples::new(10, 4, 8, 0, 8, false, false);
+        assert_eq!(nearest(&s, 8), [10, 10, 11, 11, 12, 12, 13, 13]);
+        let s = Samples::new(10, 4, 2, 0, 2, true, false);
+        assert_eq!(nearest(&s, 2), [12, 10]);
+        let s = Samples::new(0, 4, 8, 0, 8, false, true);
+        assert_eq!(nearest(&s, 8), [0, 0, 1, 1, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn samples_large() {
+        // The last pixels of a large upscale, where `(2 * d + 1) * size`
+        // doesn't fit in 32 bits
+        let (size, scaled) = (u16::MAX, 0x10000 * 64);
+        let s = Samples::new(0, size, scaled, scaled - 64, 64, false, false);
+        assert!(nearest(&s, 64).iter().all(|&v| v == size - 1));
+        let s = Samples::new(0, size, scaled, scaled - 64, 64, true, false);
+        assert!(nearest(&s, 64).iter().all(|&v| v == 0));
+    }
+}
@@ -0,0 +1,552 @@
+use core::fmt;
+
+use crate::graphics::Color;
+
+use super::{Surface, TexFormat};
+
+#[doc = "A pixel value, in the native encoding of its [`TexFormat`].\n\n Values are stored exactly as in memory, so that they can be read and\n written without losing precision. Use [`Pixel::to_color`] and\n [`Pixel::from_color`] to convert them from and to RGBA 8888 colors."]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Pixel {
+    #[doc = "RGBA 5551 color"]
+    Rgba16(u16),
+    #[doc = "RGBA 8888 color"]
+    Rgba32(Color),
+    #[doc = "Luma in the upper byte, and chroma (U for even pixels, V for odd\n pixels) in the lower byte"]
+    Yuv16(u16),
+    #[doc = "Palette index (0-15)"]
+    Ci4(u8),
+    #[doc = "Palette index"]
+    Ci8(u8),
+    #[doc = "3-bit intensity in bits 1-3, 1-bit alpha in bit 0"]
+    Ia4(u8),
+    #[doc = "4-bit intensity in the upper nibble, 4-bit alpha in the lower nibble"]
+    Ia8(u8),
+    #[doc = "8-bit intensity in the upper byte, 8-bit alpha in the lower byte"]
+    Ia16(u16),
+    #[doc = "4-bit intensity (0-15)"]
+    I4(u8),
+    #[doc = "8-bit intensity"]
+    I8(u8),
+}
+
+impl Pixel {
+    #[doc = "Create a pixel from its raw value (the bits stored in memory)\n\n Returns `None` for [`TexFormat::None`]."]
+    #[inline]
+    pub const fn from_raw(format: TexFormat, raw: u32) -> Option<Self> {
+        Some(match format {
+            TexFormat::None => return None,
+            TexFormat::RGBA16 => Self::Rgba16(raw as u16),
+            TexFormat::RGBA32 => Self::Rgba32(Color::from_u32(raw)),
+            TexFormat::YUV16 => Self::Yuv16(raw as u16),
+            TexFormat::CI4 => Self::Ci4(raw as u8 & 0xF),
+            TexFormat::CI8 => Self::Ci8(raw as u8),
+            TexFormat::IA4 => Self::Ia4(raw as u8 & 0xF),
+            TexFormat::IA8 => Self::Ia8(raw as u8),
+            TexFormat::IA16 => Self::Ia16(raw as u16),
+            TexFormat::I4 => Self::I4(raw as u8 & 0xF),
+            TexFormat::I8 => Self::I8(raw as u8),
+        })
+    }
+    #[doc = "Get the raw value of the pixel (the bits stored in memory)"]
+    #[inline]
+    pub const fn into_raw(self) -> u32 {
+        match self {
+            Self::Rgba16(v) | Self::Yuv16(v) | Self::Ia16(v) => v as u32,
+            Self::Rgba32(c) => c.into_u32(),
+            Self::Ci4(v) | Self::Ia4(v) | Self::I4(v) => (v & 0xF) as u32,
+            Self::Ci8(v) | Self::Ia8(v) | Self::I8(v) => v as u32,
+        }
+    }
+    #[doc = "Get the format of the pixel"]
+    #[inline]
+    pub const fn format(self) -> TexFormat {
+        match self {
+            Self::Rgba16(_) => TexFormat::RGBA16,
+            Self::Rgba32(_) => TexFormat::RGBA32,
+            Self::Yuv16(_) => TexFormat::YUV16,
+            Self::Ci4(_) => TexFormat::CI4,
+            Self::Ci8(_) => TexFormat::CI8,
+            Self::Ia4(_) => TexFormat::IA4,
+            Self::Ia8(_) => TexFormat::IA8,
+            Self::Ia16(_) => TexFormat::IA16,
+            Self::I4(_) => TexFormat::I4,
+            Self::I8(_) => TexFormat::I8,
+        }
+    }
+    #[doc = "Convert the pixel to an RGBA 8888 color\n\n Intensity formats are expanded to gray levels (opaque, unless they have\n an alpha channel). Color-indexed pixels are looked up in the palette,\n made of RGBA 5551 colors as in TLUTs.\n\n Returns `None` for YUV pixels (which cannot be converted without their\n neighbour), and for color-indexed pixels without a palette entry.\n\n @param palette   Palette used by color-indexed pixels"]
+    pub fn to_color(self, palette: Option<&[u16]>) -> Option<Color> {
+        let gray = |i: u8, a: u8| Color::rgba32(i, i, i, a);
+        Some(match self {
+            Self::Rgba16(v) => Color::from_u16(v),
+            Self::Rgba32(c) => c,
+            Self::Yuv16(_) => return None,
+            Self::Ci4(i) | Self::Ci8(i) => Color::from_u16(*palette?.get(i as usize)?),
+            Self::Ia4(v) => gray(expand3(v >> 1), if v & 1 != 0 { 0xFF } else { 0 }),
+            Self::Ia8(v) => gray((v >> 4) * 0x11, (v & 0xF) * 0x11),
+            Self::Ia16(v) => gray((v >> 8) as u8, v as u8),
+            Self::I4(v) => gray(v * 0x11, 0xFF),
+            Self::I8(v) => gray(v, 0xFF),
+        })
+    }
+    #[doc = "Convert an RGBA 8888 color to a pixel of the given format\n\n Colors are converted to intensity using the BT.601 luma weights. For\n color-indexed formats, the nearest color of the palette is picked.\n\n Returns `None` for [`TexFormat::None`] and [`TexFormat::YUV16`], and for\n color-indexed formats without a palette.\n\n @param format    Format of the pixel\n @param color     The color to convert\n @param palette   Palette used by color-indexed formats"]
+    pub fn from_color(format: TexFormat, color: Color, palette: Option<&[u16]>) -> Option<Self> {
+        let i = intensity(color);
+        let a = color.a;
+        Some(match format {
+            TexFormat::None | TexFormat::YUV16 => return None,
+            TexFormat::RGBA16 => Self::Rgba16(color.into_u16()),
+            TexFormat::RGBA32 => Self::Rgba32(color),
+            TexFormat::CI4 => Self::Ci4(nearest(palette?, 16, color)?),
+            TexFormat::CI8 => Self::Ci8(nearest(palette?, 256, color)?),
+            TexFormat::IA4 => Self::Ia4(((i >> 5) << 1) | (a >> 7)),
+            TexFormat::IA8 => Self::Ia8((i & 0xF0) | (a >> 4)),
+            TexFormat::IA16 => Self::Ia16(((i as u16) << 8) | a as u16),
+            TexFormat::I4 => Self::I4(i >> 4),
+            TexFormat::I8 => Self::I8(i),
+        })
+    }
+}
+
+#[doc = "Expand a 3-bit value to 8 bits"]
+#[inline]
+const fn expand3(v: u8) -> u8 {
+    let v = v & 7;
+    (v << 5) | (v << 2) | (v >> 1)
+}
+
+#[doc = "Intensity of a color, using the BT.601 luma weights"]
+#[inline]
+fn intensity(c: Color) -> u8 {
+    ((c.r as u32 * 77 + c.g as u32 * 150 + c.b as u32 * 29 + 128) >> 8) as u8
+}
+
+#[doc = "Find the index of the palette entry closest to a color, among the first\n `size` entries"]
+pub(crate) fn nearest(palette: &[u16], size: usize, color: Color) -> Option<u8> {
+    let dist = |c: Color| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(c.r, color.r) + d(c.g, color.g) + d(c.b, color.b) + d(c.a, color.a)
+    };
+    palette
+        .iter()
+        .take(size)
+        .enumerate()
+        .min_by_key(|&(_, &c)| dist(Color::from_u16(c)))
+        .map(|(i, _)| i as u8)
+}
+
+#[doc = "Read the raw value of the pixel at column `x` of a row"]
+#[inline]
+pub(crate) fn read_raw(format: TexFormat, row: &[u8], x: usize) -> u32 {
+    match format.bitdepth() {
+        4 => {
+            let b = row[x / 2];
+            (if x.is_multiple_of(2) { b >> 4 } else { b & 0xF }) as u32
+        }
+        8 => row[x] as u32,
+        16 => u16::from_be_bytes([row[2 * x], row[2 * x + 1]]) as u32,
+        _ => u32::from_be_bytes(row[4 * x..4 * x + 4].try_into().unwrap()),
+    }
+}
+
+#[doc = "Write the raw value of the pixel at column `x` of a row"]
+#[inline]
+pub(crate) fn write_raw(format: TexFormat, row: &mut [u8], x: usize, raw: u32) {
+    match format.bitdepth() {
+        4 => {
+            let b = &mut row[x / 2];
+            *b = if x.is_multiple_of(2) {
+                (*b & 0x0F) | ((raw as u8) << 4)
+            } else {
+                (*b & 0xF0) | (raw as u8 & 0xF)
+            };
+        }
+        8 => row[x] = raw as u8,
+        16 => row[2 * x..2 * x + 2].copy_from_slice(&(raw as u16).to_be_bytes()),
+        _ => row[4 * x..4 * x + 4].copy_from_slice(&raw.to_be_bytes()),
+    }
+}
+
+#[doc = "Error returned when converting a surface to another format"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConvertError {
+    #[doc = "The format cannot be converted from or to (YUV16, or no format)"]
+    UnsupportedFormat(TexFormat),
+    #[doc = "A color-indexed format was used without a palette"]
+    MissingPalette,
+    #[doc = "The surfaces have different sizes"]
+    SizeMismatch,
+    #[doc = "The surface has no pixel buffer (eg: it is a placeholder)"]
+    NoBuffer,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => write!(f, "cannot convert format {format:?}"),
+            Self::MissingPalette => f.write_str("color-indexed format requires a palette"),
+            Self::SizeMismatch => f.write_str("surfaces have different sizes"),
+            Self::NoBuffer => f.write_str("surface has no pixel buffer"),
+        }
+    }
+}
+
+impl core::error::Error for ConvertError {}
+
+#[doc = "Convert a row of pixels to another format\n\n Both formats must be convertible, and palettes must be provided for\n color-indexed formats (see [`check_convert`])."]
+pub(crate) fn convert_row(
+    src_format: TexFormat,
+    src: &[u8],
+    src_palette: Option<&[u16]>,
+    dst_format: TexFormat,
+    dst: &mut [u8],
+    dst_palette: Option<&[u16]>,
+    width: usize,
+) {
+    if src_format == dst_format && src_palette == dst_palette {
+        let len = src_format.pixels_to_bytes(width as u32) as usize;
+        dst[..len].copy_from_slice(&src[..len]);
+        return;
+    }
+    // Images tend to have runs of identical pixels: remember the last
+    // conversion, which avoids most of the palette lookups.
+    let mut last = None;
+    for x in 0..width {
+        let raw = read_raw(src_format, src, x);
+        let out = match last {
+            Some((r, out)) if r == raw => out,
+            _ => {
+                let color = Pixel::from_raw(src_format, raw)
+                    .and_then(|p| p.to_color(src_palette))
+                    .unwrap_or(Color::BLACK);
+                let out =
+                    Pixel::from_color(dst_format, color, dst_palette).map_or(0, Pixel::into_raw);
+                last = Some((raw, out));
+                out
+            }
+        };
+        write_raw(dst_format, dst, x, out);
+    }
+}
+
+#[doc = "Check that a format can be converted, given its palette"]
+pub(crate) fn check_convert(
+    format: TexFormat,
+    palette: Option<&[u16]>,
+) -> Result<(), ConvertError> {
+    match format {
+        TexFormat::None | TexFormat::YUV16 => Err(ConvertError::UnsupportedFormat(format)),
+        TexFormat::CI4 | TexFormat::CI8 if palette.is_none_or(|p| p.is_empty()) => {
+            Err(ConvertError::MissingPalette)
+        }
+        _ => Ok(()),
+    }
+}
+
+impl<'s> Surface<'s> {
+    #[doc = "Length of a row in bytes, excluding the padding up to the stride"]
+    #[inline]
+    pub fn row_bytes(&self) -> usize {
+        self.format().pixels_to_bytes(self.width() as u32) as usize
+    }
+    #[doc = "Iterate over the rows of pixels\n\n Each row is exactly [`Surface::row_bytes`] long: the padding up to the\n stride is skipped. Nothing is returned if the surface has no buffer."]
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &[u8]> + DoubleEndedIterator {
+        let (stride, len) = (self.stride().max(1) as usize, self.row_bytes());
+        self.buffer()
+            .unwrap_or_default()
+            .chunks(stride)
+            .map(move |row| &row[..len])
+    }
+    #[doc = "Iterate mutably over the rows of pixels (see [`Surface::rows`])"]
+    pub fn rows_mut(&mut self) -> impl ExactSizeIterator<Item = &mut [u8]> + DoubleEndedIterator {
+        let (stride, len) = (self.stride().max(1) as usize, self.row_bytes());
+        self.mut_buffer()
+            .unwrap_or_default()
+            .chunks_mut(stride)
+            .map(move |row| &mut row[..len])
+    }
+    #[doc = "Get a row of pixels\n\n Returns `None` if `y` is out of bounds, or the surface has no buffer."]
+    #[inline]
+    pub fn row(&self, y: u16) -> Option<&[u8]> {
+        let start = y as usize * self.stride() as usize;
+        let len = self.row_bytes();
+        self.buffer()?.get(start..start + len)
+    }
+    #[doc = "Get a mutable row of pixels (see [`Surface::row`])"]
+    #[inline]
+    pub fn row_mut(&mut self, y: u16) -> Option<&mut [u8]> {
+        let start = y as usize * self.stride() as usize;
+        let len = self.row_bytes();
+        self.mut_buffer()?.get_mut(start..start + len)
+    }
+    #[doc = "Read a pixel\n\n Returns `None` if the coordinates are out of bounds, or the surface has\n no buffer. The RDP must have finished drawing to the surface, and the\n CPU cache must be in sync with memory."]
+    #[inline]
+    pub fn pixel(&self, x: u16, y: u16) -> Option<Pixel> {
+        if x >= self.width() {
+            return None;
+        }
+        let raw = read_raw(self.format(), self.row(y)?, x as usize);
+        Pixel::from_raw(self.format(), raw)
+    }
+    #[doc = "Write a pixel\n\n Writes outside of the surface are ignored.\n\n # Panics\n\n Panics if the pixel has a different format than the surface."]
+    #[inline]
+    pub fn set_pixel(&mut self, x: u16, y: u16, pixel: Pixel) {
+        let format = self.format();
+        assert_eq!(pixel.format(), format, "pixel format mismatch");
+        if x >= self.width() {
+            return;
+        }
+        if let Some(row) = self.row_mut(y) {
+            write_raw(format, row, x as usize, pixel.into_raw());
+        }
+    }
+    #[doc = "Read a pixel as an RGBA 8888 color (see [`Pixel::to_color`])\n\n @param x         X coordinate\n @param y         Y coordinate\n @param palette   Palette of the surface, if it is color-indexed"]
+    #[inline]
+    pub fn pixel_color(&self, x: u16, y: u16, palette: Option<&[u16]>) -> Option<Color> {
+        self.pixel(x, y)?.to_color(palette)
+    }
+    #[doc = "Write a pixel from an RGBA 8888 color (see [`Pixel::from_color`])\n\n Writes outside of the surface, and colors that cannot be converted to\n the format of the surface, are ignored.\n\n @param x         X coordinate\n @param y         Y coordinate\n @param color     The color\n @param palette   Palette of the surface, if it is color-indexed"]
+    #[inline]
+    pub fn set_pixel_color(&mut self, x: u16, y: u16, color: Color, palette: Option<&[u16]>) {
+        if let Some(pixel) = Pixel::from_color(self.format(), color, palette) {
+            self.set_pixel(x, y, pixel);
+        }
+    }
+    #[doc = "Convert the pixels of this surface into another surface of the same size\n\n The conversion is done by the CPU, going through RGBA 8888 colors (see\n [`Pixel::to_color`] and [`Pixel::from_color`]). The RDP must have\n finished drawing to the source surface.\n\n @param dst           The destination surface\n @param src_palette   Palette of this surface, if it is color-indexed\n @param dst_palette   Palette of the destination, if it is color-indexed"]
+    pub fn convert_into(
+        &self,
+        dst: &mut Surface,
+        src_palette: Option<&[u16]>,
+        dst_palette: Option<&[u16]>,
+    ) -> Result<(), ConvertError> {
+        check_convert(self.format(), src_palette)?;
+        check_convert(dst.format(), dst_palette)?;
+        if (self.width(), self.height()) != (dst.width(), dst.height()) {
+            return Err(ConvertError::SizeMismatch);
+        }
+        if self.buffer().is_none() || dst.buffer().is_none() {
+            return Err(ConvertError::NoBuffer);
+        }
+        let (src_format, dst_format, width) = (self.format(), dst.format(), self.width() as usize);
+        for (src, dst) in self.rows().zip(dst.rows_mut()) {
+            convert_row(
+                src_format,
+                src,
+                src_palette,
+                dst_format,
+                dst,
+                dst_palette,
+                width,
+            );
+        }
+        Ok(())
+    }
+    #[doc = "Convert this surface to another format, into a new surface\n\n See [`Surface::convert_into`].\n\n @param format        Format of the new surface\n @param src_palette   Palette of this surface, if it is color-indexed\n @param dst_palette   Palette of the new surface, if it is color-indexed"]
+    pub fn convert(
+        &self,
+        format: TexFormat,
+        src_palette: Option<&[u16]>,
+        dst_palette: Option<&[u16]>,
+    ) -> Result<Surface<'static>, ConvertError> {
+        check_convert(self.format(), src_palette)?;
+        check_convert(format, dst_palette)?;
+        let mut dst = Surface::new(format, self.width(), self.height());
+        self.convert_into(&mut dst, src_palette, dst_palette)?;
+        Ok(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::ManuallyDrop;
+
+    use super::*;
+
+    // Surfaces are not dropped, as that would call into libdragon to free
+    // them: the buffers are leaked instead.
+    fn surface(
+        format: TexFormat,
+        width: u16,
+        height: u16,
+        stride: u16,
+    ) -> ManuallyDrop<Surface<'static>> {
+        let buffer = alloc_::vec![0; stride as usize * height as usize].into_boxed_slice();
+        ManuallyDrop::new(Surface::from_buffer(buffer, format, width, height, stride))
+    }
+
+    const FORMATS: [TexFormat; 10] = [
+        TexFormat::RGBA16,
+        TexFormat::RGBA32,
+        TexFormat::YUV16,
+        TexFormat::CI4,
+        TexFormat::CI8,
+        TexFormat::IA4,
+        TexFormat::IA8,
+        TexFormat::IA16,
+        TexFormat::I4,
+        TexFormat::I8,
+    ];
+
+    #[test]
+    fn raw_round_trip() {
+        assert_eq!(Pixel::from_raw(TexFormat::None, 0), None);
+        for format in FORMATS {
+            let mask = match format.bitdepth() {
+                32 => u32::MAX,
+                bits => (1 << bits) - 1,
+            };
+            for raw in [0, 1, 0x5A, 0xA5C3, 0x1234_5678, u32::MAX] {
+                let pixel = Pixel::from_raw(format, raw).unwrap();
+                assert_eq!(pixel.format(), format);
+                assert_eq!(pixel.into_raw(), raw & mask, "{format:?} {raw:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn packed_rows() {
+        let mut row = [0; 3];
+        for (x, v) in [1, 2, 3, 4, 5].into_iter().enumerate() {
+            write_raw(TexFormat::I4, &mut row, x, v);
+        }
+        assert_eq!(row, [0x12, 0x34, 0x50]);
+        write_raw(TexFormat::CI4, &mut row, 1, 0xFF);
+        assert_eq!(row, [0x1F, 0x34, 0x50]);
+        assert_eq!(
+            (0..5)
+                .map(|x| read_raw(TexFormat::I4, &row, x))
+                .collect::<Vec<_>>(),
+            [1, 0xF, 3, 4, 5]
+        );
+
+        let mut row = [0; 8];
+        write_raw(TexFormat::RGBA16, &mut row, 1, 0xABCD);
+        write_raw(TexFormat::IA8, &mut row, 0, 0x42);
+        assert_eq!(row[..4], [0x42, 0, 0xAB, 0xCD]);
+        write_raw(TexFormat::RGBA32, &mut row, 1, 0x1122_3344);
+        assert_eq!(row[4..], [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(read_raw(TexFormat::RGBA32, &row, 1), 0x1122_3344);
+        assert_eq!(read_raw(TexFormat::IA16, &row, 1), 0xABCD);
+    }
+
+    #[test]
+    fn to_color() {
+        let gray = |i, a| Some(Color::rgba32(i, i, i, a));
+        assert_eq!(Pixel::I4(0xA).to_color(None), gray(0xAA, 0xFF));
+        assert_eq!(Pixel::I8(0x12).to_color(None), gray(0x12, 0xFF));
+        assert_eq!(Pixel::Ia4(0b1011).to_color(None), gray(0xB6, 0xFF));
+        assert_eq!(Pixel::Ia4(0b1110).to_color(None), gray(0xFF, 0));
+        assert_eq!(Pixel::Ia8(0x3C).to_color(None), gray(0x33, 0xCC));
+        assert_eq!(Pixel::Ia16(0x1234).to_color(None), gray(0x12, 0x34));
+        assert_eq!(
+            Pixel::Rgba16(0xF801).to_color(None),
+            Some(Color::rgba32(255, 0, 0, 255))
+        );
+        assert_eq!(
+            Pixel::Rgba32(Color::WHITE).to_color(None),
+            Some(Color::WHITE)
+        );
+        assert_eq!(Pixel::Yuv16(0).to_color(None), None);
+
+        let palette = [0x0001, 0xFFFF];
+        assert_eq!(Pixel::Ci4(1).to_color(Some(&palette)), Some(Color::WHITE));
+        assert_eq!(Pixel::Ci8(0).to_color(Some(&palette)), Some(Color::BLACK));
+        assert_eq!(Pixel::Ci8(2).to_color(Some(&palette)), None);
+        assert_eq!(Pixel::Ci8(0).to_color(None), None);
+    }
+
+    #[test]
+    fn from_color() {
+        let c = Color::rgba32(200, 100, 50, 128);
+        let pixel = |format| Pixel::from_color(format, c, None);
+        // (200 * 77 + 100 * 150 + 50 * 29 + 128) >> 8 = 124
+        assert_eq!(pixel(TexFormat::I8), Some(Pixel::I8(124)));
+        assert_eq!(pixel(TexFormat::I4), Some(Pixel::I4(7)));
+        assert_eq!(pixel(TexFormat::IA4), Some(Pixel::Ia4(0b0111)));
+        assert_eq!(pixel(TexFormat::IA8), Some(Pixel::Ia8(0x78)));
+        assert_eq!(pixel(TexFormat::IA16), Some(Pixel::Ia16(0x7C80)));
+        assert_eq!(pixel(TexFormat::RGBA16), Some(Pixel::Rgba16(c.into_u16())));
+        assert_eq!(pixel(TexFormat::RGBA32), Some(Pixel::Rgba32(c)));
+        assert_eq!(pixel(TexFormat::YUV16), None);
+        assert_eq!(pixel(TexFormat::None), None);
+        assert_eq!(pixel(TexFormat::CI8), None);
+
+        // Nearest palette entry, among the entries addressable by the format
+        let mut palette = [0u16; 17];
+        palette[3] = Color::rgba32(160, 100, 50, 255).into_u16();
+        palette[16] = c.into_u16();
+        let ci = |format| Pixel::from_color(format, c, Some(&palette));
+        assert_eq!(ci(TexFormat::CI4), Some(Pixel::Ci4(3)));
+        assert_eq!(ci(TexFormat::CI8), Some(Pixel::Ci8(16)));
+    }
+
+    #[test]
+    fn surface_pixels() {
+        // Rows are padded up to the stride, and the padding is not touched
+        let mut surf = surface(TexFormat::I4, 3, 2, 4);
+        assert_eq!(surf.row_bytes(), 2);
+        surf.set_pixel(0, 0, Pixel::I4(1));
+        surf.set_pixel(2, 0, Pixel::I4(2));
+        surf.set_pixel(1, 1, Pixel::I4(3));
+        surf.set_pixel(3, 0, Pixel::I4(4));
+        surf.set_pixel(0, 2, Pixel::I4(5));
+        assert_eq!(surf.buffer().unwrap(), [0x10, 0x20, 0, 0, 0x03, 0x00]);
+        assert_eq!(surf.pixel(1, 1), Some(Pixel::I4(3)));
+        assert_eq!(surf.pixel(3, 0), None);
+        assert_eq!(surf.pixel(0, 2), None);
+        assert_eq!(
+            surf.rows().collect::<Vec<_>>(),
+            [&[0x10, 0x20][..], &[0x03, 0x00]]
+        );
+        assert_eq!(surf.rows().next_back(), surf.row(1));
+
+        let mut surf = surface(TexFormat::RGBA16, 2, 2, 8);
+        surf.set_pixel_color(1, 1, Color::WHITE, None);
+        assert_eq!(surf.row(1), Some(&[0, 0, 0xFF, 0xFF][..]));
+        assert_eq!(surf.pixel_color(1, 1, None), Some(Color::WHITE));
+        for row in surf.rows_mut() {
+            row.fill(0xAA);
+        }
+        assert_eq!(surf.buffer().unwrap()[4..8], [0; 4]);
+    }
+
+    #[test]
+    #[should_panic = "pixel format mismatch"]
+    fn set_pixel_format() {
+        surface(TexFormat::I8, 1, 1, 1).set_pixel(0, 0, Pixel::Ci8(0));
+    }
+
+    #[test]
+    fn convert() {
+        let mut src = surface(TexFormat::RGBA32, 3, 1, 12);
+        for (x, c) in [Color::WHITE, Color::BLACK, Color::rgba32(250, 0, 0, 255)]
+            .into_iter()
+            .enumerate()
+        {
+            src.set_pixel_color(x as u16, 0, c, None);
+        }
+        let palette = [0x0001, 0xF801, 0xFFFF];
+        let mut dst = surface(TexFormat::CI4, 3, 1, 2);
+        src.convert_into(&mut dst, None, Some(&palette)).unwrap();
+        assert_eq!(dst.buffer().unwrap(), [0x20, 0x10]);
+
+        let mut dst = surface(TexFormat::IA8, 3, 1, 3);
+        src.convert_into(&mut dst, None, None).unwrap();
+        assert_eq!(dst.buffer().unwrap(), [0xFF, 0x0F, 0x4F]);
+
+        let mut small = surface(TexFormat::I8, 2, 1, 2);
+        assert_eq!(
+            src.convert_into(&mut small, None, None),
+            Err(ConvertError::SizeMismatch)
+        );
+        let mut ci = surface(TexFormat::CI8, 3, 1, 3);
+        assert_eq!(
+            src.convert_into(&mut ci, None, Some(&[])),
+            Err(ConvertError::MissingPalette)
+        );
+        let mut yuv = surface(TexFormat::YUV16, 3, 1, 6);
+        assert_eq!(
+            src.convert_into(&mut yuv, None, None),
+            Err(ConvertError::UnsupportedFormat(TexFormat::YUV16))
+        );
+    }
+}
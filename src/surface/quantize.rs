@@ -0,0 +1,349 @@
+use core::ops::Range;
+
+use alloc_::vec::Vec;
+
+use crate::graphics::Color;
+
+use super::{ConvertError, Pixel, Surface, TexFormat, nearest, read_raw, write_raw};
+
+#[doc = "4x4 Bayer matrix, used for ordered dithering"]
+const BAYER: [[i8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[doc = "Parameters of [`Surface::quantize`].\n\n As for the other parameter structures, the zero value of every field is\n the most reasonable default: use as many colors as allowed by the format,\n without dithering nor refinement."]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct QuantizeParms {
+    colors: u16,
+    dither: u8,
+    refine: u8,
+}
+
+impl QuantizeParms {
+    pub const EMPTY: Self = Self::new();
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            colors: 0,
+            dither: 0,
+            refine: 0,
+        }
+    }
+    #[doc = "Maximum number of colors of the palette. If 0 (or too large), the\n maximum allowed by the format is used (16 for CI4, 256 for CI8).\n\n If the surface has transparent pixels, color 0 is reserved for them and\n counts towards the limit, but the palette always keeps at least one\n opaque color: with a limit of 1, the palette has 2 colors"]
+    #[inline]
+    pub const fn colors(mut self, colors: u16) -> Self {
+        self.colors = colors;
+        self
+    }
+    #[doc = "Strength of the ordered dithering, as the maximum offset added to each\n color component (0-255). If 0, no dithering is performed. Values between\n 8 and 32 work well, larger ones being better suited to smaller palettes"]
+    #[inline]
+    pub const fn dither(mut self, dither: u8) -> Self {
+        self.dither = dither;
+        self
+    }
+    #[doc = "Number of k-means iterations used to refine the palette found by the\n median cut. This improves the quality, but it is slow for images with\n many colors. If 0, no refinement is performed"]
+    #[inline]
+    pub const fn refine(mut self, iterations: u8) -> Self {
+        self.refine = iterations;
+        self
+    }
+}
+
+#[doc = "A color-indexed surface and its palette, created by [`Surface::quantize`]"]
+#[derive(Debug)]
+pub struct Quantized {
+    #[doc = "The CI4 or CI8 surface"]
+    pub surface: Surface<'static>,
+    #[doc = "The palette, as RGBA 5551 colors, ready for\n [`crate::rdpq::RdpQ::tex_upload_tlut`]. If the surface has transparent\n pixels, they all use color 0"]
+    pub tlut: Vec<u16>,
+}
+
+#[doc = "Median cut of the colors of the image, with their pixel count"]
+fn median_cut(entries: &mut [(Color, u32)], max: usize) -> Vec<Color> {
+    let channels = |c: Color| [c.r, c.g, c.b];
+    // Widest channel of a box, and its extent
+    let widest = |entries: &[(Color, u32)]| {
+        let (mut lo, mut hi) = ([255u8; 3], [0u8; 3]);
+        for (c, _) in entries {
+            for (i, v) in channels(*c).into_iter().enumerate() {
+                lo[i] = lo[i].min(v);
+                hi[i] = hi[i].max(v);
+            }
+        }
+        (0..3)
+            .map(|i| (i, hi[i] - lo[i]))
+            .max_by_key(|&(_, r)| r)
+            .unwrap()
+    };
+
+    let mut boxes: Vec<Range<usize>> = alloc_::vec![0..entries.len()];
+    while boxes.len() < max {
+        let Some((idx, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(idx, b)| {
+                let (channel, range) = widest(&entries[b.clone()]);
+                let count: u64 = entries[b.clone()].iter().map(|e| e.1 as u64).sum();
+                (idx, channel, range as u64 * count)
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+            .map(|(idx, channel, _)| (idx, channel))
+        else {
+            break;
+        };
+
+        let b = boxes[idx].clone();
+        let slice = &mut entries[b.clone()];
+        slice.sort_unstable_by_key(|(c, _)| channels(*c)[channel]);
+        let half = slice.iter().map(|e| e.1 as u64).sum::<u64>() / 2;
+        let mut acc = 0;
+        let split = slice
+            .iter()
+            .position(|e| {
+                acc += e.1 as u64;
+                acc > half
+            })
+            .unwrap_or(0)
+            .clamp(1, slice.len() - 1);
+        boxes[idx] = b.start..b.start + split;
+        boxes.push(b.start + split..b.end);
+    }
+
+    boxes
+        .into_iter()
+        .map(|b| average(entries[b].iter().copied()))
+        .collect()
+}
+
+#[doc = "Average of colors weighted by their pixel count"]
+fn average(colors: impl Iterator<Item = (Color, u32)>) -> Color {
+    let mut sum = [0u64; 4];
+    for (c, n) in colors {
+        sum[0] += c.r as u64 * n as u64;
+        sum[1] += c.g as u64 * n as u64;
+        sum[2] += c.b as u64 * n as u64;
+        sum[3] += n as u64;
+    }
+    let n = sum[3].max(1);
+    let avg = |v: u64| ((v + n / 2) / n) as u8;
+    Color::rgba32(avg(sum[0]), avg(sum[1]), avg(sum[2]), 255)
+}
+
+#[doc = "Refine a palette with k-means iterations"]
+fn refine(entries: &[(Color, u32)], palette: &mut [u16], iterations: u8) {
+    for _ in 0..iterations {
+        let mut clusters: Vec<Vec<(Color, u32)>> = alloc_::vec![Vec::new(); palette.len()];
+        for &(c, n) in entries {
+            let idx = nearest(palette, palette.len(), c).unwrap() as usize;
+            clusters[idx].push((c, n));
+        }
+        let mut changed = false;
+        for (entry, cluster) in palette.iter_mut().zip(clusters) {
+            if cluster.is_empty() {
+                continue;
+            }
+            let c = average(cluster.into_iter()).into_u16();
+            changed |= c != *entry;
+            *entry = c;
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+#[doc = "Generate a palette of at most `max` colors for a set of pixels, and\n write the index of each pixel into `out`"]
+pub(crate) fn quantize(
+    pixels: &[Color],
+    max: usize,
+    dither: u8,
+    iterations: u8,
+    width: usize,
+    mut out: impl FnMut(usize, u8),
+) -> Vec<u16> {
+    // The TLUT stores RGBA 5551 colors, so there is no point in telling
+    // apart the colors that only differ in the lower bits.
+    let mut keys: Vec<u16> = pixels.iter().map(|c| c.into_u16()).collect();
+    keys.sort_unstable();
+    let mut entries: Vec<(u16, u32)> = Vec::new();
+    for key in keys {
+        match entries.last_mut() {
+            Some((k, n)) if *k == key => *n += 1,
+            _ => entries.push((key, 1)),
+        }
+    }
+
+    // Transparent pixels all share the first color
+    let transparent = entries.iter().any(|(k, _)| k & 1 == 0);
+    let mut opaque: Vec<(Color, u32)> = entries
+        .iter()
+        .filter(|(k, _)| k & 1 != 0)
+        .map(|&(k, n)| (Color::from_u16(k), n))
+        .collect();
+    // Reserve an extra slot if needed, so that opaque pixels are never
+    // mapped to the transparent color.
+    let max = if transparent { max.max(2) } else { max };
+    let mut tlut = Vec::with_capacity(max);
+    if transparent {
+        tlut.push(0);
+    }
+    let first = tlut.len();
+    if !opaque.is_empty() {
+        tlut.extend(
+            median_cut(&mut opaque, max - first)
+                .into_iter()
+                .map(Color::into_u16),
+        );
+        refine(&opaque, &mut tlut[first..], iterations);
+    }
+
+    let index = |c: Color| {
+        if c.a < 0x80 && transparent {
+            return 0;
+        }
+        nearest(&tlut[first..], tlut.len() - first, c).map_or(0, |i| i + first as u8)
+    };
+    if dither == 0 {
+        // Map each distinct color once
+        let mapping: Vec<u8> = entries
+            .iter()
+            .map(|&(k, _)| index(Color::from_u16(k)))
+            .collect();
+        for (i, c) in pixels.iter().enumerate() {
+            let key = c.into_u16();
+            out(
+                i,
+                mapping[entries.binary_search_by_key(&key, |e| e.0).unwrap()],
+            );
+        }
+    } else {
+        let mut last = None;
+        for (i, &c) in pixels.iter().enumerate() {
+            let offset =
+                (BAYER[(i / width) % 4][(i % width) % 4] as i32 * 2 - 15) * dither as i32 / 16;
+            let d = |v: u8| (v as i32 + offset).clamp(0, 255) as u8;
+            let c = Color::rgba32(d(c.r), d(c.g), d(c.b), c.a);
+            let idx = match last {
+                Some((l, idx)) if l == c => idx,
+                _ => index(c),
+            };
+            last = Some((c, idx));
+            out(i, idx);
+        }
+    }
+    tlut
+}
+
+impl<'s> Surface<'s> {
+    #[doc = "Convert this surface to a color-indexed surface, generating its palette\n\n The palette is computed with the median cut algorithm, optionally\n refined via k-means iterations, and the pixels are mapped to the nearest\n color of the palette, optionally with ordered dithering (see\n [`QuantizeParms`]). Transparent pixels (alpha below 50%) are all mapped\n to color 0, which is transparent.\n\n The result can be drawn by uploading its palette with\n [`crate::rdpq::RdpQ::tex_upload_tlut`], and enabling palette lookups via\n [`crate::rdpq::RdpQModes::mode_tlut`]. The palette is written back from\n the CPU cache, so it can be uploaded right away.\n\n @param format    Format of the new surface ([`TexFormat::CI4`] or [`TexFormat::CI8`])\n @param parms     Quantization parameters"]
+    pub fn quantize(
+        &self,
+        format: TexFormat,
+        parms: &QuantizeParms,
+    ) -> Result<Quantized, ConvertError> {
+        let max = match format {
+            TexFormat::CI4 => 16,
+            TexFormat::CI8 => 256,
+            _ => return Err(ConvertError::UnsupportedFormat(format)),
+        };
+        let max = match parms.colors as usize {
+            0 => max,
+            n => n.min(max),
+        };
+        let sf = self.format();
+        if matches!(
+            sf,
+            TexFormat::None | TexFormat::YUV16 | TexFormat::CI4 | TexFormat::CI8
+        ) {
+            return Err(ConvertError::UnsupportedFormat(sf));
+        }
+        if self.buffer().is_none() {
+            return Err(ConvertError::NoBuffer);
+        }
+
+        let width = self.width() as usize;
+        let mut pixels = Vec::with_capacity(width * self.height() as usize);
+        for row in self.rows() {
+            pixels.extend((0..width).map(|x| {
+                Pixel::from_raw(sf, read_raw(sf, row, x))
+                    .and_then(|p| p.to_color(None))
+                    .unwrap_or(Color::BLACK)
+            }));
+        }
+
+        let mut surface = Surface::new(format, self.width(), self.height());
+        let mut rows: Vec<&mut [u8]> = surface.rows_mut().collect();
+        let tlut = quantize(&pixels, max, parms.dither, parms.refine, width, |i, idx| {
+            write_raw(format, rows[i / width], i % width, idx as u32)
+        });
+        drop(rows);
+        crate::n64::data_cache_hit_writeback(&tlut);
+        Ok(Quantized { surface, tlut })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(pixels: &[Color], max: usize, dither: u8) -> (Vec<u16>, Vec<u8>) {
+        let mut out = alloc_::vec![0xFF; pixels.len()];
+        let tlut = quantize(pixels, max, dither, 0, pixels.len().min(4), |i, idx| {
+            out[i] = idx
+        });
+        (tlut, out)
+    }
+
+    const RED: Color = Color::rgba32(255, 0, 0, 255);
+    const BLUE: Color = Color::rgba32(0, 0, 255, 255);
+    const CLEAR: Color = Color::rgba32(0, 255, 0, 0);
+
+    #[test]
+    fn exact() {
+        let pixels = [RED, BLUE, RED, Color::WHITE];
+        let (tlut, out) = run(&pixels, 16, 0);
+        assert_eq!(tlut.len(), 3);
+        for (c, idx) in pixels.iter().zip(out) {
+            assert_eq!(tlut[idx as usize], c.into_u16());
+        }
+    }
+
+    #[test]
+    fn transparent() {
+        let pixels = [CLEAR, RED, BLUE, CLEAR];
+        let (tlut, out) = run(&pixels, 16, 0);
+        assert_eq!(tlut, [0, RED.into_u16(), BLUE.into_u16()]);
+        assert_eq!(out, [0, 1, 2, 0]);
+
+        // Opaque pixels keep a color of their own, even with a single color
+        let (tlut, out) = run(&pixels, 1, 0);
+        assert_eq!(tlut.len(), 2);
+        assert_eq!(tlut[0], 0);
+        assert_eq!(out, [0, 1, 1, 0]);
+        let (tlut, out) = run(&pixels, 1, 16);
+        assert_eq!(tlut.len(), 2);
+        assert_eq!(out, [0, 1, 1, 0]);
+
+        // Fully transparent images
+        let (tlut, out) = run(&[CLEAR; 3], 16, 0);
+        assert_eq!((tlut, out), (alloc_::vec![0], alloc_::vec![0; 3]));
+    }
+
+    #[test]
+    fn reduce() {
+        // Two clusters of similar colors
+        let pixels = [
+            Color::rgba32(250, 0, 0, 255),
+            Color::rgba32(240, 8, 8, 255),
+            Color::rgba32(0, 0, 250, 255),
+            Color::rgba32(8, 8, 240, 255),
+        ];
+        let (tlut, out) = run(&pixels, 2, 0);
+        assert_eq!(tlut.len(), 2);
+        assert_eq!(out[0], out[1]);
+        assert_eq!(out[2], out[3]);
+        assert_ne!(out[0], out[2]);
+        let red = Color::from_u16(tlut[out[0] as usize]);
+        assert!(red.r > 200 && red.b < 16, "{red:?}");
+    }
+}
@@ -0,0 +1,365 @@
+use alloc_::boxed::Box;
+
+use crate::sys::vi::*;
+
+type VblankFn = dyn FnMut() + Send;
+
+#[doc = "Anti-aliasing and resampling modes of the Video Interface"]
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum AaMode {
+    #[doc = "No anti-aliasing nor resampling: the framebuffer is displayed as is.\n\n This is only possible when the framebuffer is not scaled."]
+    None = vi_aa_mode_e_VI_AA_MODE_NONE,
+    #[doc = "Resample the framebuffer with a bilinear filter, without anti-aliasing"]
+    Resample = vi_aa_mode_e_VI_AA_MODE_RESAMPLE,
+    #[doc = "Anti-aliasing and resampling, fetching extra lines only when needed"]
+    ResampleFetchNeeded = vi_aa_mode_e_VI_AA_MODE_RESAMPLE_FETCH_NEEDED,
+    #[doc = "Anti-aliasing and resampling, always fetching extra lines"]
+    ResampleFetchAlways = vi_aa_mode_e_VI_AA_MODE_RESAMPLE_FETCH_ALWAYS,
+}
+
+#[doc = "Gamma correction modes of the Video Interface"]
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Gamma {
+    #[doc = "Gamma correction disabled"]
+    Disable = vi_gamma_e_VI_GAMMA_DISABLE,
+    #[doc = "Gamma correction enabled"]
+    Enable = vi_gamma_e_VI_GAMMA_ENABLE,
+    #[doc = "Gamma correction enabled, with hardware dithering of the output"]
+    DitherEnable = vi_gamma_e_VI_GAMMA_DITHER_ENABLE,
+}
+
+#[doc = "Borders around the displayed picture, in TV pixels.\n\n The VI always outputs a virtual 640x480 picture (640x576 on PAL); borders\n shrink the area where the framebuffer is shown within it. They are\n useful to change the aspect ratio of the picture, or to keep it within\n the safe area of CRT TVs, whose edges are often hidden by the bezel\n (overscan)."]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Borders {
+    #[doc = "Left border"]
+    pub left: i16,
+    #[doc = "Right border"]
+    pub right: i16,
+    #[doc = "Top border"]
+    pub up: i16,
+    #[doc = "Bottom border"]
+    pub down: i16,
+}
+
+impl Borders {
+    #[doc = "No borders: the picture covers the whole TV output"]
+    pub const NONE: Self = Self::new(0, 0, 0, 0);
+
+    #[inline]
+    pub const fn new(left: i16, right: i16, up: i16, down: i16) -> Self {
+        Self {
+            left,
+            right,
+            up,
+            down,
+        }
+    }
+
+    #[doc = "Borders of the same size on all sides"]
+    #[inline]
+    pub const fn uniform(margin: i16) -> Self {
+        Self::new(margin, margin, margin, margin)
+    }
+
+    #[doc = "Calculate the borders for a given aspect ratio and overscan margin\n\n The aspect ratio is the one of the displayed picture (eg: 4/3 or 16/9):\n borders are added to letterbox or pillarbox it within the 4:3 TV output.\n The overscan margin is the percentage of the picture (0.0 - 1.0) to\n reserve on each side, so that it is not hidden by the bezel of CRT TVs.\n\n @param aspect_ratio      Aspect ratio of the displayed picture\n @param overscan_margin   Overscan margin (eg: 0.05 for 5%)"]
+    #[inline]
+    pub fn calc(aspect_ratio: f32, overscan_margin: f32) -> Self {
+        unsafe { vi_calc_borders(aspect_ratio, overscan_margin) }.into()
+    }
+
+    #[doc = "Borders currently configured in the VI"]
+    #[inline]
+    pub fn current() -> Self {
+        unsafe { vi_get_borders() }.into()
+    }
+}
+
+impl From<vi_borders_t> for Borders {
+    #[inline]
+    fn from(b: vi_borders_t) -> Self {
+        Self::new(b.left, b.right, b.up, b.down)
+    }
+}
+
+impl From<Borders> for vi_borders_t {
+    #[inline]
+    fn from(b: Borders) -> Self {
+        vi_borders_t {
+            left: b.left,
+            right: b.right,
+            up: b.up,
+            down: b.down,
+        }
+    }
+}
+
+#[doc = "Wait until the next vblank starts\n\n The VI must be active (eg: via [`crate::display::init`]), otherwise this\n function would never return."]
+#[inline]
+pub fn wait_vblank() {
+    unsafe { vi_wait_vblank() }
+}
+
+#[doc = "Apply several VI changes atomically\n\n All the VI registers written by `f` are committed together at the next\n vblank, so that the picture never shows an intermediate configuration."]
+#[inline]
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    unsafe { vi_write_begin() };
+    let ret = f();
+    unsafe { vi_write_end() };
+    ret
+}
+
+#[doc = "Blank the video output\n\n While blanked, the VI outputs a black picture, without reading the\n framebuffer."]
+#[inline]
+pub fn blank(blank: bool) {
+    unsafe { vi_blank(blank) }
+}
+
+#[doc = "Get the current refresh rate of the video output in Hz\n\n The refresh rate is normally 50 for PAL and 60 for NTSC, but this function\n returns the hardware-accurate number which is close to those but not quite\n exact."]
+#[inline]
+pub fn refresh_rate() -> f32 {
+    unsafe { vi_get_refresh_rate() }
+}
+
+#[doc = "Get the rectangle of the TV output where the framebuffer is displayed\n\n @return (x0, y0, x1, y1) in TV pixels"]
+#[inline]
+pub fn output() -> (i32, i32, i32, i32) {
+    let (mut x0, mut y0, mut x1, mut y1) = (0, 0, 0, 0);
+    unsafe { vi_get_output(&mut x0, &mut y0, &mut x1, &mut y1) };
+    (x0, y0, x1, y1)
+}
+
+#[doc = "Get the bounds of the TV output, that is the maximum rectangle that can\n be passed to [`set_output`]\n\n @return (x0, y0, x1, y1) in TV pixels"]
+#[inline]
+pub fn output_bounds() -> (i32, i32, i32, i32) {
+    let (mut x0, mut y0, mut x1, mut y1) = (0, 0, 0, 0);
+    unsafe { vi_get_output_bounds(&mut x0, &mut y0, &mut x1, &mut y1) };
+    (x0, y0, x1, y1)
+}
+
+#[doc = "Set the rectangle of the TV output where the framebuffer is displayed\n\n The framebuffer is scaled to fit the rectangle. Coordinates are in TV\n pixels, and can go beyond [`output_bounds`] to move part of the picture\n offscreen."]
+#[inline]
+pub fn set_output(x0: i32, y0: i32, x1: i32, y1: i32) {
+    unsafe { vi_set_output(x0, y0, x1, y1) }
+}
+
+#[doc = "Move the output rectangle to a new position, keeping its size\n\n @param x     New left coordinate, in TV pixels\n @param y     New top coordinate, in TV pixels"]
+#[inline]
+pub fn move_output(x: i32, y: i32) {
+    unsafe { vi_move_output(x, y) }
+}
+
+#[doc = "Scroll the output rectangle by a relative amount, keeping its size\n\n This is a cheap way to move the whole picture, eg: for screen shake\n effects (see [`Shake`]).\n\n @param dx    Horizontal offset, in TV pixels\n @param dy    Vertical offset, in TV pixels"]
+#[inline]
+pub fn scroll_output(dx: i32, dy: i32) {
+    unsafe { vi_scroll_output(dx, dy) }
+}
+
+#[doc = "Get the borders currently configured in the VI"]
+#[inline]
+pub fn borders() -> Borders {
+    Borders::current()
+}
+
+#[doc = "Configure the borders around the displayed picture\n\n This changes the output rectangle so that it covers the TV output,\n minus the borders."]
+#[inline]
+pub fn set_borders(borders: Borders) {
+    unsafe { vi_set_borders(borders.into()) }
+}
+
+#[doc = "Set the horizontal scale so that a framebuffer of the given width covers\n the output rectangle"]
+#[inline]
+pub fn set_xscale(fb_width: f32) {
+    unsafe { vi_set_xscale(fb_width) }
+}
+
+#[doc = "Set the vertical scale so that a framebuffer of the given height covers\n the output rectangle"]
+#[inline]
+pub fn set_yscale(fb_height: f32) {
+    unsafe { vi_set_yscale(fb_height) }
+}
+
+#[doc = "Set the horizontal scale as a factor (eg: 2.0 to double the framebuffer\n pixels)"]
+#[inline]
+pub fn set_xscale_factor(factor: f32) {
+    unsafe { vi_set_xscale_factor(factor) }
+}
+
+#[doc = "Set the vertical scale as a factor (eg: 2.0 to double the framebuffer\n lines)"]
+#[inline]
+pub fn set_yscale_factor(factor: f32) {
+    unsafe { vi_set_yscale_factor(factor) }
+}
+
+#[doc = "Enable or disable the interlaced output"]
+#[inline]
+pub fn set_interlaced(interlaced: bool) {
+    unsafe { vi_set_interlaced(interlaced) }
+}
+
+#[doc = "Configure the anti-aliasing and resampling mode"]
+#[inline]
+pub fn set_aa_mode(mode: AaMode) {
+    unsafe { vi_set_aa_mode(mode as vi_aa_mode_t) }
+}
+
+#[doc = "Enable or disable the divot filter\n\n The divot filter removes the artifacts of the anti-aliasing on the\n silhouettes of the objects. It only makes sense together with\n anti-aliasing."]
+#[inline]
+pub fn set_divot(divot: bool) {
+    unsafe { vi_set_divot(divot) }
+}
+
+#[doc = "Enable or disable the dedither filter\n\n The dedither filter reconstructs a 32-bit output from a dithered 16-bit\n framebuffer."]
+#[inline]
+pub fn set_dedither(dedither: bool) {
+    unsafe { vi_set_dedither(dedither) }
+}
+
+#[doc = "Configure the gamma correction mode"]
+#[inline]
+pub fn set_gamma(gamma: Gamma) {
+    unsafe { vi_set_gamma(gamma as vi_gamma_t) }
+}
+
+#[doc = "Dump the VI registers on the debug output\n\n @param verbose   Also decode the meaning of each register"]
+#[inline]
+pub fn debug_dump(verbose: bool) {
+    unsafe { vi_debug_dump(verbose as _) }
+}
+
+#[doc = "A vblank handler, created by [`install_vblank_handler`].\n\n The handler is uninstalled when this is dropped."]
+pub struct VblankHandler(*mut Box<VblankFn>);
+
+impl core::fmt::Debug for VblankHandler {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("VblankHandler").field(&self.0).finish()
+    }
+}
+
+unsafe extern "C" fn vblank_trampoline(ctx: *mut core::ffi::c_void) {
+    unsafe {
+        let handler = &mut *(ctx as *mut Box<VblankFn>);
+        handler();
+    }
+}
+
+#[doc = "Install a handler that is called at each vblank\n\n The handler runs within the interrupt handler, so it must be quick and\n must not block. Several handlers can be installed at the same time."]
+#[inline]
+pub fn install_vblank_handler<F: FnMut() + Send + 'static>(handler: F) -> VblankHandler {
+    let handler: Box<VblankFn> = Box::new(handler);
+    let ctx = Box::into_raw(Box::new(handler));
+    unsafe { vi_install_vblank_handler(Some(vblank_trampoline), ctx as _) };
+    VblankHandler(ctx)
+}
+
+impl Drop for VblankHandler {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            vi_uninstall_vblank_handler(Some(vblank_trampoline), self.0 as _);
+            drop(Box::from_raw(self.0));
+        }
+    }
+}
+
+#[doc = "Largest decay factor of a [`Shake`], just below 1"]
+const MAX_DECAY: f32 = 1.0 - f32::EPSILON / 2.0;
+
+#[doc = "Screen shake effect, moving the whole picture via [`scroll_output`].\n\n Call [`Shake::update`] once per frame: the picture is moved by a random\n offset within the current intensity, which then decays. The picture is\n moved back to its original position when the shake ends, or when this is\n dropped."]
+#[derive(Debug)]
+pub struct Shake {
+    intensity: f32,
+    decay: f32,
+    offset: (i32, i32),
+    seed: u32,
+}
+
+impl Shake {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            intensity: 0.0,
+            decay: 0.0,
+            offset: (0, 0),
+            seed: 0x2545_f491,
+        }
+    }
+
+    #[doc = "Start shaking the screen\n\n Horizontal offsets are limited to the width of the TV output, and vertical\n ones to its height (see [`output_bounds`]): larger offsets would move the\n picture offscreen anyway. A non-finite intensity does not shake the screen.\n\n @param intensity     Maximum offset of the picture, in TV pixels\n @param decay         Factor applied to the intensity at each update (eg: 0.9),\n                      clamped to [0, 1) so that the shake always ends"]
+    #[inline]
+    pub fn start(&mut self, intensity: f32, decay: f32) {
+        self.intensity = if intensity.is_finite() {
+            intensity
+        } else {
+            0.0
+        };
+        // Also maps NaN to 0
+        self.decay = if decay > 0.0 {
+            decay.min(MAX_DECAY)
+        } else {
+            0.0
+        };
+    }
+
+    #[doc = "Stop shaking, moving the picture back to its original position"]
+    #[inline]
+    pub fn stop(&mut self) {
+        self.intensity = 0.0;
+        self.move_to((0, 0));
+    }
+
+    #[doc = "Whether the screen is still shaking"]
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.intensity >= 1.0 || self.offset != (0, 0)
+    }
+
+    #[doc = "Move the picture to a new random offset, and decay the intensity"]
+    pub fn update(&mut self) {
+        if self.intensity < 1.0 {
+            self.stop();
+            return;
+        }
+        let (x0, y0, x1, y1) = output_bounds();
+        let (width, height) = ((x1 - x0).max(1), (y1 - y0).max(1));
+        self.intensity = self.intensity.min(width.max(height) as f32);
+        let offset = (self.random_offset(width), self.random_offset(height));
+        self.move_to(offset);
+        self.intensity *= self.decay;
+    }
+
+    fn move_to(&mut self, offset: (i32, i32)) {
+        if offset != self.offset {
+            scroll_output(offset.0 - self.offset.0, offset.1 - self.offset.1);
+            self.offset = offset;
+        }
+    }
+
+    fn random_offset(&mut self, limit: i32) -> i32 {
+        let half = self.intensity.min(limit as f32) as i32;
+        (self.next() % (half as u32 * 2 + 1)) as i32 - half
+    }
+
+    fn next(&mut self) -> u32 {
+        // xorshift32
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+}
+
+impl Default for Shake {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Shake {
+    #[inline]
+    fn drop(&mut self) {
+        self.move_to((0, 0));
+    }
+}
@@ -0,0 +1,502 @@
+use core::{ffi::CStr, marker::PhantomData, ptr::NonNull};
+
+use crate::{
+    display::Display,
+    graphics::Color,
+    mixer::Mixer,
+    rdpq::{BlitParms, RdpQ},
+    surface::Surface,
+    sys::{mpeg2::*, yuv::yuv_frame_t, yuv::*},
+    wav64::Wav64,
+};
+
+// The generated mpeg2 bindings declare `yuv_frame_t` as an opaque type, as
+// yuv.h is not included by mpeg2.h, so the frame can't be returned by value
+// through them.
+unsafe extern "C" {
+    #[allow(clashing_extern_declarations)]
+    #[link_name = "mpeg2_get_frame"]
+    fn mpeg2_get_yuv_frame(mp2: *mut mpeg2_t) -> yuv_frame_t;
+}
+
+#[doc = "A YUV colorspace, describing the conversion of YUV pixels to RGB.\n\n Most videos are encoded using [`Colorspace::bt601_tv`] (standard\n definition) or [`Colorspace::bt709_tv`] (high definition). Other\n colorspaces can be created via [`Colorspace::new`]."]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct Colorspace(pub(crate) yuv_colorspace_t);
+
+impl Colorspace {
+    #[doc = "Calculate coefficients for a new YUV colorspace.\n\n This function is mostly for advanced usages, as most videos use the\n predefined colorspaces.\n\n @param Kr        Kr constant (red coefficient)\n @param Kb        Kb constant (blue coefficient)\n @param y0        Luma offset (16 for TV range, 0 for full range)\n @param yrange    Number of luma levels (219 for TV range, 256 for full range)\n @param crange    Number of chroma levels (224 for TV range, 256 for full range)"]
+    #[inline]
+    pub fn new(kr: f32, kb: f32, y0: i32, yrange: i32, crange: i32) -> Self {
+        Self(unsafe { yuv_new_colorspace(kr, kb, y0, yrange, crange) })
+    }
+    #[doc = "Colorspace for ITU-R BT.601 (standard definition), TV range"]
+    #[inline]
+    pub fn bt601_tv() -> Self {
+        Self(unsafe { YUV_BT601_TV })
+    }
+    #[doc = "Colorspace for ITU-R BT.601 (standard definition), full range"]
+    #[inline]
+    pub fn bt601_full() -> Self {
+        Self(unsafe { YUV_BT601_FULL })
+    }
+    #[doc = "Colorspace for ITU-R BT.709 (high definition), TV range"]
+    #[inline]
+    pub fn bt709_tv() -> Self {
+        Self(unsafe { YUV_BT709_TV })
+    }
+    #[doc = "Colorspace for ITU-R BT.709 (high definition), full range"]
+    #[inline]
+    pub fn bt709_full() -> Self {
+        Self(unsafe { YUV_BT709_FULL })
+    }
+    #[doc = "Convert a single YUV pixel into RGB.\n\n This function is a CPU fallback, mostly useful for testing and for\n computing background colors: it is too slow for full frames.\n\n @param y     Y component\n @param u     U component\n @param v     V component\n @return      The converted pixel in RGB format"]
+    #[inline]
+    pub fn to_rgb(&self, y: u8, u: u8, v: u8) -> Color {
+        Color::from_raw(unsafe { yuv_to_rgb(y, u, v, &self.0) })
+    }
+}
+
+impl Default for Colorspace {
+    #[inline]
+    fn default() -> Self {
+        Self::bt601_tv()
+    }
+}
+
+#[doc = "Alignment of the video on the screen"]
+#[repr(u32)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Align {
+    #[doc = "Align to center of the available space"]
+    #[default]
+    Center = yuv_align_t_YUV_ALIGN_CENTER,
+    #[doc = "Align to left/top of the available space"]
+    Min = yuv_align_t_YUV_ALIGN_MIN,
+    #[doc = "Align to right/bottom of the available space"]
+    Max = yuv_align_t_YUV_ALIGN_MAX,
+}
+
+#[doc = "Video zoom mode"]
+#[repr(u32)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Zoom {
+    #[doc = "Zoom the frame to fit the screen, keeping the aspect ratio"]
+    #[default]
+    KeepAspect = yuv_zoom_t_YUV_ZOOM_KEEP_ASPECT,
+    #[doc = "Zoom the frame to fit the screen, distorting the aspect ratio"]
+    Full = yuv_zoom_t_YUV_ZOOM_FULL,
+    #[doc = "Do not zoom the frame"]
+    None = yuv_zoom_t_YUV_ZOOM_NONE,
+}
+
+#[doc = "Parameters for drawing a full-motion video (to be passed to [`YuvBlitter::new_fmv`])"]
+#[derive(Debug, Copy, Clone)]
+pub struct FmvParms {
+    cs: Option<Colorspace>,
+    halign: Align,
+    valign: Align,
+    zoom: Zoom,
+    bkg_color: Color,
+}
+
+impl FmvParms {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            cs: None,
+            halign: Align::Center,
+            valign: Align::Center,
+            zoom: Zoom::KeepAspect,
+            bkg_color: Color::rgba32(0, 0, 0, 0xFF),
+        }
+    }
+    #[doc = "Color space to use during conversion (default: BT.601 TV range)"]
+    #[inline]
+    pub const fn colorspace(mut self, cs: Colorspace) -> Self {
+        self.cs = Some(cs);
+        self
+    }
+    #[doc = "Horizontal alignment of the video (default: center)"]
+    #[inline]
+    pub const fn halign(mut self, halign: Align) -> Self {
+        self.halign = halign;
+        self
+    }
+    #[doc = "Vertical alignment of the video (default: center)"]
+    #[inline]
+    pub const fn valign(mut self, valign: Align) -> Self {
+        self.valign = valign;
+        self
+    }
+    #[doc = "Zoom mode of the video (default: keep aspect ratio)"]
+    #[inline]
+    pub const fn zoom(mut self, zoom: Zoom) -> Self {
+        self.zoom = zoom;
+        self
+    }
+    #[doc = "Color used to fill the screen area not covered by the video (default: black)"]
+    #[inline]
+    pub const fn bkg_color(mut self, bkg_color: Color) -> Self {
+        self.bkg_color = bkg_color;
+        self
+    }
+}
+
+impl Default for FmvParms {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[doc = "A YUV frame, made of three planes (Y, U and V) stored as I8 surfaces.\n\n The U and V planes are usually half the size of the Y plane in both\n directions (4:2:0 subsampling)."]
+#[repr(transparent)]
+pub struct YuvFrame<'m>(yuv_frame_t, PhantomData<&'m ()>);
+
+impl<'m> YuvFrame<'m> {
+    #[doc = "Create a frame from three I8 surfaces\n\n @param y     Y plane\n @param u     U plane\n @param v     V plane"]
+    #[inline]
+    pub fn new(y: &'m Surface, u: &'m Surface, v: &'m Surface) -> Self {
+        // The planes are borrowed: copy the descriptors without taking ownership.
+        unsafe {
+            Self(
+                yuv_frame_t {
+                    y: core::ptr::read(&y.0),
+                    u: core::ptr::read(&u.0),
+                    v: core::ptr::read(&v.0),
+                },
+                PhantomData,
+            )
+        }
+    }
+    #[doc = "Y (luma) plane"]
+    #[inline]
+    pub fn y(&self) -> &Surface<'m> {
+        unsafe { &*(&self.0.y as *const _ as *const Surface) }
+    }
+    #[doc = "U (chroma) plane"]
+    #[inline]
+    pub fn u(&self) -> &Surface<'m> {
+        unsafe { &*(&self.0.u as *const _ as *const Surface) }
+    }
+    #[doc = "V (chroma) plane"]
+    #[inline]
+    pub fn v(&self) -> &Surface<'m> {
+        unsafe { &*(&self.0.v as *const _ as *const Surface) }
+    }
+    #[inline]
+    pub const fn as_raw(&self) -> *mut yuv_frame_t {
+        &self.0 as *const _ as _
+    }
+}
+
+impl<'m> core::fmt::Debug for YuvFrame<'m> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("YuvFrame")
+            .field("y", self.y())
+            .field("u", self.u())
+            .field("v", self.v())
+            .finish()
+    }
+}
+
+impl<'r> RdpQ<'r> {
+    #[doc = "Draw a YUV frame\n\n This function draws a YUV frame, converting it to RGB on the fly via\n the RDP. It is similar to [`RdpQ::blit`], and accepts the same\n parameters for scaling, flipping and cropping. The conversion uses\n the YUV mode of the RDP, so the current render mode is changed.\n\n To draw many frames with the same parameters (eg: a video), a\n [`YuvBlitter`] is faster.\n\n @param frame     Frame to draw\n @param x0        X coordinate on the framebuffer where to draw the frame\n @param y0        Y coordinate on the framebuffer where to draw the frame\n @param parms     Parameters for the blitting operation (if any)\n @param cs        Colorspace to use for the conversion"]
+    #[inline]
+    pub fn yuv_blit(
+        &mut self,
+        frame: &YuvFrame<'_>,
+        x0: f32,
+        y0: f32,
+        parms: Option<&BlitParms>,
+        cs: &Colorspace,
+    ) {
+        unsafe {
+            yuv_init();
+            yuv_tex_blit(
+                frame.as_raw(),
+                x0,
+                y0,
+                parms.map(|p| &p.0 as _).unwrap_or_else(core::ptr::null),
+                &cs.0,
+            )
+        }
+        self.state_invalidate();
+    }
+}
+
+#[doc = "A YUV blitter, optimized for drawing multiple frames with the same parameters.\n\n The blitter precomputes the RDP commands needed to draw a frame into a\n block, so that drawing a frame only requires to patch the frame addresses\n and run the block. This is the preferred way to draw a video."]
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct YuvBlitter(yuv_blitter_t);
+
+impl YuvBlitter {
+    #[doc = "Create a YUV blitter, with generic blitting parameters\n\n @param video_width     Width of the video frames in pixels\n @param video_height    Height of the video frames in pixels\n @param x0              X coordinate on the framebuffer where to draw the frames\n @param y0              Y coordinate on the framebuffer where to draw the frames\n @param parms           Parameters for the blitting operation (if any)\n @param cs              Colorspace to use for the conversion"]
+    #[inline]
+    pub fn new(
+        video_width: u32,
+        video_height: u32,
+        x0: f32,
+        y0: f32,
+        parms: Option<&BlitParms>,
+        cs: &Colorspace,
+    ) -> Self {
+        unsafe {
+            yuv_init();
+            Self(yuv_blitter_new(
+                video_width as _,
+                video_height as _,
+                x0,
+                y0,
+                parms.map(|p| &p.0 as _).unwrap_or_else(core::ptr::null),
+                &cs.0,
+            ))
+        }
+    }
+    #[doc = "Create a YUV blitter for a full-motion video\n\n The video is zoomed and aligned on the screen according to the\n parameters, and the rest of the screen is filled with the background\n color.\n\n @param video_width     Width of the video frames in pixels\n @param video_height    Height of the video frames in pixels\n @param screen_width    Width of the screen in pixels\n @param screen_height   Height of the screen in pixels\n @param parms           Parameters for the video (if any)"]
+    #[inline]
+    pub fn new_fmv(
+        video_width: u32,
+        video_height: u32,
+        screen_width: u32,
+        screen_height: u32,
+        parms: Option<&FmvParms>,
+    ) -> Self {
+        let parms = parms.copied().unwrap_or_default();
+        let cs = parms.cs.unwrap_or_default();
+        let raw = yuv_fmv_parms_t {
+            cs: &cs.0,
+            halign: parms.halign as _,
+            valign: parms.valign as _,
+            zoom: parms.zoom as _,
+            bkg_color: parms.bkg_color.into_raw(),
+        };
+        unsafe {
+            yuv_init();
+            Self(yuv_blitter_new_fmv(
+                video_width as _,
+                video_height as _,
+                screen_width as _,
+                screen_height as _,
+                &raw,
+            ))
+        }
+    }
+    #[doc = "Create a YUV blitter for a full-motion video, covering the whole display\n\n This is the same as [`YuvBlitter::new_fmv`], using the current\n resolution of the display as screen size."]
+    #[inline]
+    pub fn for_display(
+        display: &Display,
+        video_width: u32,
+        video_height: u32,
+        parms: Option<&FmvParms>,
+    ) -> Self {
+        Self::new_fmv(
+            video_width,
+            video_height,
+            display.width(),
+            display.height(),
+            parms,
+        )
+    }
+    #[doc = "Draw a YUV frame using the blitter\n\n The current render mode is changed to the YUV mode.\n\n @param rdpq      RDP queue, attached to the target surface\n @param frame     Frame to draw"]
+    #[inline]
+    pub fn run(&mut self, rdpq: &mut RdpQ, frame: &YuvFrame<'_>) {
+        unsafe { yuv_blitter_run(&mut self.0, frame.as_raw()) }
+        rdpq.state_invalidate();
+    }
+}
+
+impl Drop for YuvBlitter {
+    #[doc = "Free the memory allocated by a blitter\n\n Blocks run by the blitter might still be referenced in the RSP queue, so\n the blitter must not be dropped before the RSP has processed them."]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { yuv_blitter_free(&mut self.0) }
+    }
+}
+
+#[doc = "An MPEG-2 video decoder.\n\n The decoder streams the video from a file (eg: in the DFS filesystem),\n decoding frames via the RSP. Only the video stream is decoded: audio must\n be stored in a separate file (eg: a WAV64), and can be kept in sync via\n [`Player::sync_audio`]. The decoder is closed when dropped."]
+#[derive(Debug)]
+pub struct Mpeg2 {
+    ptr: NonNull<mpeg2_t>,
+}
+
+impl Mpeg2 {
+    #[doc = "Open a MPEG-2 video file\n\n @param fn    Path to the video file (eg: \"rom:/intro.m1v\")"]
+    #[inline]
+    pub fn open(filename: &CStr) -> Self {
+        Self {
+            ptr: NonNull::new(unsafe { mpeg2_open(filename.as_ptr()) }).unwrap(),
+        }
+    }
+    #[doc = "Get the framerate of the video, in frames per second"]
+    #[inline]
+    pub fn framerate(&self) -> f32 {
+        unsafe { mpeg2_get_framerate(self.as_raw()) }
+    }
+    #[doc = "Get the width of the video frames in pixels"]
+    #[inline]
+    pub fn width(&self) -> u32 {
+        unsafe { mpeg2_get_width(self.as_raw()) as _ }
+    }
+    #[doc = "Get the height of the video frames in pixels"]
+    #[inline]
+    pub fn height(&self) -> u32 {
+        unsafe { mpeg2_get_height(self.as_raw()) as _ }
+    }
+    #[doc = "Decode the next frame of the video\n\n @return true if a frame was decoded, false if the video is finished"]
+    #[inline]
+    pub fn next_frame(&mut self) -> bool {
+        unsafe { mpeg2_next_frame(self.as_raw()) }
+    }
+    #[doc = "Get the last decoded frame\n\n The frame is stored in buffers owned by the decoder: its contents are\n valid only until the next frame is decoded."]
+    #[inline]
+    pub fn frame(&self) -> YuvFrame<'_> {
+        YuvFrame(unsafe { mpeg2_get_yuv_frame(self.as_raw()) }, PhantomData)
+    }
+    #[doc = "Rewind the video to the beginning"]
+    #[inline]
+    pub fn rewind(&mut self) {
+        unsafe { mpeg2_rewind(self.as_raw()) }
+    }
+    #[doc = "Iterate over the frames of the video, decoding them one at a time\n\n Each frame is stored in buffers owned by the decoder: its contents are\n valid only until the next frame is decoded, so [`Frames::next_frame`]\n borrows the frame from the iterator and frames must be drawn before\n advancing it."]
+    #[inline]
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames(self)
+    }
+    #[inline]
+    pub const fn as_raw(&self) -> *mut mpeg2_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Drop for Mpeg2 {
+    #[doc = "Close the video and free all the resources associated with it"]
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { mpeg2_close(self.as_raw()) }
+    }
+}
+
+#[doc = "Iterator over the frames of a video, created by [`Mpeg2::frames`]\n\n This is not an [`Iterator`], as each frame borrows the decoder buffers,\n which are overwritten by the next frame:\n\n ```ignore\n let mut frames = video.frames();\n while let Some(frame) = frames.next_frame() {\n     blitter.run(&mut rdpq, &frame);\n }\n ```"]
+#[derive(Debug)]
+pub struct Frames<'m>(&'m mut Mpeg2);
+
+impl Frames<'_> {
+    #[doc = "Decode the next frame of the video\n\n @return The decoded frame, or `None` if the video is finished"]
+    #[inline]
+    pub fn next_frame(&mut self) -> Option<YuvFrame<'_>> {
+        self.0.next_frame().then(|| self.0.frame())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct AudioSync {
+    ch: u32,
+    frequency: f32,
+}
+
+#[doc = "A full-motion video player.\n\n The player decodes a [`Mpeg2`] video at its own framerate, independently\n of the rate at which the game renders frames, and draws it covering the\n display via a [`YuvBlitter`].\n\n The playback clock is the system timer, started when the first frame is\n decoded. If an audio track is synchronized via [`Player::sync_audio`],\n the clock follows the playback position of the audio instead, so that\n audio and video never drift apart.\n\n A typical playback loop is:\n\n ```ignore\n let mut player = Player::new(Mpeg2::open(c\"rom:/intro.m1v\"), &display, None);\n while player.update(Some(&mut mixer)) {\n     let mut fb = display.color_buffer();\n     let att = rdpq.attach(&mut fb, None);\n     player.draw(&mut att);\n     att.detach_show();\n }\n ```"]
+#[derive(Debug)]
+pub struct Player {
+    video: Mpeg2,
+    blitter: YuvBlitter,
+    fps: f32,
+    refresh_rate: f32,
+    start: Option<u64>,
+    decoded: u32,
+    dropped: u32,
+    audio: Option<AudioSync>,
+}
+
+impl Player {
+    #[doc = "Create a player for a video, covering the whole display\n\n @param video     Video to play\n @param display   Display the video will be shown on\n @param parms     Parameters for the video (if any)"]
+    pub fn new(video: Mpeg2, display: &Display, parms: Option<&FmvParms>) -> Self {
+        let blitter = YuvBlitter::for_display(display, video.width(), video.height(), parms);
+        Self {
+            fps: video.framerate(),
+            refresh_rate: display.refresh_rate(),
+            video,
+            blitter,
+            start: None,
+            decoded: 0,
+            dropped: 0,
+            audio: None,
+        }
+    }
+    #[doc = "Start playing an audio track, and synchronize the video to it\n\n The track should be played from the beginning together with the video,\n so this should be called before the first [`Player::update`].\n\n @param mixer     Mixer to play the track on\n @param ch        Mixer channel to use for playback\n @param wav       Audio track"]
+    pub fn sync_audio(&mut self, mixer: &mut Mixer, ch: u32, wav: &mut Wav64) {
+        wav.play(mixer, ch);
+        self.audio = Some(AudioSync {
+            ch,
+            frequency: wav.frequency(),
+        });
+    }
+    #[doc = "Advance the playback, decoding frames to catch up with the clock\n\n This must be called once per rendered frame. The frame that is decoded\n is the one that should be on screen at the next vertical blank, so the\n video is paced against the refresh rate of the display. If rendering is\n slower than the video framerate, frames are dropped; if it is faster,\n the same frame is drawn multiple times.\n\n @param mixer     Mixer playing the synchronized audio track (if any)\n @return          false when the video is finished"]
+    pub fn update(&mut self, mixer: Option<&mut Mixer>) -> bool {
+        let now = crate::n64::ticks();
+        let tps = crate::n64::ticks_per_second() as f32;
+        let audio_time = match (self.audio, mixer) {
+            (Some(sync), Some(mixer)) => mixer
+                .ch_is_playing(sync.ch)
+                .then(|| mixer.ch_pos(sync.ch) as f32 / sync.frequency),
+            _ => None,
+        };
+        let time = match audio_time {
+            Some(time) => {
+                // Keep the timer aligned, so that the clock continues
+                // seamlessly if the audio track ends before the video.
+                self.start = Some(now.saturating_sub((time * tps) as u64));
+                time
+            }
+            None => {
+                let start = *self.start.get_or_insert(now);
+                (now - start) as f32 / tps
+            }
+        };
+
+        let target = ((time + 0.5 / self.refresh_rate) * self.fps) as u32 + 1;
+        let mut decoded = 0;
+        while self.decoded < target {
+            if !self.video.next_frame() {
+                return false;
+            }
+            self.decoded += 1;
+            decoded += 1;
+        }
+        if decoded > 1 {
+            self.dropped += decoded - 1;
+        }
+        true
+    }
+    #[doc = "Draw the current frame, covering the render target\n\n @param rdpq      RDP queue, attached to the target surface"]
+    #[inline]
+    pub fn draw(&mut self, rdpq: &mut RdpQ) {
+        let frame = self.video.frame();
+        self.blitter.run(rdpq, &frame);
+    }
+    #[doc = "Number of frames decoded so far"]
+    #[inline]
+    pub fn frame_index(&self) -> u32 {
+        self.decoded
+    }
+    #[doc = "Number of frames that were decoded but never drawn, because rendering was too slow"]
+    #[inline]
+    pub fn dropped_frames(&self) -> u32 {
+        self.dropped
+    }
+    #[doc = "Current playback position, in seconds"]
+    #[inline]
+    pub fn position(&self) -> f32 {
+        self.decoded.saturating_sub(1) as f32 / self.fps
+    }
+    #[inline]
+    pub fn video(&self) -> &Mpeg2 {
+        &self.video
+    }
+    #[doc = "Stop playback, returning the video"]
+    #[inline]
+    pub fn into_video(self) -> Mpeg2 {
+        self.video
+    }
+}
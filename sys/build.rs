@@ -1,5 +1,64 @@
+use std::{env, fs, path::Path};
+
 fn main() {
-    println!("cargo::rustc-link-lib=c");
-    println!("cargo::rustc-link-lib=dragon");
-    println!("cargo::rustc-link-lib=dragonsys");
+    if env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("mips") {
+        println!("cargo::rustc-link-lib=c");
+        println!("cargo::rustc-link-lib=dragon");
+        println!("cargo::rustc-link-lib=dragonsys");
+        return;
+    }
+    // Host builds (eg: unit tests) only use the pure Rust parts of the crate,
+    // but the bindings are generated for the N64 ABI: use a copy of them as
+    // bindgen emits it with `layout_tests(false)`, with C `long` kept 32-bit so
+    // the crate type-checks the same way as on the console.
+    println!("cargo::rerun-if-changed=src");
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    for entry in fs::read_dir("src").unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_name() == Some("lib.rs".as_ref()) {
+            continue;
+        }
+        let bindings = fs::read_to_string(&path).unwrap();
+        let bindings = host_bindings(&bindings);
+        fs::write(
+            Path::new(&out_dir).join(path.file_name().unwrap()),
+            bindings,
+        )
+        .unwrap();
+    }
+}
+
+fn host_bindings(bindings: &str) -> String {
+    let mut out = String::with_capacity(bindings.len());
+    let mut lines = bindings.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line == "#[allow(clippy::unnecessary_operation, clippy::identity_op)]"
+            && lines.peek() == Some(&"const _: () = {")
+        {
+            lines.find(|&line| line == "};");
+            continue;
+        }
+        let line = replace_type(line, "::core::ffi::c_long", "i32");
+        let line = replace_type(&line, "::core::ffi::c_ulong", "u32");
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn replace_type(line: &str, from: &str, to: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(from) {
+        let end = start + from.len();
+        out.push_str(&rest[..start]);
+        // Leave longer names such as `c_longlong` alone
+        match rest[end..].starts_with(|c: char| c == '_' || c.is_ascii_alphanumeric()) {
+            true => out.push_str(from),
+            false => out.push_str(to),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
 }
@@ -5,87 +5,25 @@
 #![allow(unused_imports)]
 #![allow(unnecessary_transmutes)]
 
-pub mod asset;
-pub mod audio;
-pub mod ay8910;
-pub mod backtrace;
-pub mod bb_rtc;
-pub mod bbfs;
-pub mod bio_sensor;
-pub mod console;
-pub mod controller;
-pub mod cpak;
-pub mod cpakfs;
-pub mod dd;
-pub mod debug;
-pub mod dir;
-pub mod display;
-pub mod dlfcn;
-pub mod dma;
-pub mod dragonfs;
-pub mod eeprom;
-pub mod eepromfs;
-pub mod eia608;
-pub mod entropy;
-pub mod exception;
-pub mod fat;
-pub mod fcntl;
-pub mod fgeom;
-pub mod fmath;
-pub mod font;
-pub mod graphics;
-pub mod interrupt;
-pub mod ioctl;
-pub mod joybus;
-pub mod joybus_accessory;
-pub mod joypad;
-pub mod kernel;
-pub mod kirq;
-pub mod kqueue;
-pub mod ksemaphore;
-pub mod ktls;
-pub mod malloc;
-pub mod mempak;
-pub mod mixer;
-pub mod model64;
-pub mod mpeg2;
-pub mod n64sys;
-pub mod nand;
-pub mod pifile;
-pub mod pixelfx;
-pub mod rdp;
-pub mod rdpq;
-pub mod rdpq_attach;
-pub mod rdpq_constants;
-pub mod rdpq_debug;
-pub mod rdpq_font;
-pub mod rdpq_macros;
-pub mod rdpq_mat;
-pub mod rdpq_mode;
-pub mod rdpq_paragraph;
-pub mod rdpq_rect;
-pub mod rdpq_sprite;
-pub mod rdpq_tex;
-pub mod rdpq_text;
-pub mod rdpq_tri;
-pub mod rsp;
-pub mod rspq;
-pub mod rspq_constants;
-pub mod rspq_profile;
-pub mod rtc;
-pub mod samplebuffer;
-pub mod skc;
-pub mod sprite;
-pub mod stdio;
-pub mod string_hash;
-pub mod surface;
-pub mod system;
-pub mod timer;
-pub mod tpak;
-pub mod unistd;
-pub mod usb;
-pub mod vi;
-pub mod wav64;
-pub mod xm64;
-pub mod ym64;
-pub mod yuv;
+macro_rules! bindings {
+    ($($name:ident)*) => {$(
+        pub mod $name {
+            #[cfg(target_arch = "mips")]
+            include!(concat!(stringify!($name), ".rs"));
+            // Host copy without the N64 layout tests, see build.rs
+            #[cfg(not(target_arch = "mips"))]
+            include!(concat!(env!("OUT_DIR"), "/", stringify!($name), ".rs"));
+        }
+    )*};
+}
+
+bindings! {
+    asset audio ay8910 backtrace bb_rtc bbfs bio_sensor console controller cpak cpakfs dd debug
+    dir display dlfcn dma dragonfs eeprom eepromfs eia608 entropy exception fat fcntl fgeom
+    fmath font graphics interrupt ioctl joybus joybus_accessory joypad kernel kirq kqueue
+    ksemaphore ktls malloc mempak mixer model64 mpeg2 n64sys nand pifile pixelfx rdp rdpq
+    rdpq_attach rdpq_constants rdpq_debug rdpq_font rdpq_macros rdpq_mat rdpq_mode
+    rdpq_paragraph rdpq_rect rdpq_sprite rdpq_tex rdpq_text rdpq_tri rsp rspq rspq_constants
+    rspq_profile rtc samplebuffer skc sprite stdio string_hash surface system timer tpak unistd
+    usb vi wav64 xm64 ym64 yuv
+}